@@ -0,0 +1,190 @@
+/* FLEM C API (features = ["ffi"]). Generated with cbindgen, do not edit. */
+
+#ifndef FLEM_H
+#define FLEM_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// `Status` for C, plus `NullPointer`
+typedef enum FlemStatus {
+  FLEM_STATUS_OK = 0,
+  FLEM_STATUS_PACKET_RECEIVED = 1,
+  FLEM_STATUS_PACKET_BUILDING = 2,
+  FLEM_STATUS_GET_BYTE_FINISHED = 3,
+  FLEM_STATUS_VERSION_LENGTH = 4,
+  FLEM_STATUS_PACKET_OVERFLOW = 5,
+  FLEM_STATUS_HEADER_BYTES_NOT_FOUND = 6,
+  FLEM_STATUS_GET_BYTE_ISSUE = 7,
+  FLEM_STATUS_CHECKSUM_ERROR = 8,
+  FLEM_STATUS_UNSPECIFIED_ERROR = 9,
+  FLEM_STATUS_UNRECOGNIZED_REQUEST = 10,
+  FLEM_STATUS_INVALID_DATA_LENGTH_DETECTED = 11,
+  FLEM_STATUS_DECOMPRESSION_FAILED = 12,
+  FLEM_STATUS_DECRYPTION_FAILED = 13,
+  FLEM_STATUS_REPLAY_DETECTED = 14,
+  FLEM_STATUS_NOT_ADDRESSED = 15,
+  // A pointer argument was NULL
+  FLEM_STATUS_NULL_POINTER = 255,
+} FlemStatus;
+
+// Opaque packet handle
+typedef struct FlemPacket FlemPacket;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Data bytes a packet can hold, `FLEM_FFI_DATA_SIZE`
+size_t flem_max_data_size(void);
+
+// Bytes of storage `flem_packet_init` needs for a packet, with any alignment
+size_t flem_packet_size(void);
+
+// Sets up a reset packet in caller provided storage, e.g. a static buffer, and returns its
+// handle. Returns NULL if `storage` is NULL or `size` is less than `flem_packet_size()`. The
+// handle lives as long as the storage and must not be passed to `flem_packet_free`.
+//
+// # Safety
+// `storage` must be NULL or point to `size` writable bytes that aren't used for anything else
+// while the handle is.
+struct FlemPacket *flem_packet_init(void *storage, size_t size);
+
+// Allocates a new, reset packet. Release it with `flem_packet_free`. (requires features = ["std"])
+struct FlemPacket *flem_packet_new(void);
+
+// Releases a packet from `flem_packet_new`. NULL is ignored. (requires features = ["std"])
+//
+// # Safety
+// `packet` must be NULL or a handle from `flem_packet_new` that wasn't freed yet.
+void flem_packet_free(struct FlemPacket *packet);
+
+// Clears the whole packet, data included
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+enum FlemStatus flem_packet_reset(struct FlemPacket *packet);
+
+// Clears the header and counters but leaves the old data in place, which is faster
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+enum FlemStatus flem_packet_reset_lazy(struct FlemPacket *packet);
+
+// Appends `length` bytes of data, `FLEM_STATUS_PACKET_OVERFLOW` if they don't fit
+//
+// # Safety
+// `packet` must be NULL or a live handle, and `data` must point to `length` readable bytes (it may
+// be NULL if `length` is 0).
+enum FlemStatus flem_packet_add_data(struct FlemPacket *packet, const uint8_t *data, size_t length);
+
+// Computes the checksum and fills in the header after `flem_packet_add_data`
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+enum FlemStatus flem_packet_pack(struct FlemPacket *packet);
+
+// Resets the packet and packs `request` with a `response::SUCCESS` response and `data`
+//
+// # Safety
+// As `flem_packet_add_data`.
+enum FlemStatus flem_packet_pack_data(struct FlemPacket *packet,
+                                      uint16_t request,
+                                      const uint8_t *data,
+                                      size_t length);
+
+// Resets the packet and packs `request` with the `error` response and `data`
+//
+// # Safety
+// As `flem_packet_add_data`.
+enum FlemStatus flem_packet_pack_error(struct FlemPacket *packet,
+                                       uint16_t request,
+                                       uint16_t error,
+                                       const uint8_t *data,
+                                       size_t length);
+
+// Feeds one received byte. Returns `FLEM_STATUS_OK` once a valid packet is complete,
+// `FLEM_STATUS_PACKET_BUILDING` while more bytes are needed, and an error otherwise, after which
+// the packet should be reset.
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+enum FlemStatus flem_packet_construct(struct FlemPacket *packet, uint8_t byte);
+
+// Writes the next byte to send to `byte`. Returns `FLEM_STATUS_GET_BYTE_FINISHED` once every byte
+// was read.
+//
+// # Safety
+// `packet` must be NULL or a live handle, and `byte` NULL or writable.
+enum FlemStatus flem_packet_get_byte(struct FlemPacket *packet, uint8_t *byte);
+
+// Checks the checksum of the packet
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+bool flem_packet_validate(struct FlemPacket *packet);
+
+// Request code, 0 for NULL
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+uint16_t flem_packet_get_request(const struct FlemPacket *packet);
+
+// Sets the request code, call `flem_packet_pack` afterwards
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+void flem_packet_set_request(struct FlemPacket *packet, uint16_t request);
+
+// Response code, 0 for NULL
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+uint16_t flem_packet_get_response(const struct FlemPacket *packet);
+
+// Sets the response code, call `flem_packet_pack` afterwards
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+void flem_packet_set_response(struct FlemPacket *packet, uint16_t response);
+
+// Checksum field, 0 for NULL
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+uint16_t flem_packet_get_checksum(const struct FlemPacket *packet);
+
+// The data, `flem_packet_data_length` bytes long. Valid until the packet is changed or freed.
+// A length over `flem_max_data_size`, e.g. after `FLEM_STATUS_INVALID_DATA_LENGTH_DETECTED`, is
+// capped.
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+const uint8_t *flem_packet_data(const struct FlemPacket *packet);
+
+// Bytes of data, at most `flem_max_data_size`, 0 for NULL
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+size_t flem_packet_data_length(const struct FlemPacket *packet);
+
+// The whole packet as sent on the wire, `flem_packet_length` bytes long. Valid until the packet
+// is changed or freed. The data is capped like `flem_packet_data`.
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+const uint8_t *flem_packet_bytes(const struct FlemPacket *packet);
+
+// Bytes on the wire, header included, with the data capped like `flem_packet_data_length`. 0 for
+// NULL.
+//
+// # Safety
+// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+size_t flem_packet_length(const struct FlemPacket *packet);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FLEM_H */
@@ -0,0 +1,150 @@
+//! Source and destination addressing for multi-drop buses such as RS-485.
+//!
+//! The header has no room for addresses, so addressed packets start their data with a 2 byte
+//! prefix:
+//!
+//! | Destination (1) | Source (1) | Data |
+//!
+//! Addresses are 0 to 0xFE, and `BROADCAST` (0xFF) as a destination reaches every device. Devices
+//! shouldn't reply to broadcasts, as the replies would collide on the bus.
+//!
+//! Addressing is off by default and packets are point-to-point as before. A receiver joins a bus by
+//! setting its own address with `Packet::set_local_address`. `construct` then looks at the first
+//! data byte and skips packets for other addresses without storing their data or computing the
+//! checksum, returning `Status::NotAddressed` once the skipped packet ends. As with any other
+//! error, reset the packet before the next byte.
+//!
+//! Services such as `param` expect the data without the prefix: `strip_address` removes it from a
+//! received request and `add_address` puts one in front of the packed reply.
+//!
+//! With features = ["secure"], seal addressed packets with `SecureSession::seal_addressed` and open
+//! them with `open_addressed`. `seal` would encrypt the prefix, these keep it in the clear for
+//! routing and authenticate it.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::address::Address;
+//!     use flem::{Packet, Status};
+//!
+//!     let mut tx = Packet::<64>::new();
+//!     let mut rx = Packet::<64>::new();
+//!     rx.set_local_address(Some(3));
+//!
+//!     // For device 4, skipped
+//!     tx.pack_addressed(0x10, Address::new(4, 0), &[1, 2, 3]).unwrap();
+//!     let mut status = Ok(());
+//!     for byte in tx.bytes() {
+//!         status = rx.construct(*byte);
+//!     }
+//!     assert_eq!(status, Err(Status::NotAddressed));
+//!     rx.reset_lazy();
+//!
+//!     // For device 3
+//!     tx.pack_addressed(0x10, Address::new(3, 0), &[1, 2, 3]).unwrap();
+//!     for byte in tx.bytes() {
+//!         status = rx.construct(*byte);
+//!     }
+//!     assert_eq!(status, Ok(()));
+//!     assert_eq!(rx.strip_address(), Some(Address::new(3, 0)));
+//!     assert_eq!(rx.payload(), &[1, 2, 3]);
+//! }
+//! ```
+
+use crate::{response, Packet, Status};
+
+/// Destination that reaches every device on the bus
+pub const BROADCAST: u8 = 0xFF;
+/// Bytes of the destination and source at the start of addressed data
+pub const ADDRESS_PREFIX_SIZE: usize = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub destination: u8,
+    pub source: u8,
+}
+
+impl Address {
+    pub const fn new(destination: u8, source: u8) -> Self {
+        Address {
+            destination,
+            source,
+        }
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        self.destination == BROADCAST
+    }
+
+    /// The address of a reply to this packet, i.e. source and destination swapped
+    pub fn reply(&self) -> Address {
+        Address::new(self.source, self.destination)
+    }
+}
+
+impl<const T: usize> Packet<T> {
+    /// Sets the address this packet receives for, or `None` (the default) to receive every packet.
+    /// Kept across resets.
+    pub fn set_local_address(&mut self, address: Option<u8>) {
+        assert!(
+            address != Some(BROADCAST),
+            "The broadcast address can't be a local address"
+        );
+        self.local_address = address;
+    }
+
+    pub fn local_address(&self) -> Option<u8> {
+        self.local_address
+    }
+
+    /// Like `pack_data`, with an address prefix in front of `data`
+    pub fn pack_addressed(
+        &mut self,
+        request: u16,
+        address: Address,
+        data: &[u8],
+    ) -> Result<(), Status> {
+        self.reset_lazy();
+        self.request = request;
+        self.add_data(&[address.destination, address.source])?;
+        self.add_data(data)?;
+        self.response = response::SUCCESS;
+        self.pack();
+        Ok(())
+    }
+
+    /// The address prefix, if the data is long enough to have one
+    pub fn address(&self) -> Option<Address> {
+        match self.payload() {
+            [destination, source, ..] => Some(Address::new(*destination, *source)),
+            _ => None,
+        }
+    }
+
+    /// Removes the address prefix from the data and packs the packet again. Returns `None`, leaving
+    /// the packet untouched, if the data is too short to have one.
+    pub fn strip_address(&mut self) -> Option<Address> {
+        let address = self.address()?;
+        let length = self.length as usize;
+        self.data_mut().copy_within(ADDRESS_PREFIX_SIZE..length, 0);
+        self.length = (length - ADDRESS_PREFIX_SIZE) as u16;
+        self.pack();
+        Some(address)
+    }
+
+    /// Puts an address prefix in front of the data and packs the packet again, keeping the request
+    /// and response
+    pub fn add_address(&mut self, address: Address) -> Result<(), Status> {
+        let length = self.length as usize;
+        if length + ADDRESS_PREFIX_SIZE > T {
+            return Err(Status::PacketOverflow);
+        }
+        let data = self.data_mut();
+        data.copy_within(0..length, ADDRESS_PREFIX_SIZE);
+        data[0] = address.destination;
+        data[1] = address.source;
+        self.length = (length + ADDRESS_PREFIX_SIZE) as u16;
+        self.pack();
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+//! Challenge-response authentication for privileged requests (requires features = ["auth"]).
+//!
+//! The device keeps a shared secret and a list of privileged request codes. Until a host has
+//! authenticated, privileged requests are answered with `response::UNAUTHORIZED` and never reach
+//! the application. The exchange is:
+//!
+//! 1. Host sends `request::AUTH_CHALLENGE` with no data.
+//! 2. Device replies with a fresh random nonce of `NONCE_SIZE` bytes.
+//! 3. Host sends `request::AUTH_RESPONSE` with HMAC-SHA256(secret, nonce) (see `respond`).
+//! 4. Device verifies the MAC in constant time and replies `response::SUCCESS`, raising the session
+//!    to `Privilege::Authenticated`, or `response::UNAUTHORIZED`.
+//!
+//! Each nonce can be answered once, so a captured response can't be replayed and every guess costs
+//! a round trip. The device supplies its own random source as a plain `fn`, keeping this `no_std`.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::{auth::{self, AuthRouter, Privilege, Route}, request, response, Packet};
+//!
+//!     const ERASE: u16 = 0x20;
+//!     const SECRET: &[u8] = b"calibration secret";
+//!
+//!     // Use the hardware RNG on a real device
+//!     fn fill_nonce(nonce: &mut [u8; auth::NONCE_SIZE]) {
+//!         nonce.fill(0xA5);
+//!     }
+//!
+//!     let mut router = AuthRouter::new(SECRET, &[ERASE], fill_nonce);
+//!     let mut rx = Packet::<64>::new();
+//!     let mut tx = Packet::<64>::new();
+//!
+//!     rx.pack_data(ERASE, &[]).unwrap();
+//!     assert_eq!(router.route(&rx, &mut tx), Route::Rejected);
+//!     assert_eq!(tx.get_response(), response::UNAUTHORIZED);
+//!
+//!     rx.pack_data(request::AUTH_CHALLENGE, &[]).unwrap();
+//!     assert_eq!(router.route(&rx, &mut tx), Route::Handled);
+//!
+//!     // Host side, answering the challenge in `tx`
+//!     auth::respond(SECRET, &tx, &mut rx).unwrap();
+//!     assert_eq!(router.route(&rx, &mut tx), Route::Handled);
+//!     assert_eq!(router.privilege(), Privilege::Authenticated);
+//!
+//!     rx.pack_data(ERASE, &[]).unwrap();
+//!     assert_eq!(router.route(&rx, &mut tx), Route::Allowed);
+//! }
+//! ```
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{request, response, Packet, Status};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Bytes in a device challenge
+pub const NONCE_SIZE: usize = 16;
+/// Bytes in a host response, the HMAC-SHA256 output
+pub const MAC_SIZE: usize = 32;
+
+/// Session privilege tracked by the device
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Privilege {
+    Unauthenticated,
+    Authenticated,
+}
+
+/// What `AuthRouter::route` did with a packet
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Route {
+    /// An authentication request, the reply has been packed
+    Handled,
+    /// A privileged request without an authenticated session, `response::UNAUTHORIZED` has been
+    /// packed as the reply
+    Rejected,
+    /// The application should handle the request as usual
+    Allowed,
+}
+
+pub struct AuthRouter<'a> {
+    secret: &'a [u8],
+    privileged: &'a [u16],
+    fill_nonce: fn(&mut [u8; NONCE_SIZE]),
+    nonce: Option<[u8; NONCE_SIZE]>,
+    privilege: Privilege,
+}
+
+impl<'a> AuthRouter<'a> {
+    /// `privileged` lists the request codes that require authentication. `fill_nonce` must fill the
+    /// array with unpredictable bytes, e.g. from a hardware RNG.
+    pub const fn new(
+        secret: &'a [u8],
+        privileged: &'a [u16],
+        fill_nonce: fn(&mut [u8; NONCE_SIZE]),
+    ) -> Self {
+        AuthRouter {
+            secret,
+            privileged,
+            fill_nonce,
+            nonce: None,
+            privilege: Privilege::Unauthenticated,
+        }
+    }
+
+    pub fn privilege(&self) -> Privilege {
+        self.privilege
+    }
+
+    pub fn is_privileged(&self, request: u16) -> bool {
+        self.privileged.contains(&request)
+    }
+
+    /// Drops back to `Privilege::Unauthenticated`, e.g. when the link goes down
+    pub fn revoke(&mut self) {
+        self.privilege = Privilege::Unauthenticated;
+        self.nonce = None;
+    }
+
+    /// Checks a received packet before it is dispatched. Authentication requests are answered
+    /// here, and privileged requests are refused until the session is authenticated.
+    pub fn route<const T: usize>(&mut self, packet: &Packet<T>, reply: &mut Packet<T>) -> Route {
+        match packet.get_request() {
+            request::AUTH_CHALLENGE => {
+                let mut nonce = [0u8; NONCE_SIZE];
+                (self.fill_nonce)(&mut nonce);
+                self.nonce = Some(nonce);
+                // Asking for a new challenge ends the current session
+                self.privilege = Privilege::Unauthenticated;
+                let _ = reply.pack_data(request::AUTH_CHALLENGE, &nonce);
+                Route::Handled
+            }
+            request::AUTH_RESPONSE => {
+                let verified = match self.nonce.take() {
+                    Some(nonce) => verify(self.secret, &nonce, packet.payload()),
+                    None => false,
+                };
+                if verified {
+                    self.privilege = Privilege::Authenticated;
+                    let _ = reply.pack_data(request::AUTH_RESPONSE, &[]);
+                } else {
+                    self.privilege = Privilege::Unauthenticated;
+                    let _ = reply.pack_error(request::AUTH_RESPONSE, response::UNAUTHORIZED, &[]);
+                }
+                Route::Handled
+            }
+            code if self.is_privileged(code) && self.privilege != Privilege::Authenticated => {
+                let _ = reply.pack_error(code, response::UNAUTHORIZED, &[]);
+                Route::Rejected
+            }
+            _ => Route::Allowed,
+        }
+    }
+}
+
+/// HMAC-SHA256 of `nonce` with `secret`, as sent by the host
+pub fn compute_mac(secret: &[u8], nonce: &[u8]) -> [u8; MAC_SIZE] {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(nonce);
+    mac.finalize().into_bytes().into()
+}
+
+fn verify(secret: &[u8], nonce: &[u8], received: &[u8]) -> bool {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(nonce);
+    mac.verify_slice(received).is_ok()
+}
+
+/// Host side: packs the `request::AUTH_RESPONSE` for a challenge reply received from the device.
+/// Returns `Status::UnrecognizedRequest` if `challenge` isn't a successful challenge reply.
+pub fn respond<const T: usize>(
+    secret: &[u8],
+    challenge: &Packet<T>,
+    packet: &mut Packet<T>,
+) -> Result<(), Status> {
+    if challenge.get_request() != request::AUTH_CHALLENGE
+        || challenge.get_response() != response::SUCCESS
+        || challenge.payload().len() != NONCE_SIZE
+    {
+        return Err(Status::UnrecognizedRequest);
+    }
+
+    packet.pack_data(
+        request::AUTH_RESPONSE,
+        &compute_mac(secret, challenge.payload()),
+    )
+}
@@ -0,0 +1,484 @@
+//! `flem` command-line tool (requires features = ["cli"]).
+//!
+//! ```text
+//! flem send <LINK> <REQUEST> [HEX_PAYLOAD] [--timeout MS] [--baud RATE]
+//! flem id <LINK> [--timeout MS] [--baud RATE]
+//! flem monitor <LINK> [--baud RATE]
+//! flem describe <LINK> [--timeout MS] [--baud RATE]
+//! flem call <LINK> <NAME> [ARGS...] [--timeout MS] [--baud RATE]
+//! flem decode [FILE]
+//! flem sign <KEY_FILE> <IMAGE> <MAJOR.MINOR.PATCH> <MANIFEST_OUT>
+//! flem verify <PUBLIC_KEY_FILE> <IMAGE> <MANIFEST>
+//! flem pubkey <KEY_FILE>
+//! ```
+//!
+//! `LINK` is a `HOST:PORT` TCP address, or a serial port such as `/dev/ttyUSB0` or `COM3` opened
+//! at `--baud` (115200 by default), 8N1.
+//!
+//! `REQUEST` is decimal or `0x` prefixed hex. `HEX_PAYLOAD` is a string of hex bytes, e.g.
+//! `"01 02 ff"` or `0102ff`. `decode` reads a hex dump from `FILE`, or stdin, in any format accepted
+//! by `flem::decode::parse_hex` and prints the packets and framing errors found in it.
+//!
+//! `describe` lists the requests and response codes a device reports with `request::DESCRIBE`, see
+//! `flem::introspect`. `call` sends the request called `NAME` with one argument per data field, e.g.
+//! `flem call 127.0.0.1:9000 SET_TARGET 21.5 2`, and prints the reply field by field. Array
+//! arguments are comma separated.
+//!
+//! `sign` writes a `flem::signing::Manifest` for a firmware image, `verify` checks a manifest against
+//! an image, and `pubkey` prints the public key to build into the device. Key files hold 32 bytes,
+//! either raw or as hex text.
+
+use flem::{
+    client::{Client, ClientError},
+    decode::{format_bytes, parse_hex, Decoder},
+    introspect::{self, DeviceDescription},
+    signing::{Manifest, SigningKey, VerifyingKey},
+    traits::Channel,
+    transport::{SerialChannel, TcpChannel, DEFAULT_BAUD_RATE},
+    DataId, Packet, Status,
+};
+use std::{
+    fmt,
+    io::{self, Read},
+    net::TcpStream,
+    process::ExitCode,
+    sync::mpsc::{Receiver, Sender},
+    time::Duration,
+};
+
+/// Large enough to receive a packet from any FLEM device
+const CLI_PACKET_SIZE: usize = u16::MAX as usize - 1;
+
+const DEFAULT_TIMEOUT_MS: u64 = 1000;
+
+const USAGE: &str = "Usage:
+    flem send <LINK> <REQUEST> [HEX_PAYLOAD] [--timeout MS] [--baud RATE]
+    flem id <LINK> [--timeout MS] [--baud RATE]
+    flem monitor <LINK> [--baud RATE]
+    flem describe <LINK> [--timeout MS] [--baud RATE]
+    flem call <LINK> <NAME> [ARGS...] [--timeout MS] [--baud RATE]
+    flem decode [FILE]
+    flem sign <KEY_FILE> <IMAGE> <MAJOR.MINOR.PATCH> <MANIFEST_OUT>
+    flem verify <PUBLIC_KEY_FILE> <IMAGE> <MANIFEST>
+    flem pubkey <KEY_FILE>
+
+LINK is HOST:PORT, or a serial port such as /dev/ttyUSB0 or COM3";
+
+/// Where to reach the device
+enum Target {
+    Tcp(String),
+    Serial { port: String, baud_rate: u32 },
+}
+
+impl Target {
+    /// Serial ports are device paths on Unix (`/dev/...`) and `COMn` or `\\.\COMn` on Windows,
+    /// anything else is a TCP address
+    fn parse(link: &str, baud_rate: u32) -> Self {
+        let upper = link.to_ascii_uppercase();
+        if link.starts_with('/') || link.starts_with("\\\\.\\") || upper.starts_with("COM") {
+            Target::Serial {
+                port: link.to_string(),
+                baud_rate,
+            }
+        } else {
+            Target::Tcp(link.to_string())
+        }
+    }
+
+    fn connect(&self) -> Result<Link, String> {
+        let error = |e: &dyn fmt::Display| format!("Unable to connect to {}: {}", self, e);
+        match self {
+            Target::Tcp(address) => {
+                let mut channel = TcpChannel::new();
+                channel.connect(address).map_err(|e| error(&e))?;
+                Ok(Link::Tcp(channel))
+            }
+            Target::Serial { port, baud_rate } => {
+                let mut channel = SerialChannel::new(*baud_rate);
+                channel.connect(port).map_err(|e| error(&e))?;
+                Ok(Link::Serial(channel))
+            }
+        }
+    }
+
+    /// Opens the raw byte stream, for `monitor`
+    fn open(&self) -> Result<Box<dyn Read>, String> {
+        let error = |e: &dyn fmt::Display| format!("Unable to connect to {}: {}", self, e);
+        match self {
+            Target::Tcp(address) => Ok(Box::new(
+                TcpStream::connect(address).map_err(|e| error(&e))?,
+            )),
+            Target::Serial { port, baud_rate } => Ok(serialport::new(port, *baud_rate)
+                .timeout(Duration::from_secs(1))
+                .open()
+                .map_err(|e| error(&e))?),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Tcp(address) => write!(f, "{}", address),
+            Target::Serial { port, baud_rate } => write!(f, "{} at {} baud", port, baud_rate),
+        }
+    }
+}
+
+/// A connected `Channel`
+enum Link {
+    Tcp(TcpChannel<CLI_PACKET_SIZE>),
+    Serial(SerialChannel<CLI_PACKET_SIZE>),
+}
+
+impl Link {
+    fn listen(
+        &mut self,
+    ) -> (
+        Sender<Packet<CLI_PACKET_SIZE>>,
+        Receiver<Packet<CLI_PACKET_SIZE>>,
+    ) {
+        match self {
+            Link::Tcp(channel) => channel.listen(10, 10),
+            Link::Serial(channel) => channel.listen(10, 10),
+        }
+    }
+
+    fn disconnect(&mut self) {
+        match self {
+            Link::Tcp(channel) => {
+                let _ = channel.disconnect();
+            }
+            Link::Serial(channel) => {
+                let _ = channel.disconnect();
+            }
+        }
+    }
+}
+
+fn parse_u16(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse::<u16>(),
+    };
+    parsed.map_err(|_| format!("Invalid request code '{}'", text))
+}
+
+fn print_packet(packet: &Packet<CLI_PACKET_SIZE>) {
+    let decoder = Decoder::new();
+    println!(
+        "request: {} response: {} length: {} checksum: 0x{:04X}",
+        decoder.format_request(packet.get_request()),
+        decoder.format_response(packet.get_response()),
+        packet.payload().len(),
+        packet.get_checksum()
+    );
+
+    if packet.get_request() == flem::request::ID && packet.get_response() == flem::response::SUCCESS
+    {
+        if let Some(id) = DataId::from(packet.payload()) {
+            let name: String = id.get_name().iter().take_while(|c| **c != '\0').collect();
+            println!(
+                "id: name \"{}\" version {}.{}.{} max packet size {}",
+                name,
+                id.get_major(),
+                id.get_minor(),
+                id.get_patch(),
+                id.get_max_packet_size()
+            );
+            return;
+        }
+    }
+
+    if !packet.payload().is_empty() {
+        println!("payload: {}", format_bytes(packet.payload()));
+    }
+}
+
+fn send(target: &Target, request: u16, payload: &[u8], timeout_ms: u64) -> Result<(), String> {
+    let mut link = target.connect()?;
+    let (tx, rx) = link.listen();
+    let client = Client::new(&tx, &rx)
+        .with_timeout(Duration::from_millis(timeout_ms))
+        .with_retries(0);
+
+    // Other traffic, e.g. ASYNC packets or stale replies, is skipped
+    let result = match client.request(request, payload) {
+        Ok(reply) => {
+            print_packet(&reply);
+            Ok(())
+        }
+        Err(ClientError::Packet(e)) => Err(format!("Unable to pack payload: {:?}", e)),
+        Err(ClientError::Timeout) => Err(format!("No reply within {} ms", timeout_ms)),
+        Err(_) => Err("Transport closed before a reply".to_string()),
+    };
+
+    link.disconnect();
+    result
+}
+
+/// Prints every packet on the link, and every `Status` error with the byte offset it occurred at,
+/// until the connection is closed. Runs of bytes that aren't a header are reported once.
+fn monitor(target: &Target) -> Result<(), String> {
+    let mut stream = target.open()?;
+    let mut packet = Packet::<CLI_PACKET_SIZE>::new();
+    let mut buffer = [0u8; 256];
+    let mut offset: usize = 0;
+    // Bytes accepted into the current packet so far
+    let mut building: usize = 0;
+    // Start offset and length of the current run of bytes that were not a header
+    let mut discarded: Option<(usize, usize)> = None;
+
+    let report_discarded = |discarded: &mut Option<(usize, usize)>| {
+        if let Some((start, length)) = discarded.take() {
+            println!(
+                "error: {:?} at byte {}, {} byte(s) discarded",
+                Status::HeaderBytesNotFound,
+                start,
+                length
+            );
+        }
+    };
+
+    loop {
+        let count = match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+            Err(e) => {
+                report_discarded(&mut discarded);
+                return Err(format!("Connection error: {}", e));
+            }
+        };
+
+        for byte in buffer[..count].iter() {
+            let result = packet.construct(*byte);
+            if !matches!(result, Err(Status::HeaderBytesNotFound)) {
+                report_discarded(&mut discarded);
+            }
+
+            match result {
+                Ok(_) => {
+                    print_packet(&packet);
+                    packet.reset_lazy();
+                    building = 0;
+                }
+                Err(Status::PacketBuilding) => building += 1,
+                Err(Status::HeaderBytesNotFound) => {
+                    // A failed second header byte also discards the first one
+                    match discarded.as_mut() {
+                        Some((_, length)) => *length += building + 1,
+                        None => discarded = Some((offset - building, building + 1)),
+                    }
+                    building = 0;
+                }
+                Err(status) => {
+                    println!("error: {:?} at byte {}", status, offset);
+                    packet.reset_lazy();
+                    building = 0;
+                }
+            }
+            offset += 1;
+        }
+    }
+
+    report_discarded(&mut discarded);
+    Ok(())
+}
+
+/// Connects to `target`, reads the device description and runs `action` on the same connection
+fn with_description(
+    target: &Target,
+    timeout_ms: u64,
+    action: impl FnOnce(&Client<CLI_PACKET_SIZE>, &DeviceDescription) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut link = target.connect()?;
+    let (tx, rx) = link.listen();
+    let client = Client::new(&tx, &rx).with_timeout(Duration::from_millis(timeout_ms));
+
+    let result = introspect::describe(&client)
+        .map_err(|e| format!("Unable to read the device description: {:?}", e))
+        .and_then(|description| action(&client, &description));
+
+    link.disconnect();
+    result
+}
+
+fn describe(target: &Target, timeout_ms: u64) -> Result<(), String> {
+    with_description(target, timeout_ms, |_, description| {
+        let list = |fields: &[introspect::FieldInfo]| {
+            fields
+                .iter()
+                .map(|field| field.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        for request in description.requests.iter() {
+            println!(
+                "request 0x{:04X} {}({}) -> ({})",
+                request.code,
+                request.name,
+                list(&request.data),
+                list(&request.reply)
+            );
+        }
+        for response in description.responses.iter() {
+            println!("response 0x{:04X} {}", response.code, response.name);
+        }
+        Ok(())
+    })
+}
+
+fn call(target: &Target, name: &str, args: &[&str], timeout_ms: u64) -> Result<(), String> {
+    with_description(target, timeout_ms, |client, description| {
+        let packet = description
+            .build::<CLI_PACKET_SIZE>(name, args)
+            .map_err(|e| e.to_string())?;
+        let reply = client
+            .request(packet.get_request(), packet.payload())
+            .map_err(|e| format!("No reply: {:?}", e))?;
+        println!("{}", description.format_reply(&reply));
+        Ok(())
+    })
+}
+
+fn decode(path: Option<&str>) -> Result<(), String> {
+    let text = match path {
+        Some(path) => {
+            std::fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))?
+        }
+        None => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| format!("Unable to read stdin: {}", e))?;
+            text
+        }
+    };
+
+    let bytes = parse_hex(&text).map_err(|e| e.to_string())?;
+    println!("{}", Decoder::new().report::<CLI_PACKET_SIZE>(&bytes));
+    Ok(())
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("Unable to read {}: {}", path, e))
+}
+
+/// Reads a 32 byte key, stored raw or as hex text
+fn read_key(path: &str) -> Result<[u8; 32], String> {
+    let contents = read_file(path)?;
+    let bytes = match contents.len() {
+        32 => contents,
+        _ => parse_hex(&String::from_utf8_lossy(&contents))
+            .map_err(|e| format!("Invalid key in {}: {}", path, e))?,
+    };
+    bytes
+        .try_into()
+        .map_err(|_| format!("Key in {} should be 32 bytes", path))
+}
+
+fn parse_version(text: &str) -> Result<(u8, u8, u8), String> {
+    let parts: Vec<Option<u8>> = text.split('.').map(|part| part.parse().ok()).collect();
+    match parts.as_slice() {
+        [Some(major), Some(minor), Some(patch)] => Ok((*major, *minor, *patch)),
+        _ => Err(format!(
+            "Invalid version '{}', expected MAJOR.MINOR.PATCH",
+            text
+        )),
+    }
+}
+
+fn sign(key: &str, image: &str, version: &str, output: &str) -> Result<(), String> {
+    let key = SigningKey::from_bytes(&read_key(key)?);
+    let (major, minor, patch) = parse_version(version)?;
+    let manifest = Manifest::sign(&key, &read_file(image)?, major, minor, patch);
+
+    std::fs::write(output, manifest.to_bytes())
+        .map_err(|e| format!("Unable to write {}: {}", output, e))?;
+    println!(
+        "signed {} version {}.{}.{} size {}",
+        image, major, minor, patch, manifest.size
+    );
+    Ok(())
+}
+
+fn verify(public_key: &str, image: &str, manifest: &str) -> Result<(), String> {
+    let key = VerifyingKey::from_bytes(&read_key(public_key)?)
+        .map_err(|_| format!("{} is not a valid public key", public_key))?;
+    let manifest = Manifest::from_bytes(&read_file(manifest)?)
+        .ok_or_else(|| format!("{} is not a FLEM manifest", manifest))?;
+
+    if !manifest.verify_signature(&key) {
+        return Err("Manifest signature is invalid".to_string());
+    }
+    if !manifest.verify_image(&key, &read_file(image)?) {
+        return Err(format!("Manifest does not match {}", image));
+    }
+    println!(
+        "valid: version {}.{}.{} size {}",
+        manifest.major, manifest.minor, manifest.patch, manifest.size
+    );
+    Ok(())
+}
+
+fn pubkey(key: &str) -> Result<(), String> {
+    let key = SigningKey::from_bytes(&read_key(key)?);
+    println!("{}", format_bytes(key.verifying_key().as_bytes()));
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut positional = Vec::new();
+    let mut timeout_ms = DEFAULT_TIMEOUT_MS;
+    let mut baud_rate = DEFAULT_BAUD_RATE;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--timeout" {
+            timeout_ms = iter
+                .next()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| "--timeout expects a value in milliseconds".to_string())?;
+        } else if arg == "--baud" {
+            baud_rate = iter
+                .next()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| "--baud expects a baud rate".to_string())?;
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+
+    let target = |link: &str| Target::parse(link, baud_rate);
+    match positional.as_slice() {
+        ["send", link, request] => send(&target(link), parse_u16(request)?, &[], timeout_ms),
+        ["send", link, request, payload] => send(
+            &target(link),
+            parse_u16(request)?,
+            &parse_hex(payload).map_err(|e| format!("Invalid hex payload: {}", e))?,
+            timeout_ms,
+        ),
+        ["id", link] => send(&target(link), flem::request::ID, &[], timeout_ms),
+        ["monitor", link] => monitor(&target(link)),
+        ["describe", link] => describe(&target(link), timeout_ms),
+        ["call", link, name, args @ ..] => call(&target(link), name, args, timeout_ms),
+        ["decode"] => decode(None),
+        ["decode", path] => decode(Some(path)),
+        ["sign", key, image, version, output] => sign(key, image, version, output),
+        ["verify", public_key, image, manifest] => verify(public_key, image, manifest),
+        ["pubkey", key] => pubkey(key),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
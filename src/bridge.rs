@@ -0,0 +1,304 @@
+//! Gateway that forwards packets between links, e.g. a hub relaying between a USB host and several
+//! UART attached modules.
+//!
+//! A bridge has one upstream link (towards the host) and any number of downstream ports (towards
+//! devices). Packets from upstream go to the port of the first matching `Route`:
+//!
+//! - `Route::address` matches the destination of an `address` prefix. Broadcasts go to every port
+//!   with an address route instead of only the first.
+//! - `Route::requests` matches a range of request codes.
+//! - `Route::any` matches everything, as a default at the end of the list.
+//!
+//! Packets from downstream ports all go upstream. The two sides may use different packet sizes:
+//! `convert` copies the request, response and data into a packet of the other size and packs it
+//! again, and packets that don't fit are dropped. `RouteStatistics` counts the packets, bytes and
+//! drops of every route.
+//!
+//! `find_route`, `convert` and `RouteStatistics` are `no_std`, for bridges in firmware. With
+//! features = ["std"], `Bridge` connects the `Sender` / `Receiver` pairs returned by
+//! `Channel::listen`.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::bridge::{convert, find_route, Route};
+//!     use flem::Packet;
+//!
+//!     const ROUTES: [Route; 2] = [Route::requests(0x10, 0x1F, 0), Route::any(1)];
+//!
+//!     let mut upstream = Packet::<512>::new();
+//!     upstream.pack_data(0x12, &[1, 2, 3]).unwrap();
+//!
+//!     let route = find_route(&ROUTES, &upstream).unwrap();
+//!     assert_eq!(ROUTES[route].port, 0);
+//!
+//!     let mut downstream = Packet::<128>::new();
+//!     convert(&upstream, &mut downstream).unwrap();
+//!     assert_eq!(downstream.payload(), &[1, 2, 3]);
+//!     assert!(downstream.validate());
+//! }
+//! ```
+
+#[cfg(feature = "std")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{address::BROADCAST, Packet, Status};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RouteMatch {
+    Any,
+    /// Destination address, see `address`
+    Address(u8),
+    /// Request codes from `first` to `last`, inclusive
+    Requests {
+        first: u16,
+        last: u16,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub matches: RouteMatch,
+    /// Downstream port the packets go to
+    pub port: usize,
+}
+
+impl Route {
+    pub const fn any(port: usize) -> Self {
+        Route {
+            matches: RouteMatch::Any,
+            port,
+        }
+    }
+
+    pub const fn address(destination: u8, port: usize) -> Self {
+        Route {
+            matches: RouteMatch::Address(destination),
+            port,
+        }
+    }
+
+    pub const fn requests(first: u16, last: u16, port: usize) -> Self {
+        Route {
+            matches: RouteMatch::Requests { first, last },
+            port,
+        }
+    }
+
+    /// True if the route takes `packet`. Address routes also take broadcasts.
+    pub fn matches<const T: usize>(&self, packet: &Packet<T>) -> bool {
+        match self.matches {
+            RouteMatch::Any => true,
+            RouteMatch::Address(destination) => match packet.address() {
+                Some(address) => {
+                    address.destination == destination || address.destination == BROADCAST
+                }
+                None => false,
+            },
+            RouteMatch::Requests { first, last } => (first..=last).contains(&packet.get_request()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RouteStatistics {
+    /// Packets forwarded
+    pub packets: u32,
+    /// Bytes forwarded, headers included
+    pub bytes: u32,
+    /// Packets that didn't fit the other side's packet size, or whose link was closed
+    pub dropped: u32,
+}
+
+impl RouteStatistics {
+    pub const fn new() -> Self {
+        RouteStatistics {
+            packets: 0,
+            bytes: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Counts a forwarded packet of `length` bytes
+    pub fn record(&mut self, length: usize) {
+        self.packets = self.packets.wrapping_add(1);
+        self.bytes = self.bytes.wrapping_add(length as u32);
+    }
+
+    pub fn record_drop(&mut self) {
+        self.dropped = self.dropped.wrapping_add(1);
+    }
+}
+
+/// Index of the first route in `routes` that takes `packet`
+pub fn find_route<const T: usize>(routes: &[Route], packet: &Packet<T>) -> Option<usize> {
+    routes.iter().position(|route| route.matches(packet))
+}
+
+/// Copies the request, response and data of `from` into `to` and packs it. Returns
+/// `Status::PacketOverflow` if the data doesn't fit.
+pub fn convert<const S: usize, const D: usize>(
+    from: &Packet<S>,
+    to: &mut Packet<D>,
+) -> Result<(), Status> {
+    to.reset_lazy();
+    to.set_request(from.get_request());
+    to.add_data(from.payload())
+        .map_err(|_| Status::PacketOverflow)?;
+    to.set_response(from.get_response());
+    to.pack();
+    Ok(())
+}
+
+/// Forwards packets between an upstream link of size `H` and downstream ports of size `D`
+/// (requires features = ["std"])
+#[cfg(feature = "std")]
+pub struct Bridge<const H: usize, const D: usize> {
+    upstream: (Sender<Packet<H>>, Receiver<Packet<H>>),
+    ports: Vec<(Sender<Packet<D>>, Receiver<Packet<D>>)>,
+    routes: Vec<Route>,
+    route_statistics: Vec<RouteStatistics>,
+    upstream_statistics: RouteStatistics,
+    unrouted: u32,
+}
+
+#[cfg(feature = "std")]
+impl<const H: usize, const D: usize> Bridge<H, D> {
+    /// `upstream` is the pair returned by `Channel::listen` on the host side link
+    pub fn new(upstream: (Sender<Packet<H>>, Receiver<Packet<H>>)) -> Self {
+        Bridge {
+            upstream,
+            ports: Vec::new(),
+            routes: Vec::new(),
+            route_statistics: Vec::new(),
+            upstream_statistics: RouteStatistics::new(),
+            unrouted: 0,
+        }
+    }
+
+    /// Adds a downstream port and returns its number for use in routes
+    pub fn add_port(&mut self, port: (Sender<Packet<D>>, Receiver<Packet<D>>)) -> usize {
+        self.ports.push(port);
+        self.ports.len() - 1
+    }
+
+    /// Adds a route after the existing ones and returns its index
+    pub fn add_route(&mut self, route: Route) -> usize {
+        assert!(
+            route.port < self.ports.len(),
+            "Add the port before routing to it"
+        );
+        self.routes.push(route);
+        self.route_statistics.push(RouteStatistics::new());
+        self.routes.len() - 1
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    /// Statistics of the route at `index`, as returned by `add_route`
+    pub fn route_statistics(&self, index: usize) -> RouteStatistics {
+        self.route_statistics[index]
+    }
+
+    /// Statistics of packets sent upstream
+    pub fn upstream_statistics(&self) -> RouteStatistics {
+        self.upstream_statistics
+    }
+
+    /// Packets from upstream that no route took
+    pub fn unrouted(&self) -> u32 {
+        self.unrouted
+    }
+
+    /// Forwards the packet on route `index`
+    fn forward(&mut self, index: usize, packet: &Packet<H>) {
+        let mut converted = Packet::<D>::new();
+        let sent = convert(packet, &mut converted).is_ok()
+            && self.ports[self.routes[index].port]
+                .0
+                .send(converted)
+                .is_ok();
+
+        let statistics = &mut self.route_statistics[index];
+        if sent {
+            statistics.record(converted.length());
+        } else {
+            statistics.record_drop();
+        }
+    }
+
+    fn downstream(&mut self, packet: &Packet<H>) {
+        let broadcast =
+            matches!(packet.address(), Some(address) if address.destination == BROADCAST);
+        if broadcast {
+            let mut ports = Vec::new();
+            for index in 0..self.routes.len() {
+                let route = self.routes[index];
+                if matches!(route.matches, RouteMatch::Address(_)) && !ports.contains(&route.port) {
+                    ports.push(route.port);
+                    self.forward(index, packet);
+                }
+            }
+            if !ports.is_empty() {
+                return;
+            }
+        }
+
+        match find_route(&self.routes, packet) {
+            Some(index) => self.forward(index, packet),
+            None => self.unrouted = self.unrouted.wrapping_add(1),
+        }
+    }
+
+    /// Forwards every packet waiting in either direction without blocking and returns how many
+    /// there were
+    pub fn poll(&mut self) -> usize {
+        let mut count = 0;
+
+        while let Ok(packet) = self.upstream.1.try_recv() {
+            self.downstream(&packet);
+            count += 1;
+        }
+
+        for port in 0..self.ports.len() {
+            while let Ok(packet) = self.ports[port].1.try_recv() {
+                let mut converted = Packet::<H>::new();
+                let sent = convert(&packet, &mut converted).is_ok()
+                    && self.upstream.0.send(converted).is_ok();
+                if sent {
+                    self.upstream_statistics.record(converted.length());
+                } else {
+                    self.upstream_statistics.record_drop();
+                }
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    /// Calls `poll` until `stop` is set, sleeping `idle` whenever there was nothing to forward
+    pub fn run(&mut self, stop: &AtomicBool, idle: Duration) {
+        while !stop.load(Ordering::Relaxed) {
+            if self.poll() == 0 {
+                thread::sleep(idle);
+            }
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! Packet capture and replay in pcapng format (requires features = ["std"]).
+//!
+//! `CaptureWriter` records every packet with its direction, a microsecond timestamp, the raw bytes
+//! from `Packet::bytes()` and the parse `Status`. Captures use the `LINKTYPE_USER0` link-type, so
+//! they open in Wireshark and other pcapng tools. The direction is stored in the standard
+//! `epb_flags` option and the status name in the packet comment.
+//!
+//! `CaptureReader` iterates the records of a capture. Records can be fed back through `construct`
+//! with `CaptureRecord::to_packet`, or sent into a `Channel` with `replay`.
+//!
+//! A `Recorder` is a cloneable handle to a writer, and `Recorder::tap` attaches it to the `Sender` /
+//! `Receiver` pair returned by any `Channel::listen`.
+
+extern crate std;
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use std::{
+    io::{self, Read, Write},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Packet, Status};
+
+/// pcapng link-type reserved for private use, used for all FLEM captures
+pub const LINKTYPE_USER0: u16 = 147;
+
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPTION_END: u16 = 0;
+const OPTION_COMMENT: u16 = 1;
+const OPTION_EPB_FLAGS: u16 = 2;
+const OPTION_IF_TSRESOL: u16 = 9;
+
+const EPB_FLAGS_INBOUND: u32 = 0b01;
+const EPB_FLAGS_OUTBOUND: u32 = 0b10;
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(io::Error),
+    /// The input is not a pcapng file, or a block is malformed
+    InvalidFormat(&'static str),
+}
+
+impl From<io::Error> for CaptureError {
+    fn from(error: io::Error) -> Self {
+        CaptureError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Received from the partner
+    Inbound,
+    /// Transmitted to the partner
+    Outbound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    pub direction: Direction,
+    /// Microseconds since the Unix epoch
+    pub timestamp_us: u64,
+    /// Raw packet bytes, header included
+    pub bytes: Vec<u8>,
+    pub status: Status,
+}
+
+impl CaptureRecord {
+    /// Runs the captured bytes through `construct` and returns the rebuilt packet, or the first error
+    /// `construct` reported.
+    pub fn to_packet<const T: usize>(&self) -> Result<Packet<T>, Status> {
+        let mut packet = Packet::<T>::new();
+        for byte in self.bytes.iter() {
+            match packet.construct(*byte) {
+                Ok(_) => return Ok(packet),
+                Err(Status::PacketBuilding) => {}
+                Err(status) => return Err(status),
+            }
+        }
+        Err(packet.get_status())
+    }
+}
+
+fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+fn padding(length: usize) -> usize {
+    (4 - length % 4) % 4
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    body.resize(body.len() + padding(value.len()), 0);
+}
+
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Writes the section header and a single `LINKTYPE_USER0` interface with microsecond timestamps
+    pub fn new(mut writer: W) -> Result<Self, CaptureError> {
+        let mut section = Vec::new();
+        section.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        section.extend_from_slice(&1u16.to_le_bytes()); // Major version
+        section.extend_from_slice(&0u16.to_le_bytes()); // Minor version
+        section.extend_from_slice(&(-1i64).to_le_bytes()); // Section length not specified
+        write_block(&mut writer, BLOCK_SECTION_HEADER, &section)?;
+
+        let mut interface = Vec::new();
+        interface.extend_from_slice(&LINKTYPE_USER0.to_le_bytes());
+        interface.extend_from_slice(&0u16.to_le_bytes()); // Reserved
+        interface.extend_from_slice(&0u32.to_le_bytes()); // No snap length limit
+        push_option(&mut interface, OPTION_IF_TSRESOL, &[6]); // 10^-6 s
+        push_option(&mut interface, OPTION_END, &[]);
+        write_block(&mut writer, BLOCK_INTERFACE_DESCRIPTION, &interface)?;
+
+        Ok(CaptureWriter { writer })
+    }
+
+    /// Records a packet with the current time
+    pub fn record_packet<const T: usize>(
+        &mut self,
+        direction: Direction,
+        packet: &Packet<T>,
+    ) -> Result<(), CaptureError> {
+        let status = packet.status;
+        self.write_record(&CaptureRecord {
+            direction,
+            timestamp_us: now_us(),
+            // Packets that failed with `InvalidDataLengthDetected` claim more data than they hold
+            bytes: packet.wire_bytes().to_vec(),
+            status,
+        })
+    }
+
+    pub fn write_record(&mut self, record: &CaptureRecord) -> Result<(), CaptureError> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&0u32.to_le_bytes()); // Interface ID
+        body.extend_from_slice(&((record.timestamp_us >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(record.timestamp_us as u32).to_le_bytes());
+        body.extend_from_slice(&(record.bytes.len() as u32).to_le_bytes()); // Captured length
+        body.extend_from_slice(&(record.bytes.len() as u32).to_le_bytes()); // Original length
+        body.extend_from_slice(&record.bytes);
+        body.resize(body.len() + padding(record.bytes.len()), 0);
+
+        let flags = match record.direction {
+            Direction::Inbound => EPB_FLAGS_INBOUND,
+            Direction::Outbound => EPB_FLAGS_OUTBOUND,
+        };
+        push_option(&mut body, OPTION_EPB_FLAGS, &flags.to_le_bytes());
+        push_option(&mut body, OPTION_COMMENT, record.status.name().as_bytes());
+        push_option(&mut body, OPTION_END, &[]);
+
+        write_block(&mut self.writer, BLOCK_ENHANCED_PACKET, &body)
+    }
+
+    pub fn flush(&mut self) -> Result<(), CaptureError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> Result<(), CaptureError> {
+    let total_length = (body.len() + 12) as u32;
+    writer.write_all(&block_type.to_le_bytes())?;
+    writer.write_all(&total_length.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&total_length.to_le_bytes())?;
+    Ok(())
+}
+
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    big_endian: bool,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Reads the section header. Captures written in either byte order are accepted.
+    pub fn new(mut reader: R) -> Result<Self, CaptureError> {
+        let mut start = [0u8; 12];
+        reader.read_exact(&mut start)?;
+        if u32::from_le_bytes([start[0], start[1], start[2], start[3]]) != BLOCK_SECTION_HEADER {
+            return Err(CaptureError::InvalidFormat("missing section header block"));
+        }
+
+        let big_endian = match u32::from_le_bytes([start[8], start[9], start[10], start[11]]) {
+            BYTE_ORDER_MAGIC => false,
+            x if x.swap_bytes() == BYTE_ORDER_MAGIC => true,
+            _ => return Err(CaptureError::InvalidFormat("invalid byte order magic")),
+        };
+
+        let mut capture = CaptureReader { reader, big_endian };
+        let total_length = capture.u32_from(&start[4..8]) as usize;
+        if total_length < 28 || total_length % 4 != 0 {
+            return Err(CaptureError::InvalidFormat("invalid section header length"));
+        }
+        // Rest of the section header is not needed
+        let mut rest = alloc::vec![0u8; total_length - 12];
+        capture.reader.read_exact(&mut rest)?;
+
+        Ok(capture)
+    }
+
+    fn u16_from(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32_from(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    /// Reads the next block, returning its type and body, or `None` at the end of the capture
+    fn next_block(&mut self) -> Result<Option<(u32, Vec<u8>)>, CaptureError> {
+        let mut header = [0u8; 8];
+        match self.reader.read_exact(&mut header) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(CaptureError::Io(e)),
+        }
+
+        let block_type = self.u32_from(&header[0..4]);
+        let total_length = self.u32_from(&header[4..8]) as usize;
+        if total_length < 12 || total_length % 4 != 0 {
+            return Err(CaptureError::InvalidFormat("invalid block length"));
+        }
+
+        let mut body = alloc::vec![0u8; total_length - 8];
+        self.reader.read_exact(&mut body)?;
+        body.truncate(total_length - 12);
+        Ok(Some((block_type, body)))
+    }
+
+    fn parse_packet(&self, body: &[u8]) -> Result<CaptureRecord, CaptureError> {
+        if body.len() < 20 {
+            return Err(CaptureError::InvalidFormat(
+                "enhanced packet block too short",
+            ));
+        }
+        let timestamp_us =
+            ((self.u32_from(&body[4..8]) as u64) << 32) | self.u32_from(&body[8..12]) as u64;
+        let captured_length = self.u32_from(&body[12..16]) as usize;
+        let data_end = 20 + captured_length;
+        if data_end > body.len() {
+            return Err(CaptureError::InvalidFormat("packet data exceeds block"));
+        }
+
+        let mut record = CaptureRecord {
+            direction: Direction::Inbound,
+            timestamp_us,
+            bytes: body[20..data_end].to_vec(),
+            status: Status::Ok,
+        };
+
+        let mut offset = data_end + padding(captured_length);
+        while offset + 4 <= body.len() {
+            let code = self.u16_from(&body[offset..offset + 2]);
+            let length = self.u16_from(&body[offset + 2..offset + 4]) as usize;
+            let value_end = offset + 4 + length;
+            if code == OPTION_END || value_end > body.len() {
+                break;
+            }
+            let value = &body[offset + 4..value_end];
+            match code {
+                OPTION_EPB_FLAGS
+                    if length == 4 && self.u32_from(value) & 0b11 == EPB_FLAGS_OUTBOUND =>
+                {
+                    record.direction = Direction::Outbound;
+                }
+                OPTION_COMMENT => {
+                    let name = String::from_utf8_lossy(value);
+                    if let Some(status) = (0..=u8::MAX)
+                        .filter_map(Status::from_u8)
+                        .find(|status| status.name() == name)
+                    {
+                        record.status = status;
+                    }
+                }
+                _ => {}
+            }
+            offset = value_end + padding(length);
+        }
+
+        Ok(record)
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_block() {
+                Ok(Some((BLOCK_ENHANCED_PACKET, body))) => return Some(self.parse_packet(&body)),
+                Ok(Some((BLOCK_SECTION_HEADER, _))) => {
+                    return Some(Err(CaptureError::InvalidFormat(
+                        "multiple sections are not supported",
+                    )))
+                }
+                // Interface descriptions and unknown blocks carry nothing needed for replay
+                Ok(Some(_)) => {}
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Rebuilds the captured packets travelling in `direction` and sends them on `tx`, e.g. the `Sender`
+/// returned by `Channel::listen`. Records that fail to parse are skipped. If `realtime` is true the
+/// original spacing between packets is reproduced. Returns the number of packets sent.
+pub fn replay<R: Read, const T: usize>(
+    reader: CaptureReader<R>,
+    direction: Direction,
+    tx: &Sender<Packet<T>>,
+    realtime: bool,
+) -> Result<usize, CaptureError> {
+    let mut sent = 0;
+    let mut previous_timestamp: Option<u64> = None;
+
+    for record in reader {
+        let record = record?;
+        if record.direction != direction {
+            continue;
+        }
+        let packet = match record.to_packet::<T>() {
+            Ok(packet) => packet,
+            Err(_) => continue,
+        };
+
+        if realtime {
+            if let Some(previous) = previous_timestamp {
+                let delay = record.timestamp_us.saturating_sub(previous);
+                thread::sleep(Duration::from_micros(delay));
+            }
+            previous_timestamp = Some(record.timestamp_us);
+        }
+
+        if tx.send(packet).is_err() {
+            break;
+        }
+        sent += 1;
+    }
+
+    Ok(sent)
+}
+
+/// Cloneable, thread safe handle to a `CaptureWriter` so one capture can be shared by the transmit and
+/// receive paths of a transport.
+pub struct Recorder<W: Write> {
+    writer: Arc<Mutex<CaptureWriter<W>>>,
+}
+
+impl<W: Write> Clone for Recorder<W> {
+    fn clone(&self) -> Self {
+        Recorder {
+            writer: self.writer.clone(),
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> Recorder<W> {
+    pub fn new(writer: CaptureWriter<W>) -> Self {
+        Recorder {
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    pub fn record<const T: usize>(
+        &self,
+        direction: Direction,
+        packet: &Packet<T>,
+    ) -> Result<(), CaptureError> {
+        self.writer.lock().unwrap().record_packet(direction, packet)
+    }
+
+    pub fn flush(&self) -> Result<(), CaptureError> {
+        self.writer.lock().unwrap().flush()
+    }
+
+    /// Attaches the recorder to the `Sender` / `Receiver` pair from `Channel::listen`. Use the returned
+    /// pair in place of the original one; every packet passing through is recorded. Forwarding stops
+    /// when either side of the transport is closed.
+    pub fn tap<const T: usize>(
+        &self,
+        tx: Sender<Packet<T>>,
+        rx: Receiver<Packet<T>>,
+    ) -> (Sender<Packet<T>>, Receiver<Packet<T>>) {
+        let (tapped_tx, outbound) = mpsc::channel::<Packet<T>>();
+        let (inbound, tapped_rx) = mpsc::channel::<Packet<T>>();
+
+        let recorder = self.clone();
+        thread::spawn(move || {
+            for packet in outbound {
+                let _ = recorder.record(Direction::Outbound, &packet);
+                if tx.send(packet).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let recorder = self.clone();
+        thread::spawn(move || {
+            for packet in rx {
+                let _ = recorder.record(Direction::Inbound, &packet);
+                if inbound.send(packet).is_err() {
+                    break;
+                }
+            }
+        });
+
+        (tapped_tx, tapped_rx)
+    }
+}
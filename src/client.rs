@@ -0,0 +1,157 @@
+//! Request / reply helper for host side service APIs (requires features = ["std"]).
+//!
+//! `Client` wraps the `Sender` / `Receiver` pair returned by `Channel::listen`. It sends a request,
+//! waits for the non-`ASYNC` reply with the same request code and retries on timeouts. Packets that
+//! arrive in the meantime, e.g. telemetry samples or stale replies, are kept for `take_unmatched`
+//! (and `telemetry::Subscriber::from_client`).
+
+extern crate std;
+
+use core::cell::RefCell;
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender},
+    time::{Duration, Instant},
+};
+
+use crate::{response, Packet, Status};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientError {
+    /// No reply after all retries
+    Timeout,
+    /// The channel was closed
+    Disconnected,
+    /// The request, or a decompressed reply, doesn't fit in a packet
+    Packet(Status),
+    /// The device answered with this error response
+    Response(u16),
+    /// The reply data couldn't be decoded
+    InvalidReply,
+    /// The requested address range runs past `u32::MAX`
+    AddressOverflow,
+}
+
+/// Packets kept for `take_unmatched`. The oldest are dropped beyond this.
+pub const UNMATCHED_CAPACITY: usize = 64;
+
+pub struct Client<'a, const T: usize> {
+    tx: &'a Sender<Packet<T>>,
+    pub(crate) rx: &'a Receiver<Packet<T>>,
+    timeout: Duration,
+    retries: u8,
+    unmatched: RefCell<VecDeque<Packet<T>>>,
+}
+
+impl<'a, const T: usize> Client<'a, T> {
+    pub fn new(tx: &'a Sender<Packet<T>>, rx: &'a Receiver<Packet<T>>) -> Self {
+        Client {
+            tx,
+            rx,
+            timeout: Duration::from_millis(500),
+            retries: 3,
+            unmatched: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// How long to wait for each reply
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Resends after a timeout this many times before giving up
+    pub fn with_retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sends `request` with `data` and returns the reply, whatever its response code
+    pub fn request(&self, request: u16, data: &[u8]) -> Result<Packet<T>, ClientError> {
+        let mut packet = Packet::<T>::new();
+        packet.set_request(request);
+        packet.add_data(data).map_err(ClientError::Packet)?;
+        packet.pack();
+
+        for _ in 0..=self.retries {
+            self.tx
+                .send(packet)
+                .map_err(|_| ClientError::Disconnected)?;
+
+            match self.wait(request) {
+                Err(ClientError::Timeout) => {}
+                result => return result,
+            }
+        }
+
+        Err(ClientError::Timeout)
+    }
+
+    /// Waits for another reply to `request` without sending anything, e.g. when `call` returned a
+    /// late reply to an earlier request. The response is checked the same way `call` does.
+    pub fn next_reply(&self, request: u16) -> Result<Packet<T>, ClientError> {
+        self.check(self.wait(request)?)
+    }
+
+    /// Returns the oldest packet that arrived while waiting for a reply but wasn't that reply, e.g.
+    /// an `ASYNC` packet. At most `UNMATCHED_CAPACITY` are kept.
+    pub fn take_unmatched(&self) -> Option<Packet<T>> {
+        self.unmatched.borrow_mut().pop_front()
+    }
+
+    /// Waits up to the timeout for a non-`ASYNC` reply with the request code `request`
+    fn wait(&self, request: u16) -> Result<Packet<T>, ClientError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.rx.recv_timeout(remaining) {
+                Ok(reply)
+                    if reply.get_request() == request
+                        && reply.get_response() != response::ASYNC =>
+                {
+                    return Ok(reply)
+                }
+                Ok(packet) => {
+                    let mut unmatched = self.unmatched.borrow_mut();
+                    if unmatched.len() == UNMATCHED_CAPACITY {
+                        unmatched.pop_front();
+                    }
+                    unmatched.push_back(packet);
+                }
+                Err(RecvTimeoutError::Timeout) => return Err(ClientError::Timeout),
+                Err(RecvTimeoutError::Disconnected) => return Err(ClientError::Disconnected),
+            }
+        }
+    }
+
+    /// Like `request`, but compressed replies are decompressed and any response other than
+    /// `response::SUCCESS` is returned as `ClientError::Response`. A reply that doesn't fit in a
+    /// `Packet<T>` once decompressed is `ClientError::Packet(Status::PacketOverflow)`; use `request`
+    /// and `Packet::decompress_data` for those.
+    pub fn call(&self, request: u16, data: &[u8]) -> Result<Packet<T>, ClientError> {
+        self.check(self.request(request, data)?)
+    }
+
+    fn check(&self, mut reply: Packet<T>) -> Result<Packet<T>, ClientError> {
+        if reply.is_compressed() {
+            let mut buffer = [0u8; T];
+            let length = reply
+                .decompress_data(&mut buffer)
+                .map_err(|status| match status {
+                    Status::PacketOverflow => ClientError::Packet(status),
+                    _ => ClientError::InvalidReply,
+                })?;
+            let (request, code) = (reply.get_request(), reply.get_response());
+            reply.reset_lazy();
+            reply.set_request(request);
+            reply.set_response(code & !response::COMPRESSED);
+            // Fits, `buffer` is `T` bytes
+            let _ = reply.add_data(&buffer[..length]);
+            reply.pack();
+        }
+        match reply.get_response() {
+            response::SUCCESS => Ok(reply),
+            code => Err(ClientError::Response(code)),
+        }
+    }
+}
@@ -0,0 +1,700 @@
+//! Code generation from a request / response schema (requires features = ["codegen"]).
+//!
+//! Instead of hand writing a crate of `pub const` request codes per project, describe the requests,
+//! response codes and payload layouts once in TOML (or the same structure in JSON):
+//!
+//! ```toml
+//! name = "thermostat"
+//!
+//! [[requests]]
+//! name = "GET_TEMPERATURE"
+//! code = 0x0010
+//! doc = "Reads the current temperature"
+//! reply = "Temperature"
+//!
+//! [[responses]]
+//! name = "SENSOR_FAULT"
+//! code = 0x0100
+//!
+//! [[payloads]]
+//! name = "Temperature"
+//! fields = [
+//!     { name = "celsius", type = "f32" },
+//!     { name = "raw", type = "u16", count = 4 },
+//! ]
+//! ```
+//!
+//! `Schema::rust` emits `request` and `response` modules of constants and a struct per payload that
+//! implements `traits::DataInterface`. `Schema::c_header` emits matching `#define`s and packed
+//! structs for C partners. Field types are `bool`, `u8` to `u64`, `i8` to `i64`, `f32` and `f64`,
+//! optionally fixed size arrays with `count`; everything is little endian with no padding.
+//!
+//! Schemas are validated before anything is generated: names must be valid identifiers and unique,
+//! codes unique, payload references must exist, and codes reserved by FLEM (see `request::name`
+//! and `response::name`) are refused.
+//!
+//! In the `main` of a `build.rs`, with flem as a build dependency:
+//!
+//! ```no_run
+//! let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+//! let header = out.join("protocol.h");
+//! flem::codegen::generate("protocol.toml", &out.join("protocol.rs"), Some(&header)).unwrap();
+//! println!("cargo:rerun-if-changed=protocol.toml");
+//! ```
+//!
+//! and `include!(concat!(env!("OUT_DIR"), "/protocol.rs"));` in the crate.
+
+extern crate alloc;
+extern crate std;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+use std::{collections::HashSet, fs, io, path::Path};
+
+use serde::Deserialize;
+
+use crate::{request, response};
+
+#[derive(Debug)]
+pub enum CodegenError {
+    Io(io::Error),
+    /// The schema isn't valid TOML / JSON, or doesn't have the expected structure
+    Parse(String),
+    /// The schema parsed but breaks a rule, e.g. a duplicate code
+    Invalid(String),
+}
+
+impl From<io::Error> for CodegenError {
+    fn from(error: io::Error) -> Self {
+        CodegenError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    /// Project name, used for the C prefix and include guard
+    pub name: String,
+    #[serde(default)]
+    pub requests: Vec<RequestDef>,
+    #[serde(default)]
+    pub responses: Vec<ResponseDef>,
+    #[serde(default)]
+    pub payloads: Vec<PayloadDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RequestDef {
+    pub name: String,
+    pub code: u16,
+    #[serde(default)]
+    pub doc: Option<String>,
+    /// Payload sent with the request
+    #[serde(default)]
+    pub data: Option<String>,
+    /// Payload of a successful reply
+    #[serde(default)]
+    pub reply: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseDef {
+    pub name: String,
+    pub code: u16,
+    #[serde(default)]
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PayloadDef {
+    pub name: String,
+    #[serde(default)]
+    pub doc: Option<String>,
+    pub fields: Vec<FieldDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldDef {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    /// Fixed array length, if the field is an array
+    #[serde(default)]
+    pub count: Option<usize>,
+    #[serde(default)]
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl FieldType {
+    /// Encoded bytes of one value
+    pub fn size(&self) -> usize {
+        match self {
+            FieldType::Bool | FieldType::U8 | FieldType::I8 => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => 4,
+            FieldType::U64 | FieldType::I64 | FieldType::F64 => 8,
+        }
+    }
+
+    fn rust(&self) -> &'static str {
+        match self {
+            FieldType::Bool => "bool",
+            FieldType::U8 => "u8",
+            FieldType::U16 => "u16",
+            FieldType::U32 => "u32",
+            FieldType::U64 => "u64",
+            FieldType::I8 => "i8",
+            FieldType::I16 => "i16",
+            FieldType::I32 => "i32",
+            FieldType::I64 => "i64",
+            FieldType::F32 => "f32",
+            FieldType::F64 => "f64",
+        }
+    }
+
+    fn c(&self) -> &'static str {
+        match self {
+            FieldType::Bool => "bool",
+            FieldType::U8 => "uint8_t",
+            FieldType::U16 => "uint16_t",
+            FieldType::U32 => "uint32_t",
+            FieldType::U64 => "uint64_t",
+            FieldType::I8 => "int8_t",
+            FieldType::I16 => "int16_t",
+            FieldType::I32 => "int32_t",
+            FieldType::I64 => "int64_t",
+            FieldType::F32 => "float",
+            FieldType::F64 => "double",
+        }
+    }
+
+    fn zero(&self) -> &'static str {
+        match self {
+            FieldType::Bool => "false",
+            FieldType::F32 | FieldType::F64 => "0.0",
+            _ => "0",
+        }
+    }
+}
+
+impl FieldDef {
+    /// Encoded bytes of the field
+    pub fn size(&self) -> usize {
+        self.field_type.size() * self.count.unwrap_or(1)
+    }
+}
+
+impl PayloadDef {
+    /// Encoded bytes of the payload
+    pub fn size(&self) -> usize {
+        self.fields.iter().map(FieldDef::size).sum()
+    }
+}
+
+/// Lower case Rust keywords, strict and reserved, that can't be used as identifiers
+const RUST_KEYWORDS: [&str; 49] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "abstract", "become", "box", "final", "macro", "override", "priv", "try",
+    "typeof", "unsized", "virtual", "yield",
+];
+
+/// Lower case C keywords, the field names also end up in the generated C header
+const C_KEYWORDS: [&str; 35] = [
+    "auto", "bool", "break", "case", "char", "const", "continue", "default", "do", "double",
+    "else", "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register",
+    "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch", "typedef",
+    "union", "unsigned", "void", "volatile", "while",
+];
+
+fn is_constant(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some('A'..='Z'))
+        && chars.all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn is_type(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some('A'..='Z')) && chars.all(|c| c.is_ascii_alphanumeric())
+}
+
+fn is_field(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some('a'..='z' | '_'))
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && name != "_"
+        && !RUST_KEYWORDS.contains(&name)
+        && !C_KEYWORDS.contains(&name)
+}
+
+/// `CamelCase` to `snake_case`
+fn snake(name: &str) -> String {
+    let mut snake = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && index > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+/// A doc line that can't end the `/* */` comment it is written into
+fn c_comment(line: &str) -> String {
+    line.replace("*/", "* /")
+}
+
+fn doc_lines(doc: &Option<String>) -> Vec<&str> {
+    doc.as_deref()
+        .map(|doc| doc.lines().collect())
+        .unwrap_or_default()
+}
+
+impl Schema {
+    pub fn from_toml(text: &str) -> Result<Self, CodegenError> {
+        let schema: Schema =
+            toml::from_str(text).map_err(|error| CodegenError::Parse(error.to_string()))?;
+        schema.validate()?;
+        Ok(schema)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, CodegenError> {
+        let schema: Schema =
+            serde_json::from_str(text).map_err(|error| CodegenError::Parse(error.to_string()))?;
+        schema.validate()?;
+        Ok(schema)
+    }
+
+    /// Reads a schema, as JSON if the file ends in `.json` and as TOML otherwise
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, CodegenError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Schema::from_json(&text),
+            _ => Schema::from_toml(&text),
+        }
+    }
+
+    fn payload(&self, name: &str) -> Option<&PayloadDef> {
+        self.payloads.iter().find(|payload| payload.name == name)
+    }
+
+    /// Checks names, codes and payload references, see the module documentation
+    pub fn validate(&self) -> Result<(), CodegenError> {
+        let invalid = |message: String| Err(CodegenError::Invalid(message));
+
+        if !is_field(&self.name) {
+            return invalid(format!(
+                "Schema name \"{}\" should be snake_case",
+                self.name
+            ));
+        }
+
+        let mut names = HashSet::new();
+        let mut codes = HashSet::new();
+        for definition in self.requests.iter() {
+            if !is_constant(&definition.name) {
+                return invalid(format!(
+                    "Request \"{}\" should be UPPER_CASE",
+                    definition.name
+                ));
+            }
+            if !names.insert(&definition.name) || !codes.insert(definition.code) {
+                return invalid(format!("Request \"{}\" is not unique", definition.name));
+            }
+            if let Some(reserved) = request::name(definition.code) {
+                return invalid(format!(
+                    "Request \"{}\" uses code {:#06X}, reserved for {}",
+                    definition.name, definition.code, reserved
+                ));
+            }
+            for payload in [&definition.data, &definition.reply].into_iter().flatten() {
+                if self.payload(payload).is_none() {
+                    return invalid(format!(
+                        "Request \"{}\" refers to unknown payload \"{}\"",
+                        definition.name, payload
+                    ));
+                }
+            }
+        }
+
+        let mut names = HashSet::new();
+        let mut codes = HashSet::new();
+        for definition in self.responses.iter() {
+            if !is_constant(&definition.name) {
+                return invalid(format!(
+                    "Response \"{}\" should be UPPER_CASE",
+                    definition.name
+                ));
+            }
+            if !names.insert(&definition.name) || !codes.insert(definition.code) {
+                return invalid(format!("Response \"{}\" is not unique", definition.name));
+            }
+            if let Some(reserved) = response::name(definition.code) {
+                return invalid(format!(
+                    "Response \"{}\" uses code {:#06X}, reserved for {}",
+                    definition.name, definition.code, reserved
+                ));
+            }
+            if definition.code & 0xC000 == response::COMPRESSED {
+                return invalid(format!(
+                    "Response \"{}\" sets the COMPRESSED flag",
+                    definition.name
+                ));
+            }
+        }
+
+        let mut names = HashSet::new();
+        for payload in self.payloads.iter() {
+            if !is_type(&payload.name) {
+                return invalid(format!("Payload \"{}\" should be CamelCase", payload.name));
+            }
+            if !names.insert(&payload.name) {
+                return invalid(format!("Payload \"{}\" is not unique", payload.name));
+            }
+            if payload.fields.is_empty() {
+                return invalid(format!("Payload \"{}\" has no fields", payload.name));
+            }
+            let mut fields = HashSet::new();
+            for field in payload.fields.iter() {
+                if !is_field(&field.name) {
+                    return invalid(format!(
+                        "Field \"{}.{}\" should be snake_case and not a Rust or C keyword",
+                        payload.name, field.name
+                    ));
+                }
+                if !fields.insert(&field.name) {
+                    return invalid(format!(
+                        "Field \"{}.{}\" is not unique",
+                        payload.name, field.name
+                    ));
+                }
+                if field.count == Some(0) {
+                    return invalid(format!(
+                        "Field \"{}.{}\" has a count of 0",
+                        payload.name, field.name
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rust source with `request` / `response` constants and a `DataInterface` struct per payload
+    pub fn rust(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "// Generated by flem::codegen from the \"{}\" schema. Do not edit.\n",
+            self.name
+        );
+
+        for (module, constants) in [
+            ("request", self.constants(&self.requests_as_constants())),
+            ("response", self.constants(&self.responses_as_constants())),
+        ] {
+            let _ = writeln!(out, "#[allow(dead_code)]\npub mod {} {{", module);
+            out.push_str(&constants);
+            out.push_str("}\n\n");
+        }
+
+        for payload in self.payloads.iter() {
+            self.rust_payload(&mut out, payload);
+        }
+        out
+    }
+
+    fn requests_as_constants(&self) -> Vec<(&str, u16, Vec<String>)> {
+        self.requests
+            .iter()
+            .map(|definition| {
+                let mut doc: Vec<String> = doc_lines(&definition.doc)
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                if let Some(data) = &definition.data {
+                    doc.push(format!("Data: `{}`", data));
+                }
+                if let Some(reply) = &definition.reply {
+                    doc.push(format!("Reply: `{}`", reply));
+                }
+                (definition.name.as_str(), definition.code, doc)
+            })
+            .collect()
+    }
+
+    fn responses_as_constants(&self) -> Vec<(&str, u16, Vec<String>)> {
+        self.responses
+            .iter()
+            .map(|definition| {
+                let doc = doc_lines(&definition.doc)
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                (definition.name.as_str(), definition.code, doc)
+            })
+            .collect()
+    }
+
+    fn constants(&self, constants: &[(&str, u16, Vec<String>)]) -> String {
+        let mut out = String::new();
+        for (name, code, doc) in constants {
+            for line in doc {
+                let _ = writeln!(out, "    /// {}", line);
+            }
+            let _ = writeln!(out, "    pub const {}: u16 = {:#06X};", name, code);
+        }
+        out
+    }
+
+    fn rust_payload(&self, out: &mut String, payload: &PayloadDef) {
+        let name = &payload.name;
+        let size = payload.size();
+
+        for line in doc_lines(&payload.doc) {
+            let _ = writeln!(out, "/// {}", line);
+        }
+        let _ = writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq)]");
+        let _ = writeln!(out, "pub struct {} {{", name);
+        for field in payload.fields.iter() {
+            for line in doc_lines(&field.doc) {
+                let _ = writeln!(out, "    /// {}", line);
+            }
+            let rust = field.field_type.rust();
+            match field.count {
+                Some(count) => {
+                    let _ = writeln!(out, "    pub {}: [{}; {}],", field.name, rust, count);
+                }
+                None => {
+                    let _ = writeln!(out, "    pub {}: {},", field.name, rust);
+                }
+            }
+        }
+        let _ = writeln!(out, "}}\n");
+
+        let _ = writeln!(out, "impl {} {{", name);
+        let _ = writeln!(out, "    /// Encoded bytes");
+        let _ = writeln!(out, "    pub const SIZE: usize = {};", size);
+        let _ = writeln!(out, "}}\n");
+
+        let _ = writeln!(out, "impl Default for {} {{", name);
+        let _ = writeln!(out, "    fn default() -> Self {{");
+        let _ = writeln!(out, "        {} {{", name);
+        for field in payload.fields.iter() {
+            let zero = field.field_type.zero();
+            match field.count {
+                Some(count) => {
+                    let _ = writeln!(out, "            {}: [{}; {}],", field.name, zero, count);
+                }
+                None => {
+                    let _ = writeln!(out, "            {}: {},", field.name, zero);
+                }
+            }
+        }
+        let _ = writeln!(out, "        }}\n    }}\n}}\n");
+
+        let _ = writeln!(
+            out,
+            "impl<const T: usize> ::flem::traits::DataInterface<T> for {} {{",
+            name
+        );
+        let _ = writeln!(
+            out,
+            "    fn encode(&self, packet: &mut ::flem::Packet<T>) -> Result<(), ::flem::traits::DataInterfaceErrors> {{"
+        );
+        let _ = writeln!(out, "        let mut buffer = [0u8; {}::SIZE];", name);
+        let _ = writeln!(out, "        let mut offset = 0;");
+        for field in payload.fields.iter() {
+            let value = match field.count {
+                Some(_) => "value".to_string(),
+                None => format!("self.{}", field.name),
+            };
+            let size = field.field_type.size();
+            let bytes = match field.field_type {
+                // Array elements are borrowed, and a reference can't be cast
+                FieldType::Bool if field.count.is_some() => "[*value as u8]".to_string(),
+                FieldType::Bool => format!("[{} as u8]", value),
+                _ => format!("{}.to_le_bytes()", value),
+            };
+            let indent = match field.count {
+                Some(_) => {
+                    let _ = writeln!(out, "        for value in self.{}.iter() {{", field.name);
+                    "            "
+                }
+                None => "        ",
+            };
+            let _ = writeln!(
+                out,
+                "{}buffer[offset..offset + {}].copy_from_slice(&{});",
+                indent, size, bytes
+            );
+            let _ = writeln!(out, "{}offset += {};", indent, size);
+            if field.count.is_some() {
+                let _ = writeln!(out, "        }}");
+            }
+        }
+        let _ = writeln!(out, "        let _ = offset;");
+        let _ = writeln!(out, "        packet");
+        let _ = writeln!(out, "            .add_data(&buffer)");
+        let _ = writeln!(
+            out,
+            "            .map_err(|_| ::flem::traits::DataInterfaceErrors::IncorrectBufferLength)"
+        );
+        let _ = writeln!(out, "    }}\n");
+
+        let _ = writeln!(
+            out,
+            "    fn decode(&mut self, packet: &::flem::Packet<T>) -> Result<&Self, ::flem::traits::DataInterfaceErrors> {{"
+        );
+        let _ = writeln!(out, "        let data = packet.payload();");
+        let _ = writeln!(out, "        if data.len() != {}::SIZE {{", name);
+        let _ = writeln!(
+            out,
+            "            return Err(::flem::traits::DataInterfaceErrors::IncorrectDataLength);"
+        );
+        let _ = writeln!(out, "        }}");
+        let _ = writeln!(out, "        let mut offset = 0;");
+        for field in payload.fields.iter() {
+            let size = field.field_type.size();
+            let read = match field.field_type {
+                FieldType::Bool => "data[offset] != 0".to_string(),
+                FieldType::U8 => "data[offset]".to_string(),
+                other => format!(
+                    "{}::from_le_bytes(data[offset..offset + {}].try_into().unwrap())",
+                    other.rust(),
+                    size
+                ),
+            };
+            match field.count {
+                Some(_) => {
+                    let _ = writeln!(
+                        out,
+                        "        for value in self.{}.iter_mut() {{",
+                        field.name
+                    );
+                    let _ = writeln!(out, "            *value = {};", read);
+                    let _ = writeln!(out, "            offset += {};", size);
+                    let _ = writeln!(out, "        }}");
+                }
+                None => {
+                    let _ = writeln!(out, "        self.{} = {};", field.name, read);
+                    let _ = writeln!(out, "        offset += {};", size);
+                }
+            }
+        }
+        let _ = writeln!(out, "        let _ = offset;");
+        let _ = writeln!(out, "        Ok(self)");
+        let _ = writeln!(out, "    }}\n}}\n");
+    }
+
+    /// C header with `#define`s for the codes and a packed struct per payload
+    pub fn c_header(&self) -> String {
+        let prefix = self.name.to_ascii_uppercase();
+        let guard = format!("{}_FLEM_H", prefix);
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "/* Generated by flem::codegen from the \"{}\" schema. Do not edit. */",
+            self.name
+        );
+        let _ = writeln!(
+            out,
+            "/* Payload structs are packed and little endian, like the wire format. */\n"
+        );
+        let _ = writeln!(out, "#ifndef {}\n#define {}\n", guard, guard);
+        let _ = writeln!(out, "#include <stdbool.h>\n#include <stdint.h>\n");
+
+        for (kind, constants) in [
+            ("REQUEST", self.requests_as_constants()),
+            ("RESPONSE", self.responses_as_constants()),
+        ] {
+            for (name, code, doc) in constants {
+                for line in doc {
+                    let _ = writeln!(out, "/* {} */", c_comment(&line.replace('`', "")));
+                }
+                let _ = writeln!(out, "#define {}_{}_{} {:#06X}u", prefix, kind, name, code);
+            }
+            out.push('\n');
+        }
+
+        if !self.payloads.is_empty() {
+            let _ = writeln!(out, "#pragma pack(push, 1)\n");
+            for payload in self.payloads.iter() {
+                let type_name = format!("{}_{}_t", self.name, snake(&payload.name));
+                for line in doc_lines(&payload.doc) {
+                    let _ = writeln!(out, "/* {} */", c_comment(line));
+                }
+                let _ = writeln!(out, "typedef struct {{");
+                for field in payload.fields.iter() {
+                    for line in doc_lines(&field.doc) {
+                        let _ = writeln!(out, "    /* {} */", c_comment(line));
+                    }
+                    let c = field.field_type.c();
+                    match field.count {
+                        Some(count) => {
+                            let _ = writeln!(out, "    {} {}[{}];", c, field.name, count);
+                        }
+                        None => {
+                            let _ = writeln!(out, "    {} {};", c, field.name);
+                        }
+                    }
+                }
+                let _ = writeln!(out, "}} {};", type_name);
+                let _ = writeln!(
+                    out,
+                    "_Static_assert(sizeof({}) == {}, \"{} should be {} bytes\");\n",
+                    type_name,
+                    payload.size(),
+                    type_name,
+                    payload.size()
+                );
+            }
+            let _ = writeln!(out, "#pragma pack(pop)\n");
+        }
+
+        let _ = writeln!(out, "#endif /* {} */", guard);
+        out
+    }
+}
+
+/// Reads the schema at `schema` and writes the Rust source to `rust`, and the C header to `header`
+/// if given. Meant to be called from a `build.rs`.
+pub fn generate<P: AsRef<Path>>(
+    schema: P,
+    rust: &Path,
+    header: Option<&Path>,
+) -> Result<(), CodegenError> {
+    let schema = Schema::from_file(schema)?;
+    fs::write(rust, schema.rust())?;
+    if let Some(header) = header {
+        fs::write(header, schema.c_header())?;
+    }
+    Ok(())
+}
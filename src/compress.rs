@@ -0,0 +1,188 @@
+//! Optional payload compression.
+//!
+//! A small LZ77 style compressor with bounded memory: compression uses a 1 KiB hash table on the
+//! stack and decompression none at all, so both run on the device. Compressed packets carry the
+//! `response::COMPRESSED` flag in the response field, which leaves the header layout unchanged.
+//!
+//! The compressed stream is a sequence of tokens:
+//!
+//! - `0b0LLL_LLLL` followed by L + 1 literal bytes
+//! - `0b1LLL_LLLL` followed by a 2 byte little-endian offset: copy L + 4 bytes starting `offset`
+//!   bytes back in the output. Offset may be smaller than the length, which repeats a pattern.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::{response, Packet};
+//!
+//!     let telemetry = [0x12u8; 200];
+//!     let mut tx = Packet::<64>::new();
+//!     tx.pack_data_compressed(0x10, &telemetry).unwrap();
+//!     assert!(tx.is_compressed());
+//!     assert_eq!(tx.get_response(), response::SUCCESS | response::COMPRESSED);
+//!
+//!     let mut buffer = [0u8; 256];
+//!     let length = tx.decompress_data(&mut buffer).unwrap();
+//!     assert_eq!(&buffer[..length], &telemetry);
+//! }
+//! ```
+
+use crate::{response, Packet, Status};
+
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = MIN_MATCH + 0x7F;
+const MAX_LITERALS: usize = 0x80;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const MATCH_FLAG: u8 = 0x80;
+
+const HASH_BITS: u32 = 8;
+const HASH_SIZE: usize = 1 << HASH_BITS;
+
+fn hash(bytes: &[u8]) -> usize {
+    let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn emit_literals(literals: &[u8], output: &mut [u8], mut out: usize) -> Option<usize> {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        *output.get_mut(out)? = (chunk.len() - 1) as u8;
+        out += 1;
+        output
+            .get_mut(out..out + chunk.len())?
+            .copy_from_slice(chunk);
+        out += chunk.len();
+    }
+    Some(out)
+}
+
+/// Compresses `input` into `output`, returning the compressed length, or `None` if it doesn't fit.
+/// Incompressible data grows by one byte per 128 bytes.
+pub fn compress(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    // Last position + 1 of each 4 byte hash, 0 if unused
+    let mut table = [0u32; HASH_SIZE];
+    let mut out = 0;
+    let mut literal_start = 0;
+    let mut i = 0;
+
+    while i + MIN_MATCH <= input.len() {
+        let slot = hash(&input[i..]);
+        let candidate = table[slot] as usize;
+        table[slot] = (i + 1) as u32;
+
+        if candidate > 0 {
+            let candidate = candidate - 1;
+            let offset = i - candidate;
+            if offset <= MAX_OFFSET
+                && input[candidate..candidate + MIN_MATCH] == input[i..i + MIN_MATCH]
+            {
+                let mut length = MIN_MATCH;
+                while length < MAX_MATCH
+                    && i + length < input.len()
+                    && input[candidate + length] == input[i + length]
+                {
+                    length += 1;
+                }
+
+                out = emit_literals(&input[literal_start..i], output, out)?;
+                let offset = (offset as u16).to_le_bytes();
+                output.get_mut(out..out + 3)?.copy_from_slice(&[
+                    MATCH_FLAG | (length - MIN_MATCH) as u8,
+                    offset[0],
+                    offset[1],
+                ]);
+                out += 3;
+
+                i += length;
+                literal_start = i;
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+    emit_literals(&input[literal_start..], output, out)
+}
+
+/// Decompresses `input` into `output`, returning the decompressed length. Returns
+/// `Status::PacketOverflow` if `output` is too small and `Status::DecompressionFailed` if `input` is
+/// malformed.
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<usize, Status> {
+    let mut i = 0;
+    let mut out = 0;
+
+    while i < input.len() {
+        let token = input[i];
+        i += 1;
+
+        if token & MATCH_FLAG == 0 {
+            let length = token as usize + 1;
+            let literals = input
+                .get(i..i + length)
+                .ok_or(Status::DecompressionFailed)?;
+            output
+                .get_mut(out..out + length)
+                .ok_or(Status::PacketOverflow)?
+                .copy_from_slice(literals);
+            i += length;
+            out += length;
+        } else {
+            let length = (token & !MATCH_FLAG) as usize + MIN_MATCH;
+            let offset = input.get(i..i + 2).ok_or(Status::DecompressionFailed)?;
+            let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+            i += 2;
+
+            if offset == 0 || offset > out {
+                return Err(Status::DecompressionFailed);
+            }
+            if out + length > output.len() {
+                return Err(Status::PacketOverflow);
+            }
+            // Byte by byte, the source may overlap the bytes being written
+            for _ in 0..length {
+                output[out] = output[out - offset];
+                out += 1;
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+impl<const T: usize> Packet<T> {
+    /// Like `pack_data`, but compresses `data` straight into the packet when that makes it smaller,
+    /// and sets `response::COMPRESSED`. Data that doesn't shrink is packed as is, without the flag,
+    /// so this also accepts anything `pack_data` does.
+    pub fn pack_data_compressed(&mut self, request: u16, data: &[u8]) -> Result<(), Status> {
+        self.reset_lazy();
+        self.request = request;
+
+        match compress(data, self.data_mut()) {
+            Some(length) if length < data.len() => {
+                self.length = length as u16;
+                self.response = response::SUCCESS | response::COMPRESSED;
+                self.pack();
+                Ok(())
+            }
+            _ => self.pack_data(request, data),
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        response::is_compressed(self.get_response())
+    }
+
+    /// Copies the data into `buffer`, decompressing it if the packet is compressed, and returns the
+    /// number of bytes written
+    pub fn decompress_data(&self, buffer: &mut [u8]) -> Result<usize, Status> {
+        let payload = self.payload();
+        if self.is_compressed() {
+            decompress(payload, buffer)
+        } else {
+            buffer
+                .get_mut(..payload.len())
+                .ok_or(Status::PacketOverflow)?
+                .copy_from_slice(payload);
+            Ok(payload.len())
+        }
+    }
+}
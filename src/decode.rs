@@ -0,0 +1,333 @@
+//! Offline decoding of hex dumps (requires features = ["std"]).
+//!
+//! `parse_hex` turns pasted UART dumps into bytes. It accepts:
+//!
+//! - space or comma separated bytes: `55 55 0a 1b` or `0x55, 0x55, 0x0A`
+//! - runs of hex digits: `55550a1b`
+//! - `xxd` output: `00000000: 5555 0a1b  UU..`
+//! - `hexdump -C` output: `00000000  55 55 0a 1b  |UU..|`
+//!
+//! `Decoder` runs the bytes through `construct` the same way a receiver would and reports each
+//! packet (header fields, checksum validity, request / response names and payload) as well as the
+//! byte offsets where framing broke.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::decode::{parse_hex, Decoder};
+//!
+//!     // A stray byte, then an ID reply without data
+//!     let bytes = parse_hex("00 55 55 00 2d 01 00 01 00 00 00").unwrap();
+//!     let report = Decoder::new().report::<64>(&bytes);
+//!
+//!     assert!(report.contains("@000000 framing error: HeaderBytesNotFound"));
+//!     assert!(report.contains("request: 0x0001 (ID) response: 0x0001 (SUCCESS)"));
+//!     assert!(report.contains("(valid)"));
+//! }
+//! ```
+
+extern crate std;
+
+extern crate alloc;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use std::{collections::HashMap, fmt};
+
+use crate::{request, response, Packet, Status, FLEM_HEADER_SIZE};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexError {
+    /// 1 based line number of the offending token
+    pub line: usize,
+    pub token: String,
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid hex '{}' on line {}", self.token, self.line)
+    }
+}
+
+/// Returns the part of a line holding hex bytes, dropping `xxd` / `hexdump -C` offsets and ASCII
+/// columns
+fn hex_section(line: &str) -> &str {
+    let line = line.trim();
+
+    // xxd: "00000010: 5555 0a1b  UU.."
+    if let Some((offset, rest)) = line.split_once(':') {
+        if !offset.is_empty() && offset.chars().all(|c| c.is_ascii_hexdigit()) {
+            return rest.trim_start().split("  ").next().unwrap_or("");
+        }
+    }
+
+    // hexdump -C: "00000010  55 55 0a 1b  |UU..|"
+    if let Some(index) = line.find('|') {
+        let data = &line[..index];
+        let mut tokens = data.split_whitespace();
+        if let Some(offset) = tokens.next() {
+            if offset.len() >= 6 {
+                return data.trim_start().get(offset.len()..).unwrap_or("");
+            }
+        }
+        return data;
+    }
+
+    line
+}
+
+/// Parses hex text into bytes, see the module documentation for the accepted formats
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, HexError> {
+    let mut bytes = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        for token in hex_section(line).split(|c: char| c.is_whitespace() || c == ',') {
+            let digits = token
+                .strip_prefix("0x")
+                .or_else(|| token.strip_prefix("0X"))
+                .unwrap_or(token);
+            if digits.is_empty() {
+                continue;
+            }
+
+            let error = || HexError {
+                line: index + 1,
+                token: token.to_string(),
+            };
+            if digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(error());
+            }
+            for i in (0..digits.len()).step_by(2) {
+                bytes.push(u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| error())?);
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedPacket {
+    /// Offset of the first header byte in the input
+    pub offset: usize,
+    pub header: u16,
+    pub checksum: u16,
+    /// Checksum computed over the received bytes
+    pub computed_checksum: u16,
+    pub request: u16,
+    pub response: u16,
+    pub payload: Vec<u8>,
+}
+
+impl DecodedPacket {
+    pub fn checksum_valid(&self) -> bool {
+        self.checksum == self.computed_checksum
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeEvent {
+    Packet(DecodedPacket),
+    /// Framing broke at `offset` and `length` bytes were thrown away
+    FramingError {
+        offset: usize,
+        length: usize,
+        status: Status,
+    },
+    /// The input ended in the middle of a packet starting at `offset`
+    Truncated {
+        offset: usize,
+        length: usize,
+    },
+}
+
+/// Decodes byte streams into packets. Names for project specific request and response codes can be
+/// added on top of the pre-defined ones.
+#[derive(Debug, Default, Clone)]
+pub struct Decoder {
+    request_names: HashMap<u16, String>,
+    response_names: HashMap<u16, String>,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_request_name(mut self, request: u16, name: &str) -> Self {
+        self.request_names.insert(request, name.to_string());
+        self
+    }
+
+    pub fn with_response_name(mut self, response: u16, name: &str) -> Self {
+        self.response_names.insert(response, name.to_string());
+        self
+    }
+
+    pub fn request_name(&self, code: u16) -> Option<&str> {
+        self.request_names
+            .get(&code)
+            .map(|name| name.as_str())
+            .or_else(|| request::name(code))
+    }
+
+    pub fn response_name(&self, code: u16) -> Option<&str> {
+        self.response_names
+            .get(&code)
+            .map(|name| name.as_str())
+            .or_else(|| response::name(code))
+    }
+
+    /// Runs `bytes` through a `Packet<T>` receiver and returns what was found, in order
+    pub fn decode<const T: usize>(&self, bytes: &[u8]) -> Vec<DecodeEvent> {
+        let mut events = Vec::new();
+        let mut packet = Packet::<T>::new();
+        let mut packet_start = 0;
+        // Start offset and length of the current run of bytes that were not a header
+        let mut discarded: Option<(usize, usize)> = None;
+
+        for (offset, byte) in bytes.iter().enumerate() {
+            if packet.internal_counter == 0 {
+                packet_start = offset;
+            }
+
+            let result = packet.construct(*byte);
+            if !matches!(result, Err(Status::HeaderBytesNotFound)) {
+                if let Some((start, length)) = discarded.take() {
+                    events.push(DecodeEvent::FramingError {
+                        offset: start,
+                        length,
+                        status: Status::HeaderBytesNotFound,
+                    });
+                }
+            }
+
+            match result {
+                Ok(_) | Err(Status::ChecksumError) => {
+                    events.push(DecodeEvent::Packet(DecodedPacket {
+                        offset: packet_start,
+                        header: packet.get_header(),
+                        checksum: packet.get_checksum(),
+                        computed_checksum: packet.checksum(false),
+                        request: packet.get_request(),
+                        response: packet.get_response(),
+                        payload: packet.payload().to_vec(),
+                    }));
+                    packet.reset_lazy();
+                }
+                Err(Status::PacketBuilding) => {}
+                Err(Status::HeaderBytesNotFound) => {
+                    // A failed second header byte also discards the first one
+                    let failed = offset - packet_start + 1;
+                    match discarded.as_mut() {
+                        Some((_, length)) => *length += failed,
+                        None => discarded = Some((packet_start, failed)),
+                    }
+                }
+                Err(status) => {
+                    events.push(DecodeEvent::FramingError {
+                        offset: packet_start,
+                        length: offset - packet_start + 1,
+                        status,
+                    });
+                    packet.reset_lazy();
+                }
+            }
+        }
+
+        if let Some((offset, length)) = discarded {
+            events.push(DecodeEvent::FramingError {
+                offset,
+                length,
+                status: Status::HeaderBytesNotFound,
+            });
+        }
+        if packet.internal_counter != 0 {
+            events.push(DecodeEvent::Truncated {
+                offset: packet_start,
+                length: bytes.len() - packet_start,
+            });
+        }
+
+        events
+    }
+
+    /// Formats a single event as one or two lines of text
+    pub fn format_event(&self, event: &DecodeEvent) -> String {
+        match event {
+            DecodeEvent::Packet(packet) => {
+                let validity = if packet.checksum_valid() {
+                    "valid".to_string()
+                } else {
+                    format!("INVALID, computed 0x{:04X}", packet.computed_checksum)
+                };
+                let mut text = format!(
+                    "@{:06} packet request: {} response: {} length: {} checksum: 0x{:04X} ({})",
+                    packet.offset,
+                    self.format_request(packet.request),
+                    self.format_response(packet.response),
+                    packet.payload.len(),
+                    packet.checksum,
+                    validity
+                );
+                if !packet.payload.is_empty() {
+                    text.push_str(&format!(
+                        "\n        payload: {}",
+                        format_bytes(&packet.payload)
+                    ));
+                }
+                text
+            }
+            DecodeEvent::FramingError {
+                offset,
+                length,
+                status,
+            } => format!(
+                "@{:06} framing error: {:?}, {} byte(s) discarded",
+                offset, status, length
+            ),
+            DecodeEvent::Truncated { offset, length } => format!(
+                "@{:06} truncated: input ended {} byte(s) into a packet (header is {} bytes)",
+                offset, length, FLEM_HEADER_SIZE
+            ),
+        }
+    }
+
+    /// Decodes `bytes` and formats every event, one per line
+    pub fn report<const T: usize>(&self, bytes: &[u8]) -> String {
+        self.decode::<T>(bytes)
+            .iter()
+            .map(|event| self.format_event(event))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Formats a request code with its name, e.g. "0x0001 (ID)"
+    pub fn format_request(&self, code: u16) -> String {
+        self.format_code(code, self.request_name(code))
+    }
+
+    /// Formats a response code with its name, e.g. "0xFFFD (UNKNOWN_REQUEST)"
+    pub fn format_response(&self, code: u16) -> String {
+        self.format_code(code, self.response_name(code))
+    }
+
+    fn format_code(&self, code: u16, name: Option<&str>) -> String {
+        match name {
+            Some(name) => format!("0x{:04X} ({})", code, name),
+            None => format!("0x{:04X}", code),
+        }
+    }
+}
+
+/// Formats bytes as space separated lower case hex
+pub fn format_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
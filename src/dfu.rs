@@ -0,0 +1,666 @@
+//! Firmware update (DFU) service.
+//!
+//! A standard request set for sending a firmware image to a device:
+//!
+//! | Request               | Data                               | Device action                          |
+//! |-----------------------|------------------------------------|----------------------------------------|
+//! | `request::DFU_BEGIN`  | `ImageInfo` (size, CRC32, version) | Erase, or resume the same image        |
+//! | `request::DFU_WRITE`  | offset (u32) + chunk               | Write the chunk at the expected offset |
+//! | `request::DFU_STATUS` | -                                  | Report progress                        |
+//! | `request::DFU_VERIFY` | -                                  | Check the CRC32 of the written image   |
+//! | `request::DFU_COMMIT` | -                                  | Hand the verified image to the flash   |
+//! | `request::DFU_ABORT`  | -                                  | Forget the image                       |
+//!
+//! Every successful reply carries a `DfuStatus` (state, next offset, image size). Failures are
+//! answered with `response::DFU_ERROR` and the data is a `DfuError` code followed by the same
+//! status, so the host always knows where to continue from. Sending `DFU_BEGIN` again for the same
+//! image resumes it after a dropped link instead of starting over.
+//!
+//! With features = ["signing"], `DfuDevice::set_verifying_key` makes the device also require a signed
+//! `signing::Manifest`, sent in pieces with `request::DFU_MANIFEST` (offset (u32) + part, like
+//! `DFU_WRITE`). `DFU_VERIFY` then checks the image hash, size, version and signature, and answers
+//! `DfuError::SignatureInvalid` if any of them don't match.
+//!
+//! `DfuDevice` is the `no_std` device side, driving a `FlashWriter`. `DfuUploader`
+//! (features = ["std"]) uploads an image from the host through a `Channel`.
+
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "std")]
+use std::{
+    sync::mpsc::{Receiver, Sender},
+    time::Duration,
+    vec::Vec,
+};
+
+#[cfg(feature = "signing")]
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "signing")]
+use crate::signing::{Manifest, VerifyingKey, MANIFEST_SIZE};
+#[cfg(feature = "std")]
+use crate::{
+    client::{Client, ClientError},
+    Status,
+};
+use crate::{request, response, Packet};
+
+/// Bytes of the offset in front of each `DFU_WRITE` chunk
+pub const DFU_OFFSET_SIZE: usize = 4;
+
+/// Bytes read back from flash at a time while verifying
+const VERIFY_CHUNK_SIZE: usize = 64;
+
+/// CRC-32 (IEEE 802.3, as used by zlib and most bootloaders), computed bitwise so it needs no table
+#[derive(Debug, Copy, Clone)]
+pub struct Crc32 {
+    value: u32,
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Crc32 { value: 0xFFFF_FFFF }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.value ^= *byte as u32;
+            for _ in 0..8 {
+                let mask = (self.value & 1).wrapping_neg();
+                self.value = (self.value >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.value
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Describes the image being transferred, sent with `DFU_BEGIN`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub size: u32,
+    pub crc32: u32,
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl ImageInfo {
+    /// Bytes of an encoded `ImageInfo`
+    pub const SIZE: usize = 11;
+
+    pub fn new(image: &[u8], major: u8, minor: u8, patch: u8) -> Self {
+        ImageInfo {
+            size: image.len() as u32,
+            crc32: crc32(image),
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.size.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.crc32.to_le_bytes());
+        bytes[8] = self.major;
+        bytes[9] = self.minor;
+        bytes[10] = self.patch;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        Some(ImageInfo {
+            size: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            crc32: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            major: bytes[8],
+            minor: bytes[9],
+            patch: bytes[10],
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DfuState {
+    Idle = 0,
+    Receiving = 1,
+    /// Every byte has been written and the CRC32 matched
+    Verified = 2,
+    Committed = 3,
+}
+
+impl DfuState {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(DfuState::Idle),
+            1 => Some(DfuState::Receiving),
+            2 => Some(DfuState::Verified),
+            3 => Some(DfuState::Committed),
+            _ => None,
+        }
+    }
+}
+
+/// Error codes sent in the first data byte of a `response::DFU_ERROR` reply
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DfuError {
+    /// `DFU_BEGIN` hasn't been sent, or the transfer was aborted
+    NotStarted = 1,
+    /// The chunk isn't at the next expected offset, the status holds the right one
+    WrongOffset = 2,
+    /// The image, or a chunk, doesn't fit
+    ImageTooLarge = 3,
+    /// The `FlashWriter` reported an error
+    FlashError = 4,
+    /// The written image doesn't match the CRC32 from `DFU_BEGIN`, the transfer has to start over
+    CrcMismatch = 5,
+    /// `DFU_COMMIT` before a successful `DFU_VERIFY`
+    NotVerified = 6,
+    /// The request data is malformed
+    InvalidRequest = 7,
+    /// The manifest is missing, doesn't describe the received image, or isn't signed by the device's
+    /// key. The transfer has to start over.
+    SignatureInvalid = 8,
+}
+
+impl DfuError {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(DfuError::NotStarted),
+            2 => Some(DfuError::WrongOffset),
+            3 => Some(DfuError::ImageTooLarge),
+            4 => Some(DfuError::FlashError),
+            5 => Some(DfuError::CrcMismatch),
+            6 => Some(DfuError::NotVerified),
+            7 => Some(DfuError::InvalidRequest),
+            8 => Some(DfuError::SignatureInvalid),
+            _ => None,
+        }
+    }
+}
+
+/// Progress reported in every DFU reply
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DfuStatus {
+    pub state: DfuState,
+    /// Next offset the device expects
+    pub offset: u32,
+    pub size: u32,
+}
+
+impl DfuStatus {
+    /// Bytes of an encoded `DfuStatus`
+    pub const SIZE: usize = 9;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0] = self.state as u8;
+        bytes[1..5].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[5..9].copy_from_slice(&self.size.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        Some(DfuStatus {
+            state: DfuState::from_u8(bytes[0])?,
+            offset: u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]),
+            size: u32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]),
+        })
+    }
+
+    /// Reads the status, and the error for `response::DFU_ERROR`, from a DFU reply
+    pub fn from_reply<const T: usize>(packet: &Packet<T>) -> Option<(DfuStatus, Option<DfuError>)> {
+        let payload = packet.payload();
+        if packet.get_response() == response::DFU_ERROR {
+            let (error, status) = payload.split_first()?;
+            Some((Self::from_bytes(status)?, Some(DfuError::from_u8(*error)?)))
+        } else {
+            Some((Self::from_bytes(payload)?, None))
+        }
+    }
+}
+
+/// Storage for the incoming image, usually the inactive flash bank
+pub trait FlashWriter {
+    type Error;
+
+    /// Largest image that fits
+    fn capacity(&self) -> u32;
+    /// Prepares the first `size` bytes for writing
+    fn erase(&mut self, size: u32) -> Result<(), Self::Error>;
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+    fn read(&mut self, offset: u32, buffer: &mut [u8]) -> Result<(), Self::Error>;
+    /// Marks the verified image for boot, e.g. by writing the bootloader's swap flag
+    fn commit(&mut self, image: &ImageInfo) -> Result<(), Self::Error>;
+}
+
+/// Device side DFU state machine
+pub struct DfuDevice<F: FlashWriter> {
+    flash: F,
+    state: DfuState,
+    image: Option<ImageInfo>,
+    offset: u32,
+    #[cfg(feature = "signing")]
+    verifying_key: Option<VerifyingKey>,
+    #[cfg(feature = "signing")]
+    manifest: [u8; MANIFEST_SIZE],
+    #[cfg(feature = "signing")]
+    manifest_length: usize,
+}
+
+impl<F: FlashWriter> DfuDevice<F> {
+    pub fn new(flash: F) -> Self {
+        DfuDevice {
+            flash,
+            state: DfuState::Idle,
+            image: None,
+            offset: 0,
+            #[cfg(feature = "signing")]
+            verifying_key: None,
+            #[cfg(feature = "signing")]
+            manifest: [0; MANIFEST_SIZE],
+            #[cfg(feature = "signing")]
+            manifest_length: 0,
+        }
+    }
+
+    /// Only accepts images with a manifest signed by `key`'s signing key (requires
+    /// features = ["signing"])
+    #[cfg(feature = "signing")]
+    pub fn set_verifying_key(&mut self, key: VerifyingKey) {
+        self.verifying_key = Some(key);
+    }
+
+    pub fn state(&self) -> DfuState {
+        self.state
+    }
+
+    pub fn status(&self) -> DfuStatus {
+        DfuStatus {
+            state: self.state,
+            offset: self.offset,
+            size: self.image.map(|image| image.size).unwrap_or(0),
+        }
+    }
+
+    /// The image being received, or the last one committed
+    pub fn image(&self) -> Option<ImageInfo> {
+        self.image
+    }
+
+    pub fn flash(&self) -> &F {
+        &self.flash
+    }
+
+    pub fn flash_mut(&mut self) -> &mut F {
+        &mut self.flash
+    }
+
+    /// Handles DFU requests and packs the reply. Returns false, leaving `reply` untouched, for
+    /// requests that aren't part of the DFU service.
+    pub fn handle<const T: usize>(&mut self, packet: &Packet<T>, reply: &mut Packet<T>) -> bool {
+        let payload = packet.payload();
+        let result = match packet.get_request() {
+            request::DFU_BEGIN => self.begin(payload),
+            request::DFU_WRITE => self.write(payload),
+            request::DFU_STATUS => Ok(()),
+            request::DFU_MANIFEST => self.receive_manifest(payload),
+            request::DFU_VERIFY => self.verify(),
+            request::DFU_COMMIT => self.commit(),
+            request::DFU_ABORT => {
+                self.abort();
+                Ok(())
+            }
+            _ => return false,
+        };
+
+        let status = self.status().to_bytes();
+        let _ = match result {
+            Ok(_) => reply.pack_data(packet.get_request(), &status),
+            Err(error) => {
+                let mut data = [0u8; DfuStatus::SIZE + 1];
+                data[0] = error as u8;
+                data[1..].copy_from_slice(&status);
+                reply.pack_error(packet.get_request(), response::DFU_ERROR, &data)
+            }
+        };
+        true
+    }
+
+    pub fn abort(&mut self) {
+        self.state = DfuState::Idle;
+        self.image = None;
+        self.offset = 0;
+        #[cfg(feature = "signing")]
+        {
+            self.manifest_length = 0;
+        }
+    }
+
+    fn begin(&mut self, payload: &[u8]) -> Result<(), DfuError> {
+        let image = ImageInfo::from_bytes(payload).ok_or(DfuError::InvalidRequest)?;
+
+        // Same image as the interrupted transfer, carry on where it stopped
+        if self.state == DfuState::Receiving && self.image == Some(image) {
+            return Ok(());
+        }
+
+        self.abort();
+        if image.size > self.flash.capacity() {
+            return Err(DfuError::ImageTooLarge);
+        }
+        self.flash
+            .erase(image.size)
+            .map_err(|_| DfuError::FlashError)?;
+        self.image = Some(image);
+        self.state = DfuState::Receiving;
+        Ok(())
+    }
+
+    fn write(&mut self, payload: &[u8]) -> Result<(), DfuError> {
+        let image = match (self.state, self.image) {
+            (DfuState::Receiving, Some(image)) => image,
+            _ => return Err(DfuError::NotStarted),
+        };
+        if payload.len() <= DFU_OFFSET_SIZE {
+            return Err(DfuError::InvalidRequest);
+        }
+
+        let (offset, chunk) = payload.split_at(DFU_OFFSET_SIZE);
+        let offset = u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]);
+        if offset != self.offset {
+            return Err(DfuError::WrongOffset);
+        }
+        if offset as u64 + chunk.len() as u64 > image.size as u64 {
+            return Err(DfuError::ImageTooLarge);
+        }
+
+        self.flash
+            .write(offset, chunk)
+            .map_err(|_| DfuError::FlashError)?;
+        self.offset += chunk.len() as u32;
+        Ok(())
+    }
+
+    fn receive_manifest(&mut self, payload: &[u8]) -> Result<(), DfuError> {
+        #[cfg(feature = "signing")]
+        {
+            if self.state != DfuState::Receiving {
+                return Err(DfuError::NotStarted);
+            }
+            if payload.len() <= DFU_OFFSET_SIZE {
+                return Err(DfuError::InvalidRequest);
+            }
+
+            let (offset, part) = payload.split_at(DFU_OFFSET_SIZE);
+            let offset = u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize;
+            // Offset 0 starts the manifest over, anything else has to follow on
+            if (offset != 0 && offset != self.manifest_length)
+                || offset + part.len() > MANIFEST_SIZE
+            {
+                return Err(DfuError::InvalidRequest);
+            }
+            self.manifest[offset..offset + part.len()].copy_from_slice(part);
+            self.manifest_length = offset + part.len();
+            Ok(())
+        }
+
+        #[cfg(not(feature = "signing"))]
+        {
+            let _ = payload;
+            Err(DfuError::InvalidRequest)
+        }
+    }
+
+    #[cfg(feature = "signing")]
+    fn check_signature(&self, image: &ImageInfo, hash: &[u8; 32]) -> bool {
+        let key = match self.verifying_key.as_ref() {
+            Some(key) => key,
+            None => return true,
+        };
+        if self.manifest_length != MANIFEST_SIZE {
+            return false;
+        }
+        match Manifest::from_bytes(&self.manifest) {
+            Some(manifest) => {
+                (manifest.major, manifest.minor, manifest.patch)
+                    == (image.major, image.minor, image.patch)
+                    && manifest.verify(key, hash, image.size)
+            }
+            None => false,
+        }
+    }
+
+    fn verify(&mut self) -> Result<(), DfuError> {
+        let image = match (self.state, self.image) {
+            (DfuState::Verified, Some(_)) => return Ok(()),
+            (DfuState::Receiving, Some(image)) => image,
+            _ => return Err(DfuError::NotStarted),
+        };
+        if self.offset != image.size {
+            return Err(DfuError::WrongOffset);
+        }
+
+        let mut crc = Crc32::new();
+        #[cfg(feature = "signing")]
+        let mut sha = Sha256::new();
+        let mut buffer = [0u8; VERIFY_CHUNK_SIZE];
+        let mut offset = 0;
+        while offset < image.size {
+            let length = (image.size - offset).min(VERIFY_CHUNK_SIZE as u32) as usize;
+            self.flash
+                .read(offset, &mut buffer[..length])
+                .map_err(|_| DfuError::FlashError)?;
+            crc.update(&buffer[..length]);
+            #[cfg(feature = "signing")]
+            sha.update(&buffer[..length]);
+            offset += length as u32;
+        }
+
+        if crc.finish() != image.crc32 {
+            self.abort();
+            return Err(DfuError::CrcMismatch);
+        }
+        #[cfg(feature = "signing")]
+        if !self.check_signature(&image, &sha.finalize().into()) {
+            self.abort();
+            return Err(DfuError::SignatureInvalid);
+        }
+        self.state = DfuState::Verified;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), DfuError> {
+        let image = match (self.state, self.image) {
+            (DfuState::Verified, Some(image)) => image,
+            _ => return Err(DfuError::NotVerified),
+        };
+        self.flash
+            .commit(&image)
+            .map_err(|_| DfuError::FlashError)?;
+        self.state = DfuState::Committed;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadError {
+    /// No reply after all retries
+    Timeout,
+    /// The channel was closed
+    Disconnected,
+    /// The device refused the request
+    Device(DfuError),
+    /// A reply without a valid `DfuStatus`
+    InvalidReply,
+    /// The packet is too small to carry chunks
+    Packet(Status),
+    /// The image is not `ImageInfo::size` bytes long
+    ImageSize,
+}
+
+#[cfg(feature = "std")]
+impl From<ClientError> for UploadError {
+    fn from(error: ClientError) -> Self {
+        match error {
+            ClientError::Timeout => UploadError::Timeout,
+            ClientError::Disconnected => UploadError::Disconnected,
+            ClientError::Packet(status) => UploadError::Packet(status),
+            ClientError::Response(_) | ClientError::InvalidReply | ClientError::AddressOverflow => {
+                UploadError::InvalidReply
+            }
+        }
+    }
+}
+
+/// Host side uploader (requires features = ["std"]). Lost packets are retried, and after a dropped
+/// link the upload carries on from the offset the device reports.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct DfuUploader {
+    /// How long to wait for each reply
+    pub timeout: Duration,
+    /// Consecutive timeouts before giving up
+    pub retries: u8,
+}
+
+#[cfg(feature = "std")]
+impl Default for DfuUploader {
+    fn default() -> Self {
+        DfuUploader {
+            timeout: Duration::from_millis(500),
+            retries: 5,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl DfuUploader {
+    /// Sends `image` and commits it. `progress` is called with the acknowledged offset and the
+    /// image size after each chunk.
+    pub fn upload<const T: usize, P: FnMut(u32, u32)>(
+        &self,
+        tx: &Sender<Packet<T>>,
+        rx: &Receiver<Packet<T>>,
+        image: &[u8],
+        info: &ImageInfo,
+        progress: P,
+    ) -> Result<(), UploadError> {
+        self.transfer(tx, rx, image, info, None, progress)
+    }
+
+    /// Like `upload`, but also sends `manifest` before asking the device to verify the image
+    /// (requires features = ["signing"])
+    #[cfg(feature = "signing")]
+    pub fn upload_signed<const T: usize, P: FnMut(u32, u32)>(
+        &self,
+        tx: &Sender<Packet<T>>,
+        rx: &Receiver<Packet<T>>,
+        image: &[u8],
+        info: &ImageInfo,
+        manifest: &Manifest,
+        progress: P,
+    ) -> Result<(), UploadError> {
+        self.transfer(tx, rx, image, info, Some(&manifest.to_bytes()), progress)
+    }
+
+    fn transfer<const T: usize, P: FnMut(u32, u32)>(
+        &self,
+        tx: &Sender<Packet<T>>,
+        rx: &Receiver<Packet<T>>,
+        image: &[u8],
+        info: &ImageInfo,
+        manifest: Option<&[u8]>,
+        mut progress: P,
+    ) -> Result<(), UploadError> {
+        if T <= DFU_OFFSET_SIZE {
+            return Err(UploadError::Packet(Status::PacketOverflow));
+        }
+        if image.len() as u64 != info.size as u64 {
+            return Err(UploadError::ImageSize);
+        }
+        let chunk_size = T - DFU_OFFSET_SIZE;
+
+        let mut status = self.request(tx, rx, request::DFU_BEGIN, &info.to_bytes(), &[])?;
+        while status.offset < info.size {
+            let start = status.offset as usize;
+            let end = (start + chunk_size).min(image.len());
+            match self.request(
+                tx,
+                rx,
+                request::DFU_WRITE,
+                &status.offset.to_le_bytes(),
+                &image[start..end],
+            ) {
+                Ok(next) => status = next,
+                // A retried or stale write, continue from where the device is
+                Err(UploadError::Device(DfuError::WrongOffset)) => {
+                    status = self.request(tx, rx, request::DFU_STATUS, &[], &[])?;
+                    if status.state != DfuState::Receiving {
+                        // The device lost the transfer, e.g. it rebooted, so begin again
+                        status = self.request(tx, rx, request::DFU_BEGIN, &info.to_bytes(), &[])?;
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+            progress(status.offset, info.size);
+        }
+
+        if let Some(manifest) = manifest {
+            for (index, part) in manifest.chunks(chunk_size).enumerate() {
+                let offset = (index * chunk_size) as u32;
+                self.request(tx, rx, request::DFU_MANIFEST, &offset.to_le_bytes(), part)?;
+            }
+        }
+        self.request(tx, rx, request::DFU_VERIFY, &[], &[])?;
+        self.request(tx, rx, request::DFU_COMMIT, &[], &[])?;
+        Ok(())
+    }
+
+    /// Sends a DFU request, retrying on timeouts, and returns the device status from the reply
+    fn request<const T: usize>(
+        &self,
+        tx: &Sender<Packet<T>>,
+        rx: &Receiver<Packet<T>>,
+        code: u16,
+        header: &[u8],
+        data: &[u8],
+    ) -> Result<DfuStatus, UploadError> {
+        let client = Client::new(tx, rx)
+            .with_timeout(self.timeout)
+            .with_retries(self.retries);
+        let payload: Vec<u8> = [header, data].concat();
+
+        // `request` rather than `call`, DFU errors come back as `DFU_ERROR` with a status
+        let reply = client.request(code, &payload)?;
+        match DfuStatus::from_reply(&reply) {
+            Some((status, None)) => Ok(status),
+            Some((_, Some(error))) => Err(UploadError::Device(error)),
+            None => Err(UploadError::InvalidReply),
+        }
+    }
+}
@@ -0,0 +1,452 @@
+//! Scriptable in-process device emulator for host integration tests (requires features = ["std"]).
+//!
+//! An `Emulator` hosts a FLEM device with `D` byte packets and user state `S`. Per-request handlers
+//! get mutable access to the state and a response packet; the response is sent if the handler packed
+//! it. Requests without a handler are answered with `response::UNKNOWN_REQUEST`, and packets that fail
+//! the checksum with `response::CHECKSUM_ERROR`.
+//!
+//! The emulator implements `Channel<T>` for any host packet size `T`, and traffic is exchanged byte by
+//! byte, so the host side exercises the same parsing path it uses with hardware. `bind_tcp` serves the
+//! same device over TCP for tools using `transport::TcpChannel`.
+//!
+//! Unsolicited `response::ASYNC` packets are emitted on a schedule with `every`, and `Faults` injects
+//! latency, dropped replies and corrupted checksums.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::{emulator::Emulator, traits::Channel, Packet};
+//!     use std::time::Duration;
+//!
+//!     const READ_COUNTER: u16 = 0x10;
+//!
+//!     let mut emulator = Emulator::<u32, 64>::new(0);
+//!     emulator.handle(READ_COUNTER, |counter, _request, response| {
+//!         *counter += 1;
+//!         response.pack_data(READ_COUNTER, &counter.to_le_bytes()).unwrap();
+//!     });
+//!
+//!     let (tx, rx) = Channel::<64>::listen(&mut emulator, 1, 1);
+//!     let mut request = Packet::<64>::new();
+//!     request.set_request(READ_COUNTER);
+//!     request.pack();
+//!     tx.send(request).unwrap();
+//!
+//!     let reply = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+//!     assert_eq!(reply.payload(), &1u32.to_le_bytes());
+//!     Channel::<64>::unlisten(&mut emulator).unwrap();
+//! }
+//! ```
+
+extern crate std;
+
+extern crate alloc;
+
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{request, response, traits::Channel, DataId, Packet, Status, FLEM_HEADER};
+
+/// How often the device thread wakes up to check schedules when no bytes arrive
+const DEVICE_TICK: Duration = Duration::from_millis(1);
+
+type Handler<S, const D: usize> = Box<dyn FnMut(&mut S, &Packet<D>, &mut Packet<D>) + Send>;
+type Emitter<S, const D: usize> = Box<dyn FnMut(&mut S, &mut Packet<D>) -> bool + Send>;
+
+/// Faults injected into the device's replies. Counters are shared by requests and ASYNC packets.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Faults {
+    /// Delay before each packet is sent
+    pub latency: Duration,
+    /// Drop every Nth packet, e.g. `Some(1)` drops everything
+    pub drop_every: Option<u32>,
+    /// Flip the checksum of every Nth packet
+    pub corrupt_every: Option<u32>,
+}
+
+struct Schedule<S, const D: usize> {
+    period: Duration,
+    next: Option<Instant>,
+    emit: Emitter<S, D>,
+}
+
+struct Device<S, const D: usize> {
+    state: S,
+    handlers: HashMap<u16, Handler<S, D>>,
+    schedules: Vec<Schedule<S, D>>,
+    faults: Faults,
+    sent: u32,
+    drop_next: u32,
+    corrupt_next: u32,
+    raw: Vec<Vec<u8>>,
+}
+
+impl<S, const D: usize> Device<S, D> {
+    fn dispatch(&mut self, packet: &Packet<D>) -> Option<Packet<D>> {
+        let mut response = Packet::<D>::new();
+        match self.handlers.get_mut(&packet.get_request()) {
+            Some(handler) => handler(&mut self.state, packet, &mut response),
+            None => {
+                response
+                    .pack_error(packet.get_request(), response::UNKNOWN_REQUEST, &[])
+                    .ok()?;
+            }
+        }
+
+        if response.get_header() == FLEM_HEADER {
+            Some(response)
+        } else {
+            None
+        }
+    }
+
+    fn due_async(&mut self, now: Instant) -> Vec<Packet<D>> {
+        let mut packets = Vec::new();
+        for schedule in self.schedules.iter_mut() {
+            let next = *schedule.next.get_or_insert(now + schedule.period);
+            if now < next {
+                continue;
+            }
+            schedule.next = Some(next + schedule.period);
+
+            let mut packet = Packet::<D>::new();
+            if (schedule.emit)(&mut self.state, &mut packet) {
+                packet.set_response(response::ASYNC);
+                packet.pack();
+                packets.push(packet);
+            }
+        }
+        packets
+    }
+
+    /// Applies drop and corruption faults, returning the bytes to put on the wire
+    fn outgoing(&mut self, packet: &Packet<D>) -> Option<Vec<u8>> {
+        self.sent += 1;
+        let every = |n: Option<u32>| matches!(n, Some(n) if n > 0 && self.sent % n == 0);
+
+        if self.drop_next > 0 {
+            self.drop_next -= 1;
+            return None;
+        }
+        if every(self.faults.drop_every) {
+            return None;
+        }
+
+        let mut bytes = packet.bytes().to_vec();
+        let corrupt = if self.corrupt_next > 0 {
+            self.corrupt_next -= 1;
+            true
+        } else {
+            every(self.faults.corrupt_every)
+        };
+        if corrupt {
+            bytes[2] ^= 0xFF;
+        }
+        Some(bytes)
+    }
+}
+
+pub struct Emulator<S, const D: usize> {
+    device: Arc<Mutex<Device<S, D>>>,
+    listening: Arc<AtomicBool>,
+}
+
+impl<S: Send + 'static, const D: usize> Emulator<S, D> {
+    pub fn new(state: S) -> Self {
+        Emulator {
+            device: Arc::new(Mutex::new(Device {
+                state,
+                handlers: HashMap::new(),
+                schedules: Vec::new(),
+                faults: Faults::default(),
+                sent: 0,
+                drop_next: 0,
+                corrupt_next: 0,
+                raw: Vec::new(),
+            })),
+            listening: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Registers the handler for `request`, replacing any previous one. The handler should pack
+    /// `response` to reply, or leave it untouched to send nothing.
+    pub fn handle<F>(&mut self, request: u16, handler: F)
+    where
+        F: FnMut(&mut S, &Packet<D>, &mut Packet<D>) + Send + 'static,
+    {
+        self.device
+            .lock()
+            .unwrap()
+            .handlers
+            .insert(request, Box::new(handler));
+    }
+
+    /// Answers `request::ID` with `id`
+    pub fn set_id(&mut self, id: DataId) {
+        self.handle(request::ID, move |_, _, response| {
+            let _ = response.pack_id(&id, true);
+        });
+    }
+
+    /// Calls `emit` every `period` once listening. If it returns true, the packet it filled in is sent
+    /// to the host with the response set to `response::ASYNC`.
+    pub fn every<F>(&mut self, period: Duration, emit: F)
+    where
+        F: FnMut(&mut S, &mut Packet<D>) -> bool + Send + 'static,
+    {
+        self.device.lock().unwrap().schedules.push(Schedule {
+            period,
+            next: None,
+            emit: Box::new(emit),
+        });
+    }
+
+    pub fn set_faults(&mut self, faults: Faults) {
+        self.device.lock().unwrap().faults = faults;
+    }
+
+    /// Drops the next `count` packets the device sends
+    pub fn drop_next(&self, count: u32) {
+        self.device.lock().unwrap().drop_next += count;
+    }
+
+    /// Corrupts the checksum of the next `count` packets the device sends
+    pub fn corrupt_next(&self, count: u32) {
+        self.device.lock().unwrap().corrupt_next += count;
+    }
+
+    /// Sends raw bytes to the host as-is, e.g. line noise to test resynchronization
+    pub fn send_raw(&self, bytes: &[u8]) {
+        self.device.lock().unwrap().raw.push(bytes.to_vec());
+    }
+
+    /// Runs `f` with the device state, e.g. to check what the host changed or to change a reading
+    pub fn with_state<R, F: FnOnce(&mut S) -> R>(&self, f: F) -> R {
+        f(&mut self.device.lock().unwrap().state)
+    }
+
+    /// Serves the device over TCP on `address` (e.g. `"127.0.0.1:0"`), one connection at a time, until
+    /// `unlisten` is called. Returns the bound address.
+    pub fn bind_tcp(&mut self, address: &str) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(address)?;
+        let local_address = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+
+        self.listening.store(true, Ordering::Relaxed);
+        let listening = self.listening.clone();
+        let device = self.device.clone();
+
+        thread::spawn(move || {
+            while listening.load(Ordering::Relaxed) {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(DEVICE_TICK);
+                        continue;
+                    }
+                    Err(_) => break,
+                };
+                let _ = stream.set_nonblocking(false);
+                let _ = stream.set_read_timeout(Some(DEVICE_TICK));
+                let mut reader = match stream.try_clone() {
+                    Ok(reader) => reader,
+                    Err(_) => continue,
+                };
+                let mut writer = stream;
+
+                let (bytes_in, device_rx) = mpsc::channel::<Vec<u8>>();
+                let connected = Arc::new(AtomicBool::new(true));
+                let connected_reader = connected.clone();
+                let listening_reader = listening.clone();
+                thread::spawn(move || {
+                    let mut buffer = [0u8; 256];
+                    while listening_reader.load(Ordering::Relaxed) {
+                        match reader.read(&mut buffer) {
+                            Ok(0) => break,
+                            Ok(count) => {
+                                if bytes_in.send(buffer[..count].to_vec()).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e)
+                                if e.kind() == io::ErrorKind::WouldBlock
+                                    || e.kind() == io::ErrorKind::TimedOut => {}
+                            Err(_) => break,
+                        }
+                    }
+                    connected_reader.store(false, Ordering::Relaxed);
+                });
+
+                run_device(
+                    device.clone(),
+                    device_rx,
+                    |bytes| writer.write_all(bytes).is_ok(),
+                    &connected,
+                );
+            }
+        });
+
+        Ok(local_address)
+    }
+}
+
+/// Device main loop: parses bytes from the host, dispatches requests, emits scheduled packets and
+/// writes replies until `running` is cleared, the host side closes, or `write` fails.
+fn run_device<S, const D: usize, W: FnMut(&[u8]) -> bool>(
+    device: Arc<Mutex<Device<S, D>>>,
+    bytes_in: Receiver<Vec<u8>>,
+    mut write: W,
+    running: &AtomicBool,
+) {
+    let mut packet = Packet::<D>::new();
+
+    while running.load(Ordering::Relaxed) {
+        let mut outgoing = Vec::new();
+
+        match bytes_in.recv_timeout(DEVICE_TICK) {
+            Ok(bytes) => {
+                let mut device = device.lock().unwrap();
+                for byte in bytes {
+                    let reply = match packet.construct(byte) {
+                        Ok(_) => device.dispatch(&packet),
+                        Err(Status::PacketBuilding) => continue,
+                        Err(Status::ChecksumError) => {
+                            let mut reply = Packet::<D>::new();
+                            reply
+                                .pack_error(packet.get_request(), response::CHECKSUM_ERROR, &[])
+                                .ok()
+                                .map(|_| reply)
+                        }
+                        Err(_) => None,
+                    };
+                    packet.reset_lazy();
+                    if let Some(reply) = reply {
+                        outgoing.push(reply);
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        let (frames, latency) = {
+            let mut device = device.lock().unwrap();
+            outgoing.extend(device.due_async(Instant::now()));
+            let mut frames: Vec<Vec<u8>> = device.raw.drain(..).collect();
+            for reply in outgoing.iter() {
+                if let Some(bytes) = device.outgoing(reply) {
+                    frames.push(bytes);
+                }
+            }
+            (frames, device.faults.latency)
+        };
+
+        for frame in frames {
+            if !latency.is_zero() {
+                thread::sleep(latency);
+            }
+            if !write(&frame) {
+                return;
+            }
+        }
+    }
+}
+
+impl<S: Send + 'static, const D: usize, const T: usize> Channel<T> for Emulator<S, D> {
+    type Error = ();
+
+    fn list_devices(&self) -> Vec<String> {
+        vec![String::from("Emulator")]
+    }
+
+    fn connect(&mut self, _device: &String) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> Result<(), Self::Error> {
+        Channel::<T>::unlisten(self)
+    }
+
+    fn listen(
+        &mut self,
+        rx_sleep_time_ms: u64,
+        tx_sleep_time_ms: u64,
+    ) -> (Sender<Packet<T>>, Receiver<Packet<T>>) {
+        let (tx_packet_from_program, packet_to_transmit) = mpsc::channel::<Packet<T>>();
+        let (validated_packet, rx_packet_to_program) = mpsc::channel::<Packet<T>>();
+
+        // Simulated byte stream between host and device
+        let (host_to_device, device_rx) = mpsc::channel::<Vec<u8>>();
+        let (device_to_host, host_rx) = mpsc::channel::<u8>();
+
+        self.listening.store(true, Ordering::Relaxed);
+        let listening_tx = self.listening.clone();
+        let listening_rx = self.listening.clone();
+        let listening_device = self.listening.clone();
+        let device = self.device.clone();
+
+        // Host Tx thread
+        thread::spawn(move || {
+            while listening_tx.load(Ordering::Relaxed) {
+                match packet_to_transmit.recv_timeout(Duration::from_millis(tx_sleep_time_ms)) {
+                    Ok(packet) => {
+                        if host_to_device.send(packet.bytes().to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        // Device thread
+        thread::spawn(move || {
+            run_device(
+                device,
+                device_rx,
+                |bytes| bytes.iter().all(|byte| device_to_host.send(*byte).is_ok()),
+                &listening_device,
+            );
+        });
+
+        // Host Rx thread
+        thread::spawn(move || {
+            let mut packet = Packet::<T>::new();
+            while listening_rx.load(Ordering::Relaxed) {
+                match host_rx.recv_timeout(Duration::from_millis(rx_sleep_time_ms)) {
+                    Ok(byte) => match packet.construct(byte) {
+                        Ok(_) => {
+                            if validated_packet.send(packet).is_err() {
+                                break;
+                            }
+                            packet.reset_lazy();
+                        }
+                        Err(Status::PacketBuilding) => {}
+                        Err(_) => packet.reset_lazy(),
+                    },
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        (tx_packet_from_program, rx_packet_to_program)
+    }
+
+    fn unlisten(&mut self) -> Result<(), Self::Error> {
+        self.listening.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
@@ -0,0 +1,422 @@
+//! C API over `Packet` (requires features = ["ffi"]).
+//!
+//! C and C++ code gets an opaque `FlemPacket` handle from `flem_packet_new` and releases it with
+//! `flem_packet_free` (these two need features = ["std"]). Firmware without a heap instead hands
+//! `flem_packet_init` storage of `flem_packet_size()` bytes, so the API links into `no_std` builds.
+//! The functions mirror the `Packet` methods: building a packet with
+//! `flem_packet_add_data` / `flem_packet_pack` or `flem_packet_pack_data` /
+//! `flem_packet_pack_error`, sending it a byte at a time with `flem_packet_get_byte` (or all at
+//! once with `flem_packet_bytes`), and receiving with `flem_packet_construct`.
+//!
+//! Functions that can fail return a `FlemStatus`, which mirrors `Status` with an extra
+//! `FLEM_STATUS_NULL_POINTER` for missing arguments. Every handle holds a packet with
+//! `FLEM_FFI_DATA_SIZE` bytes of data, see `flem_max_data_size`.
+//!
+//! The header is `include/flem.h`, generated with `cbindgen --config cbindgen.toml --output
+//! include/flem.h`. On a host, build a library to link against with
+//! `cargo rustc --release --lib --features ffi,std --crate-type staticlib` (or `cdylib`). Firmware
+//! depends on the crate with features = ["ffi"] and exports the functions from its own binary.
+//!
+//! ```c
+//! FlemPacket *tx = flem_packet_new();
+//! flem_packet_pack_data(tx, 0x0010, payload, sizeof(payload));
+//! uart_write(flem_packet_bytes(tx), flem_packet_length(tx));
+//!
+//! static uint8_t storage[FLEM_STORAGE_SIZE]; // At least flem_packet_size()
+//! FlemPacket *rx = flem_packet_init(storage, sizeof(storage));
+//! while (flem_packet_construct(rx, uart_read()) == FLEM_STATUS_PACKET_BUILDING) {}
+//! ```
+
+#[cfg(feature = "std")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+use core::{
+    ffi::c_void,
+    mem::{align_of, size_of},
+    ptr, slice,
+};
+
+use crate::{Packet, Status};
+
+/// Data bytes of the packet behind every `FlemPacket` handle
+pub const FLEM_FFI_DATA_SIZE: usize = 1024;
+
+/// `Status` for C, plus `NullPointer`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FlemStatus {
+    Ok = 0,
+    PacketReceived = 1,
+    PacketBuilding = 2,
+    GetByteFinished = 3,
+    VersionLength = 4,
+    PacketOverflow = 5,
+    HeaderBytesNotFound = 6,
+    GetByteIssue = 7,
+    ChecksumError = 8,
+    UnspecifiedError = 9,
+    UnrecognizedRequest = 10,
+    InvalidDataLengthDetected = 11,
+    DecompressionFailed = 12,
+    DecryptionFailed = 13,
+    ReplayDetected = 14,
+    NotAddressed = 15,
+    /// A pointer argument was NULL
+    NullPointer = 255,
+}
+
+impl From<Status> for FlemStatus {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::Ok => FlemStatus::Ok,
+            Status::PacketReceived => FlemStatus::PacketReceived,
+            Status::PacketBuilding => FlemStatus::PacketBuilding,
+            Status::GetByteFinished => FlemStatus::GetByteFinished,
+            Status::VersionLength => FlemStatus::VersionLength,
+            Status::PacketOverflow => FlemStatus::PacketOverflow,
+            Status::HeaderBytesNotFound => FlemStatus::HeaderBytesNotFound,
+            Status::GetByteIssue => FlemStatus::GetByteIssue,
+            Status::ChecksumError => FlemStatus::ChecksumError,
+            Status::UnspecifiedError => FlemStatus::UnspecifiedError,
+            Status::UnrecognizedRequest => FlemStatus::UnrecognizedRequest,
+            Status::InvalidDataLengthDetected => FlemStatus::InvalidDataLengthDetected,
+            Status::DecompressionFailed => FlemStatus::DecompressionFailed,
+            Status::DecryptionFailed => FlemStatus::DecryptionFailed,
+            Status::ReplayDetected => FlemStatus::ReplayDetected,
+            Status::NotAddressed => FlemStatus::NotAddressed,
+        }
+    }
+}
+
+impl From<Result<(), Status>> for FlemStatus {
+    fn from(result: Result<(), Status>) -> Self {
+        match result {
+            Ok(_) => FlemStatus::Ok,
+            Err(status) => status.into(),
+        }
+    }
+}
+
+/// Opaque packet handle
+pub struct FlemPacket {
+    packet: Packet<FLEM_FFI_DATA_SIZE>,
+}
+
+// `Packet` is packed, so storage for `flem_packet_init` needs no particular alignment
+const _: () = assert!(align_of::<FlemPacket>() == 1);
+
+/// Borrows `length` bytes at `data`, allowing NULL for an empty slice
+unsafe fn input<'a>(data: *const u8, length: usize) -> Option<&'a [u8]> {
+    if length == 0 {
+        Some(&[])
+    } else if data.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(data, length))
+    }
+}
+
+/// Data bytes a packet can hold, `FLEM_FFI_DATA_SIZE`
+#[no_mangle]
+pub extern "C" fn flem_max_data_size() -> usize {
+    FLEM_FFI_DATA_SIZE
+}
+
+/// Bytes of storage `flem_packet_init` needs for a packet, with any alignment
+#[no_mangle]
+pub extern "C" fn flem_packet_size() -> usize {
+    size_of::<FlemPacket>()
+}
+
+/// Sets up a reset packet in caller provided storage, e.g. a static buffer, and returns its
+/// handle. Returns NULL if `storage` is NULL or `size` is less than `flem_packet_size()`. The
+/// handle lives as long as the storage and must not be passed to `flem_packet_free`.
+///
+/// # Safety
+/// `storage` must be NULL or point to `size` writable bytes that aren't used for anything else
+/// while the handle is.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_init(storage: *mut c_void, size: usize) -> *mut FlemPacket {
+    if storage.is_null() || size < size_of::<FlemPacket>() {
+        return ptr::null_mut();
+    }
+    let handle = storage.cast::<FlemPacket>();
+    handle.write(FlemPacket {
+        packet: Packet::new(),
+    });
+    handle
+}
+
+/// Allocates a new, reset packet. Release it with `flem_packet_free`. (requires features = ["std"])
+#[cfg(feature = "std")]
+#[no_mangle]
+pub extern "C" fn flem_packet_new() -> *mut FlemPacket {
+    Box::into_raw(Box::new(FlemPacket {
+        packet: Packet::new(),
+    }))
+}
+
+/// Releases a packet from `flem_packet_new`. NULL is ignored. (requires features = ["std"])
+///
+/// # Safety
+/// `packet` must be NULL or a handle from `flem_packet_new` that wasn't freed yet.
+#[cfg(feature = "std")]
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_free(packet: *mut FlemPacket) {
+    if !packet.is_null() {
+        drop(Box::from_raw(packet));
+    }
+}
+
+/// Clears the whole packet, data included
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_reset(packet: *mut FlemPacket) -> FlemStatus {
+    match packet.as_mut() {
+        Some(handle) => {
+            handle.packet.reset();
+            FlemStatus::Ok
+        }
+        None => FlemStatus::NullPointer,
+    }
+}
+
+/// Clears the header and counters but leaves the old data in place, which is faster
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_reset_lazy(packet: *mut FlemPacket) -> FlemStatus {
+    match packet.as_mut() {
+        Some(handle) => {
+            handle.packet.reset_lazy();
+            FlemStatus::Ok
+        }
+        None => FlemStatus::NullPointer,
+    }
+}
+
+/// Appends `length` bytes of data, `FLEM_STATUS_PACKET_OVERFLOW` if they don't fit
+///
+/// # Safety
+/// `packet` must be NULL or a live handle, and `data` must point to `length` readable bytes (it may
+/// be NULL if `length` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_add_data(
+    packet: *mut FlemPacket,
+    data: *const u8,
+    length: usize,
+) -> FlemStatus {
+    match (packet.as_mut(), input(data, length)) {
+        (Some(handle), Some(data)) => handle.packet.add_data(data).into(),
+        _ => FlemStatus::NullPointer,
+    }
+}
+
+/// Computes the checksum and fills in the header after `flem_packet_add_data`
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_pack(packet: *mut FlemPacket) -> FlemStatus {
+    match packet.as_mut() {
+        Some(handle) => {
+            handle.packet.pack();
+            FlemStatus::Ok
+        }
+        None => FlemStatus::NullPointer,
+    }
+}
+
+/// Resets the packet and packs `request` with a `response::SUCCESS` response and `data`
+///
+/// # Safety
+/// As `flem_packet_add_data`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_pack_data(
+    packet: *mut FlemPacket,
+    request: u16,
+    data: *const u8,
+    length: usize,
+) -> FlemStatus {
+    match (packet.as_mut(), input(data, length)) {
+        (Some(handle), Some(data)) => handle.packet.pack_data(request, data).into(),
+        _ => FlemStatus::NullPointer,
+    }
+}
+
+/// Resets the packet and packs `request` with the `error` response and `data`
+///
+/// # Safety
+/// As `flem_packet_add_data`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_pack_error(
+    packet: *mut FlemPacket,
+    request: u16,
+    error: u16,
+    data: *const u8,
+    length: usize,
+) -> FlemStatus {
+    match (packet.as_mut(), input(data, length)) {
+        (Some(handle), Some(data)) => handle.packet.pack_error(request, error, data).into(),
+        _ => FlemStatus::NullPointer,
+    }
+}
+
+/// Feeds one received byte. Returns `FLEM_STATUS_OK` once a valid packet is complete,
+/// `FLEM_STATUS_PACKET_BUILDING` while more bytes are needed, and an error otherwise, after which
+/// the packet should be reset.
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_construct(packet: *mut FlemPacket, byte: u8) -> FlemStatus {
+    match packet.as_mut() {
+        Some(handle) => handle.packet.construct(byte).into(),
+        None => FlemStatus::NullPointer,
+    }
+}
+
+/// Writes the next byte to send to `byte`. Returns `FLEM_STATUS_GET_BYTE_FINISHED` once every byte
+/// was read.
+///
+/// # Safety
+/// `packet` must be NULL or a live handle, and `byte` NULL or writable.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_get_byte(
+    packet: *mut FlemPacket,
+    byte: *mut u8,
+) -> FlemStatus {
+    if byte.is_null() {
+        return FlemStatus::NullPointer;
+    }
+    match packet.as_mut() {
+        Some(handle) => match handle.packet.get_byte() {
+            Ok(value) => {
+                *byte = value;
+                FlemStatus::Ok
+            }
+            Err(status) => status.into(),
+        },
+        None => FlemStatus::NullPointer,
+    }
+}
+
+/// Checks the checksum of the packet
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_validate(packet: *mut FlemPacket) -> bool {
+    match packet.as_mut() {
+        Some(handle) => handle.packet.validate(),
+        None => false,
+    }
+}
+
+/// Request code, 0 for NULL
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_get_request(packet: *const FlemPacket) -> u16 {
+    packet
+        .as_ref()
+        .map_or(0, |handle| handle.packet.get_request())
+}
+
+/// Sets the request code, call `flem_packet_pack` afterwards
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_set_request(packet: *mut FlemPacket, request: u16) {
+    if let Some(handle) = packet.as_mut() {
+        handle.packet.set_request(request);
+    }
+}
+
+/// Response code, 0 for NULL
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_get_response(packet: *const FlemPacket) -> u16 {
+    packet
+        .as_ref()
+        .map_or(0, |handle| handle.packet.get_response())
+}
+
+/// Sets the response code, call `flem_packet_pack` afterwards
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_set_response(packet: *mut FlemPacket, response: u16) {
+    if let Some(handle) = packet.as_mut() {
+        handle.packet.set_response(response);
+    }
+}
+
+/// Checksum field, 0 for NULL
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_get_checksum(packet: *const FlemPacket) -> u16 {
+    packet
+        .as_ref()
+        .map_or(0, |handle| handle.packet.get_checksum())
+}
+
+/// The data, `flem_packet_data_length` bytes long. Valid until the packet is changed or freed.
+/// A length over `flem_max_data_size`, e.g. after `FLEM_STATUS_INVALID_DATA_LENGTH_DETECTED`, is
+/// capped.
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_data(packet: *const FlemPacket) -> *const u8 {
+    packet
+        .as_ref()
+        .map_or(ptr::null(), |handle| handle.packet.payload().as_ptr())
+}
+
+/// Bytes of data, at most `flem_max_data_size`, 0 for NULL
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_data_length(packet: *const FlemPacket) -> usize {
+    packet
+        .as_ref()
+        .map_or(0, |handle| handle.packet.payload().len())
+}
+
+/// The whole packet as sent on the wire, `flem_packet_length` bytes long. Valid until the packet
+/// is changed or freed. The data is capped like `flem_packet_data`.
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_bytes(packet: *const FlemPacket) -> *const u8 {
+    packet
+        .as_ref()
+        .map_or(ptr::null(), |handle| handle.packet.wire_bytes().as_ptr())
+}
+
+/// Bytes on the wire, header included, with the data capped like `flem_packet_data_length`. 0 for
+/// NULL.
+///
+/// # Safety
+/// `packet` must be NULL or a live handle from `flem_packet_new` or `flem_packet_init`.
+#[no_mangle]
+pub unsafe extern "C" fn flem_packet_length(packet: *const FlemPacket) -> usize {
+    packet
+        .as_ref()
+        .map_or(0, |handle| handle.packet.wire_bytes().len())
+}
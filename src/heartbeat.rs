@@ -0,0 +1,249 @@
+//! Heartbeat and link-health monitoring.
+//!
+//! Each side periodically sends an empty `request::HEARTBEAT` packet. Every valid packet received
+//! from the partner (heartbeat or not) counts as a sign of life, and the time since the last one
+//! drives a small state machine:
+//!
+//! - `Up` - the partner was heard from within `degraded_timeout`
+//! - `Degraded` - nothing heard for `degraded_timeout`, but less than `down_timeout`
+//! - `Down` - nothing heard for `down_timeout`, or nothing heard yet
+//!
+//! `Heartbeat` is `no_std` and takes a `u32` millisecond tick from the caller. With
+//! `features = ["std"]`, `HeartbeatMonitor` runs the same state machine on a thread and sends
+//! heartbeats through a `Channel`'s `Sender`.
+
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "std")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+
+#[cfg(feature = "std")]
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{request, Packet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    Up,
+    Degraded,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatConfig {
+    /// Time between heartbeats sent to the partner, in ticks
+    pub interval: u32,
+    /// Silence after which the link is reported as `Degraded`, in ticks
+    pub degraded_timeout: u32,
+    /// Silence after which the link is reported as `Down`, in ticks
+    pub down_timeout: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        HeartbeatConfig {
+            interval: 250,
+            degraded_timeout: 750,
+            down_timeout: 2000,
+        }
+    }
+}
+
+pub struct Heartbeat {
+    config: HeartbeatConfig,
+    state: LinkState,
+    last_seen: u32,
+    last_sent: Option<u32>,
+    on_change: Option<fn(LinkState)>,
+}
+
+impl Heartbeat {
+    /// Creates a heartbeat in the `Down` state. The first heartbeat is sent on the first `poll`.
+    pub fn new(config: HeartbeatConfig) -> Self {
+        Heartbeat {
+            config,
+            state: LinkState::Down,
+            last_seen: 0,
+            last_sent: None,
+            on_change: None,
+        }
+    }
+
+    /// Sets a function that is called with the new state every time the link state changes. On
+    /// firmware this is a good place to enter a safe state when the link goes `Down`.
+    pub fn set_callback(&mut self, on_change: fn(LinkState)) {
+        self.on_change = Some(on_change);
+    }
+
+    /// Current state of the link
+    pub fn state(&self) -> LinkState {
+        self.state
+    }
+
+    /// Call with every valid packet received from the partner. Returns true if the packet was a
+    /// heartbeat, in which case it needs no further handling.
+    pub fn received<const T: usize>(&mut self, packet: &Packet<T>, now: u32) -> bool {
+        self.last_seen = now;
+        self.transition(LinkState::Up);
+        packet.get_request() == request::HEARTBEAT
+    }
+
+    /// Updates the link state and packs a heartbeat into `heartbeat` when one is due. Returns true if
+    /// `heartbeat` should be transmitted.
+    pub fn poll<const T: usize>(&mut self, now: u32, heartbeat: &mut Packet<T>) -> bool {
+        if self.state != LinkState::Down {
+            let silence = now.wrapping_sub(self.last_seen);
+            if silence >= self.config.down_timeout {
+                self.transition(LinkState::Down);
+            } else if silence >= self.config.degraded_timeout {
+                self.transition(LinkState::Degraded);
+            }
+        }
+
+        let due = match self.last_sent {
+            Some(last_sent) => now.wrapping_sub(last_sent) >= self.config.interval,
+            None => true,
+        };
+
+        if due && heartbeat.pack_data(request::HEARTBEAT, &[]).is_ok() {
+            self.last_sent = Some(now);
+            return true;
+        }
+        false
+    }
+
+    fn transition(&mut self, state: LinkState) {
+        if self.state != state {
+            self.state = state;
+            if let Some(on_change) = self.on_change {
+                on_change(state);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+type StateCallback = Box<dyn FnMut(LinkState) + Send>;
+
+/// The state machine and its callback share a lock, so callbacks run in the order of the
+/// transitions they report
+#[cfg(feature = "std")]
+struct Shared {
+    heartbeat: Heartbeat,
+    on_change: StateCallback,
+}
+
+#[cfg(feature = "std")]
+impl Shared {
+    fn update<R>(&mut self, f: impl FnOnce(&mut Heartbeat) -> R) -> R {
+        let previous = self.heartbeat.state();
+        let result = f(&mut self.heartbeat);
+        let current = self.heartbeat.state();
+        if previous != current {
+            (self.on_change)(current);
+        }
+        result
+    }
+}
+
+/// Runs a `Heartbeat` on a background thread, sending heartbeats through the `Sender` returned by
+/// `Channel::listen`. Received packets still have to be passed to `received`.
+#[cfg(feature = "std")]
+pub struct HeartbeatMonitor {
+    shared: Arc<Mutex<Shared>>,
+    running: Arc<AtomicBool>,
+    start: Instant,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "std")]
+impl HeartbeatMonitor {
+    /// Starts the monitor. `on_change` runs while the monitor is locked, so it must not call back
+    /// into the monitor.
+    pub fn start<const T: usize, F>(
+        config: HeartbeatConfig,
+        tx: Sender<Packet<T>>,
+        on_change: F,
+    ) -> Self
+    where
+        F: FnMut(LinkState) + Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(Shared {
+            heartbeat: Heartbeat::new(config),
+            on_change: Box::new(on_change),
+        }));
+        let running = Arc::new(AtomicBool::new(true));
+        let start = Instant::now();
+
+        let shared_clone = shared.clone();
+        let running_clone = running.clone();
+        // Poll a few times per interval so state changes are reported promptly
+        let sleep_time = Duration::from_millis((config.interval / 4).max(1) as u64);
+
+        let handle = thread::spawn(move || {
+            let mut packet = Packet::<T>::new();
+            while running_clone.load(Ordering::Relaxed) {
+                let now = start.elapsed().as_millis() as u32;
+                let send = shared_clone
+                    .lock()
+                    .unwrap()
+                    .update(|heartbeat| heartbeat.poll(now, &mut packet));
+
+                if send && tx.send(packet).is_err() {
+                    // Channel was closed, nothing left to monitor
+                    break;
+                }
+                thread::sleep(sleep_time);
+            }
+        });
+
+        HeartbeatMonitor {
+            shared,
+            running,
+            start,
+            handle: Some(handle),
+        }
+    }
+
+    /// Call with every valid packet received from the partner. Returns true if the packet was a
+    /// heartbeat.
+    pub fn received<const T: usize>(&self, packet: &Packet<T>) -> bool {
+        let now = self.start.elapsed().as_millis() as u32;
+        self.shared
+            .lock()
+            .unwrap()
+            .update(|heartbeat| heartbeat.received(packet, now))
+    }
+
+    /// Current state of the link
+    pub fn state(&self) -> LinkState {
+        self.shared.lock().unwrap().heartbeat.state()
+    }
+
+    /// Stops the background thread
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for HeartbeatMonitor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
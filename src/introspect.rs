@@ -0,0 +1,725 @@
+//! Self-describing devices: a device lists the requests it supports, their payload layouts and its
+//! own response codes, so host tools can talk to firmware they weren't compiled against.
+//!
+//! | Request             | Data          | Reply                                     |
+//! |---------------------|---------------|-------------------------------------------|
+//! | `request::DESCRIBE` | index (u16)   | count (u16), kind (u8), code (u16), entry |
+//!
+//! `count` is the number of entries. Entries list the requests first, then the response codes, and
+//! `kind` is `KIND_REQUEST` or `KIND_RESPONSE`. Every entry starts with its name. Request entries
+//! go on with the fields of the request data and then the fields of the reply data, each list
+//! prefixed with a field count (u8). A field is its type (u8, see `FieldType`), a count (u8, above
+//! 1 for arrays) and its name. Names are a length byte followed by UTF-8.
+//!
+//! Values are little endian and packed. `FieldType::Bytes` and `FieldType::Text` take the rest of
+//! the payload and so only make sense as the last field. An index past the end is answered with
+//! `response::OUT_OF_RANGE`, malformed requests and entries that don't fit the packet with
+//! `response::INVALID_ARGUMENT`.
+//!
+//! `Introspection` is the `no_std` device side, over `'static` tables of `RequestDescription` and
+//! `ResponseDescription`. With features = ["std"], `describe` reads a device's description into a
+//! `DeviceDescription`, which formats packets field by field and builds request packets from text
+//! arguments. The `flem` tool uses it for its `describe` and `call` commands.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::introspect::{Field, FieldType, Introspection, RequestDescription};
+//!     use flem::{request, Packet};
+//!
+//!     static REQUESTS: [RequestDescription; 1] = [RequestDescription::new(
+//!         0x0010,
+//!         "GET_TEMPERATURE",
+//!         &[],
+//!         &[Field::new("celsius", FieldType::F32)],
+//!     )];
+//!     let introspection = Introspection::new(&REQUESTS, &[]);
+//!
+//!     let mut rx = Packet::<64>::new();
+//!     let mut tx = Packet::<64>::new();
+//!     rx.pack_data(request::DESCRIBE, &[0x00, 0x00]).unwrap();
+//!     assert!(introspection.handle(&rx, &mut tx));
+//!     assert_eq!(&tx.payload()[..5], &[0x01, 0x00, 0x00, 0x10, 0x00]);
+//! }
+//! ```
+
+#[cfg(feature = "std")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "std")]
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+#[cfg(feature = "std")]
+use std::fmt;
+
+use crate::buffer::le_buffer_to_u16;
+#[cfg(feature = "std")]
+use crate::client::{Client, ClientError};
+#[cfg(feature = "std")]
+use crate::decode::{format_bytes, parse_hex, Decoder};
+use crate::{request, response, Packet};
+
+/// Entry kind of a request description
+pub const KIND_REQUEST: u8 = 0;
+/// Entry kind of a response code description
+pub const KIND_RESPONSE: u8 = 1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    Bool = 0,
+    U8 = 1,
+    U16 = 2,
+    U32 = 3,
+    U64 = 4,
+    I8 = 5,
+    I16 = 6,
+    I32 = 7,
+    I64 = 8,
+    F32 = 9,
+    F64 = 10,
+    /// Raw bytes up to the end of the payload
+    Bytes = 11,
+    /// UTF-8 up to the end of the payload
+    Text = 12,
+}
+
+impl FieldType {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(FieldType::Bool),
+            1 => Some(FieldType::U8),
+            2 => Some(FieldType::U16),
+            3 => Some(FieldType::U32),
+            4 => Some(FieldType::U64),
+            5 => Some(FieldType::I8),
+            6 => Some(FieldType::I16),
+            7 => Some(FieldType::I32),
+            8 => Some(FieldType::I64),
+            9 => Some(FieldType::F32),
+            10 => Some(FieldType::F64),
+            11 => Some(FieldType::Bytes),
+            12 => Some(FieldType::Text),
+            _ => None,
+        }
+    }
+
+    /// Encoded bytes of one value, `None` for the variable length `Bytes` and `Text`
+    pub fn size(&self) -> Option<usize> {
+        match self {
+            FieldType::Bool | FieldType::U8 | FieldType::I8 => Some(1),
+            FieldType::U16 | FieldType::I16 => Some(2),
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => Some(4),
+            FieldType::U64 | FieldType::I64 | FieldType::F64 => Some(8),
+            FieldType::Bytes | FieldType::Text => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub field_type: FieldType,
+    /// Number of values, above 1 for fixed size arrays
+    pub count: u8,
+}
+
+impl Field {
+    pub const fn new(name: &'static str, field_type: FieldType) -> Self {
+        Field {
+            name,
+            field_type,
+            count: 1,
+        }
+    }
+
+    pub const fn array(name: &'static str, field_type: FieldType, count: u8) -> Self {
+        Field {
+            name,
+            field_type,
+            count,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RequestDescription {
+    pub code: u16,
+    pub name: &'static str,
+    /// Layout of the request data
+    pub data: &'static [Field],
+    /// Layout of the data of a `response::SUCCESS` reply
+    pub reply: &'static [Field],
+}
+
+impl RequestDescription {
+    pub const fn new(
+        code: u16,
+        name: &'static str,
+        data: &'static [Field],
+        reply: &'static [Field],
+    ) -> Self {
+        RequestDescription {
+            code,
+            name,
+            data,
+            reply,
+        }
+    }
+}
+
+/// A project specific response code
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResponseDescription {
+    pub code: u16,
+    pub name: &'static str,
+}
+
+impl ResponseDescription {
+    pub const fn new(code: u16, name: &'static str) -> Self {
+        ResponseDescription { code, name }
+    }
+}
+
+/// Device side `request::DESCRIBE` service
+pub struct Introspection<'a> {
+    requests: &'a [RequestDescription],
+    responses: &'a [ResponseDescription],
+}
+
+impl<'a> Introspection<'a> {
+    pub const fn new(
+        requests: &'a [RequestDescription],
+        responses: &'a [ResponseDescription],
+    ) -> Self {
+        Introspection {
+            requests,
+            responses,
+        }
+    }
+
+    pub fn requests(&self) -> &[RequestDescription] {
+        self.requests
+    }
+
+    pub fn responses(&self) -> &[ResponseDescription] {
+        self.responses
+    }
+
+    /// Total number of entries, requests and response codes
+    pub fn count(&self) -> usize {
+        self.requests.len() + self.responses.len()
+    }
+
+    /// Handles `request::DESCRIBE` and packs the reply. Returns false, leaving `reply` untouched,
+    /// for any other request.
+    pub fn handle<const T: usize>(&self, packet: &Packet<T>, reply: &mut Packet<T>) -> bool {
+        let code = packet.get_request();
+        if code != request::DESCRIBE {
+            return false;
+        }
+
+        match self.entry(packet.payload(), reply) {
+            Ok(_) => {
+                reply.set_request(code);
+                reply.set_response(response::SUCCESS);
+                reply.pack();
+            }
+            Err(error) => {
+                let _ = reply.pack_error(code, error, &[]);
+            }
+        }
+        true
+    }
+
+    fn entry<const T: usize>(&self, payload: &[u8], reply: &mut Packet<T>) -> Result<(), u16> {
+        if payload.len() != 2 {
+            return Err(response::INVALID_ARGUMENT);
+        }
+        let mut offset = 0;
+        let index = le_buffer_to_u16(payload, &mut offset)
+            .map_err(|_| response::INVALID_ARGUMENT)? as usize;
+        if index >= self.count() {
+            return Err(response::OUT_OF_RANGE);
+        }
+
+        let count = (self.count() as u16).to_le_bytes();
+        reply.reset_lazy();
+        match self.requests.get(index) {
+            Some(description) => {
+                let code = description.code.to_le_bytes();
+                add(reply, &[count[0], count[1], KIND_REQUEST, code[0], code[1]])?;
+                add_text(reply, description.name)?;
+                for fields in [description.data, description.reply] {
+                    add(reply, &[fields.len().min(u8::MAX as usize) as u8])?;
+                    for field in fields.iter().take(u8::MAX as usize) {
+                        add(reply, &[field.field_type as u8, field.count])?;
+                        add_text(reply, field.name)?;
+                    }
+                }
+            }
+            None => {
+                let description = &self.responses[index - self.requests.len()];
+                let code = description.code.to_le_bytes();
+                add(
+                    reply,
+                    &[count[0], count[1], KIND_RESPONSE, code[0], code[1]],
+                )?;
+                add_text(reply, description.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn add<const T: usize>(reply: &mut Packet<T>, data: &[u8]) -> Result<(), u16> {
+    reply.add_data(data).map_err(|_| response::INVALID_ARGUMENT)
+}
+
+/// Adds a length byte and the text, cut short to 255 bytes on a character boundary
+fn add_text<const T: usize>(reply: &mut Packet<T>, text: &str) -> Result<(), u16> {
+    let mut length = text.len().min(u8::MAX as usize);
+    while !text.is_char_boundary(length) {
+        length -= 1;
+    }
+    add(reply, &[length as u8])?;
+    add(reply, &text.as_bytes()[..length])
+}
+
+/// Why a payload couldn't be formatted or a request built (requires features = ["std"])
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntrospectError {
+    /// The device doesn't describe a request with this name or code
+    UnknownRequest(String),
+    /// A request needs one argument per field
+    ArgumentCount { expected: usize, found: usize },
+    /// The argument for the named field couldn't be parsed or is out of range
+    InvalidArgument(String),
+    /// The payload doesn't match the field layout
+    InvalidPayload,
+    /// The request doesn't fit in a packet
+    PacketOverflow,
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for IntrospectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntrospectError::UnknownRequest(name) => write!(f, "unknown request '{}'", name),
+            IntrospectError::ArgumentCount { expected, found } => {
+                write!(f, "expected {} argument(s), found {}", expected, found)
+            }
+            IntrospectError::InvalidArgument(field) => write!(f, "invalid value for '{}'", field),
+            IntrospectError::InvalidPayload => write!(f, "payload doesn't match the description"),
+            IntrospectError::PacketOverflow => write!(f, "request doesn't fit in a packet"),
+        }
+    }
+}
+
+/// A decoded field value (requires features = ["std"])
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Unsigned(value) => write!(f, "{}", value),
+            Value::Signed(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Bytes(bytes) => write!(f, "[{}]", format_bytes(bytes)),
+            Value::Text(text) => write!(f, "{:?}", text),
+            Value::Array(values) => {
+                let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+        }
+    }
+}
+
+/// Field as reported by a device (requires features = ["std"])
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldInfo {
+    pub name: String,
+    pub field_type: FieldType,
+    pub count: u8,
+}
+
+#[cfg(feature = "std")]
+impl FieldInfo {
+    /// Reads one value of the field's type at `offset`
+    fn decode_one(&self, payload: &[u8], offset: &mut usize) -> Result<Value, IntrospectError> {
+        let size = match self.field_type.size() {
+            Some(size) => size,
+            None => payload.len().saturating_sub(*offset),
+        };
+        let bytes = payload
+            .get(*offset..*offset + size)
+            .ok_or(IntrospectError::InvalidPayload)?;
+        *offset += size;
+
+        let mut buffer = [0u8; 8];
+        if size <= 8 {
+            buffer[..size].copy_from_slice(bytes);
+        }
+        let unsigned = u64::from_le_bytes(buffer);
+        // Sign extend from the field's width
+        let signed = |bits: u32| ((unsigned << (64 - bits)) as i64) >> (64 - bits);
+
+        Ok(match self.field_type {
+            FieldType::Bool => Value::Bool(bytes[0] != 0),
+            FieldType::U8 | FieldType::U16 | FieldType::U32 | FieldType::U64 => {
+                Value::Unsigned(unsigned)
+            }
+            FieldType::I8 => Value::Signed(signed(8)),
+            FieldType::I16 => Value::Signed(signed(16)),
+            FieldType::I32 => Value::Signed(signed(32)),
+            FieldType::I64 => Value::Signed(unsigned as i64),
+            FieldType::F32 => Value::Float(f32::from_bits(unsigned as u32) as f64),
+            FieldType::F64 => Value::Float(f64::from_bits(unsigned)),
+            FieldType::Bytes => Value::Bytes(bytes.to_vec()),
+            FieldType::Text => Value::Text(String::from_utf8_lossy(bytes).into_owned()),
+        })
+    }
+
+    fn decode(&self, payload: &[u8], offset: &mut usize) -> Result<Value, IntrospectError> {
+        if self.count > 1 && self.field_type.size().is_some() {
+            let mut values = Vec::new();
+            for _ in 0..self.count {
+                values.push(self.decode_one(payload, offset)?);
+            }
+            Ok(Value::Array(values))
+        } else {
+            self.decode_one(payload, offset)
+        }
+    }
+
+    /// Appends one value parsed from `text`. Integers may be `0x` prefixed hex, booleans are
+    /// `true` / `false` / `1` / `0` and `Bytes` is hex as accepted by `decode::parse_hex`.
+    fn encode_one(&self, text: &str, data: &mut Vec<u8>) -> Result<(), IntrospectError> {
+        let invalid = || IntrospectError::InvalidArgument(self.name.clone());
+        let text = text.trim();
+        let unsigned = || match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => text.parse::<u64>().ok(),
+        };
+        let size = self.field_type.size().unwrap_or(0);
+
+        match self.field_type {
+            FieldType::Bool => match text {
+                "true" | "1" => data.push(1),
+                "false" | "0" => data.push(0),
+                _ => return Err(invalid()),
+            },
+            FieldType::U8 | FieldType::U16 | FieldType::U32 | FieldType::U64 => {
+                let value = unsigned().ok_or_else(invalid)?;
+                if size < 8 && value >> (size * 8) != 0 {
+                    return Err(invalid());
+                }
+                data.extend_from_slice(&value.to_le_bytes()[..size]);
+            }
+            FieldType::I8 | FieldType::I16 | FieldType::I32 | FieldType::I64 => {
+                let value = text.parse::<i64>().map_err(|_| invalid())?;
+                let bits = size as u32 * 8;
+                if bits < 64 && (value < -(1i64 << (bits - 1)) || value >= 1i64 << (bits - 1)) {
+                    return Err(invalid());
+                }
+                data.extend_from_slice(&value.to_le_bytes()[..size]);
+            }
+            FieldType::F32 => {
+                let value = text.parse::<f32>().map_err(|_| invalid())?;
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            FieldType::F64 => {
+                let value = text.parse::<f64>().map_err(|_| invalid())?;
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            FieldType::Bytes => data.extend(parse_hex(text).map_err(|_| invalid())?),
+            FieldType::Text => data.extend_from_slice(text.as_bytes()),
+        }
+        Ok(())
+    }
+
+    /// Appends the field parsed from `text`, with array values separated by commas
+    fn encode(&self, text: &str, data: &mut Vec<u8>) -> Result<(), IntrospectError> {
+        if self.count > 1 && self.field_type.size().is_some() {
+            let values: Vec<&str> = text.split(',').collect();
+            if values.len() != self.count as usize {
+                return Err(IntrospectError::InvalidArgument(self.name.clone()));
+            }
+            for value in values {
+                self.encode_one(value, data)?;
+            }
+            Ok(())
+        } else {
+            self.encode_one(text, data)
+        }
+    }
+}
+
+/// Formats as `name: type`, or `name: [type; count]` for arrays
+#[cfg(feature = "std")]
+impl fmt::Display for FieldInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field_type = format!("{:?}", self.field_type).to_lowercase();
+        if self.count > 1 && self.field_type.size().is_some() {
+            write!(f, "{}: [{}; {}]", self.name, field_type, self.count)
+        } else {
+            write!(f, "{}: {}", self.name, field_type)
+        }
+    }
+}
+
+/// Request as reported by a device (requires features = ["std"])
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestInfo {
+    pub code: u16,
+    pub name: String,
+    pub data: Vec<FieldInfo>,
+    pub reply: Vec<FieldInfo>,
+}
+
+/// Response code as reported by a device (requires features = ["std"])
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseInfo {
+    pub code: u16,
+    pub name: String,
+}
+
+/// Everything a device described about itself (requires features = ["std"])
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceDescription {
+    pub requests: Vec<RequestInfo>,
+    pub responses: Vec<ResponseInfo>,
+}
+
+#[cfg(feature = "std")]
+impl DeviceDescription {
+    pub fn request(&self, code: u16) -> Option<&RequestInfo> {
+        self.requests.iter().find(|request| request.code == code)
+    }
+
+    pub fn request_by_name(&self, name: &str) -> Option<&RequestInfo> {
+        self.requests.iter().find(|request| request.name == name)
+    }
+
+    /// Name of a response code, the device's own or a pre-defined one
+    pub fn response_name(&self, code: u16) -> Option<&str> {
+        self.responses
+            .iter()
+            .find(|response| response.code == code)
+            .map(|response| response.name.as_str())
+            .or_else(|| response::name(code))
+    }
+
+    /// A `Decoder` that knows the names of the device's requests and responses
+    pub fn decoder(&self) -> Decoder {
+        let decoder = self
+            .requests
+            .iter()
+            .fold(Decoder::new(), |decoder, request| {
+                decoder.with_request_name(request.code, &request.name)
+            });
+        self.responses.iter().fold(decoder, |decoder, response| {
+            decoder.with_response_name(response.code, &response.name)
+        })
+    }
+
+    /// Decodes `payload` field by field
+    pub fn decode_fields(
+        fields: &[FieldInfo],
+        payload: &[u8],
+    ) -> Result<Vec<(String, Value)>, IntrospectError> {
+        let mut offset = 0;
+        let mut values = Vec::new();
+        for field in fields {
+            values.push((field.name.clone(), field.decode(payload, &mut offset)?));
+        }
+        if offset != payload.len() {
+            return Err(IntrospectError::InvalidPayload);
+        }
+        Ok(values)
+    }
+
+    /// Encodes one text argument per field
+    pub fn encode_fields(fields: &[FieldInfo], args: &[&str]) -> Result<Vec<u8>, IntrospectError> {
+        if fields.len() != args.len() {
+            return Err(IntrospectError::ArgumentCount {
+                expected: fields.len(),
+                found: args.len(),
+            });
+        }
+        let mut data = Vec::new();
+        for (field, arg) in fields.iter().zip(args) {
+            field.encode(arg, &mut data)?;
+        }
+        Ok(data)
+    }
+
+    /// Builds a packet for the request called `name`, one text argument per data field
+    pub fn build<const T: usize>(
+        &self,
+        name: &str,
+        args: &[&str],
+    ) -> Result<Packet<T>, IntrospectError> {
+        let request = self
+            .request_by_name(name)
+            .ok_or_else(|| IntrospectError::UnknownRequest(name.to_string()))?;
+        let data = Self::encode_fields(&request.data, args)?;
+
+        let mut packet = Packet::<T>::new();
+        packet
+            .pack_data(request.code, &data)
+            .map_err(|_| IntrospectError::PacketOverflow)?;
+        Ok(packet)
+    }
+
+    /// Formats a request sent to the device, e.g. `SET_TARGET (0x0011) celsius=21.5 fan=2`
+    pub fn format_request<const T: usize>(&self, packet: &Packet<T>) -> String {
+        let request = self.request(packet.get_request());
+        self.format(
+            packet,
+            request,
+            request.map(|request| request.data.as_slice()),
+        )
+    }
+
+    /// Formats a reply from the device. Error responses are shown by name, with any data as hex.
+    pub fn format_reply<const T: usize>(&self, packet: &Packet<T>) -> String {
+        let request = self.request(packet.get_request());
+        let fields = match packet.get_response() {
+            response::SUCCESS | response::ASYNC => request.map(|request| request.reply.as_slice()),
+            code => {
+                let mut text = self.format(packet, request, None);
+                let name = self.response_name(code).unwrap_or("unknown response");
+                text.push_str(&format!(" error: 0x{:04X} ({})", code, name));
+                return text;
+            }
+        };
+        self.format(packet, request, fields)
+    }
+
+    fn format<const T: usize>(
+        &self,
+        packet: &Packet<T>,
+        request: Option<&RequestInfo>,
+        fields: Option<&[FieldInfo]>,
+    ) -> String {
+        let mut text = match request {
+            Some(request) => format!("{} (0x{:04X})", request.name, request.code),
+            None => match crate::request::name(packet.get_request()) {
+                Some(name) => format!("{} (0x{:04X})", name, packet.get_request()),
+                None => format!("0x{:04X}", packet.get_request()),
+            },
+        };
+
+        let payload = packet.payload();
+        let decoded = fields.map(|fields| Self::decode_fields(fields, payload));
+        match decoded {
+            Some(Ok(values)) => {
+                for (name, value) in values {
+                    text.push_str(&format!(" {}={}", name, value));
+                }
+            }
+            _ if payload.is_empty() => {}
+            _ => text.push_str(&format!(" payload: {}", format_bytes(payload))),
+        }
+        text
+    }
+}
+
+#[cfg(feature = "std")]
+fn parse_text(payload: &[u8], offset: &mut usize) -> Result<String, ClientError> {
+    let length = *payload.get(*offset).ok_or(ClientError::InvalidReply)? as usize;
+    let text = payload
+        .get(*offset + 1..*offset + 1 + length)
+        .ok_or(ClientError::InvalidReply)?;
+    *offset += 1 + length;
+    Ok(String::from_utf8_lossy(text).into_owned())
+}
+
+#[cfg(feature = "std")]
+fn parse_fields(payload: &[u8], offset: &mut usize) -> Result<Vec<FieldInfo>, ClientError> {
+    let count = *payload.get(*offset).ok_or(ClientError::InvalidReply)?;
+    *offset += 1;
+    let mut fields = Vec::new();
+    for _ in 0..count {
+        let header = payload
+            .get(*offset..*offset + 2)
+            .ok_or(ClientError::InvalidReply)?;
+        let field_type = FieldType::from_u8(header[0]).ok_or(ClientError::InvalidReply)?;
+        let count = header[1];
+        *offset += 2;
+        fields.push(FieldInfo {
+            name: parse_text(payload, offset)?,
+            field_type,
+            count,
+        });
+    }
+    Ok(fields)
+}
+
+/// Reads the device's description of its requests and response codes (requires
+/// features = ["std"])
+#[cfg(feature = "std")]
+pub fn describe<const T: usize>(client: &Client<T>) -> Result<DeviceDescription, ClientError> {
+    let mut description = DeviceDescription::default();
+    let mut index: usize = 0;
+    let mut count = 1;
+
+    while index < count {
+        let reply = match client.call(request::DESCRIBE, &(index as u16).to_le_bytes()) {
+            // A device with an empty description has no index 0
+            Err(ClientError::Response(response::OUT_OF_RANGE)) if index == 0 => {
+                return Ok(description)
+            }
+            result => result?,
+        };
+        let payload = reply.payload();
+        let invalid = |_| ClientError::InvalidReply;
+
+        let mut offset = 0;
+        count = le_buffer_to_u16(payload, &mut offset).map_err(invalid)? as usize;
+        let kind = *payload.get(offset).ok_or(ClientError::InvalidReply)?;
+        offset += 1;
+        let code = le_buffer_to_u16(payload, &mut offset).map_err(invalid)?;
+        let name = parse_text(payload, &mut offset)?;
+
+        match kind {
+            KIND_REQUEST => {
+                let data = parse_fields(payload, &mut offset)?;
+                let reply = parse_fields(payload, &mut offset)?;
+                description.requests.push(RequestInfo {
+                    code,
+                    name,
+                    data,
+                    reply,
+                });
+            }
+            KIND_RESPONSE => description.responses.push(ResponseInfo { code, name }),
+            _ => return Err(ClientError::InvalidReply),
+        }
+        index += 1;
+    }
+
+    Ok(description)
+}
@@ -1,11 +1,56 @@
 #![no_std]
 
+// The pyo3 macros refer to `::std`
+#[cfg(feature = "python")]
+extern crate std;
+
 use core::fmt::{Debug, Error, Formatter};
 
+#[macro_use]
+mod macros;
+
+pub mod address;
+#[cfg(feature = "auth")]
+pub mod auth;
+pub mod bridge;
 pub mod buffer;
+#[cfg(feature = "std")]
+pub mod capture;
+#[cfg(feature = "std")]
+pub mod client;
+#[cfg(feature = "codegen")]
+pub mod codegen;
+pub mod compress;
+#[cfg(feature = "std")]
+pub mod decode;
+pub mod dfu;
+#[cfg(feature = "std")]
+pub mod emulator;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod heartbeat;
+pub mod introspect;
+pub mod memory;
+pub mod mux;
+pub mod param;
+pub mod pool;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod reliable;
+#[cfg(feature = "secure")]
+pub mod secure;
+#[cfg(feature = "signing")]
+pub mod signing;
+pub mod statistics;
+pub mod telemetry;
+pub mod timesync;
 pub mod traits;
+#[cfg(feature = "std")]
+pub mod transport;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum Status {
     Ok,
     PacketReceived,
@@ -19,6 +64,62 @@ pub enum Status {
     UnspecifiedError,
     UnrecognizedRequest,
     InvalidDataLengthDetected,
+    /// Compressed data is malformed, see `compress`
+    DecompressionFailed,
+    /// A `secure` packet failed authentication, it was tampered with or sealed with another key
+    DecryptionFailed,
+    /// A `secure` packet reused a counter that was already accepted
+    ReplayDetected,
+    /// The packet was for another address and was skipped without checking the checksum, see
+    /// `address`
+    NotAddressed,
+}
+
+impl Status {
+    /// The `Status` whose discriminant is `value`, the inverse of `status as u8`
+    pub fn from_u8(value: u8) -> Option<Status> {
+        match value {
+            0 => Some(Status::Ok),
+            1 => Some(Status::PacketReceived),
+            2 => Some(Status::PacketBuilding),
+            3 => Some(Status::GetByteFinished),
+            4 => Some(Status::VersionLength),
+            5 => Some(Status::PacketOverflow),
+            6 => Some(Status::HeaderBytesNotFound),
+            7 => Some(Status::GetByteIssue),
+            8 => Some(Status::ChecksumError),
+            9 => Some(Status::UnspecifiedError),
+            10 => Some(Status::UnrecognizedRequest),
+            11 => Some(Status::InvalidDataLengthDetected),
+            12 => Some(Status::DecompressionFailed),
+            13 => Some(Status::DecryptionFailed),
+            14 => Some(Status::ReplayDetected),
+            15 => Some(Status::NotAddressed),
+            _ => None,
+        }
+    }
+
+    /// Name of the variant, e.g. "ChecksumError"
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Ok => "Ok",
+            Status::PacketReceived => "PacketReceived",
+            Status::PacketBuilding => "PacketBuilding",
+            Status::GetByteFinished => "GetByteFinished",
+            Status::VersionLength => "VersionLength",
+            Status::PacketOverflow => "PacketOverflow",
+            Status::HeaderBytesNotFound => "HeaderBytesNotFound",
+            Status::GetByteIssue => "GetByteIssue",
+            Status::ChecksumError => "ChecksumError",
+            Status::UnspecifiedError => "UnspecifiedError",
+            Status::UnrecognizedRequest => "UnrecognizedRequest",
+            Status::InvalidDataLengthDetected => "InvalidDataLengthDetected",
+            Status::DecompressionFailed => "DecompressionFailed",
+            Status::DecryptionFailed => "DecryptionFailed",
+            Status::ReplayDetected => "ReplayDetected",
+            Status::NotAddressed => "NotAddressed",
+        }
+    }
 }
 
 const FLEM_ID_NAME_SIZE: usize = 25;
@@ -142,18 +243,147 @@ pub struct Packet<const T: usize> {
     internal_counter: u32,
     data_length_counter: usize,
     status: Status,
+    /// Receive filter, see `address`
+    local_address: Option<u8>,
+    /// The packet being received is for another address
+    skipping: bool,
 }
 
 pub mod response {
     pub const ASYNC: u16 = 0x0000;
     pub const SUCCESS: u16 = 0x0001;
+    /// Flag OR'd into a response code below 0x8000 when the data is compressed, see `compress`.
+    /// Since 0.7.0, codes 0x4000 - 0x7FFF are reserved for it and application response codes have
+    /// to stay below 0x4000.
+    pub const COMPRESSED: u16 = 0x4000;
+    /// A parameter value is outside its min / max, see `param`
+    pub const OUT_OF_RANGE: u16 = 0xFFF7;
+    /// No parameter has the requested ID, see `param`
+    pub const UNKNOWN_PARAMETER: u16 = 0xFFF8;
+    /// The request data is malformed or has the wrong type
+    pub const INVALID_ARGUMENT: u16 = 0xFFF9;
+    /// The request touches memory outside the regions the device allows, see `memory`
+    pub const ACCESS_DENIED: u16 = 0xFFFA;
+    /// A DFU request failed, the data holds the `dfu::DfuError` and the device's `dfu::DfuStatus`
+    pub const DFU_ERROR: u16 = 0xFFFB;
+    /// A privileged request was sent without authenticating first, or authentication failed, see `auth`
+    pub const UNAUTHORIZED: u16 = 0xFFFC;
     pub const UNKNOWN_REQUEST: u16 = 0xFFFE;
     pub const CHECKSUM_ERROR: u16 = 0xFFFF;
+
+    /// True if `response` has the `COMPRESSED` flag set. Error codes (0x8000 and up) are never compressed.
+    pub fn is_compressed(response: u16) -> bool {
+        response & 0xC000 == COMPRESSED
+    }
+
+    /// Name of a pre-defined response, used when printing packets
+    pub fn name(response: u16) -> Option<&'static str> {
+        match response {
+            ASYNC => Some("ASYNC"),
+            SUCCESS => Some("SUCCESS"),
+            code if code == SUCCESS | COMPRESSED => Some("SUCCESS | COMPRESSED"),
+            OUT_OF_RANGE => Some("OUT_OF_RANGE"),
+            UNKNOWN_PARAMETER => Some("UNKNOWN_PARAMETER"),
+            INVALID_ARGUMENT => Some("INVALID_ARGUMENT"),
+            ACCESS_DENIED => Some("ACCESS_DENIED"),
+            DFU_ERROR => Some("DFU_ERROR"),
+            UNAUTHORIZED => Some("UNAUTHORIZED"),
+            UNKNOWN_REQUEST => Some("UNKNOWN_REQUEST"),
+            CHECKSUM_ERROR => Some("CHECKSUM_ERROR"),
+            _ => None,
+        }
+    }
 }
 
 /// Pre-defined requests
 pub mod request {
     pub const ID: u16 = 0x0001;
+    /// Positive acknowledgement of a packet sent through the `reliable` layer
+    pub const ACK: u16 = 0xFF01;
+    /// Negative acknowledgement, asks the partner to retransmit a `reliable` packet
+    pub const NACK: u16 = 0xFF02;
+    /// Periodic keep-alive, see `heartbeat`
+    pub const HEARTBEAT: u16 = 0xFF03;
+    /// Asks the device for an authentication nonce, see `auth`
+    pub const AUTH_CHALLENGE: u16 = 0xFF04;
+    /// Carries the host's HMAC over the nonce, see `auth`
+    pub const AUTH_RESPONSE: u16 = 0xFF05;
+    /// Starts or resumes a firmware update, see `dfu`
+    pub const DFU_BEGIN: u16 = 0xFF06;
+    /// Writes a chunk of the firmware image at an offset
+    pub const DFU_WRITE: u16 = 0xFF07;
+    /// Reports the firmware update progress
+    pub const DFU_STATUS: u16 = 0xFF08;
+    /// Checks the CRC32 of the written firmware image
+    pub const DFU_VERIFY: u16 = 0xFF09;
+    /// Marks the verified firmware image for boot
+    pub const DFU_COMMIT: u16 = 0xFF0A;
+    /// Cancels the firmware update
+    pub const DFU_ABORT: u16 = 0xFF0B;
+    /// Sends the signed image manifest, see `signing`
+    pub const DFU_MANIFEST: u16 = 0xFF0C;
+    /// Reads device memory, see `memory`
+    pub const MEMORY_READ: u16 = 0xFF0D;
+    /// Writes device memory
+    pub const MEMORY_WRITE: u16 = 0xFF0E;
+    /// Lists the memory regions the device allows access to
+    pub const MEMORY_REGIONS: u16 = 0xFF0F;
+    /// Describes a parameter by index, see `param`
+    pub const PARAM_INFO: u16 = 0xFF10;
+    /// Reads a parameter by ID
+    pub const PARAM_GET: u16 = 0xFF11;
+    /// Sets a parameter by ID
+    pub const PARAM_SET: u16 = 0xFF12;
+    /// Subscribes to, or changes the period of, a telemetry channel, see `telemetry`
+    pub const TELEMETRY_SUBSCRIBE: u16 = 0xFF13;
+    /// Stops a telemetry channel
+    pub const TELEMETRY_UNSUBSCRIBE: u16 = 0xFF14;
+    /// A telemetry sample, sent by the device as `response::ASYNC`
+    pub const TELEMETRY_DATA: u16 = 0xFF15;
+    /// Time synchronization exchange, see `timesync`
+    pub const TIME_SYNC: u16 = 0xFF16;
+    /// Tells the device which host time matches a device time
+    pub const TIME_SET: u16 = 0xFF17;
+    /// A fragment of a multiplexed stream, see `mux`
+    pub const STREAM_DATA: u16 = 0xFF18;
+    /// Grants the peer credits to send more fragments on a stream
+    pub const STREAM_CREDIT: u16 = 0xFF19;
+    /// Reads one entry of the device's description of its requests, see `introspect`
+    pub const DESCRIBE: u16 = 0xFF1A;
+
+    /// Name of a pre-defined request, used when printing packets
+    pub fn name(request: u16) -> Option<&'static str> {
+        match request {
+            ID => Some("ID"),
+            ACK => Some("ACK"),
+            NACK => Some("NACK"),
+            HEARTBEAT => Some("HEARTBEAT"),
+            AUTH_CHALLENGE => Some("AUTH_CHALLENGE"),
+            AUTH_RESPONSE => Some("AUTH_RESPONSE"),
+            DFU_BEGIN => Some("DFU_BEGIN"),
+            DFU_WRITE => Some("DFU_WRITE"),
+            DFU_STATUS => Some("DFU_STATUS"),
+            DFU_VERIFY => Some("DFU_VERIFY"),
+            DFU_COMMIT => Some("DFU_COMMIT"),
+            DFU_ABORT => Some("DFU_ABORT"),
+            DFU_MANIFEST => Some("DFU_MANIFEST"),
+            MEMORY_READ => Some("MEMORY_READ"),
+            MEMORY_WRITE => Some("MEMORY_WRITE"),
+            MEMORY_REGIONS => Some("MEMORY_REGIONS"),
+            PARAM_INFO => Some("PARAM_INFO"),
+            PARAM_GET => Some("PARAM_GET"),
+            PARAM_SET => Some("PARAM_SET"),
+            TELEMETRY_SUBSCRIBE => Some("TELEMETRY_SUBSCRIBE"),
+            TELEMETRY_UNSUBSCRIBE => Some("TELEMETRY_UNSUBSCRIBE"),
+            TELEMETRY_DATA => Some("TELEMETRY_DATA"),
+            TIME_SYNC => Some("TIME_SYNC"),
+            TIME_SET => Some("TIME_SET"),
+            STREAM_DATA => Some("STREAM_DATA"),
+            STREAM_CREDIT => Some("STREAM_CREDIT"),
+            DESCRIBE => Some("DESCRIBE"),
+            _ => None,
+        }
+    }
 }
 
 pub const FLEM_HEADER_SIZE: usize = 10;
@@ -193,7 +423,7 @@ impl<const T: usize> Packet<T> {
     ///
     /// }
     /// ```
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         assert!(T < u16::MAX as usize, "<T> should be u16::MAX or less"); // Bounds check T, must be less than u16::MAX
         return Self {
             header: 0,
@@ -205,6 +435,8 @@ impl<const T: usize> Packet<T> {
             internal_counter: 0,
             data_length_counter: 0,
             status: Status::Ok,
+            local_address: None,
+            skipping: false,
         };
     }
 
@@ -306,6 +538,7 @@ impl<const T: usize> Packet<T> {
     pub fn pack(&mut self) {
         self.checksum(true);
         self.header = FLEM_HEADER;
+        flem_trace!("FLEM packet packed for transmit: {:?}", self);
     }
 
     /// Returns a copy of the data part of the packet as a byte array
@@ -313,6 +546,28 @@ impl<const T: usize> Packet<T> {
         return self.data;
     }
 
+    /// Returns the valid part of the data buffer, i.e. the first `length` bytes, without copying.
+    /// A length over `T`, e.g. after `InvalidDataLengthDetected`, is capped at `T`.
+    pub fn payload(&self) -> &[u8] {
+        // See `data_mut` for why the field is reached through a raw pointer
+        let data = unsafe { &*::core::ptr::addr_of!(self.data) };
+        &data[..(self.length as usize).min(T)]
+    }
+
+    /// Mutable access to the whole data buffer, for in-place transforms of packed data
+    pub(crate) fn data_mut(&mut self) -> &mut [u8] {
+        // `&mut self.data` is rejected in a packed struct with a generic `T` (E0793). The field is
+        // aligned anyway, `[u8; T]` has an alignment of 1.
+        unsafe { &mut *::core::ptr::addr_of_mut!(self.data) }
+    }
+
+    /// Mutable access to the valid part of the data buffer
+    #[cfg(feature = "secure")]
+    pub(crate) fn payload_mut(&mut self) -> &mut [u8] {
+        let length = self.length as usize;
+        &mut self.data_mut()[..length]
+    }
+
     /// Adds data to a packet if there is room.
     pub fn add_data(&mut self, data: &[u8]) -> Result<(), Status> {
         if data.len() + self.length as usize > T {
@@ -343,6 +598,7 @@ impl<const T: usize> Packet<T> {
     /// - PacketOverflow - Data is being added beyond length of the packet
     /// - PacketBuilding - This should be the default most of the time and indicates the packet is being built without issues so far.
     /// - PacketReceived - All data bytes have been received and the checksum has been validated
+    /// - NotAddressed - With a local address set, the packet was for another address, see `address`
     ///
     /// # Arguments
     ///
@@ -392,6 +648,18 @@ impl<const T: usize> Packet<T> {
     /// }
     /// ```
     pub fn construct(&mut self, byte: u8) -> Result<(), Status> {
+        #[cfg(any(feature = "log", feature = "tracing"))]
+        let previous_status = self.status;
+
+        let result = self.construct_byte(byte);
+
+        #[cfg(any(feature = "log", feature = "tracing"))]
+        self.log_construct(previous_status, result);
+
+        result
+    }
+
+    fn construct_byte(&mut self, byte: u8) -> Result<(), Status> {
         let local_internal_counter = self.internal_counter;
 
         match local_internal_counter {
@@ -436,6 +704,11 @@ impl<const T: usize> Packet<T> {
                 self.length |= (byte as u16) << 8;
                 self.data_length_counter = 0;
                 if self.length == 0 {
+                    if self.local_address.is_some() {
+                        // No room for a destination, so it can't be for us
+                        self.status = Status::NotAddressed;
+                        return Err(self.status);
+                    }
                     if self.validate() {
                         self.status = Status::PacketReceived;
                         return Ok(());
@@ -451,14 +724,26 @@ impl<const T: usize> Packet<T> {
                 }
             }
             i if (FLEM_HEADER_SIZE as u32 <= i && i < FLEM_HEADER_SIZE as u32 + T as u32) => {
-                if self.data_length_counter < self.length as usize {
-                    self.data[self.data_length_counter] = byte;
-                } else {
+                if self.data_length_counter >= self.length as usize {
                     self.status = Status::PacketOverflow;
                     return Err(self.status);
                 }
+                if self.data_length_counter == 0 {
+                    // The first data byte is the destination of addressed packets
+                    self.skipping = matches!(
+                        self.local_address,
+                        Some(local) if byte != local && byte != address::BROADCAST
+                    );
+                }
+                if !self.skipping {
+                    self.data[self.data_length_counter] = byte;
+                }
                 self.data_length_counter += 1;
                 if self.length as usize == self.data_length_counter {
+                    if self.skipping {
+                        self.status = Status::NotAddressed;
+                        return Err(self.status);
+                    }
                     if self.validate() {
                         self.status = Status::PacketReceived;
                         return Ok(());
@@ -480,6 +765,24 @@ impl<const T: usize> Packet<T> {
         Err(self.status)
     }
 
+    #[cfg(any(feature = "log", feature = "tracing"))]
+    fn log_construct(&self, previous_status: Status, result: Result<(), Status>) {
+        match result {
+            Ok(_) => {
+                flem_debug!("FLEM packet received: {:?}", self);
+            }
+            Err(Status::PacketBuilding) | Err(Status::NotAddressed) => {}
+            Err(Status::HeaderBytesNotFound) => {
+                if previous_status != Status::HeaderBytesNotFound {
+                    flem_debug!("FLEM receiver lost sync, searching for header bytes");
+                }
+            }
+            Err(status) => {
+                flem_warn!("FLEM parse error {:?}: {:?}", status, self);
+            }
+        }
+    }
+
     /// This function treats the entire packet as a byte array and uses internal
     /// counters to determine the next byte. Keep calling this until either an
     /// error occurs or status is Status::GetByteFinished.
@@ -622,6 +925,13 @@ impl<const T: usize> Packet<T> {
         return stream;
     }
 
+    /// Like `bytes`, but a length over `T`, e.g. after `InvalidDataLengthDetected`, is capped at `T`
+    #[cfg(any(feature = "std", feature = "ffi"))]
+    pub(crate) fn wire_bytes(&self) -> &[u8] {
+        let length = FLEM_HEADER_SIZE + self.payload().len();
+        unsafe { ::core::slice::from_raw_parts((self as *const Packet<T>) as *const u8, length) }
+    }
+
     /// Computes a CRC16 IBM style checksum on the packet, except the header
     /// and checksum bytes
     pub fn checksum(&mut self, store: bool) -> u16 {
@@ -655,6 +965,7 @@ impl<const T: usize> Packet<T> {
         self.internal_counter = 0;
         self.status = Status::Ok;
         self.data_length_counter = 0;
+        self.skipping = false;
     }
 
     /// Resets the packet. The data array is cleared only if clear_data is true. **Packets should be
@@ -697,6 +1008,50 @@ impl<const T: usize> Packet<T> {
     }
 }
 
+#[cfg(feature = "defmt")]
+impl<const T: usize> defmt::Format for Packet<T> {
+    fn format(&self, f: defmt::Formatter) {
+        let header = self.header;
+        let checksum = self.checksum;
+        let request = self.request;
+        let response = self.response;
+        let length = self.length;
+
+        defmt::write!(
+            f,
+            "Packet {{ header: {=u16}, checksum: {=u16}, request: {=u16}, response: {=u16}, length: {=u16}, status: {} }}",
+            header,
+            checksum,
+            request,
+            response,
+            length,
+            self.status
+        );
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for DataId {
+    fn format(&self, f: defmt::Formatter) {
+        let mut name = [0u8; FLEM_ID_NAME_SIZE];
+        let mut name_length = 0;
+        for c in self.name.iter().take_while(|c| **c != '\0') {
+            name[name_length] = *c as u8;
+            name_length += 1;
+        }
+
+        defmt::write!(
+            f,
+            "DataId {{ name: {=[u8]:a}, version: {=u8}.{=u8}.{=u8}, max_packet_size: {=u16} }}",
+            &name[..name_length],
+            self.major,
+            self.minor,
+            self.patch,
+            self.max_packet_size
+        );
+    }
+}
+
 impl<const T: usize> Debug for Packet<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         let header = self.header;
@@ -0,0 +1,32 @@
+//! Internal diagnostics macros. Each forwards to `log` and/or `tracing` when the matching feature is
+//! enabled and compiles to nothing otherwise, so default builds stay dependency-free.
+
+// Some macros are only invoked from code behind the `log` / `tracing` features
+#![allow(unused_macros)]
+
+macro_rules! flem_warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        ::log::warn!($($arg)*);
+        #[cfg(feature = "tracing")]
+        ::tracing::warn!($($arg)*);
+    };
+}
+
+macro_rules! flem_debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        ::log::debug!($($arg)*);
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!($($arg)*);
+    };
+}
+
+macro_rules! flem_trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        ::log::trace!($($arg)*);
+        #[cfg(feature = "tracing")]
+        ::tracing::trace!($($arg)*);
+    };
+}
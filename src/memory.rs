@@ -0,0 +1,289 @@
+//! Memory peek / poke service for debugging.
+//!
+//! | Request                   | Data                          | Reply                                |
+//! |---------------------------|-------------------------------|--------------------------------------|
+//! | `request::MEMORY_READ`    | address (u32) + length (u16)  | Up to `length` bytes, capped to `T`  |
+//! | `request::MEMORY_WRITE`   | address (u32) + bytes         | -                                    |
+//! | `request::MEMORY_REGIONS` | first region index (u8)       | Regions from that index that fit     |
+//!
+//! Regions are encoded as start (u32), length (u32) and flags (u8, bit 0 set if writable). Every
+//! access must fall entirely inside one region of the device's allow-list, otherwise it is refused
+//! with `response::ACCESS_DENIED` before memory is touched. Malformed requests get
+//! `response::INVALID_ARGUMENT`.
+//!
+//! `MemoryService` is the `no_std` device side. Memory is reached through the `MemoryAccess` trait:
+//! `RawMemory` reads and writes the address space directly, other implementations can map addresses
+//! onto anything else. With features = ["std"], `read`, `write` and `regions` are the host API, and
+//! `read` splits reads of any length into packet sized requests.
+
+#[cfg(feature = "std")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use crate::client::{Client, ClientError};
+use crate::{request, response, Packet};
+
+/// Bytes of the address at the start of read and write requests
+pub const ADDRESS_SIZE: usize = 4;
+/// Bytes of an encoded `MemoryRegion`
+pub const REGION_SIZE: usize = 9;
+
+const WRITABLE_FLAG: u8 = 0x01;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub start: u32,
+    pub length: u32,
+    pub writable: bool,
+}
+
+impl MemoryRegion {
+    pub const fn read_only(start: u32, length: u32) -> Self {
+        MemoryRegion {
+            start,
+            length,
+            writable: false,
+        }
+    }
+
+    pub const fn read_write(start: u32, length: u32) -> Self {
+        MemoryRegion {
+            start,
+            length,
+            writable: true,
+        }
+    }
+
+    /// True if `length` bytes at `address` are all inside the region
+    pub fn contains(&self, address: u32, length: usize) -> bool {
+        let end = address as u64 + length as u64;
+        address >= self.start && end <= self.start as u64 + self.length as u64
+    }
+
+    pub fn to_bytes(&self) -> [u8; REGION_SIZE] {
+        let mut bytes = [0u8; REGION_SIZE];
+        bytes[0..4].copy_from_slice(&self.start.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.length.to_le_bytes());
+        bytes[8] = if self.writable { WRITABLE_FLAG } else { 0 };
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != REGION_SIZE {
+            return None;
+        }
+        Some(MemoryRegion {
+            start: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            length: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            writable: bytes[8] & WRITABLE_FLAG != 0,
+        })
+    }
+}
+
+/// Access to device memory. Only called for ranges inside an allowed region.
+pub trait MemoryAccess {
+    fn read(&mut self, address: u32, buffer: &mut [u8]);
+    fn write(&mut self, address: u32, data: &[u8]);
+}
+
+/// Reads and writes the address space directly with volatile accesses
+pub struct RawMemory {
+    _private: (),
+}
+
+impl RawMemory {
+    /// # Safety
+    ///
+    /// Every region given to the `MemoryService` using this must be valid to read byte by byte at
+    /// any time, and writable regions valid to write, e.g. RAM or memory mapped registers without
+    /// read side effects.
+    pub const unsafe fn new() -> Self {
+        RawMemory { _private: () }
+    }
+}
+
+impl MemoryAccess for RawMemory {
+    fn read(&mut self, address: u32, buffer: &mut [u8]) {
+        for (index, byte) in buffer.iter_mut().enumerate() {
+            // Safe per the contract of `RawMemory::new`, the range was checked against the regions
+            *byte = unsafe { core::ptr::read_volatile((address as usize + index) as *const u8) };
+        }
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) {
+        for (index, byte) in data.iter().enumerate() {
+            unsafe { core::ptr::write_volatile((address as usize + index) as *mut u8, *byte) };
+        }
+    }
+}
+
+/// Device side memory service, restricted to an allow-list of regions
+pub struct MemoryService<'a, M: MemoryAccess> {
+    regions: &'a [MemoryRegion],
+    memory: M,
+}
+
+impl<'a, M: MemoryAccess> MemoryService<'a, M> {
+    pub const fn new(regions: &'a [MemoryRegion], memory: M) -> Self {
+        MemoryService { regions, memory }
+    }
+
+    pub fn regions(&self) -> &[MemoryRegion] {
+        self.regions
+    }
+
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut M {
+        &mut self.memory
+    }
+
+    fn allowed(&self, address: u32, length: usize, write: bool) -> bool {
+        self.regions
+            .iter()
+            .any(|region| region.contains(address, length) && (region.writable || !write))
+    }
+
+    /// Handles memory requests and packs the reply. Returns false, leaving `reply` untouched, for
+    /// requests that aren't part of the memory service.
+    pub fn handle<const T: usize>(&mut self, packet: &Packet<T>, reply: &mut Packet<T>) -> bool {
+        let code = packet.get_request();
+        let payload = packet.payload();
+
+        let _ = match code {
+            request::MEMORY_READ => {
+                if payload.len() != ADDRESS_SIZE + 2 {
+                    reply.pack_error(code, response::INVALID_ARGUMENT, &[])
+                } else {
+                    let address =
+                        u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                    let length = (u16::from_le_bytes([payload[4], payload[5]]) as usize).min(T);
+                    if self.allowed(address, length, false) {
+                        reply.reset_lazy();
+                        reply.set_request(code);
+                        reply.set_response(response::SUCCESS);
+                        self.memory.read(address, &mut reply.data_mut()[..length]);
+                        reply.length = length as u16;
+                        reply.pack();
+                        Ok(())
+                    } else {
+                        reply.pack_error(code, response::ACCESS_DENIED, &[])
+                    }
+                }
+            }
+            request::MEMORY_WRITE => {
+                if payload.len() <= ADDRESS_SIZE {
+                    reply.pack_error(code, response::INVALID_ARGUMENT, &[])
+                } else {
+                    let (address, data) = payload.split_at(ADDRESS_SIZE);
+                    let address =
+                        u32::from_le_bytes([address[0], address[1], address[2], address[3]]);
+                    if self.allowed(address, data.len(), true) {
+                        self.memory.write(address, data);
+                        reply.pack_data(code, &[])
+                    } else {
+                        reply.pack_error(code, response::ACCESS_DENIED, &[])
+                    }
+                }
+            }
+            request::MEMORY_REGIONS => match payload {
+                [first] => {
+                    reply.reset_lazy();
+                    reply.set_request(code);
+                    reply.set_response(response::SUCCESS);
+                    for region in self
+                        .regions
+                        .iter()
+                        .skip(*first as usize)
+                        .take(T / REGION_SIZE)
+                    {
+                        let _ = reply.add_data(&region.to_bytes());
+                    }
+                    reply.pack();
+                    Ok(())
+                }
+                _ => reply.pack_error(code, response::INVALID_ARGUMENT, &[]),
+            },
+            _ => return false,
+        };
+        true
+    }
+}
+
+/// Returns `address + offset`, failing before anything is sent if the range wraps past `u32::MAX`
+#[cfg(feature = "std")]
+fn offset_address(address: u32, offset: usize) -> Result<u32, ClientError> {
+    u32::try_from(offset)
+        .ok()
+        .and_then(|offset| address.checked_add(offset))
+        .ok_or(ClientError::AddressOverflow)
+}
+
+/// Reads `length` bytes at `address`, in as many requests as needed (requires features = ["std"])
+#[cfg(feature = "std")]
+pub fn read<const T: usize>(
+    client: &Client<T>,
+    address: u32,
+    length: usize,
+) -> Result<Vec<u8>, ClientError> {
+    offset_address(address, length.saturating_sub(1))?;
+    let mut data = Vec::with_capacity(length);
+
+    while data.len() < length {
+        let remaining = (length - data.len()).min(T).min(u16::MAX as usize) as u16;
+        let chunk_address = offset_address(address, data.len())?;
+        let mut request = [0u8; ADDRESS_SIZE + 2];
+        request[..ADDRESS_SIZE].copy_from_slice(&chunk_address.to_le_bytes());
+        request[ADDRESS_SIZE..].copy_from_slice(&remaining.to_le_bytes());
+
+        let reply = client.call(request::MEMORY_READ, &request)?;
+        // The device may cap reads to a smaller packet size, but never returns nothing
+        if reply.payload().is_empty() || reply.payload().len() > remaining as usize {
+            return Err(ClientError::InvalidReply);
+        }
+        data.extend_from_slice(reply.payload());
+    }
+
+    Ok(data)
+}
+
+/// Writes `data` at `address`, in as many requests as needed (requires features = ["std"])
+#[cfg(feature = "std")]
+pub fn write<const T: usize>(
+    client: &Client<T>,
+    address: u32,
+    data: &[u8],
+) -> Result<(), ClientError> {
+    offset_address(address, data.len().saturating_sub(1))?;
+    let chunk_size = T.saturating_sub(ADDRESS_SIZE).max(1);
+    for (index, chunk) in data.chunks(chunk_size).enumerate() {
+        let chunk_address = offset_address(address, index * chunk_size)?;
+        let mut request = Vec::with_capacity(ADDRESS_SIZE + chunk.len());
+        request.extend_from_slice(&chunk_address.to_le_bytes());
+        request.extend_from_slice(chunk);
+        client.call(request::MEMORY_WRITE, &request)?;
+    }
+    Ok(())
+}
+
+/// Lists the regions the device allows access to (requires features = ["std"])
+#[cfg(feature = "std")]
+pub fn regions<const T: usize>(client: &Client<T>) -> Result<Vec<MemoryRegion>, ClientError> {
+    let mut regions = Vec::new();
+
+    loop {
+        let first = u8::try_from(regions.len()).map_err(|_| ClientError::InvalidReply)?;
+        let reply = client.call(request::MEMORY_REGIONS, &[first])?;
+        if reply.payload().is_empty() {
+            return Ok(regions);
+        }
+        for bytes in reply.payload().chunks(REGION_SIZE) {
+            regions.push(MemoryRegion::from_bytes(bytes).ok_or(ClientError::InvalidReply)?);
+        }
+    }
+}
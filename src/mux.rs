@@ -0,0 +1,479 @@
+//! Logical streams multiplexed over a single link.
+//!
+//! Each packet carries a stream ID, so a command channel, a log stream and bulk transfers can share
+//! one UART. Both frames are sent as `response::ASYNC`:
+//!
+//! | Request                  | Data                                  |
+//! |--------------------------|---------------------------------------|
+//! | `request::STREAM_DATA`   | stream (u8), flags (u8), fragment     |
+//! | `request::STREAM_CREDIT` | stream (u8), credits (u16)            |
+//!
+//! Messages longer than a packet are split into fragments; flags bit 0 marks the last fragment of a
+//! message. Because streams are scheduled one fragment at a time, a command never waits behind a
+//! whole bulk message, only behind the fragments already handed to the link.
+//!
+//! Flow control is per stream and credit based: a receiver grants its peer `window` fragments when
+//! the stream opens and grants more as the application consumes messages. A sender without credits
+//! on one stream keeps sending on the others. The window of a bulk stream also bounds how many of
+//! its fragments can queue up ahead of a command on the link.
+//!
+//! `Scheduler` is the `no_std` core: it tracks credits, picks the next stream to send from (highest
+//! `priority` first, round robin among equal priorities) and decides when to grant credits. A stream
+//! passed over `STARVATION_LIMIT` times in a row goes next whatever its priority, so a busy command
+//! stream slows bulk transfers down but never stalls them.
+//! `Frame`, `pack_fragment` and `pack_credit` do the framing. With features = ["std"],
+//! `Multiplexer` runs all of this on a thread on top of a `Channel` and hands out a `Stream`
+//! endpoint per stream.
+
+#[cfg(feature = "std")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "std")]
+use alloc::{collections::VecDeque, vec::Vec};
+#[cfg(feature = "std")]
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{request, response, Packet, Status};
+
+/// Bytes of the stream ID and flags in front of every fragment
+pub const STREAM_HEADER_SIZE: usize = 2;
+
+/// Times in a row a stream with data and credits can be passed over for higher priority streams
+/// before it is served anyway
+pub const STARVATION_LIMIT: u8 = 8;
+
+const LAST_FRAGMENT: u8 = 0x01;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StreamConfig {
+    pub id: u8,
+    /// Streams with a higher priority are served first, up to `STARVATION_LIMIT`
+    pub priority: u8,
+    /// Fragments the peer may send before it has to wait for more credits
+    pub window: u16,
+}
+
+impl StreamConfig {
+    pub const fn new(id: u8, priority: u8, window: u16) -> Self {
+        StreamConfig {
+            id,
+            priority,
+            window,
+        }
+    }
+}
+
+/// A decoded multiplexer packet
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Frame<'p> {
+    Data {
+        stream: u8,
+        /// Last fragment of a message
+        last: bool,
+        data: &'p [u8],
+    },
+    Credit {
+        stream: u8,
+        credits: u16,
+    },
+}
+
+impl<'p> Frame<'p> {
+    /// Decodes `packet`, or returns `None` if it isn't a multiplexer packet
+    pub fn parse<const T: usize>(packet: &'p Packet<T>) -> Option<Self> {
+        match (packet.get_request(), packet.payload()) {
+            (request::STREAM_DATA, [stream, flags, data @ ..]) => Some(Frame::Data {
+                stream: *stream,
+                last: flags & LAST_FRAGMENT != 0,
+                data,
+            }),
+            (request::STREAM_CREDIT, [stream, c0, c1]) => Some(Frame::Credit {
+                stream: *stream,
+                credits: u16::from_le_bytes([*c0, *c1]),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Packs one fragment of a message on `stream`. `data` can be at most `T - STREAM_HEADER_SIZE`
+/// bytes.
+pub fn pack_fragment<const T: usize>(
+    packet: &mut Packet<T>,
+    stream: u8,
+    last: bool,
+    data: &[u8],
+) -> Result<(), Status> {
+    let flags = if last { LAST_FRAGMENT } else { 0 };
+    packet.reset_lazy();
+    packet.set_request(request::STREAM_DATA);
+    packet.set_response(response::ASYNC);
+    packet.add_data(&[stream, flags])?;
+    packet.add_data(data)?;
+    packet.pack();
+    Ok(())
+}
+
+/// Packs a grant of `credits` more fragments on `stream`
+pub fn pack_credit<const T: usize>(
+    packet: &mut Packet<T>,
+    stream: u8,
+    credits: u16,
+) -> Result<(), Status> {
+    let credits = credits.to_le_bytes();
+    packet.reset_lazy();
+    packet.set_request(request::STREAM_CREDIT);
+    packet.set_response(response::ASYNC);
+    packet.add_data(&[stream, credits[0], credits[1]])?;
+    packet.pack();
+    Ok(())
+}
+
+/// Flow control state of one stream, kept by `Scheduler`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StreamState {
+    config: StreamConfig,
+    /// Fragments we may still send
+    credits: u16,
+    /// Fragments received and consumed since the last grant
+    consumed: u16,
+    /// The initial window has been granted
+    opened: bool,
+    /// Times in a row the stream could have sent but another stream was picked
+    passed_over: u8,
+}
+
+impl StreamState {
+    pub const fn new(config: StreamConfig) -> Self {
+        StreamState {
+            config,
+            credits: 0,
+            consumed: 0,
+            opened: false,
+            passed_over: 0,
+        }
+    }
+
+    pub fn config(&self) -> &StreamConfig {
+        &self.config
+    }
+}
+
+/// Credit accounting and fragment scheduling over a caller provided set of streams
+pub struct Scheduler<'a> {
+    streams: &'a mut [StreamState],
+    /// Round robin start
+    next: usize,
+}
+
+impl<'a> Scheduler<'a> {
+    pub fn new(streams: &'a mut [StreamState]) -> Self {
+        Scheduler { streams, next: 0 }
+    }
+
+    fn index(&self, id: u8) -> Option<usize> {
+        self.streams
+            .iter()
+            .position(|stream| stream.config.id == id)
+    }
+
+    /// Fragments that may still be sent on stream `id`
+    pub fn credits(&self, id: u8) -> Option<u16> {
+        self.index(id).map(|index| self.streams[index].credits)
+    }
+
+    /// Adds credits granted by the peer. Grants for unknown streams are ignored.
+    pub fn add_credits(&mut self, id: u8, credits: u16) {
+        if let Some(index) = self.index(id) {
+            let stream = &mut self.streams[index];
+            stream.credits = stream.credits.saturating_add(credits);
+        }
+    }
+
+    /// Picks the stream to send the next fragment from, among those with credits for which `ready`
+    /// returns true, and takes one of its credits. Returns `None` if nothing can be sent.
+    pub fn next<F: FnMut(u8) -> bool>(&mut self, mut ready: F) -> Option<u8> {
+        let count = self.streams.len();
+        let mut best: Option<usize> = None;
+        let mut starved: Option<usize> = None;
+
+        for step in 0..count {
+            let index = (self.next + step) % count;
+            let stream = &mut self.streams[index];
+            if stream.credits == 0 || !ready(stream.config.id) {
+                stream.passed_over = 0;
+                continue;
+            }
+            if stream.passed_over >= STARVATION_LIMIT && starved.is_none() {
+                starved = Some(index);
+            }
+            stream.passed_over = stream.passed_over.saturating_add(1);
+
+            let stream = &self.streams[index];
+            // Ties keep the earliest in round robin order
+            if best.map_or(true, |best| {
+                stream.config.priority > self.streams[best].config.priority
+            }) {
+                best = Some(index);
+            }
+        }
+
+        let index = starved.or(best)?;
+        self.streams[index].passed_over = 0;
+        self.streams[index].credits -= 1;
+        self.next = (index + 1) % count;
+        Some(self.streams[index].config.id)
+    }
+
+    /// Records that the application consumed `fragments` received on stream `id`
+    pub fn consumed(&mut self, id: u8, fragments: u16) {
+        if let Some(index) = self.index(id) {
+            let stream = &mut self.streams[index];
+            stream.consumed = stream.consumed.saturating_add(fragments);
+        }
+    }
+
+    /// Returns the next grant to send the peer as (stream, credits): the whole window when a stream
+    /// opens, then the consumed fragments once they add up to half the window.
+    pub fn grant(&mut self) -> Option<(u8, u16)> {
+        for stream in self.streams.iter_mut() {
+            if !stream.opened {
+                stream.opened = true;
+                return Some((stream.config.id, stream.config.window));
+            }
+            if stream.consumed > 0 && stream.consumed >= (stream.config.window / 2).max(1) {
+                let credits = stream.consumed;
+                stream.consumed = 0;
+                return Some((stream.config.id, credits));
+            }
+        }
+        None
+    }
+}
+
+/// (requires features = ["std"])
+#[cfg(feature = "std")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MuxError {
+    /// The multiplexer was stopped
+    Disconnected,
+}
+
+/// Requests from `Stream` endpoints to the multiplexer thread
+#[cfg(feature = "std")]
+enum Control {
+    Message(u8, Vec<u8>),
+    Consumed(u8),
+}
+
+/// One stream's send and receive endpoint (requires features = ["std"])
+#[cfg(feature = "std")]
+pub struct Stream {
+    id: u8,
+    control: Sender<Control>,
+    messages: Receiver<Vec<u8>>,
+}
+
+#[cfg(feature = "std")]
+impl Stream {
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Queues `message` to be sent. Never blocks; the message goes out as credits allow.
+    pub fn send(&self, message: &[u8]) -> Result<(), MuxError> {
+        self.control
+            .send(Control::Message(self.id, message.to_vec()))
+            .map_err(|_| MuxError::Disconnected)
+    }
+
+    fn consume(&self, message: Vec<u8>) -> Vec<u8> {
+        // Returning the credit can only fail once the multiplexer is gone
+        let _ = self.control.send(Control::Consumed(self.id));
+        message
+    }
+
+    /// Waits up to `timeout` for the next message
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Vec<u8>, RecvTimeoutError> {
+        self.messages
+            .recv_timeout(timeout)
+            .map(|message| self.consume(message))
+    }
+
+    pub fn try_recv(&self) -> Option<Vec<u8>> {
+        self.messages
+            .try_recv()
+            .ok()
+            .map(|message| self.consume(message))
+    }
+}
+
+/// Runs streams over the `Sender` / `Receiver` pair returned by `Channel::listen` (requires
+/// features = ["std"]).
+///
+/// Fragments are reassembled as they arrive and their credits returned right away, except for the
+/// last fragment of each message, whose credit is returned once the application takes the message.
+/// A stream's `window` is therefore both the fragments in flight and the complete messages that may
+/// wait unread, and a message may be longer than the window.
+#[cfg(feature = "std")]
+pub struct Multiplexer<const T: usize> {
+    link: (Sender<Packet<T>>, Receiver<Packet<T>>),
+    streams: Vec<(StreamConfig, Sender<Vec<u8>>)>,
+    control: (Sender<Control>, Receiver<Control>),
+}
+
+#[cfg(feature = "std")]
+impl<const T: usize> Multiplexer<T> {
+    pub fn new(link: (Sender<Packet<T>>, Receiver<Packet<T>>)) -> Self {
+        assert!(T > STREAM_HEADER_SIZE, "Packets must have room for data");
+        Multiplexer {
+            link,
+            streams: Vec::new(),
+            control: mpsc::channel(),
+        }
+    }
+
+    /// Opens a stream. The peer must open a stream with the same ID.
+    pub fn open(&mut self, config: StreamConfig) -> Stream {
+        assert!(
+            self.streams.iter().all(|(open, _)| open.id != config.id),
+            "Stream IDs must be unique"
+        );
+        let (messages_tx, messages_rx) = mpsc::channel();
+        self.streams.push((config, messages_tx));
+        Stream {
+            id: config.id,
+            control: self.control.0.clone(),
+            messages: messages_rx,
+        }
+    }
+
+    /// Starts forwarding on a thread. Streams opened so far work until the returned handle is
+    /// stopped or dropped.
+    pub fn start(self) -> MuxHandle {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = running.clone();
+            thread::spawn(move || self.run(&running))
+        };
+        MuxHandle {
+            running,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(self, running: &AtomicBool) {
+        let (link_tx, link_rx) = self.link;
+        let control = self.control.1;
+        let (configs, deliveries): (Vec<StreamConfig>, Vec<_>) = self.streams.into_iter().unzip();
+        let index = |id: u8| configs.iter().position(|config| config.id == id);
+
+        let mut states: Vec<StreamState> = configs.iter().map(|c| StreamState::new(*c)).collect();
+        let mut scheduler = Scheduler::new(&mut states);
+        let mut outbound: Vec<VecDeque<(Vec<u8>, bool)>> =
+            configs.iter().map(|_| VecDeque::new()).collect();
+        let mut inbound: Vec<Vec<u8>> = configs.iter().map(|_| Vec::new()).collect();
+        let mut packet = Packet::<T>::new();
+
+        while running.load(Ordering::Relaxed) {
+            while let Ok(request) = control.try_recv() {
+                match request {
+                    Control::Message(id, message) => {
+                        let Some(stream) = index(id) else { continue };
+                        let count = message.len().div_ceil(T - STREAM_HEADER_SIZE).max(1);
+                        for number in 0..count {
+                            let start = number * (T - STREAM_HEADER_SIZE);
+                            let end = (start + T - STREAM_HEADER_SIZE).min(message.len());
+                            outbound[stream]
+                                .push_back((message[start..end].to_vec(), number + 1 == count));
+                        }
+                    }
+                    Control::Consumed(id) => scheduler.consumed(id, 1),
+                }
+            }
+
+            let received: Vec<Packet<T>> = match link_rx.recv_timeout(Duration::from_millis(1)) {
+                Ok(first) => core::iter::once(first).chain(link_rx.try_iter()).collect(),
+                Err(RecvTimeoutError::Timeout) => Vec::new(),
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            for incoming in received.iter() {
+                match Frame::parse(incoming) {
+                    Some(Frame::Data { stream, last, data }) => {
+                        let Some(index) = index(stream) else { continue };
+                        inbound[index].extend_from_slice(data);
+                        if !last {
+                            scheduler.consumed(stream, 1);
+                        } else if deliveries[index]
+                            .send(core::mem::take(&mut inbound[index]))
+                            .is_err()
+                        {
+                            // Nobody is listening, so the message counts as read
+                            scheduler.consumed(stream, 1);
+                        }
+                    }
+                    Some(Frame::Credit { stream, credits }) => {
+                        scheduler.add_credits(stream, credits)
+                    }
+                    None => {}
+                }
+            }
+
+            while let Some((id, credits)) = scheduler.grant() {
+                if pack_credit(&mut packet, id, credits).is_ok() && link_tx.send(packet).is_err() {
+                    return;
+                }
+            }
+
+            while let Some(id) =
+                scheduler.next(|id| index(id).is_some_and(|stream| !outbound[stream].is_empty()))
+            {
+                let stream = index(id).unwrap();
+                let (data, last) = outbound[stream].pop_front().unwrap();
+                if pack_fragment(&mut packet, id, last, &data).is_ok()
+                    && link_tx.send(packet).is_err()
+                {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Stops the multiplexer thread when stopped or dropped (requires features = ["std"])
+#[cfg(feature = "std")]
+pub struct MuxHandle {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "std")]
+impl MuxHandle {
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for MuxHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
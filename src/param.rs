@@ -0,0 +1,555 @@
+//! Typed parameter service.
+//!
+//! Devices declare a table of `Parameter`s (ID, type, range, units, read-only flag and name) and
+//! hosts enumerate, get and set them by ID. Values are type checked and range checked on the device.
+//!
+//! | Request              | Data                    | Reply                                                 |
+//! |----------------------|-------------------------|-------------------------------------------------------|
+//! | `request::PARAM_INFO` | index (u16)            | count (u16), ID (u16), type, flags, min, max, name, units |
+//! | `request::PARAM_GET`  | ID (u16)               | ID (u16), type, value                                 |
+//! | `request::PARAM_SET`  | ID (u16), type, value  | ID (u16), type, new value                             |
+//!
+//! Values are little endian, encoded with the `buffer` module, and take 1 (`Bool`, `U8`), 2
+//! (`U16`, `I16`) or 4 (`U32`, `I32`, `F32`) bytes. Flags bit 0 marks a read-only parameter. Name
+//! and units are each a length byte followed by UTF-8.
+//!
+//! Errors are answered with standard responses: `response::UNKNOWN_PARAMETER` for a bad ID or
+//! index, `response::OUT_OF_RANGE` for a value outside min / max, `response::ACCESS_DENIED` for
+//! read-only parameters and `response::INVALID_ARGUMENT` for malformed data or the wrong type.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::param::{ParamService, ParamValue, Parameter};
+//!     use flem::{request, Packet};
+//!
+//!     static TABLE: [Parameter; 1] = [Parameter::new(
+//!         0x01, "gain", "dB", ParamValue::F32(0.0), ParamValue::F32(-6.0), ParamValue::F32(24.0),
+//!     )];
+//!     let mut values = [ParamValue::Bool(false); 1];
+//!     let mut params = ParamService::new(&TABLE, &mut values);
+//!
+//!     let mut rx = Packet::<64>::new();
+//!     let mut tx = Packet::<64>::new();
+//!     rx.pack_data(request::PARAM_SET, &[0x01, 0x00, 6, 0x00, 0x00, 0x20, 0x41]).unwrap(); // 10.0
+//!     assert!(params.handle(&rx, &mut tx));
+//!     assert_eq!(params.get(0x01), Some(ParamValue::F32(10.0)));
+//! }
+//! ```
+
+#[cfg(feature = "std")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+use alloc::{string::String, vec::Vec};
+
+use crate::buffer::{
+    f32_to_le_buffer, i16_to_le_buffer, i32_to_le_buffer, le_buffer_to_f32, le_buffer_to_i16,
+    le_buffer_to_i32, le_buffer_to_u16, le_buffer_to_u32, u16_to_le_buffer, u32_to_le_buffer,
+    DataBufferErrors,
+};
+#[cfg(feature = "std")]
+use crate::client::{Client, ClientError};
+use crate::{request, response, Packet};
+
+const READ_ONLY_FLAG: u8 = 0x01;
+
+/// Largest encoded ID, type and value
+const VALUE_SIZE: usize = 2 + 1 + 4;
+/// Largest encoded count, ID, type, flags, min and max
+const INFO_HEADER_SIZE: usize = 2 + 2 + 1 + 1 + 4 + 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ParamType {
+    Bool = 0,
+    U8 = 1,
+    U16 = 2,
+    I16 = 3,
+    U32 = 4,
+    I32 = 5,
+    F32 = 6,
+}
+
+impl ParamType {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ParamType::Bool),
+            1 => Some(ParamType::U8),
+            2 => Some(ParamType::U16),
+            3 => Some(ParamType::I16),
+            4 => Some(ParamType::U32),
+            5 => Some(ParamType::I32),
+            6 => Some(ParamType::F32),
+            _ => None,
+        }
+    }
+
+    /// Bytes of an encoded value
+    pub fn size(&self) -> usize {
+        match self {
+            ParamType::Bool | ParamType::U8 => 1,
+            ParamType::U16 | ParamType::I16 => 2,
+            ParamType::U32 | ParamType::I32 | ParamType::F32 => 4,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParamValue {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+}
+
+impl ParamValue {
+    pub fn param_type(&self) -> ParamType {
+        match self {
+            ParamValue::Bool(_) => ParamType::Bool,
+            ParamValue::U8(_) => ParamType::U8,
+            ParamValue::U16(_) => ParamType::U16,
+            ParamValue::I16(_) => ParamType::I16,
+            ParamValue::U32(_) => ParamType::U32,
+            ParamValue::I32(_) => ParamType::I32,
+            ParamValue::F32(_) => ParamType::F32,
+        }
+    }
+
+    /// Adds the value to `buffer` at `offset`, little endian
+    pub fn encode(&self, buffer: &mut [u8], offset: &mut usize) -> Result<(), DataBufferErrors> {
+        match *self {
+            ParamValue::Bool(value) => u8_to_buffer(value as u8, buffer, offset),
+            ParamValue::U8(value) => u8_to_buffer(value, buffer, offset),
+            ParamValue::U16(value) => u16_to_le_buffer(value, buffer, offset),
+            ParamValue::I16(value) => i16_to_le_buffer(value, buffer, offset),
+            ParamValue::U32(value) => u32_to_le_buffer(value, buffer, offset),
+            ParamValue::I32(value) => i32_to_le_buffer(value, buffer, offset),
+            ParamValue::F32(value) => f32_to_le_buffer(value, buffer, offset),
+        }
+    }
+
+    /// Reads a value of type `param_type` from `buffer` at `offset`
+    pub fn decode(
+        param_type: ParamType,
+        buffer: &[u8],
+        offset: &mut usize,
+    ) -> Result<Self, DataBufferErrors> {
+        Ok(match param_type {
+            ParamType::Bool => ParamValue::Bool(buffer_to_u8(buffer, offset)? != 0),
+            ParamType::U8 => ParamValue::U8(buffer_to_u8(buffer, offset)?),
+            ParamType::U16 => ParamValue::U16(le_buffer_to_u16(buffer, offset)?),
+            ParamType::I16 => ParamValue::I16(le_buffer_to_i16(buffer, offset)?),
+            ParamType::U32 => ParamValue::U32(le_buffer_to_u32(buffer, offset)?),
+            ParamType::I32 => ParamValue::I32(le_buffer_to_i32(buffer, offset)?),
+            ParamType::F32 => ParamValue::F32(le_buffer_to_f32(buffer, offset)?),
+        })
+    }
+
+    /// True if the value is between `min` and `max` inclusive. Values of another type, and NaN, are
+    /// never within range.
+    pub fn within(&self, min: &ParamValue, max: &ParamValue) -> bool {
+        match (*self, *min, *max) {
+            (ParamValue::Bool(_), ParamValue::Bool(_), ParamValue::Bool(_)) => true,
+            (ParamValue::U8(v), ParamValue::U8(lo), ParamValue::U8(hi)) => lo <= v && v <= hi,
+            (ParamValue::U16(v), ParamValue::U16(lo), ParamValue::U16(hi)) => lo <= v && v <= hi,
+            (ParamValue::I16(v), ParamValue::I16(lo), ParamValue::I16(hi)) => lo <= v && v <= hi,
+            (ParamValue::U32(v), ParamValue::U32(lo), ParamValue::U32(hi)) => lo <= v && v <= hi,
+            (ParamValue::I32(v), ParamValue::I32(lo), ParamValue::I32(hi)) => lo <= v && v <= hi,
+            (ParamValue::F32(v), ParamValue::F32(lo), ParamValue::F32(hi)) => lo <= v && v <= hi,
+            _ => false,
+        }
+    }
+}
+
+fn u8_to_buffer(value: u8, buffer: &mut [u8], offset: &mut usize) -> Result<(), DataBufferErrors> {
+    let slot = buffer
+        .get_mut(*offset)
+        .ok_or(DataBufferErrors::NotEnoughRoomInBuffer)?;
+    *slot = value;
+    *offset += 1;
+    Ok(())
+}
+
+fn buffer_to_u8(buffer: &[u8], offset: &mut usize) -> Result<u8, DataBufferErrors> {
+    let value = *buffer
+        .get(*offset)
+        .ok_or(DataBufferErrors::ConversionWouldOverflow)?;
+    *offset += 1;
+    Ok(value)
+}
+
+fn encode_value(id: u16, value: &ParamValue, buffer: &mut [u8]) -> Result<usize, DataBufferErrors> {
+    let mut offset = 0;
+    u16_to_le_buffer(id, buffer, &mut offset)?;
+    u8_to_buffer(value.param_type() as u8, buffer, &mut offset)?;
+    value.encode(buffer, &mut offset)?;
+    Ok(offset)
+}
+
+fn encode_info(
+    parameter: &Parameter,
+    count: u16,
+    buffer: &mut [u8],
+) -> Result<usize, DataBufferErrors> {
+    let flags = if parameter.read_only {
+        READ_ONLY_FLAG
+    } else {
+        0
+    };
+
+    let mut offset = 0;
+    u16_to_le_buffer(count, buffer, &mut offset)?;
+    u16_to_le_buffer(parameter.id, buffer, &mut offset)?;
+    u8_to_buffer(parameter.param_type() as u8, buffer, &mut offset)?;
+    u8_to_buffer(flags, buffer, &mut offset)?;
+    parameter.min.encode(buffer, &mut offset)?;
+    parameter.max.encode(buffer, &mut offset)?;
+    Ok(offset)
+}
+
+/// One entry of a device's parameter table. The type is the type of `default`, and `min` / `max`
+/// must have the same type.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Parameter {
+    pub id: u16,
+    pub name: &'static str,
+    pub units: &'static str,
+    pub default: ParamValue,
+    pub min: ParamValue,
+    pub max: ParamValue,
+    pub read_only: bool,
+}
+
+impl Parameter {
+    pub const fn new(
+        id: u16,
+        name: &'static str,
+        units: &'static str,
+        default: ParamValue,
+        min: ParamValue,
+        max: ParamValue,
+    ) -> Self {
+        Parameter {
+            id,
+            name,
+            units,
+            default,
+            min,
+            max,
+            read_only: false,
+        }
+    }
+
+    /// A parameter the host can read but not set, e.g. a serial number or a measured value. The range
+    /// spans the whole type, so device code can still update it with `ParamService::set`.
+    pub const fn read_only(
+        id: u16,
+        name: &'static str,
+        units: &'static str,
+        default: ParamValue,
+    ) -> Self {
+        Parameter {
+            id,
+            name,
+            units,
+            default,
+            min: full_range(default).0,
+            max: full_range(default).1,
+            read_only: true,
+        }
+    }
+
+    pub fn param_type(&self) -> ParamType {
+        self.default.param_type()
+    }
+}
+
+/// The smallest and largest values of `value`'s type
+const fn full_range(value: ParamValue) -> (ParamValue, ParamValue) {
+    match value {
+        ParamValue::Bool(_) => (ParamValue::Bool(false), ParamValue::Bool(true)),
+        ParamValue::U8(_) => (ParamValue::U8(u8::MIN), ParamValue::U8(u8::MAX)),
+        ParamValue::U16(_) => (ParamValue::U16(u16::MIN), ParamValue::U16(u16::MAX)),
+        ParamValue::I16(_) => (ParamValue::I16(i16::MIN), ParamValue::I16(i16::MAX)),
+        ParamValue::U32(_) => (ParamValue::U32(u32::MIN), ParamValue::U32(u32::MAX)),
+        ParamValue::I32(_) => (ParamValue::I32(i32::MIN), ParamValue::I32(i32::MAX)),
+        ParamValue::F32(_) => (ParamValue::F32(f32::MIN), ParamValue::F32(f32::MAX)),
+    }
+}
+
+/// Device side parameter service. Values live in a caller provided slice, one per table entry.
+pub struct ParamService<'a> {
+    table: &'a [Parameter],
+    values: &'a mut [ParamValue],
+    callback: Option<fn(&Parameter, ParamValue)>,
+}
+
+impl<'a> ParamService<'a> {
+    /// Sets every value to its default. `values` must be at least as long as `table`.
+    pub fn new(table: &'a [Parameter], values: &'a mut [ParamValue]) -> Self {
+        assert!(
+            values.len() >= table.len(),
+            "One value is needed per parameter"
+        );
+        for (value, parameter) in values.iter_mut().zip(table.iter()) {
+            *value = parameter.default;
+        }
+        ParamService {
+            table,
+            values,
+            callback: None,
+        }
+    }
+
+    /// Called after the host changes a parameter
+    pub fn set_callback(&mut self, callback: fn(&Parameter, ParamValue)) {
+        self.callback = Some(callback);
+    }
+
+    pub fn table(&self) -> &[Parameter] {
+        self.table
+    }
+
+    fn index(&self, id: u16) -> Option<usize> {
+        self.table.iter().position(|parameter| parameter.id == id)
+    }
+
+    pub fn get(&self, id: u16) -> Option<ParamValue> {
+        self.index(id).map(|index| self.values[index])
+    }
+
+    /// Sets a value from device code. Read-only parameters can be updated this way. The type and
+    /// range are checked for every parameter. Returns the response code the host would get.
+    pub fn set(&mut self, id: u16, value: ParamValue) -> Result<(), u16> {
+        let index = self.index(id).ok_or(response::UNKNOWN_PARAMETER)?;
+        let parameter = &self.table[index];
+        if value.param_type() != parameter.param_type() {
+            return Err(response::INVALID_ARGUMENT);
+        }
+        if !value.within(&parameter.min, &parameter.max) {
+            return Err(response::OUT_OF_RANGE);
+        }
+        self.values[index] = value;
+        Ok(())
+    }
+
+    /// Handles parameter requests and packs the reply. Returns false, leaving `reply` untouched, for
+    /// requests that aren't part of the parameter service.
+    pub fn handle<const T: usize>(&mut self, packet: &Packet<T>, reply: &mut Packet<T>) -> bool {
+        let code = packet.get_request();
+        let result = match code {
+            request::PARAM_INFO => self.info(packet.payload(), reply),
+            request::PARAM_GET => self.read(packet.payload(), reply),
+            request::PARAM_SET => self.write(packet.payload(), reply),
+            _ => return false,
+        };
+
+        match result {
+            Ok(_) => {
+                reply.set_request(code);
+                reply.set_response(response::SUCCESS);
+                reply.pack();
+            }
+            Err(error) => {
+                let _ = reply.pack_error(code, error, &[]);
+            }
+        }
+        true
+    }
+
+    fn id(payload: &[u8]) -> Result<u16, u16> {
+        let mut offset = 0;
+        le_buffer_to_u16(payload, &mut offset).map_err(|_| response::INVALID_ARGUMENT)
+    }
+
+    fn info<const T: usize>(&self, payload: &[u8], reply: &mut Packet<T>) -> Result<(), u16> {
+        if payload.len() != 2 {
+            return Err(response::INVALID_ARGUMENT);
+        }
+        let index = Self::id(payload)? as usize;
+        let parameter = self.table.get(index).ok_or(response::UNKNOWN_PARAMETER)?;
+
+        let mut buffer = [0u8; INFO_HEADER_SIZE];
+        let length = encode_info(parameter, self.table.len() as u16, &mut buffer)
+            .map_err(|_| response::INVALID_ARGUMENT)?;
+
+        reply.reset_lazy();
+        reply
+            .add_data(&buffer[..length])
+            .map_err(|_| response::INVALID_ARGUMENT)?;
+        for text in [parameter.name, parameter.units] {
+            // Longer text is cut short, on a character boundary
+            let mut length = text.len().min(u8::MAX as usize);
+            while !text.is_char_boundary(length) {
+                length -= 1;
+            }
+            reply
+                .add_data(&[length as u8])
+                .and_then(|_| reply.add_data(&text.as_bytes()[..length]))
+                .map_err(|_| response::INVALID_ARGUMENT)?;
+        }
+        Ok(())
+    }
+
+    fn pack_value<const T: usize>(&self, index: usize, reply: &mut Packet<T>) -> Result<(), u16> {
+        let mut buffer = [0u8; VALUE_SIZE];
+        let length = encode_value(self.table[index].id, &self.values[index], &mut buffer)
+            .map_err(|_| response::INVALID_ARGUMENT)?;
+
+        reply.reset_lazy();
+        reply
+            .add_data(&buffer[..length])
+            .map_err(|_| response::INVALID_ARGUMENT)
+    }
+
+    fn read<const T: usize>(&self, payload: &[u8], reply: &mut Packet<T>) -> Result<(), u16> {
+        if payload.len() != 2 {
+            return Err(response::INVALID_ARGUMENT);
+        }
+        let index = self
+            .index(Self::id(payload)?)
+            .ok_or(response::UNKNOWN_PARAMETER)?;
+        self.pack_value(index, reply)
+    }
+
+    fn write<const T: usize>(&mut self, payload: &[u8], reply: &mut Packet<T>) -> Result<(), u16> {
+        let index = self
+            .index(Self::id(payload)?)
+            .ok_or(response::UNKNOWN_PARAMETER)?;
+        let parameter = self.table[index];
+        if parameter.read_only {
+            return Err(response::ACCESS_DENIED);
+        }
+
+        let mut offset = 2;
+        let param_type = payload
+            .get(offset)
+            .and_then(|value| ParamType::from_u8(*value))
+            .ok_or(response::INVALID_ARGUMENT)?;
+        offset += 1;
+        let value = ParamValue::decode(param_type, payload, &mut offset)
+            .map_err(|_| response::INVALID_ARGUMENT)?;
+        if offset != payload.len() {
+            return Err(response::INVALID_ARGUMENT);
+        }
+
+        self.set(parameter.id, value)?;
+        if let Some(callback) = self.callback {
+            callback(&parameter, value);
+        }
+        self.pack_value(index, reply)
+    }
+}
+
+/// Parameter metadata as reported by a device (requires features = ["std"])
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamInfo {
+    pub id: u16,
+    pub param_type: ParamType,
+    pub read_only: bool,
+    pub min: ParamValue,
+    pub max: ParamValue,
+    pub name: String,
+    pub units: String,
+}
+
+#[cfg(feature = "std")]
+fn parse_value(payload: &[u8]) -> Result<(u16, ParamValue), ClientError> {
+    let mut offset = 0;
+    let id = le_buffer_to_u16(payload, &mut offset).map_err(|_| ClientError::InvalidReply)?;
+    let param_type = payload
+        .get(offset)
+        .and_then(|value| ParamType::from_u8(*value))
+        .ok_or(ClientError::InvalidReply)?;
+    offset += 1;
+    let value = ParamValue::decode(param_type, payload, &mut offset)
+        .map_err(|_| ClientError::InvalidReply)?;
+    Ok((id, value))
+}
+
+#[cfg(feature = "std")]
+fn parse_text(payload: &[u8], offset: &mut usize) -> Result<String, ClientError> {
+    let length = buffer_to_u8(payload, offset).map_err(|_| ClientError::InvalidReply)? as usize;
+    let text = payload
+        .get(*offset..*offset + length)
+        .ok_or(ClientError::InvalidReply)?;
+    *offset += length;
+    Ok(String::from_utf8_lossy(text).into_owned())
+}
+
+/// Lists every parameter of the device (requires features = ["std"])
+#[cfg(feature = "std")]
+pub fn list<const T: usize>(client: &Client<T>) -> Result<Vec<ParamInfo>, ClientError> {
+    let mut parameters = Vec::new();
+    let mut count = 1;
+
+    while parameters.len() < count {
+        let reply = match client.call(
+            request::PARAM_INFO,
+            &(parameters.len() as u16).to_le_bytes(),
+        ) {
+            // A device without parameters has no index 0
+            Err(ClientError::Response(response::UNKNOWN_PARAMETER)) if parameters.is_empty() => {
+                return Ok(parameters)
+            }
+            result => result?,
+        };
+        let payload = reply.payload();
+        let invalid = |_| ClientError::InvalidReply;
+
+        let mut offset = 0;
+        count = le_buffer_to_u16(payload, &mut offset).map_err(invalid)? as usize;
+        let id = le_buffer_to_u16(payload, &mut offset).map_err(invalid)?;
+        let param_type = ParamType::from_u8(buffer_to_u8(payload, &mut offset).map_err(invalid)?)
+            .ok_or(ClientError::InvalidReply)?;
+        let flags = buffer_to_u8(payload, &mut offset).map_err(invalid)?;
+        let min = ParamValue::decode(param_type, payload, &mut offset).map_err(invalid)?;
+        let max = ParamValue::decode(param_type, payload, &mut offset).map_err(invalid)?;
+        let name = parse_text(payload, &mut offset)?;
+        let units = parse_text(payload, &mut offset)?;
+
+        parameters.push(ParamInfo {
+            id,
+            param_type,
+            read_only: flags & READ_ONLY_FLAG != 0,
+            min,
+            max,
+            name,
+            units,
+        });
+    }
+
+    Ok(parameters)
+}
+
+/// Reads a parameter (requires features = ["std"])
+#[cfg(feature = "std")]
+pub fn get<const T: usize>(client: &Client<T>, id: u16) -> Result<ParamValue, ClientError> {
+    let reply = client.call(request::PARAM_GET, &id.to_le_bytes())?;
+    match parse_value(reply.payload())? {
+        (reply_id, value) if reply_id == id => Ok(value),
+        _ => Err(ClientError::InvalidReply),
+    }
+}
+
+/// Sets a parameter and returns the value the device stored (requires features = ["std"])
+#[cfg(feature = "std")]
+pub fn set<const T: usize>(
+    client: &Client<T>,
+    id: u16,
+    value: ParamValue,
+) -> Result<ParamValue, ClientError> {
+    let mut data = [0u8; VALUE_SIZE];
+    let length = encode_value(id, &value, &mut data).map_err(|_| ClientError::InvalidReply)?;
+
+    let reply = client.call(request::PARAM_SET, &data[..length])?;
+    match parse_value(reply.payload())? {
+        (reply_id, value) if reply_id == id => Ok(value),
+        _ => Err(ClientError::InvalidReply),
+    }
+}
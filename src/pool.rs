@@ -0,0 +1,137 @@
+//! Fixed-capacity pool of pre-allocated packets.
+//!
+//! `Packet<T>` is `Copy`, so every hand-off between an ISR, a queue and the
+//! application copies the entire `T` byte data buffer. A `PacketPool` owns `N`
+//! packets up front and hands out `PooledPacket` handles instead. A handle is
+//! the size of a pointer plus an index, can be moved through queues freely, and
+//! returns its packet to the pool when dropped.
+//!
+//! The pool can be placed in a `static`, which gives `'static` handles that can
+//! be passed between interrupt and thread context.
+//!
+//! **Note:** the pool uses atomic compare-and-swap to claim packets, which is
+//! not available on targets without CAS support (e.g. `thumbv6m`).
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::pool::PacketPool;
+//!
+//!     static POOL: PacketPool<1024, 3> = PacketPool::new();
+//!
+//!     let mut rx = POOL.alloc().unwrap();
+//!     rx.set_request(0xF);
+//!     rx.pack();
+//!
+//!     let mut queue = heapless::spsc::Queue::<_, 4>::new();
+//!     queue.enqueue(rx).unwrap(); // Only the handle is moved, not the 1 KB buffer
+//!
+//!     assert_eq!(POOL.available(), 2);
+//!     let rx = queue.dequeue().unwrap();
+//!     assert_eq!(rx.get_request(), 0xF);
+//!
+//!     drop(rx); // Packet goes back to the pool
+//!     assert_eq!(POOL.available(), 3);
+//! }
+//! ```
+
+use core::cell::UnsafeCell;
+use core::fmt::{Debug, Error, Formatter};
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::Packet;
+
+pub struct PacketPool<const T: usize, const N: usize> {
+    packets: [UnsafeCell<Packet<T>>; N],
+    taken: [AtomicBool; N],
+}
+
+// Safety: a packet is only ever accessed through the single `PooledPacket` that
+// claimed its `taken` flag, so there is never shared mutable access to a slot.
+unsafe impl<const T: usize, const N: usize> Sync for PacketPool<T, N> {}
+
+impl<const T: usize, const N: usize> PacketPool<T, N> {
+    /// Creates a pool of `N` packets with a data buffer of `T` bytes each. This is a `const fn`
+    /// so the pool can live in a `static`.
+    pub const fn new() -> Self {
+        Self {
+            packets: [const { UnsafeCell::new(Packet::new()) }; N],
+            taken: [const { AtomicBool::new(false) }; N],
+        }
+    }
+
+    /// Claims a free packet from the pool. The packet is lazily reset before being handed out.
+    /// Returns `None` if every packet is in use.
+    pub fn alloc(&self) -> Option<PooledPacket<'_, T, N>> {
+        for (index, taken) in self.taken.iter().enumerate() {
+            if taken
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                let mut handle = PooledPacket { pool: self, index };
+                handle.reset_lazy();
+                return Some(handle);
+            }
+        }
+        None
+    }
+
+    /// Number of packets that can currently be claimed with `alloc`
+    pub fn available(&self) -> usize {
+        self.taken
+            .iter()
+            .filter(|taken| !taken.load(Ordering::Relaxed))
+            .count()
+    }
+
+    /// Total number of packets owned by the pool
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    fn release(&self, index: usize) {
+        self.taken[index].store(false, Ordering::Release);
+    }
+}
+
+impl<const T: usize, const N: usize> Default for PacketPool<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Owned handle to a packet inside a `PacketPool`. Dereferences to `Packet<T>` so it can be
+/// filled in place with `construct` or `add_data`, and returns the packet to the pool on drop.
+pub struct PooledPacket<'a, const T: usize, const N: usize> {
+    pool: &'a PacketPool<T, N>,
+    index: usize,
+}
+
+impl<'a, const T: usize, const N: usize> Deref for PooledPacket<'a, T, N> {
+    type Target = Packet<T>;
+
+    fn deref(&self) -> &Packet<T> {
+        // Safety: this handle holds the claim on the slot
+        unsafe { &*self.pool.packets[self.index].get() }
+    }
+}
+
+impl<'a, const T: usize, const N: usize> DerefMut for PooledPacket<'a, T, N> {
+    fn deref_mut(&mut self) -> &mut Packet<T> {
+        // Safety: this handle holds the claim on the slot
+        unsafe { &mut *self.pool.packets[self.index].get() }
+    }
+}
+
+impl<'a, const T: usize, const N: usize> Drop for PooledPacket<'a, T, N> {
+    fn drop(&mut self) {
+        self.pool.release(self.index);
+    }
+}
+
+impl<'a, const T: usize, const N: usize> Debug for PooledPacket<'a, T, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        self.deref().fmt(f)
+    }
+}
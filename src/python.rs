@@ -0,0 +1,760 @@
+//! Python bindings for host side test scripts (requires features = ["python"]).
+//!
+//! Build and install the `flem` Python module into the active virtual environment with
+//! `maturin develop` (see `pyproject.toml`), then run the tests in `tests/python` with `pytest`.
+//!
+//! | Python                          | Rust                                                   |
+//! |---------------------------------|--------------------------------------------------------|
+//! | `flem.Packet`                   | `Packet`, with `DATA_SIZE` bytes of data               |
+//! | `flem.DataId`                   | `DataId`                                               |
+//! | `flem.buffer`                   | the `buffer` encoders, writing into a `bytearray`      |
+//! | `flem.request`, `flem.response` | the pre-defined codes and their `name` functions       |
+//! | `flem.Client`                   | `client::Client` over TCP, a serial port or an emulator |
+//! | `flem.Emulator`                 | `emulator::Emulator`, with handlers written in Python  |
+//!
+//! Errors are raised as subclasses of `flem.FlemError`: `StatusError` for a `Status` (its `status`
+//! attribute holds the variant name), `BufferError` for `buffer::DataBufferErrors`, and
+//! `ClientError` for `client::ClientError`, specialized as `ClientTimeoutError`,
+//! `DisconnectedError`, `ResponseError` (with the `response` code) and `InvalidReplyError`.
+//!
+//! ```python
+//! import flem
+//!
+//! emulator = flem.Emulator()
+//! emulator.handle(0x0010, lambda packet: packet.payload[::-1])
+//!
+//! with flem.Client.emulator(emulator) as client:
+//!     assert client.call(0x0010, b"\x01\x02").payload == b"\x02\x01"
+//! ```
+
+extern crate alloc;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use std::{
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::{PyByteArray, PyBytes},
+};
+
+use crate::{
+    buffer::{self, DataBufferErrors},
+    client::{Client, ClientError as RustClientError},
+    emulator::Emulator,
+    request, response,
+    traits::Channel,
+    transport::{SerialChannel, TcpChannel, DEFAULT_BAUD_RATE},
+    DataId, Packet, Status,
+};
+
+/// Data bytes of every Python `Packet`, and of the packets exchanged by `Client` and `Emulator`
+pub const DATA_SIZE: usize = 1024;
+
+/// Longest `DataId` name
+const ID_NAME_SIZE: usize = 25;
+
+create_exception!(
+    flem,
+    FlemError,
+    PyException,
+    "Base class of all FLEM errors"
+);
+create_exception!(flem, StatusError, FlemError, "A packet `Status` error");
+create_exception!(flem, BufferError, FlemError, "A `buffer` conversion failed");
+create_exception!(flem, ClientError, FlemError, "A client request failed");
+create_exception!(
+    flem,
+    ClientTimeoutError,
+    ClientError,
+    "No reply after all retries"
+);
+create_exception!(flem, DisconnectedError, ClientError, "The link was closed");
+create_exception!(
+    flem,
+    ResponseError,
+    ClientError,
+    "The device answered with an error response"
+);
+create_exception!(
+    flem,
+    InvalidReplyError,
+    ClientError,
+    "The reply data couldn't be decoded"
+);
+
+fn status_error(status: Status) -> PyErr {
+    let name = format!("{:?}", status);
+    let error = StatusError::new_err(name.clone());
+    Python::with_gil(|py| {
+        let _ = error.value(py).setattr("status", name);
+    });
+    error
+}
+
+fn buffer_error(error: DataBufferErrors) -> PyErr {
+    BufferError::new_err(format!("{:?}", error))
+}
+
+fn client_error(error: RustClientError) -> PyErr {
+    match error {
+        RustClientError::Timeout => ClientTimeoutError::new_err("no reply after all retries"),
+        RustClientError::Disconnected => DisconnectedError::new_err("the link was closed"),
+        RustClientError::Packet(status) => status_error(status),
+        RustClientError::Response(code) => {
+            let message = match response::name(code) {
+                Some(name) => format!("0x{:04X} ({})", code, name),
+                None => format!("0x{:04X}", code),
+            };
+            let error = ResponseError::new_err(message);
+            Python::with_gil(|py| {
+                let _ = error.value(py).setattr("response", code);
+            });
+            error
+        }
+        RustClientError::InvalidReply => {
+            InvalidReplyError::new_err("the reply couldn't be decoded")
+        }
+        RustClientError::AddressOverflow => {
+            ClientError::new_err("the address range runs past 0xFFFFFFFF")
+        }
+    }
+}
+
+#[pyclass(name = "Packet", module = "flem")]
+#[derive(Clone, Copy)]
+pub struct PyPacket {
+    packet: Packet<DATA_SIZE>,
+}
+
+#[pymethods]
+impl PyPacket {
+    #[new]
+    fn new() -> Self {
+        PyPacket {
+            packet: Packet::new(),
+        }
+    }
+
+    /// Parses the first packet in `data`. Raises `StatusError` if it is malformed or incomplete.
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let mut packet = Packet::new();
+        for byte in data {
+            match packet.construct(*byte) {
+                Ok(_) => return Ok(PyPacket { packet }),
+                Err(Status::PacketBuilding) => {}
+                Err(status) => return Err(status_error(status)),
+            }
+        }
+        Err(status_error(Status::PacketBuilding))
+    }
+
+    /// Feeds one received byte. Returns True once a valid packet is complete and False while more
+    /// bytes are needed, and raises `StatusError` otherwise. A header announcing more data than fits
+    /// resets the packet, so the next byte starts a new one.
+    fn construct(&mut self, byte: u8) -> PyResult<bool> {
+        match self.packet.construct(byte) {
+            Ok(_) => Ok(true),
+            Err(Status::PacketBuilding) => Ok(false),
+            Err(Status::InvalidDataLengthDetected) => {
+                self.packet.reset_lazy();
+                Err(status_error(Status::InvalidDataLengthDetected))
+            }
+            Err(status) => Err(status_error(status)),
+        }
+    }
+
+    fn add_data(&mut self, data: &[u8]) -> PyResult<()> {
+        self.packet.add_data(data).map_err(status_error)
+    }
+
+    fn pack(&mut self) {
+        self.packet.pack();
+    }
+
+    #[pyo3(signature = (request, data = Vec::new()))]
+    fn pack_data(&mut self, request: u16, data: Vec<u8>) -> PyResult<()> {
+        self.packet.pack_data(request, &data).map_err(status_error)
+    }
+
+    #[pyo3(signature = (request, error, data = Vec::new()))]
+    fn pack_error(&mut self, request: u16, error: u16, data: Vec<u8>) -> PyResult<()> {
+        self.packet
+            .pack_error(request, error, &data)
+            .map_err(status_error)
+    }
+
+    #[pyo3(signature = (id, ascii = true))]
+    fn pack_id(&mut self, id: &PyDataId, ascii: bool) -> PyResult<()> {
+        self.packet
+            .pack_id(&id.to_data_id(), ascii)
+            .map_err(status_error)
+    }
+
+    fn reset(&mut self) {
+        self.packet.reset();
+    }
+
+    fn reset_lazy(&mut self) {
+        self.packet.reset_lazy();
+    }
+
+    fn validate(&mut self) -> bool {
+        self.packet.validate()
+    }
+
+    #[getter]
+    fn get_request(&self) -> u16 {
+        self.packet.get_request()
+    }
+
+    #[setter]
+    fn set_request(&mut self, request: u16) {
+        self.packet.set_request(request);
+    }
+
+    #[getter]
+    fn get_response(&self) -> u16 {
+        self.packet.get_response()
+    }
+
+    #[setter]
+    fn set_response(&mut self, response: u16) {
+        self.packet.set_response(response);
+    }
+
+    #[getter]
+    fn checksum(&self) -> u16 {
+        self.packet.get_checksum()
+    }
+
+    #[getter]
+    fn payload<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.packet.payload())
+    }
+
+    /// Bytes on the wire, header included
+    fn __len__(&self) -> usize {
+        self.packet.wire_bytes().len()
+    }
+
+    /// The whole packet as sent on the wire
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.packet.wire_bytes())
+    }
+
+    fn __repr__(&self) -> String {
+        let code = |code: u16, name: Option<&str>| match name {
+            Some(name) => format!("0x{:04X} ({})", code, name),
+            None => format!("0x{:04X}", code),
+        };
+        format!(
+            "Packet(request={}, response={}, payload={} bytes)",
+            code(
+                self.packet.get_request(),
+                request::name(self.packet.get_request())
+            ),
+            code(
+                self.packet.get_response(),
+                response::name(self.packet.get_response())
+            ),
+            self.packet.payload().len()
+        )
+    }
+}
+
+#[pyclass(name = "DataId", module = "flem", get_all)]
+#[derive(Clone)]
+pub struct PyDataId {
+    name: String,
+    major: u8,
+    minor: u8,
+    patch: u8,
+    max_packet_size: u16,
+}
+
+impl PyDataId {
+    fn to_data_id(&self) -> DataId {
+        DataId::new(
+            &self.name,
+            self.major,
+            self.minor,
+            self.patch,
+            self.max_packet_size as usize,
+        )
+    }
+}
+
+#[pymethods]
+impl PyDataId {
+    #[new]
+    fn new(name: String, major: u8, minor: u8, patch: u8, max_packet_size: u16) -> PyResult<Self> {
+        if name.len() > ID_NAME_SIZE {
+            return Err(PyValueError::new_err(format!(
+                "DataId names are at most {} bytes",
+                ID_NAME_SIZE
+            )));
+        }
+        Ok(PyDataId {
+            name,
+            major,
+            minor,
+            patch,
+            max_packet_size,
+        })
+    }
+
+    /// Decodes the payload of a `request.ID` reply packed with ascii = True
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        // Version and packet size come before the name
+        let id = DataId::from(data)
+            .filter(|_| data.len() >= 5)
+            .ok_or_else(|| PyValueError::new_err("Not a DataId"))?;
+        Ok(PyDataId {
+            name: id.get_name().iter().take_while(|c| **c != '\0').collect(),
+            major: id.get_major(),
+            minor: id.get_minor(),
+            patch: id.get_patch(),
+            max_packet_size: id.get_max_packet_size(),
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "DataId(name={:?}, version={}.{}.{}, max_packet_size={})",
+            self.name, self.major, self.minor, self.patch, self.max_packet_size
+        )
+    }
+}
+
+/// Runs a `buffer` encoder on a `bytearray` and returns the offset after the value
+fn encode_into<V>(
+    value: V,
+    buffer: &Bound<'_, PyByteArray>,
+    offset: usize,
+    encode: fn(V, &mut [u8], &mut usize) -> Result<(), DataBufferErrors>,
+) -> PyResult<usize> {
+    let mut offset = offset;
+    // No Python code runs while the bytes are borrowed
+    let bytes = unsafe { buffer.as_bytes_mut() };
+    encode(value, bytes, &mut offset).map_err(buffer_error)?;
+    Ok(offset)
+}
+
+/// Runs a `buffer` decoder and returns the value and the offset after it
+fn decode_from<V>(
+    data: &[u8],
+    offset: usize,
+    decode: fn(&[u8], &mut usize) -> Result<V, DataBufferErrors>,
+) -> PyResult<(V, usize)> {
+    let mut offset = offset;
+    let value = decode(data, &mut offset).map_err(buffer_error)?;
+    Ok((value, offset))
+}
+
+#[pyfunction]
+#[pyo3(signature = (value, buffer, offset = 0))]
+fn f32_to_le_buffer(value: f32, buffer: &Bound<'_, PyByteArray>, offset: usize) -> PyResult<usize> {
+    encode_into(value, buffer, offset, buffer::f32_to_le_buffer)
+}
+
+#[pyfunction]
+#[pyo3(signature = (value, buffer, offset = 0))]
+fn u32_to_le_buffer(value: u32, buffer: &Bound<'_, PyByteArray>, offset: usize) -> PyResult<usize> {
+    encode_into(value, buffer, offset, buffer::u32_to_le_buffer)
+}
+
+#[pyfunction]
+#[pyo3(signature = (value, buffer, offset = 0))]
+fn i32_to_le_buffer(value: i32, buffer: &Bound<'_, PyByteArray>, offset: usize) -> PyResult<usize> {
+    encode_into(value, buffer, offset, buffer::i32_to_le_buffer)
+}
+
+#[pyfunction]
+#[pyo3(signature = (value, buffer, offset = 0))]
+fn u16_to_le_buffer(value: u16, buffer: &Bound<'_, PyByteArray>, offset: usize) -> PyResult<usize> {
+    encode_into(value, buffer, offset, buffer::u16_to_le_buffer)
+}
+
+#[pyfunction]
+#[pyo3(signature = (value, buffer, offset = 0))]
+fn i16_to_le_buffer(value: i16, buffer: &Bound<'_, PyByteArray>, offset: usize) -> PyResult<usize> {
+    encode_into(value, buffer, offset, buffer::i16_to_le_buffer)
+}
+
+#[pyfunction]
+#[pyo3(signature = (data, offset = 0))]
+fn le_buffer_to_f32(data: &[u8], offset: usize) -> PyResult<(f32, usize)> {
+    decode_from(data, offset, buffer::le_buffer_to_f32)
+}
+
+#[pyfunction]
+#[pyo3(signature = (data, offset = 0))]
+fn le_buffer_to_u32(data: &[u8], offset: usize) -> PyResult<(u32, usize)> {
+    decode_from(data, offset, buffer::le_buffer_to_u32)
+}
+
+#[pyfunction]
+#[pyo3(signature = (data, offset = 0))]
+fn le_buffer_to_i32(data: &[u8], offset: usize) -> PyResult<(i32, usize)> {
+    decode_from(data, offset, buffer::le_buffer_to_i32)
+}
+
+#[pyfunction]
+#[pyo3(signature = (data, offset = 0))]
+fn le_buffer_to_u16(data: &[u8], offset: usize) -> PyResult<(u16, usize)> {
+    decode_from(data, offset, buffer::le_buffer_to_u16)
+}
+
+#[pyfunction]
+#[pyo3(signature = (data, offset = 0))]
+fn le_buffer_to_i16(data: &[u8], offset: usize) -> PyResult<(i16, usize)> {
+    decode_from(data, offset, buffer::le_buffer_to_i16)
+}
+
+#[pyfunction(name = "name")]
+fn request_name(code: u16) -> Option<&'static str> {
+    request::name(code)
+}
+
+#[pyfunction(name = "name")]
+fn response_name(code: u16) -> Option<&'static str> {
+    response::name(code)
+}
+
+/// What a handler returned: a `Packet` is sent as is, bytes as a `response::SUCCESS` reply to the
+/// request and None sends nothing
+fn apply_reply(
+    result: &Bound<'_, PyAny>,
+    request: u16,
+    reply: &mut Packet<DATA_SIZE>,
+) -> PyResult<()> {
+    if result.is_none() {
+        return Ok(());
+    }
+    if let Ok(packet) = result.extract::<PyPacket>() {
+        *reply = packet.packet;
+        return Ok(());
+    }
+    let data: Vec<u8> = result.extract()?;
+    reply.pack_data(request, &data).map_err(status_error)
+}
+
+#[pyclass(name = "Emulator", module = "flem")]
+pub struct PyEmulator {
+    emulator: Emulator<(), DATA_SIZE>,
+}
+
+#[pymethods]
+impl PyEmulator {
+    #[new]
+    fn new() -> Self {
+        PyEmulator {
+            emulator: Emulator::new(()),
+        }
+    }
+
+    /// Calls `handler(packet)` for every `request` packet. It returns a `Packet` to send, bytes to
+    /// reply with `response.SUCCESS`, or None to send nothing. Exceptions are printed and nothing
+    /// is sent.
+    fn handle(&mut self, py: Python<'_>, request: u16, handler: PyObject) {
+        let emulator = &mut self.emulator;
+        // The device thread may be waiting for the GIL while holding the device
+        py.allow_threads(move || {
+            emulator.handle(request, move |_, packet, reply| {
+                Python::with_gil(|py| {
+                    let result = handler
+                        .call1(py, (PyPacket { packet: *packet },))
+                        .and_then(|result| apply_reply(result.bind(py), request, reply));
+                    if let Err(error) = result {
+                        error.print(py);
+                    }
+                })
+            })
+        });
+    }
+
+    fn set_id(&mut self, py: Python<'_>, id: &PyDataId) {
+        let id = id.to_data_id();
+        let emulator = &mut self.emulator;
+        py.allow_threads(move || emulator.set_id(id));
+    }
+
+    /// Drops the next `count` packets the device sends
+    fn drop_next(&self, py: Python<'_>, count: u32) {
+        py.allow_threads(|| self.emulator.drop_next(count));
+    }
+
+    /// Corrupts the checksum of the next `count` packets the device sends
+    fn corrupt_next(&self, py: Python<'_>, count: u32) {
+        py.allow_threads(|| self.emulator.corrupt_next(count));
+    }
+
+    /// Sends raw bytes to the host as is
+    fn send_raw(&self, py: Python<'_>, data: Vec<u8>) {
+        py.allow_threads(|| self.emulator.send_raw(&data));
+    }
+
+    /// Serves the device over TCP on `address`, e.g. `"127.0.0.1:0"`, and returns the bound address
+    fn bind_tcp(&mut self, address: &str) -> PyResult<String> {
+        self.emulator
+            .bind_tcp(address)
+            .map(|address| address.to_string())
+            .map_err(|error| DisconnectedError::new_err(error.to_string()))
+    }
+
+    /// Stops serving, for `bind_tcp` as well as clients
+    fn close(&mut self) {
+        let _ = Channel::<DATA_SIZE>::unlisten(&mut self.emulator);
+    }
+}
+
+enum Link {
+    Tcp(TcpChannel<DATA_SIZE>),
+    /// Locked only because `SerialPort` isn't `Sync`, which `#[pyclass]` needs
+    Serial(Mutex<SerialChannel<DATA_SIZE>>),
+    Emulator(Py<PyEmulator>),
+}
+
+type Endpoints = (Sender<Packet<DATA_SIZE>>, Receiver<Packet<DATA_SIZE>>);
+
+#[pyclass(name = "Client", module = "flem")]
+pub struct PyClient {
+    link: Option<Link>,
+    endpoints: Option<Mutex<Endpoints>>,
+    timeout: Duration,
+    retries: u8,
+}
+
+impl PyClient {
+    fn endpoints(&self) -> PyResult<&Mutex<Endpoints>> {
+        self.endpoints
+            .as_ref()
+            .ok_or_else(|| DisconnectedError::new_err("the client is closed"))
+    }
+
+    fn with_client<R: Send>(
+        &self,
+        py: Python<'_>,
+        f: impl FnOnce(&Client<DATA_SIZE>) -> Result<R, RustClientError> + Send,
+    ) -> PyResult<R> {
+        let endpoints = self.endpoints()?;
+        let (timeout, retries) = (self.timeout, self.retries);
+        // Emulator handlers need the GIL while the client waits
+        py.allow_threads(move || {
+            let endpoints = endpoints.lock().unwrap();
+            let client = Client::new(&endpoints.0, &endpoints.1)
+                .with_timeout(timeout)
+                .with_retries(retries);
+            f(&client)
+        })
+        .map_err(client_error)
+    }
+}
+
+#[pymethods]
+impl PyClient {
+    /// Connects to a device, or serial-to-Ethernet bridge, at `address` (`host:port`)
+    #[staticmethod]
+    #[pyo3(signature = (address, timeout_ms = 500, retries = 3))]
+    fn tcp(address: String, timeout_ms: u64, retries: u8) -> PyResult<Self> {
+        let mut channel = TcpChannel::<DATA_SIZE>::new();
+        channel
+            .connect(&address)
+            .map_err(|error| DisconnectedError::new_err(error.to_string()))?;
+        let endpoints = channel.listen(1, 1);
+        Ok(PyClient {
+            link: Some(Link::Tcp(channel)),
+            endpoints: Some(Mutex::new(endpoints)),
+            timeout: Duration::from_millis(timeout_ms),
+            retries,
+        })
+    }
+
+    /// Opens the serial `port`, e.g. `"/dev/ttyUSB0"` or `"COM3"`, at `baud_rate`
+    #[staticmethod]
+    #[pyo3(signature = (port, baud_rate = DEFAULT_BAUD_RATE, timeout_ms = 500, retries = 3))]
+    fn serial(port: String, baud_rate: u32, timeout_ms: u64, retries: u8) -> PyResult<Self> {
+        let mut channel = SerialChannel::<DATA_SIZE>::new(baud_rate);
+        channel
+            .connect(&port)
+            .map_err(|error| DisconnectedError::new_err(error.to_string()))?;
+        let endpoints = channel.listen(1, 1);
+        Ok(PyClient {
+            link: Some(Link::Serial(Mutex::new(channel))),
+            endpoints: Some(Mutex::new(endpoints)),
+            timeout: Duration::from_millis(timeout_ms),
+            retries,
+        })
+    }
+
+    /// Talks to an in-process `Emulator`
+    #[staticmethod]
+    #[pyo3(signature = (emulator, timeout_ms = 500, retries = 3))]
+    fn emulator(emulator: Py<PyEmulator>, timeout_ms: u64, retries: u8) -> PyResult<Self> {
+        let endpoints = Python::with_gil(|py| {
+            Channel::<DATA_SIZE>::listen(&mut emulator.borrow_mut(py).emulator, 1, 1)
+        });
+        Ok(PyClient {
+            link: Some(Link::Emulator(emulator)),
+            endpoints: Some(Mutex::new(endpoints)),
+            timeout: Duration::from_millis(timeout_ms),
+            retries,
+        })
+    }
+
+    /// Sends `request` with `data` and returns the reply, whatever its response code
+    #[pyo3(signature = (request, data = Vec::new()))]
+    fn request(&self, py: Python<'_>, request: u16, data: Vec<u8>) -> PyResult<PyPacket> {
+        let packet = self.with_client(py, move |client| client.request(request, &data))?;
+        Ok(PyPacket { packet })
+    }
+
+    /// Like `request`, but raises `ResponseError` unless the response is `response.SUCCESS`
+    #[pyo3(signature = (request, data = Vec::new()))]
+    fn call(&self, py: Python<'_>, request: u16, data: Vec<u8>) -> PyResult<PyPacket> {
+        let packet = self.with_client(py, move |client| client.call(request, &data))?;
+        Ok(PyPacket { packet })
+    }
+
+    /// Reads the device's `DataId`
+    fn id(&self, py: Python<'_>) -> PyResult<PyDataId> {
+        let packet = self.with_client(py, |client| client.call(request::ID, &[]))?;
+        PyDataId::from_bytes(packet.payload())
+    }
+
+    /// Sends a packet without waiting for a reply
+    fn send(&self, py: Python<'_>, packet: &PyPacket) -> PyResult<()> {
+        let packet = packet.packet;
+        let endpoints = self.endpoints()?;
+        if py.allow_threads(move || endpoints.lock().unwrap().0.send(packet).is_ok()) {
+            Ok(())
+        } else {
+            Err(client_error(RustClientError::Disconnected))
+        }
+    }
+
+    /// Waits up to `timeout_ms` for the next packet, e.g. an `ASYNC` one. Returns None on timeout.
+    #[pyo3(signature = (timeout_ms = 500))]
+    fn receive(&self, py: Python<'_>, timeout_ms: u64) -> PyResult<Option<PyPacket>> {
+        let endpoints = self.endpoints()?;
+        let result = py.allow_threads(move || {
+            endpoints
+                .lock()
+                .unwrap()
+                .1
+                .recv_timeout(Duration::from_millis(timeout_ms))
+        });
+        match result {
+            Ok(packet) => Ok(Some(PyPacket { packet })),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(client_error(RustClientError::Disconnected)),
+        }
+    }
+
+    /// Disconnects. Further requests raise `DisconnectedError`.
+    fn close(&mut self, py: Python<'_>) {
+        self.endpoints = None;
+        match self.link.take() {
+            Some(Link::Tcp(mut channel)) => {
+                let _ = channel.disconnect();
+            }
+            Some(Link::Serial(channel)) => {
+                if let Ok(mut channel) = channel.into_inner() {
+                    let _ = channel.disconnect();
+                }
+            }
+            Some(Link::Emulator(emulator)) => {
+                let _ = Channel::<DATA_SIZE>::unlisten(&mut emulator.borrow_mut(py).emulator);
+            }
+            None => {}
+        }
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> bool {
+        self.close(py);
+        false
+    }
+}
+
+/// Adds a submodule with a constant per named code in 0..=0xFFFF
+fn codes<'py>(
+    py: Python<'py>,
+    module: &str,
+    name: fn(u16) -> Option<&'static str>,
+) -> PyResult<Bound<'py, PyModule>> {
+    let submodule = PyModule::new(py, module)?;
+    for code in 0..=u16::MAX {
+        if let Some(constant) = name(code) {
+            submodule.add(constant, code)?;
+        }
+    }
+    Ok(submodule)
+}
+
+#[pymodule]
+#[pyo3(name = "flem")]
+fn flem_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+
+    m.add("DATA_SIZE", DATA_SIZE)?;
+    m.add_class::<PyPacket>()?;
+    m.add_class::<PyDataId>()?;
+    m.add_class::<PyClient>()?;
+    m.add_class::<PyEmulator>()?;
+
+    m.add("FlemError", py.get_type::<FlemError>())?;
+    m.add("StatusError", py.get_type::<StatusError>())?;
+    m.add("BufferError", py.get_type::<BufferError>())?;
+    m.add("ClientError", py.get_type::<ClientError>())?;
+    m.add("ClientTimeoutError", py.get_type::<ClientTimeoutError>())?;
+    m.add("DisconnectedError", py.get_type::<DisconnectedError>())?;
+    m.add("ResponseError", py.get_type::<ResponseError>())?;
+    m.add("InvalidReplyError", py.get_type::<InvalidReplyError>())?;
+
+    let requests = codes(py, "request", request::name)?;
+    requests.add_function(wrap_pyfunction!(request_name, &requests)?)?;
+    m.add_submodule(&requests)?;
+
+    let responses = codes(py, "response", response::name)?;
+    responses.add_function(wrap_pyfunction!(response_name, &responses)?)?;
+    m.add_submodule(&responses)?;
+
+    let buffers = PyModule::new(py, "buffer")?;
+    buffers.add_function(wrap_pyfunction!(f32_to_le_buffer, &buffers)?)?;
+    buffers.add_function(wrap_pyfunction!(u32_to_le_buffer, &buffers)?)?;
+    buffers.add_function(wrap_pyfunction!(i32_to_le_buffer, &buffers)?)?;
+    buffers.add_function(wrap_pyfunction!(u16_to_le_buffer, &buffers)?)?;
+    buffers.add_function(wrap_pyfunction!(i16_to_le_buffer, &buffers)?)?;
+    buffers.add_function(wrap_pyfunction!(le_buffer_to_f32, &buffers)?)?;
+    buffers.add_function(wrap_pyfunction!(le_buffer_to_u32, &buffers)?)?;
+    buffers.add_function(wrap_pyfunction!(le_buffer_to_i32, &buffers)?)?;
+    buffers.add_function(wrap_pyfunction!(le_buffer_to_u16, &buffers)?)?;
+    buffers.add_function(wrap_pyfunction!(le_buffer_to_i16, &buffers)?)?;
+    m.add_submodule(&buffers)?;
+
+    Ok(())
+}
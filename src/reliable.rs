@@ -0,0 +1,322 @@
+//! Optional reliable delivery layer on top of `Packet<T>`.
+//!
+//! Plain FLEM gives no delivery guarantee; a `CHECKSUM_ERROR` reply is just another
+//! packet and nothing is retransmitted. This module adds:
+//!
+//! - A 2 byte little endian sequence number prepended to the payload of every reliable packet.
+//! - `request::ACK` / `request::NACK` packets carrying the acknowledged sequence number as data.
+//! - A `ReliableSender` that keeps up to `W` unacknowledged packets, retransmitting them with
+//!   a configurable retry count and exponential backoff.
+//! - A `ReliableReceiver` that acknowledges every packet and filters duplicates, so each
+//!   packet is delivered to the application exactly once.
+//!
+//! Time is passed in by the caller as a free running `u32` millisecond tick (wrapping is
+//! handled), so the layer works without `std`. Both ends should be reset together when a
+//! link is re-established, since sequence numbers restart at 0.
+
+use crate::{request, Packet, Status};
+
+/// Bytes prepended to the payload of every reliable packet
+pub const SEQUENCE_SIZE: usize = 2;
+
+/// Largest sender window supported by the receiver's duplicate filter
+pub const MAX_WINDOW: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReliableError {
+    /// `W` packets are already waiting for an acknowledgement
+    WindowFull,
+    /// The packet with this sequence number was sent `max_retries` times without an ACK and was dropped
+    RetriesExhausted(u16),
+    /// The packet could not be built or parsed
+    Packet(Status),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryConfig {
+    /// Number of retransmissions after the first attempt before giving up
+    pub max_retries: u8,
+    /// Time to wait for an ACK after the first transmission, in ticks
+    pub timeout: u32,
+    /// The timeout is multiplied by this value after every retransmission. 1 disables backoff.
+    pub backoff: u32,
+    /// Upper bound for the timeout after backoff, in ticks
+    pub max_timeout: u32,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: 3,
+            timeout: 100,
+            backoff: 2,
+            max_timeout: 1000,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Outstanding<const T: usize> {
+    packet: Packet<T>,
+    sequence: u16,
+    deadline: u32,
+    timeout: u32,
+    retries: u8,
+}
+
+/// Returns true if tick `now` is at or past `deadline`, tolerating wrap around
+fn expired(now: u32, deadline: u32) -> bool {
+    now.wrapping_sub(deadline) as i32 >= 0
+}
+
+fn sequence_of<const T: usize>(packet: &Packet<T>) -> Result<u16, ReliableError> {
+    let payload = packet.payload();
+    if payload.len() < SEQUENCE_SIZE {
+        return Err(ReliableError::Packet(Status::InvalidDataLengthDetected));
+    }
+    Ok(u16::from_le_bytes([payload[0], payload[1]]))
+}
+
+/// Result of passing a received packet to `ReliableSender::receive`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Acknowledgement {
+    /// The packet with this sequence number was acknowledged and removed from the window
+    Acked(u16),
+    /// The partner asked for this sequence number again, it will be retransmitted on the next `poll`
+    Nacked(u16),
+    /// The packet was neither an ACK nor a NACK and should be handled by the application
+    None,
+}
+
+/// Sending side of the reliable layer. Holds up to `W` packets of `T` bytes until they are
+/// acknowledged.
+pub struct ReliableSender<const T: usize, const W: usize> {
+    config: RetryConfig,
+    next_sequence: u16,
+    window: [Option<Outstanding<T>>; W],
+}
+
+impl<const T: usize, const W: usize> ReliableSender<T, W> {
+    pub fn new(config: RetryConfig) -> Self {
+        assert!(W <= MAX_WINDOW, "<W> should be 32 or less");
+        assert!(
+            T >= SEQUENCE_SIZE,
+            "<T> is too small to hold a sequence number"
+        );
+        ReliableSender {
+            config,
+            next_sequence: 0,
+            window: [None; W],
+        }
+    }
+
+    /// Packs `data` behind the next sequence number and stores the packet in the window. The returned
+    /// packet should be transmitted right away; retransmissions are handled by `poll`.
+    pub fn send(
+        &mut self,
+        request: u16,
+        data: &[u8],
+        now: u32,
+    ) -> Result<&Packet<T>, ReliableError> {
+        let slot = self
+            .window
+            .iter()
+            .position(|slot| slot.is_none())
+            .ok_or(ReliableError::WindowFull)?;
+
+        let sequence = self.next_sequence;
+        let mut packet = Packet::<T>::new();
+        packet.set_request(request);
+        packet
+            .add_data(&sequence.to_le_bytes())
+            .map_err(ReliableError::Packet)?;
+        packet.add_data(data).map_err(ReliableError::Packet)?;
+        packet.pack();
+
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        let outstanding = self.window[slot].insert(Outstanding {
+            packet,
+            sequence,
+            deadline: now.wrapping_add(self.config.timeout),
+            timeout: self.config.timeout,
+            retries: 0,
+        });
+
+        Ok(&outstanding.packet)
+    }
+
+    /// Inspects a packet from the partner. ACKs free their slot in the window and NACKs schedule an
+    /// immediate retransmission.
+    pub fn receive(
+        &mut self,
+        packet: &Packet<T>,
+        now: u32,
+    ) -> Result<Acknowledgement, ReliableError> {
+        match packet.get_request() {
+            request::ACK => {
+                let sequence = sequence_of(packet)?;
+                for slot in self.window.iter_mut() {
+                    if matches!(slot, Some(x) if x.sequence == sequence) {
+                        *slot = None;
+                    }
+                }
+                Ok(Acknowledgement::Acked(sequence))
+            }
+            request::NACK => {
+                let sequence = sequence_of(packet)?;
+                for outstanding in self.window.iter_mut().flatten() {
+                    if outstanding.sequence == sequence {
+                        outstanding.deadline = now;
+                    }
+                }
+                Ok(Acknowledgement::Nacked(sequence))
+            }
+            _ => Ok(Acknowledgement::None),
+        }
+    }
+
+    /// Retransmits every packet whose ACK timed out by calling `transmit`, and returns the number
+    /// of packets retransmitted. A packet that runs out of retries is dropped from the window and
+    /// reported as `ReliableError::RetriesExhausted`; call `poll` again to service the rest.
+    pub fn poll<F: FnMut(&Packet<T>)>(
+        &mut self,
+        now: u32,
+        mut transmit: F,
+    ) -> Result<usize, ReliableError> {
+        let mut retransmitted = 0;
+        for slot in self.window.iter_mut() {
+            if let Some(outstanding) = slot {
+                if !expired(now, outstanding.deadline) {
+                    continue;
+                }
+
+                if outstanding.retries >= self.config.max_retries {
+                    let sequence = outstanding.sequence;
+                    *slot = None;
+                    return Err(ReliableError::RetriesExhausted(sequence));
+                }
+
+                outstanding.retries += 1;
+                outstanding.timeout = outstanding
+                    .timeout
+                    .saturating_mul(self.config.backoff)
+                    .min(self.config.max_timeout);
+                outstanding.deadline = now.wrapping_add(outstanding.timeout);
+                transmit(&outstanding.packet);
+                retransmitted += 1;
+            }
+        }
+        Ok(retransmitted)
+    }
+
+    /// Number of packets waiting for an acknowledgement
+    pub fn in_flight(&self) -> usize {
+        self.window.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    /// Drops all outstanding packets and restarts the sequence numbers at 0
+    pub fn reset(&mut self) {
+        self.window = [None; W];
+        self.next_sequence = 0;
+    }
+}
+
+/// Result of passing a received packet to `ReliableReceiver::receive`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery<'a> {
+    /// First time this sequence number was seen; `data` is the payload without the sequence number
+    Deliver { sequence: u16, data: &'a [u8] },
+    /// Already delivered, the packet should be ignored. The ACK is still sent since the previous one
+    /// was probably lost.
+    Duplicate(u16),
+}
+
+/// Receiving side of the reliable layer. Acknowledges packets and filters duplicates using a bitmap
+/// of the last `MAX_WINDOW` sequence numbers.
+pub struct ReliableReceiver {
+    highest: u16,
+    seen: u32,
+    started: bool,
+}
+
+impl ReliableReceiver {
+    pub fn new() -> Self {
+        ReliableReceiver {
+            highest: 0,
+            seen: 0,
+            started: false,
+        }
+    }
+
+    /// Checks the sequence number of a received reliable packet and packs the matching ACK into `ack`,
+    /// which should be transmitted back to the sender.
+    pub fn receive<'a, const T: usize>(
+        &mut self,
+        packet: &'a Packet<T>,
+        ack: &mut Packet<T>,
+    ) -> Result<Delivery<'a>, ReliableError> {
+        let sequence = sequence_of(packet)?;
+        ack.pack_data(request::ACK, &sequence.to_le_bytes())
+            .map_err(ReliableError::Packet)?;
+
+        if !self.accept(sequence) {
+            return Ok(Delivery::Duplicate(sequence));
+        }
+
+        Ok(Delivery::Deliver {
+            sequence,
+            data: &packet.payload()[SEQUENCE_SIZE..],
+        })
+    }
+
+    /// Packs a NACK for the next expected sequence number. Use this when a packet fails its checksum,
+    /// since the sequence number inside a corrupted packet can't be trusted.
+    pub fn pack_nack<const T: usize>(&self, nack: &mut Packet<T>) -> Result<(), ReliableError> {
+        let expected = if self.started {
+            self.highest.wrapping_add(1)
+        } else {
+            0
+        };
+        nack.pack_data(request::NACK, &expected.to_le_bytes())
+            .map_err(ReliableError::Packet)
+    }
+
+    /// Forgets every sequence number seen so far
+    pub fn reset(&mut self) {
+        *self = ReliableReceiver::new();
+    }
+
+    fn accept(&mut self, sequence: u16) -> bool {
+        if !self.started {
+            self.started = true;
+            self.highest = sequence;
+            self.seen = 1;
+            return true;
+        }
+
+        let ahead = sequence.wrapping_sub(self.highest) as i16;
+        if ahead > 0 {
+            self.seen = if ahead as usize >= MAX_WINDOW {
+                0
+            } else {
+                self.seen << ahead
+            };
+            self.seen |= 1;
+            self.highest = sequence;
+            return true;
+        }
+
+        let behind = ahead.unsigned_abs() as usize;
+        if behind >= MAX_WINDOW || self.seen & (1 << behind) != 0 {
+            return false;
+        }
+        self.seen |= 1 << behind;
+        true
+    }
+}
+
+impl Default for ReliableReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,287 @@
+//! Authenticated encryption of packet payloads (requires features = ["secure"]).
+//!
+//! A `SecureSession` encrypts and authenticates the data part of a packet with ChaCha20-Poly1305 and a
+//! 32 byte pre-shared key. The FLEM header is left in the clear so packets can still be framed,
+//! checksummed and routed by parties without the key. The request and response codes are
+//! authenticated as associated data, so they can't be swapped without detection.
+//!
+//! The data part of a sealed packet is laid out as:
+//!
+//! | Counter (8 bytes, LE) | Ciphertext (length - 24 bytes) | Tag (16 bytes) |
+//!
+//! The 12 byte nonce is built from the counter and the sender's `Role`, so host and device can share a
+//! key without ever reusing a nonce. The receiver keeps a 64 packet window of counters it has accepted
+//! and rejects old or repeated counters with `Status::ReplayDetected`, and packets that fail
+//! authentication with `Status::DecryptionFailed`.
+//!
+//! Addressed packets (see `address`) are sealed with `seal_addressed` and opened with
+//! `open_addressed`. The 2 byte address prefix stays in the clear in front of the counter, so buses
+//! and bridges can still route the packet, and is authenticated along with the codes.
+//!
+//! Nothing here allocates, so sessions can live in a `static` on the device.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::{secure::{Role, SecureSession}, Packet, Status};
+//!
+//!     let key = [0x42u8; 32];
+//!     let mut host = SecureSession::new(&key, Role::Host);
+//!     let mut device = SecureSession::new(&key, Role::Device);
+//!
+//!     let mut packet = Packet::<64>::new();
+//!     host.seal(&mut packet, 0x10, flem::response::SUCCESS, &[1, 2, 3]).unwrap();
+//!     assert_ne!(&packet.payload()[8..11], &[1, 2, 3]);
+//!
+//!     let mut buffer = [0u8; 64];
+//!     assert_eq!(device.open(&packet, &mut buffer), Ok(&[1u8, 2, 3][..]));
+//!     assert_eq!(device.open(&packet, &mut buffer), Err(Status::ReplayDetected));
+//! }
+//! ```
+
+use chacha20poly1305::{
+    aead::{AeadInPlace, KeyInit},
+    ChaCha20Poly1305, Key, Nonce, Tag,
+};
+
+use crate::address::{Address, ADDRESS_PREFIX_SIZE};
+use crate::{Packet, Status};
+
+/// Bytes used by the counter at the start of the data
+pub const COUNTER_SIZE: usize = 8;
+/// Bytes used by the authentication tag at the end of the data
+pub const TAG_SIZE: usize = 16;
+/// Data bytes a sealed packet needs on top of the plaintext
+pub const SECURE_OVERHEAD: usize = COUNTER_SIZE + TAG_SIZE;
+
+/// Counters accepted out of order, behind the highest counter seen
+const REPLAY_WINDOW: u64 = 64;
+
+/// Which end of the link a session sends as. Each end must use a different role.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Role {
+    Host,
+    Device,
+}
+
+impl Role {
+    fn id(self) -> u8 {
+        match self {
+            Role::Host => 0,
+            Role::Device => 1,
+        }
+    }
+
+    fn peer(self) -> Role {
+        match self {
+            Role::Host => Role::Device,
+            Role::Device => Role::Host,
+        }
+    }
+}
+
+pub struct SecureSession {
+    cipher: ChaCha20Poly1305,
+    role: Role,
+    tx_counter: u64,
+    /// Highest counter accepted so far, `None` until the first packet is opened
+    rx_highest: Option<u64>,
+    /// Bit n set means counter `rx_highest - n` has been accepted
+    rx_window: u64,
+}
+
+impl SecureSession {
+    pub fn new(key: &[u8; 32], role: Role) -> Self {
+        SecureSession {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+            role,
+            tx_counter: 0,
+            rx_highest: None,
+            rx_window: 0,
+        }
+    }
+
+    /// Encrypts `data` into `packet` and packs it. `packet` must have room for
+    /// `data.len() + SECURE_OVERHEAD` bytes, otherwise `Status::PacketOverflow` is returned.
+    pub fn seal<const T: usize>(
+        &mut self,
+        packet: &mut Packet<T>,
+        request: u16,
+        response: u16,
+        data: &[u8],
+    ) -> Result<(), Status> {
+        self.seal_with_prefix(packet, request, response, &[], data)
+    }
+
+    /// Like `seal`, with `address` as a clear, authenticated prefix in front of the counter. `packet`
+    /// must have room for `data.len() + ADDRESS_PREFIX_SIZE + SECURE_OVERHEAD` bytes.
+    pub fn seal_addressed<const T: usize>(
+        &mut self,
+        packet: &mut Packet<T>,
+        request: u16,
+        response: u16,
+        address: Address,
+        data: &[u8],
+    ) -> Result<(), Status> {
+        let prefix = [address.destination, address.source];
+        self.seal_with_prefix(packet, request, response, &prefix, data)
+    }
+
+    fn seal_with_prefix<const T: usize>(
+        &mut self,
+        packet: &mut Packet<T>,
+        request: u16,
+        response: u16,
+        prefix: &[u8],
+        data: &[u8],
+    ) -> Result<(), Status> {
+        if prefix.len() + data.len() + SECURE_OVERHEAD > T {
+            return Err(Status::PacketOverflow);
+        }
+        // Never reuse a nonce, even after 2^64 packets
+        let counter = self.tx_counter;
+        self.tx_counter = counter.checked_add(1).ok_or(Status::UnspecifiedError)?;
+
+        packet.reset_lazy();
+        packet.set_request(request);
+        packet.set_response(response);
+        packet.add_data(prefix)?;
+        packet.add_data(&counter.to_le_bytes())?;
+        packet.add_data(data)?;
+
+        let (aad, aad_length) = associated_data(request, response, prefix);
+        let tag = self
+            .cipher
+            .encrypt_in_place_detached(
+                &nonce(counter, self.role),
+                &aad[..aad_length],
+                &mut packet.payload_mut()[prefix.len() + COUNTER_SIZE..],
+            )
+            .map_err(|_| Status::UnspecifiedError)?;
+        packet.add_data(&tag)?;
+        packet.pack();
+
+        Ok(())
+    }
+
+    /// Authenticates and decrypts a sealed packet from the peer into `buffer`, returning the plaintext.
+    /// The counter is only marked as used once the packet has been authenticated, so forged packets
+    /// can't be used to block genuine ones.
+    pub fn open<'a, const T: usize>(
+        &mut self,
+        packet: &Packet<T>,
+        buffer: &'a mut [u8],
+    ) -> Result<&'a [u8], Status> {
+        self.open_with_prefix(packet, 0, buffer)
+    }
+
+    /// Like `open`, for packets sealed with `seal_addressed`. Returns the address prefix along with
+    /// the plaintext; a changed prefix fails authentication.
+    pub fn open_addressed<'a, const T: usize>(
+        &mut self,
+        packet: &Packet<T>,
+        buffer: &'a mut [u8],
+    ) -> Result<(Address, &'a [u8]), Status> {
+        let plaintext = self.open_with_prefix(packet, ADDRESS_PREFIX_SIZE, buffer)?;
+        // The prefix is there, `open_with_prefix` checked the length
+        let address = packet.address().ok_or(Status::DecryptionFailed)?;
+        Ok((address, plaintext))
+    }
+
+    fn open_with_prefix<'a, const T: usize>(
+        &mut self,
+        packet: &Packet<T>,
+        prefix_size: usize,
+        buffer: &'a mut [u8],
+    ) -> Result<&'a [u8], Status> {
+        let payload = packet.payload();
+        if payload.len() < prefix_size + SECURE_OVERHEAD {
+            return Err(Status::DecryptionFailed);
+        }
+
+        let (prefix, sealed) = payload.split_at(prefix_size);
+        let (counter, rest) = sealed.split_at(COUNTER_SIZE);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_SIZE);
+        let counter = u64::from_le_bytes(counter.try_into().unwrap());
+        if buffer.len() < ciphertext.len() {
+            return Err(Status::PacketOverflow);
+        }
+        if self.is_replay(counter) {
+            return Err(Status::ReplayDetected);
+        }
+
+        let plaintext = &mut buffer[..ciphertext.len()];
+        plaintext.copy_from_slice(ciphertext);
+        let (aad, aad_length) =
+            associated_data(packet.get_request(), packet.get_response(), prefix);
+        self.cipher
+            .decrypt_in_place_detached(
+                &nonce(counter, self.role.peer()),
+                &aad[..aad_length],
+                plaintext,
+                Tag::from_slice(tag),
+            )
+            .map_err(|_| Status::DecryptionFailed)?;
+
+        self.accept(counter);
+        Ok(plaintext)
+    }
+
+    /// Forgets sent and received counters. Only safe after switching to a new key.
+    pub fn reset(&mut self) {
+        self.tx_counter = 0;
+        self.rx_highest = None;
+        self.rx_window = 0;
+    }
+
+    fn is_replay(&self, counter: u64) -> bool {
+        match self.rx_highest {
+            None => false,
+            Some(highest) if counter > highest => false,
+            Some(highest) => {
+                let age = highest - counter;
+                age >= REPLAY_WINDOW || self.rx_window & (1 << age) != 0
+            }
+        }
+    }
+
+    fn accept(&mut self, counter: u64) {
+        match self.rx_highest {
+            Some(highest) if counter <= highest => self.rx_window |= 1 << (highest - counter),
+            Some(highest) => {
+                let shift = counter - highest;
+                self.rx_window = if shift >= REPLAY_WINDOW {
+                    1
+                } else {
+                    (self.rx_window << shift) | 1
+                };
+                self.rx_highest = Some(counter);
+            }
+            None => {
+                self.rx_window = 1;
+                self.rx_highest = Some(counter);
+            }
+        }
+    }
+}
+
+fn nonce(counter: u64, sender: Role) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[..COUNTER_SIZE].copy_from_slice(&counter.to_le_bytes());
+    nonce[COUNTER_SIZE] = sender.id();
+    Nonce::from(nonce)
+}
+
+/// The request and response codes, then the address prefix if any. Returns the buffer and the
+/// number of bytes used.
+fn associated_data(
+    request: u16,
+    response: u16,
+    prefix: &[u8],
+) -> ([u8; 4 + ADDRESS_PREFIX_SIZE], usize) {
+    let mut aad = [0u8; 4 + ADDRESS_PREFIX_SIZE];
+    aad[..2].copy_from_slice(&request.to_le_bytes());
+    aad[2..4].copy_from_slice(&response.to_le_bytes());
+    aad[4..4 + prefix.len()].copy_from_slice(prefix);
+    (aad, 4 + prefix.len())
+}
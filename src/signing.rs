@@ -0,0 +1,130 @@
+//! Signed firmware image manifests (requires features = ["signing"]).
+//!
+//! A `Manifest` describes a firmware image and carries an Ed25519 signature over that description:
+//!
+//! | Magic "FLMF" (4) | Format (1) | Major, minor, patch (3) | Size (4, LE) | SHA-256 (32) | Signature (64) |
+//!
+//! The signature covers every byte before it, so the image hash, size and version can't be altered
+//! without the signing key. Manifests are a fixed `MANIFEST_SIZE` bytes and are checked without
+//! allocating, so devices can hold them in a packet buffer.
+//!
+//! With a `VerifyingKey` set, `dfu::DfuDevice` requires a manifest (`request::DFU_MANIFEST`) and
+//! checks it against the received image during `request::DFU_VERIFY`, refusing to commit unsigned
+//! or tampered firmware with `dfu::DfuError::SignatureInvalid`.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::signing::{Manifest, SigningKey};
+//!
+//!     let key = SigningKey::from_bytes(&[7u8; 32]);
+//!     let firmware = [0xA5u8; 1000];
+//!
+//!     let manifest = Manifest::sign(&key, &firmware, 1, 4, 0);
+//!     let bytes = manifest.to_bytes();
+//!
+//!     let received = Manifest::from_bytes(&bytes).unwrap();
+//!     assert!(received.verify_image(&key.verifying_key(), &firmware));
+//!     assert!(!received.verify_image(&key.verifying_key(), &[0u8; 1000]));
+//! }
+//! ```
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use ed25519_dalek::{Signature, Signer};
+use sha2::{Digest, Sha256};
+
+const MAGIC: [u8; 4] = *b"FLMF";
+const FORMAT: u8 = 1;
+
+pub const HASH_SIZE: usize = 32;
+pub const SIGNATURE_SIZE: usize = 64;
+/// Bytes covered by the signature
+const SIGNED_SIZE: usize = 12 + HASH_SIZE;
+/// Bytes of an encoded manifest
+pub const MANIFEST_SIZE: usize = SIGNED_SIZE + SIGNATURE_SIZE;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+    pub size: u32,
+    /// SHA-256 of the image
+    pub hash: [u8; HASH_SIZE],
+    pub signature: [u8; SIGNATURE_SIZE],
+}
+
+impl Manifest {
+    /// Hashes and signs `image`
+    pub fn sign(key: &SigningKey, image: &[u8], major: u8, minor: u8, patch: u8) -> Self {
+        let mut manifest = Manifest {
+            major,
+            minor,
+            patch,
+            size: image.len() as u32,
+            hash: Sha256::digest(image).into(),
+            signature: [0; SIGNATURE_SIZE],
+        };
+        manifest.signature = key.sign(&manifest.signed_bytes()).to_bytes();
+        manifest
+    }
+
+    /// Decodes a manifest, returning `None` if the length, magic or format don't match
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != MANIFEST_SIZE || bytes[0..4] != MAGIC || bytes[4] != FORMAT {
+            return None;
+        }
+
+        let mut manifest = Manifest {
+            major: bytes[5],
+            minor: bytes[6],
+            patch: bytes[7],
+            size: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            hash: [0; HASH_SIZE],
+            signature: [0; SIGNATURE_SIZE],
+        };
+        manifest.hash.copy_from_slice(&bytes[12..SIGNED_SIZE]);
+        manifest.signature.copy_from_slice(&bytes[SIGNED_SIZE..]);
+        Some(manifest)
+    }
+
+    pub fn to_bytes(&self) -> [u8; MANIFEST_SIZE] {
+        let mut bytes = [0u8; MANIFEST_SIZE];
+        bytes[..SIGNED_SIZE].copy_from_slice(&self.signed_bytes());
+        bytes[SIGNED_SIZE..].copy_from_slice(&self.signature);
+        bytes
+    }
+
+    /// True if the signature over the version, size and hash was made with `key`'s signing key
+    pub fn verify_signature(&self, key: &VerifyingKey) -> bool {
+        key.verify_strict(
+            &self.signed_bytes(),
+            &Signature::from_bytes(&self.signature),
+        )
+        .is_ok()
+    }
+
+    /// True if the signature is valid and `hash` / `size` describe the signed image. Use this when
+    /// the image is hashed in pieces, e.g. while reading it back from flash.
+    pub fn verify(&self, key: &VerifyingKey, hash: &[u8; HASH_SIZE], size: u32) -> bool {
+        self.size == size && self.hash == *hash && self.verify_signature(key)
+    }
+
+    /// True if the signature is valid and the manifest describes `image`
+    pub fn verify_image(&self, key: &VerifyingKey, image: &[u8]) -> bool {
+        self.verify(key, &Sha256::digest(image).into(), image.len() as u32)
+    }
+
+    fn signed_bytes(&self) -> [u8; SIGNED_SIZE] {
+        let mut bytes = [0u8; SIGNED_SIZE];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4] = FORMAT;
+        bytes[5] = self.major;
+        bytes[6] = self.minor;
+        bytes[7] = self.patch;
+        bytes[8..12].copy_from_slice(&self.size.to_le_bytes());
+        bytes[12..].copy_from_slice(&self.hash);
+        bytes
+    }
+}
@@ -0,0 +1,158 @@
+//! Cumulative link statistics for the receive path.
+//!
+//! `Status` only describes the last byte passed to `construct`, so errors are lost as soon as the
+//! next byte arrives. Receiving with `Packet::construct_counted` instead keeps running totals in a
+//! `LinkStatistics`, which is a handful of `u32` counters and cheap to copy out or reset on `no_std`.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::{statistics::LinkStatistics, Packet};
+//!
+//!     let mut stats = LinkStatistics::new();
+//!     let mut tx = Packet::<64>::new();
+//!     let mut rx = Packet::<64>::new();
+//!
+//!     tx.pack_data(0xF, &[1, 2, 3]).unwrap();
+//!
+//!     // Line noise, followed by a good packet
+//!     for byte in [0x00, 0xFF].iter().chain(tx.bytes()) {
+//!         let _ = rx.construct_counted(*byte, &mut stats);
+//!     }
+//!
+//!     let snapshot = stats.snapshot();
+//!     assert_eq!(snapshot.packets_received, 1);
+//!     assert_eq!(snapshot.header_resyncs, 1);
+//!     assert_eq!(snapshot.bytes_discarded, 2);
+//! }
+//! ```
+
+use crate::{Packet, Status, FLEM_HEADER_SIZE};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LinkStatistics {
+    /// Packets that passed the checksum
+    pub packets_received: u32,
+    /// Packets that failed the checksum
+    pub checksum_errors: u32,
+    /// Number of times the receiver lost sync and had to hunt for the next header
+    pub header_resyncs: u32,
+    /// Packets with more data than announced in the header
+    pub overflows: u32,
+    /// Packets announcing a length larger than the receive buffer
+    pub invalid_lengths: u32,
+    /// Bytes thrown away, either while hunting for a header or as part of a rejected packet
+    pub bytes_discarded: u32,
+    /// Partially received packets abandoned with `Packet::timeout_counted`
+    pub timeouts: u32,
+    /// Packets for other addresses skipped by the address filter, see `address`. Their bytes don't
+    /// count as discarded.
+    pub not_addressed: u32,
+}
+
+impl LinkStatistics {
+    pub const fn new() -> Self {
+        LinkStatistics {
+            packets_received: 0,
+            checksum_errors: 0,
+            header_resyncs: 0,
+            overflows: 0,
+            invalid_lengths: 0,
+            bytes_discarded: 0,
+            timeouts: 0,
+            not_addressed: 0,
+        }
+    }
+
+    /// Returns a copy of the current counters
+    pub fn snapshot(&self) -> LinkStatistics {
+        *self
+    }
+
+    /// Zeroes all counters
+    pub fn reset(&mut self) {
+        *self = LinkStatistics::new();
+    }
+
+    /// Returns a copy of the current counters and zeroes them, e.g. for periodic reporting
+    pub fn take(&mut self) -> LinkStatistics {
+        let snapshot = *self;
+        self.reset();
+        snapshot
+    }
+}
+
+impl<const T: usize> Packet<T> {
+    /// Same as `construct`, but also updates `stats`. On any error other than `PacketBuilding` the
+    /// packet should be reset before receiving the next byte, just like with `construct`.
+    pub fn construct_counted(
+        &mut self,
+        byte: u8,
+        stats: &mut LinkStatistics,
+    ) -> Result<(), Status> {
+        let counter_before = self.internal_counter;
+        let status_before = self.status;
+        let result = self.construct(byte);
+
+        match result {
+            Ok(_) => {
+                stats.packets_received = stats.packets_received.wrapping_add(1);
+            }
+            Err(Status::PacketBuilding) => {}
+            Err(Status::NotAddressed) => {
+                stats.not_addressed = stats.not_addressed.wrapping_add(1);
+            }
+            Err(Status::HeaderBytesNotFound) => {
+                if status_before != Status::HeaderBytesNotFound {
+                    stats.header_resyncs = stats.header_resyncs.wrapping_add(1);
+                }
+                // A lone 0x55 followed by a non-header byte is discarded as well
+                stats.bytes_discarded = stats.bytes_discarded.wrapping_add(counter_before + 1);
+            }
+            Err(status) => {
+                let mut discarded = self.consumed(counter_before);
+                match status {
+                    Status::ChecksumError => {
+                        stats.checksum_errors = stats.checksum_errors.wrapping_add(1);
+                    }
+                    Status::PacketOverflow => {
+                        stats.overflows = stats.overflows.wrapping_add(1);
+                        // The overflowing byte was never stored
+                        discarded += 1;
+                    }
+                    Status::InvalidDataLengthDetected => {
+                        stats.invalid_lengths = stats.invalid_lengths.wrapping_add(1);
+                    }
+                    _ => {}
+                }
+                stats.bytes_discarded = stats.bytes_discarded.wrapping_add(discarded);
+            }
+        }
+
+        result
+    }
+
+    /// Abandons a partially received packet, e.g. when no byte arrived within the inter-byte timeout.
+    /// Counts a timeout and the discarded bytes in `stats` and lazily resets the packet. Does nothing
+    /// if no packet is in progress.
+    pub fn timeout_counted(&mut self, stats: &mut LinkStatistics) {
+        if self.internal_counter == 0 {
+            return;
+        }
+        stats.timeouts = stats.timeouts.wrapping_add(1);
+        stats.bytes_discarded = stats
+            .bytes_discarded
+            .wrapping_add(self.consumed(self.internal_counter - 1));
+        self.reset_lazy();
+    }
+
+    /// Number of bytes of the current packet consumed so far, given the internal counter before the
+    /// last call to `construct`
+    fn consumed(&self, counter_before: u32) -> u32 {
+        if counter_before < FLEM_HEADER_SIZE as u32 {
+            counter_before + 1
+        } else {
+            FLEM_HEADER_SIZE as u32 + self.data_length_counter as u32
+        }
+    }
+}
@@ -0,0 +1,465 @@
+//! Telemetry subscriptions streamed as `response::ASYNC` packets.
+//!
+//! Devices declare a table of `TelemetryChannel`s. Hosts subscribe to a channel at a period, which
+//! the device raises to the channel's minimum if needed. Subscribing again changes the period from
+//! the next sample on.
+//!
+//! | Request                           | Data                              | Reply                                 |
+//! |-----------------------------------|-----------------------------------|---------------------------------------|
+//! | `request::TELEMETRY_SUBSCRIBE`    | channel (u16), period in ms (u32) | channel (u16), granted period (u32)   |
+//! | `request::TELEMETRY_UNSUBSCRIBE`  | channel (u16)                     | channel (u16)                         |
+//! | `request::TELEMETRY_DATA` (ASYNC) | -                                 | channel (u16), sequence (u16), sample |
+//!
+//! Unknown channels and a period of 0 are refused with `response::INVALID_ARGUMENT`. The sequence
+//! counts up by one per sample of a channel, so hosts can tell how many samples were lost.
+//!
+//! `Publisher` is the `no_std` device side. It has no clock of its own: the application calls `poll`
+//! with the current time in milliseconds (wrapping is fine) until it returns false, sending each
+//! packet it fills in. With a bandwidth limit set, samples wait until the link has room for them. A
+//! channel that falls behind skips the samples it missed rather than bursting to catch up.
+//!
+//! With features = ["std"], `subscribe` and `unsubscribe` are the host API and `Subscriber` decodes
+//! the samples arriving on the receive queue. Samples that arrive while a `client::Client` waits for
+//! a reply are kept by the client; create the subscriber with `Subscriber::from_client` to get them
+//! too.
+//!
+//! # Example
+//! ```
+//! pub fn main() {
+//!     use flem::telemetry::{Publisher, Subscription, TelemetryChannel};
+//!     use flem::{request, Packet};
+//!
+//!     static CHANNELS: [TelemetryChannel; 1] = [TelemetryChannel::new(0x01, 10)];
+//!     let mut subscriptions = [Subscription::new(); 1];
+//!     let mut publisher = Publisher::new(&CHANNELS, &mut subscriptions);
+//!
+//!     let mut rx = Packet::<64>::new();
+//!     let mut tx = Packet::<64>::new();
+//!     rx.pack_data(request::TELEMETRY_SUBSCRIBE, &[0x01, 0x00, 100, 0, 0, 0]).unwrap();
+//!     assert!(publisher.handle(&rx, &mut tx));
+//!
+//!     let sample = |_channel: u16, buffer: &mut [u8]| {
+//!         buffer[0] = 42;
+//!         1
+//!     };
+//!     assert!(publisher.poll(0, &mut tx, sample)); // The first sample is sent right away
+//!     assert_eq!(tx.payload(), &[0x01, 0x00, 0x00, 0x00, 42]);
+//!     assert!(!publisher.poll(50, &mut tx, sample));
+//!     assert!(publisher.poll(100, &mut tx, sample));
+//! }
+//! ```
+
+#[cfg(feature = "std")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "std")]
+use crate::client::{Client, ClientError};
+use crate::{request, response, Packet, FLEM_HEADER_SIZE};
+
+/// Bytes of the channel and sequence at the start of every sample packet
+pub const SAMPLE_HEADER_SIZE: usize = 4;
+
+/// One entry of a device's telemetry table
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TelemetryChannel {
+    pub id: u16,
+    /// Shortest period a host may subscribe at
+    pub min_period_ms: u32,
+}
+
+impl TelemetryChannel {
+    pub const fn new(id: u16, min_period_ms: u32) -> Self {
+        TelemetryChannel { id, min_period_ms }
+    }
+}
+
+/// Subscription state of one channel, kept by `Publisher`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Subscription {
+    /// 0 when not subscribed
+    period_ms: u32,
+    /// `None` until the first sample, which is sent on the next `poll`
+    next_due: Option<u32>,
+    sequence: u16,
+}
+
+impl Subscription {
+    pub const fn new() -> Self {
+        Subscription {
+            period_ms: 0,
+            next_due: None,
+            sequence: 0,
+        }
+    }
+
+    pub fn period_ms(&self) -> Option<u32> {
+        match self.period_ms {
+            0 => None,
+            period => Some(period),
+        }
+    }
+}
+
+/// True if `now` is at or past `due`, allowing for the millisecond clock wrapping
+fn reached(now: u32, due: u32) -> bool {
+    (now.wrapping_sub(due) as i32) >= 0
+}
+
+/// Device side telemetry publisher. Subscription state lives in a caller provided slice, one per
+/// table entry.
+pub struct Publisher<'a> {
+    channels: &'a [TelemetryChannel],
+    subscriptions: &'a mut [Subscription],
+    /// Bytes per second, 0 for no limit
+    bandwidth: u32,
+    /// Available budget in thousandths of a byte
+    budget: u64,
+    last_poll: Option<u32>,
+    /// Round robin start, so a busy channel can't starve the others
+    next_channel: usize,
+}
+
+impl<'a> Publisher<'a> {
+    /// `subscriptions` must be at least as long as `channels`.
+    pub fn new(channels: &'a [TelemetryChannel], subscriptions: &'a mut [Subscription]) -> Self {
+        assert!(
+            subscriptions.len() >= channels.len(),
+            "One subscription is needed per channel"
+        );
+        subscriptions.fill(Subscription::new());
+        Publisher {
+            channels,
+            subscriptions,
+            bandwidth: 0,
+            budget: 0,
+            last_poll: None,
+            next_channel: 0,
+        }
+    }
+
+    /// Limits telemetry to `bytes_per_second` on the wire, headers included. Up to 100 ms worth of
+    /// budget (at least one full packet) is saved up while idle.
+    pub fn set_bandwidth(&mut self, bytes_per_second: u32) {
+        self.bandwidth = bytes_per_second;
+        self.budget = 0;
+    }
+
+    pub fn channels(&self) -> &[TelemetryChannel] {
+        self.channels
+    }
+
+    /// The subscription state of channel `id`, if it is in the table
+    pub fn subscription(&self, id: u16) -> Option<&Subscription> {
+        self.index(id).map(|index| &self.subscriptions[index])
+    }
+
+    /// Unsubscribes every channel, e.g. when the host disconnects
+    pub fn clear(&mut self) {
+        self.subscriptions.fill(Subscription::new());
+    }
+
+    fn index(&self, id: u16) -> Option<usize> {
+        self.channels.iter().position(|channel| channel.id == id)
+    }
+
+    /// Handles subscription requests and packs the reply. Returns false, leaving `reply` untouched,
+    /// for requests that aren't part of the telemetry service.
+    pub fn handle<const T: usize>(&mut self, packet: &Packet<T>, reply: &mut Packet<T>) -> bool {
+        let code = packet.get_request();
+        let payload = packet.payload();
+
+        let _ = match code {
+            request::TELEMETRY_SUBSCRIBE => match payload {
+                [c0, c1, p0, p1, p2, p3] => {
+                    let id = u16::from_le_bytes([*c0, *c1]);
+                    let period = u32::from_le_bytes([*p0, *p1, *p2, *p3]);
+                    match self.index(id) {
+                        Some(index) if period > 0 => {
+                            let granted = period.max(self.channels[index].min_period_ms).max(1);
+                            let subscription = &mut self.subscriptions[index];
+                            if subscription.period_ms == 0 {
+                                subscription.next_due = None;
+                            }
+                            subscription.period_ms = granted;
+
+                            let mut data = [0u8; 6];
+                            data[..2].copy_from_slice(&id.to_le_bytes());
+                            data[2..].copy_from_slice(&granted.to_le_bytes());
+                            reply.pack_data(code, &data)
+                        }
+                        _ => reply.pack_error(code, response::INVALID_ARGUMENT, &[]),
+                    }
+                }
+                _ => reply.pack_error(code, response::INVALID_ARGUMENT, &[]),
+            },
+            request::TELEMETRY_UNSUBSCRIBE => match payload {
+                [c0, c1] => match self.index(u16::from_le_bytes([*c0, *c1])) {
+                    Some(index) => {
+                        self.subscriptions[index].period_ms = 0;
+                        reply.pack_data(code, payload)
+                    }
+                    None => reply.pack_error(code, response::INVALID_ARGUMENT, &[]),
+                },
+                _ => reply.pack_error(code, response::INVALID_ARGUMENT, &[]),
+            },
+            _ => return false,
+        };
+        true
+    }
+
+    fn refill(&mut self, now_ms: u32, packet_size: usize) {
+        let elapsed = match self.last_poll {
+            Some(last) => now_ms.wrapping_sub(last) as u64,
+            None => 0,
+        };
+        self.last_poll = Some(now_ms);
+        if self.bandwidth == 0 {
+            return;
+        }
+        let burst = (self.bandwidth as u64 / 10).max(packet_size as u64) * 1000;
+        self.budget = (self.budget + elapsed * self.bandwidth as u64).min(burst);
+    }
+
+    /// Fills in `packet` with the next due sample. `sample` is called with the channel ID and the
+    /// space left in the packet, and returns how many bytes of sample it wrote. Returns true if
+    /// `packet` should be sent; call again until it returns false. With a bandwidth limit, nothing is
+    /// sampled until the budget covers a full packet.
+    pub fn poll<const T: usize, F>(
+        &mut self,
+        now_ms: u32,
+        packet: &mut Packet<T>,
+        mut sample: F,
+    ) -> bool
+    where
+        F: FnMut(u16, &mut [u8]) -> usize,
+    {
+        self.refill(now_ms, FLEM_HEADER_SIZE + T);
+        if T < SAMPLE_HEADER_SIZE {
+            return false;
+        }
+
+        let count = self.channels.len();
+        for step in 0..count {
+            let index = (self.next_channel + step) % count;
+            let subscription = &mut self.subscriptions[index];
+            if subscription.period_ms == 0 {
+                continue;
+            }
+            let due = *subscription.next_due.get_or_insert(now_ms);
+            if !reached(now_ms, due) {
+                continue;
+            }
+
+            // Checked against a full packet before sampling, so a sample is never taken (nor
+            // `packet` touched) only to be thrown away. Stays due and goes out once the link has room.
+            if self.bandwidth != 0 && self.budget < (FLEM_HEADER_SIZE + T) as u64 * 1000 {
+                return false;
+            }
+
+            let id = self.channels[index].id;
+            packet.reset_lazy();
+            let data = packet.data_mut();
+            let length = SAMPLE_HEADER_SIZE
+                + sample(id, &mut data[SAMPLE_HEADER_SIZE..]).min(T - SAMPLE_HEADER_SIZE);
+            if self.bandwidth != 0 {
+                self.budget -= (FLEM_HEADER_SIZE + length) as u64 * 1000;
+            }
+
+            let subscription = &mut self.subscriptions[index];
+            let data = packet.data_mut();
+            data[..2].copy_from_slice(&id.to_le_bytes());
+            data[2..4].copy_from_slice(&subscription.sequence.to_le_bytes());
+            subscription.sequence = subscription.sequence.wrapping_add(1);
+
+            let next = due.wrapping_add(subscription.period_ms);
+            subscription.next_due = Some(if reached(now_ms, next) {
+                now_ms.wrapping_add(subscription.period_ms)
+            } else {
+                next
+            });
+            self.next_channel = (index + 1) % count;
+
+            packet.length = length as u16;
+            packet.set_request(request::TELEMETRY_DATA);
+            packet.set_response(response::ASYNC);
+            packet.pack();
+            return true;
+        }
+        false
+    }
+}
+
+/// A decoded telemetry sample (requires features = ["std"])
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub channel: u16,
+    pub sequence: u16,
+    pub data: Vec<u8>,
+    /// Samples of this channel lost since the previous one seen by the `Subscriber`
+    pub missed: u16,
+}
+
+#[cfg(feature = "std")]
+impl Sample {
+    /// Decodes a `request::TELEMETRY_DATA` packet, with `missed` left at 0
+    pub fn from_packet<const T: usize>(packet: &Packet<T>) -> Option<Self> {
+        if packet.get_request() != request::TELEMETRY_DATA
+            || packet.get_response() != response::ASYNC
+        {
+            return None;
+        }
+        match packet.payload() {
+            [c0, c1, s0, s1, data @ ..] => Some(Sample {
+                channel: u16::from_le_bytes([*c0, *c1]),
+                sequence: u16::from_le_bytes([*s0, *s1]),
+                data: data.to_vec(),
+                missed: 0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Subscribes to `channel` and returns the period the device granted (requires features = ["std"])
+#[cfg(feature = "std")]
+pub fn subscribe<const T: usize>(
+    client: &Client<T>,
+    channel: u16,
+    period_ms: u32,
+) -> Result<u32, ClientError> {
+    let mut data = [0u8; 6];
+    data[..2].copy_from_slice(&channel.to_le_bytes());
+    data[2..].copy_from_slice(&period_ms.to_le_bytes());
+
+    let reply = client.call(request::TELEMETRY_SUBSCRIBE, &data)?;
+    match reply.payload() {
+        [c0, c1, p0, p1, p2, p3] if u16::from_le_bytes([*c0, *c1]) == channel => {
+            Ok(u32::from_le_bytes([*p0, *p1, *p2, *p3]))
+        }
+        _ => Err(ClientError::InvalidReply),
+    }
+}
+
+/// Stops the samples of `channel` (requires features = ["std"])
+#[cfg(feature = "std")]
+pub fn unsubscribe<const T: usize>(client: &Client<T>, channel: u16) -> Result<(), ClientError> {
+    client.call(request::TELEMETRY_UNSUBSCRIBE, &channel.to_le_bytes())?;
+    Ok(())
+}
+
+/// Decodes the samples arriving on a receive queue, skipping every other packet (requires features
+/// = ["std"])
+#[cfg(feature = "std")]
+pub struct Subscriber<'a, const T: usize> {
+    rx: &'a Receiver<Packet<T>>,
+    /// Drained for samples the client received while waiting for a reply
+    client: Option<&'a Client<'a, T>>,
+    /// Next expected sequence per channel
+    expected: Vec<(u16, u16)>,
+}
+
+#[cfg(feature = "std")]
+impl<'a, const T: usize> Subscriber<'a, T> {
+    pub fn new(rx: &'a Receiver<Packet<T>>) -> Self {
+        Subscriber {
+            rx,
+            client: None,
+            expected: Vec::new(),
+        }
+    }
+
+    /// Reads the client's receive queue, starting with the samples it kept while waiting for replies
+    pub fn from_client(client: &'a Client<'a, T>) -> Self {
+        Subscriber {
+            rx: client.rx,
+            client: Some(client),
+            expected: Vec::new(),
+        }
+    }
+
+    fn track(&mut self, mut sample: Sample) -> Sample {
+        match self
+            .expected
+            .iter_mut()
+            .find(|(channel, _)| *channel == sample.channel)
+        {
+            Some((_, expected)) => {
+                sample.missed = sample.sequence.wrapping_sub(*expected);
+                *expected = sample.sequence.wrapping_add(1);
+            }
+            None => self
+                .expected
+                .push((sample.channel, sample.sequence.wrapping_add(1))),
+        }
+        sample
+    }
+
+    /// Waits up to `timeout` for the next sample. Returns `None` on timeout or disconnect.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<Sample> {
+        while let Some(packet) = self.client.and_then(|client| client.take_unmatched()) {
+            if let Some(sample) = Sample::from_packet(&packet) {
+                return Some(self.track(sample));
+            }
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.rx.recv_timeout(remaining) {
+                Ok(packet) => {
+                    if let Some(sample) = Sample::from_packet(&packet) {
+                        return Some(self.track(sample));
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                    return None
+                }
+            }
+        }
+    }
+
+    /// Iterates over samples until none arrives for `timeout`
+    pub fn iter(&mut self, timeout: Duration) -> Samples<'_, 'a, T> {
+        Samples {
+            subscriber: self,
+            timeout,
+        }
+    }
+
+    /// Calls `callback` with every sample arriving within `duration`
+    pub fn run_for<F: FnMut(Sample)>(&mut self, duration: Duration, mut callback: F) {
+        let deadline = Instant::now() + duration;
+        while let Some(sample) =
+            self.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            callback(sample);
+        }
+    }
+}
+
+/// Iterator returned by `Subscriber::iter` (requires features = ["std"])
+#[cfg(feature = "std")]
+pub struct Samples<'s, 'a, const T: usize> {
+    subscriber: &'s mut Subscriber<'a, T>,
+    timeout: Duration,
+}
+
+#[cfg(feature = "std")]
+impl<const T: usize> Iterator for Samples<'_, '_, T> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        self.subscriber.recv_timeout(self.timeout)
+    }
+}
@@ -0,0 +1,242 @@
+//! NTP style time synchronization between host and device.
+//!
+//! | Request              | Data                               | Reply                                      |
+//! |----------------------|------------------------------------|--------------------------------------------|
+//! | `request::TIME_SYNC` | T1, host transmit time (u64)       | T1, T2 device receive, T3 device transmit  |
+//! | `request::TIME_SET`  | device time (u64), host time (u64) | -                                          |
+//!
+//! All times are microseconds, little endian. The host records T4 when the reply arrives and
+//! computes the device clock offset `((T2 - T1) + (T3 - T4)) / 2` and the round trip delay
+//! `(T4 - T1) - (T3 - T2)`, see `Exchange`. It then tells the device which host time matches one of
+//! its own with `request::TIME_SET`.
+//!
+//! `TimeSync` is the `no_std` device side. Device time comes from a `TimeSource` tick counter.
+//! After the first `request::TIME_SET`, `to_host_us` converts device time to host time. Later
+//! points spaced at least `MIN_DRIFT_INTERVAL_US` apart also measure how fast the device clock runs
+//! compared to the host's, and conversions are corrected for that drift.
+//!
+//! With features = ["std"], `sync` runs the exchange against host wall clock time (microseconds
+//! since the UNIX epoch), keeping the round with the shortest delay.
+
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "std")]
+use crate::client::{Client, ClientError};
+use crate::{request, response, Packet};
+
+/// Shortest device time between two `request::TIME_SET` points used to measure drift
+pub const MIN_DRIFT_INTERVAL_US: u64 = 1_000_000;
+
+/// A free running device tick counter
+pub trait TimeSource {
+    /// Ticks since an arbitrary start. Must not wrap; extend narrower hardware counters to 64 bits.
+    fn ticks(&mut self) -> u64;
+    /// Ticks per second
+    fn frequency(&self) -> u64;
+}
+
+/// The four timestamps of one exchange, in microseconds. T1 and T4 are host time, T2 and T3 device
+/// time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    pub t1: u64,
+    pub t2: u64,
+    pub t3: u64,
+    pub t4: u64,
+}
+
+impl Exchange {
+    /// Device time minus host time
+    pub fn offset(&self) -> i64 {
+        let outbound = self.t2 as i128 - self.t1 as i128;
+        let inbound = self.t3 as i128 - self.t4 as i128;
+        ((outbound + inbound) / 2) as i64
+    }
+
+    /// Round trip time, not counting the time the device took to reply
+    pub fn delay(&self) -> u64 {
+        let total = self.t4.saturating_sub(self.t1);
+        total.saturating_sub(self.t3.saturating_sub(self.t2))
+    }
+}
+
+/// A device time and the host time it matches
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct SyncPoint {
+    device_us: u64,
+    host_us: u64,
+}
+
+/// Device side time synchronization
+pub struct TimeSync<S: TimeSource> {
+    source: S,
+    /// Newest point, conversions are relative to it
+    reference: Option<SyncPoint>,
+    /// Start of the interval the next drift measurement covers
+    baseline: Option<SyncPoint>,
+    /// How much faster the host clock runs than the device's, in parts per billion
+    drift_ppb: i64,
+}
+
+impl<S: TimeSource> TimeSync<S> {
+    pub const fn new(source: S) -> Self {
+        TimeSync {
+            source,
+            reference: None,
+            baseline: None,
+            drift_ppb: 0,
+        }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Current device time in microseconds
+    pub fn device_us(&mut self) -> u64 {
+        let frequency = self.source.frequency().max(1) as u128;
+        (self.source.ticks() as u128 * 1_000_000 / frequency) as u64
+    }
+
+    /// True once the host has set the time
+    pub fn is_synchronized(&self) -> bool {
+        self.reference.is_some()
+    }
+
+    /// Measured drift of the host clock against the device's, in parts per billion. Positive if the
+    /// device clock runs slow.
+    pub fn drift_ppb(&self) -> i64 {
+        self.drift_ppb
+    }
+
+    /// Host time matching `device_us`, or `None` before the first `request::TIME_SET`
+    pub fn to_host_us(&self, device_us: u64) -> Option<u64> {
+        let reference = self.reference?;
+        let elapsed = device_us as i128 - reference.device_us as i128;
+        let corrected = elapsed + elapsed * self.drift_ppb as i128 / 1_000_000_000;
+        Some((reference.host_us as i128 + corrected).max(0) as u64)
+    }
+
+    /// Current host time, or `None` before the first `request::TIME_SET`
+    pub fn host_us(&mut self) -> Option<u64> {
+        let now = self.device_us();
+        self.to_host_us(now)
+    }
+
+    /// Records that `device_us` matches `host_us`, as the host does with `request::TIME_SET`
+    pub fn set(&mut self, device_us: u64, host_us: u64) {
+        let point = SyncPoint { device_us, host_us };
+        match self.baseline {
+            Some(baseline) if device_us >= baseline.device_us + MIN_DRIFT_INTERVAL_US => {
+                let device_elapsed = (device_us - baseline.device_us) as i128;
+                let host_elapsed = host_us as i128 - baseline.host_us as i128;
+                self.drift_ppb =
+                    ((host_elapsed - device_elapsed) * 1_000_000_000 / device_elapsed) as i64;
+                self.baseline = Some(point);
+            }
+            Some(baseline) if device_us >= baseline.device_us => {}
+            // First point, or the device clock restarted
+            _ => {
+                self.baseline = Some(point);
+                self.drift_ppb = 0;
+            }
+        }
+        self.reference = Some(point);
+    }
+
+    /// Handles time requests and packs the reply. Returns false, leaving `reply` untouched, for
+    /// requests that aren't part of time synchronization.
+    pub fn handle<const T: usize>(&mut self, packet: &Packet<T>, reply: &mut Packet<T>) -> bool {
+        let received = self.device_us();
+        let code = packet.get_request();
+        let payload = packet.payload();
+
+        let _ = match code {
+            request::TIME_SYNC => {
+                if payload.len() == 8 {
+                    let mut data = [0u8; 24];
+                    data[..8].copy_from_slice(payload);
+                    data[8..16].copy_from_slice(&received.to_le_bytes());
+                    data[16..].copy_from_slice(&self.device_us().to_le_bytes());
+                    reply.pack_data(code, &data)
+                } else {
+                    reply.pack_error(code, response::INVALID_ARGUMENT, &[])
+                }
+            }
+            request::TIME_SET => {
+                if payload.len() == 16 {
+                    let mut device_us = [0u8; 8];
+                    let mut host_us = [0u8; 8];
+                    device_us.copy_from_slice(&payload[..8]);
+                    host_us.copy_from_slice(&payload[8..]);
+                    self.set(u64::from_le_bytes(device_us), u64::from_le_bytes(host_us));
+                    reply.pack_data(code, &[])
+                } else {
+                    reply.pack_error(code, response::INVALID_ARGUMENT, &[])
+                }
+            }
+            _ => return false,
+        };
+        true
+    }
+}
+
+/// Microseconds since the UNIX epoch (requires features = ["std"])
+#[cfg(feature = "std")]
+pub fn host_now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+/// Runs `rounds` exchanges, sets the device time from the one with the shortest delay and returns
+/// it (requires features = ["std"])
+#[cfg(feature = "std")]
+pub fn sync<const T: usize>(client: &Client<T>, rounds: u8) -> Result<Exchange, ClientError> {
+    let mut best: Option<Exchange> = None;
+
+    for _ in 0..rounds.max(1) {
+        let t1 = host_now_us();
+        let mut reply = client.call(request::TIME_SYNC, &t1.to_le_bytes())?;
+        let (t2, t3) = loop {
+            let payload = reply.payload();
+            if payload.len() != 24 {
+                return Err(ClientError::InvalidReply);
+            }
+            let field = |index: usize| {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&payload[index * 8..index * 8 + 8]);
+                u64::from_le_bytes(bytes)
+            };
+            if field(0) == t1 {
+                break (field(1), field(2));
+            }
+            // A late reply to an earlier exchange, the reply to this one is queued behind it
+            reply = client.next_reply(request::TIME_SYNC)?;
+        };
+        let t4 = host_now_us();
+
+        let exchange = Exchange { t1, t2, t3, t4 };
+        if best.map_or(true, |best| exchange.delay() < best.delay()) {
+            best = Some(exchange);
+        }
+    }
+
+    let exchange = best.ok_or(ClientError::InvalidReply)?;
+    let host_us = (exchange.t3 as i128 - exchange.offset() as i128).max(0) as u64;
+    let mut data = [0u8; 16];
+    data[..8].copy_from_slice(&exchange.t3.to_le_bytes());
+    data[8..].copy_from_slice(&host_us.to_le_bytes());
+    client.call(request::TIME_SET, &data)?;
+
+    Ok(exchange)
+}
@@ -0,0 +1,295 @@
+//! Transports implementing the `Channel` trait (requires features = ["std"]).
+//!
+//! `TcpChannel` carries FLEM over a TCP stream, e.g. to a serial-to-Ethernet bridge, a device
+//! emulator or a hub board. `SerialChannel` (requires features = ["serial"]) carries it over a
+//! serial port such as a USB-UART adapter.
+//!
+//! Both receive bytes on one thread and run them through `construct`, and only validated packets
+//! are passed to the program. Packets sent to the `Sender` are transmitted by a second thread.
+
+extern crate std;
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+#[cfg(feature = "serial")]
+use alloc::boxed::Box;
+#[cfg(feature = "serial")]
+use serialport::SerialPort;
+
+use crate::{traits::Channel, Packet, Status};
+
+pub struct TcpChannel<const T: usize> {
+    stream: Option<TcpStream>,
+    listening: Arc<AtomicBool>,
+}
+
+impl<const T: usize> TcpChannel<T> {
+    pub fn new() -> Self {
+        TcpChannel {
+            stream: None,
+            listening: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Wraps an already connected stream, e.g. one returned by `TcpListener::accept`
+    pub fn from_stream(stream: TcpStream) -> Self {
+        TcpChannel {
+            stream: Some(stream),
+            listening: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl<const T: usize> Default for TcpChannel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const T: usize> Channel<T> for TcpChannel<T> {
+    type Error = io::Error;
+
+    /// TCP peers can't be enumerated, so this is always empty. Pass a `host:port` address to `connect`.
+    fn list_devices(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn connect(&mut self, device: &String) -> Result<(), Self::Error> {
+        let stream = TcpStream::connect(device.as_str())?;
+        stream.set_nodelay(true)?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> Result<(), Self::Error> {
+        self.unlisten()?;
+        if let Some(stream) = self.stream.take() {
+            stream.shutdown(Shutdown::Both)?;
+        }
+        Ok(())
+    }
+
+    /// Starts the receive and transmit threads. `connect` (or `from_stream`) must be called first,
+    /// otherwise the returned channels are already closed.
+    fn listen(
+        &mut self,
+        rx_sleep_time_ms: u64,
+        tx_sleep_time_ms: u64,
+    ) -> (Sender<Packet<T>>, Receiver<Packet<T>>) {
+        let (tx_packet_from_program, packet_to_transmit) = mpsc::channel::<Packet<T>>();
+        let (validated_packet, rx_packet_to_program) = mpsc::channel::<Packet<T>>();
+
+        let (rx_stream, tx_stream) = match self.stream.as_ref().map(|stream| {
+            let rx_stream = stream.try_clone()?;
+            let tx_stream = stream.try_clone()?;
+            rx_stream.set_read_timeout(Some(Duration::from_millis(rx_sleep_time_ms.max(1))))?;
+            Ok::<_, io::Error>((rx_stream, tx_stream))
+        }) {
+            Some(Ok(streams)) => streams,
+            _ => return (tx_packet_from_program, rx_packet_to_program),
+        };
+
+        spawn_link(
+            "TCP",
+            rx_stream,
+            tx_stream,
+            self.listening.clone(),
+            validated_packet,
+            packet_to_transmit,
+            tx_sleep_time_ms,
+        );
+        (tx_packet_from_program, rx_packet_to_program)
+    }
+
+    fn unlisten(&mut self) -> Result<(), Self::Error> {
+        self.listening.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Default baud rate of `SerialChannel::new`
+#[cfg(feature = "serial")]
+pub const DEFAULT_BAUD_RATE: u32 = 115_200;
+
+#[cfg(feature = "serial")]
+pub struct SerialChannel<const T: usize> {
+    baud_rate: u32,
+    port: Option<Box<dyn SerialPort>>,
+    listening: Arc<AtomicBool>,
+}
+
+#[cfg(feature = "serial")]
+impl<const T: usize> SerialChannel<T> {
+    /// Creates a channel that opens ports at `baud_rate`, 8N1 without flow control
+    pub fn new(baud_rate: u32) -> Self {
+        SerialChannel {
+            baud_rate,
+            port: None,
+            listening: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Wraps an already open port, e.g. one end of `serialport::TTYPort::pair`
+    pub fn from_port(port: Box<dyn SerialPort>) -> Self {
+        SerialChannel {
+            baud_rate: port.baud_rate().unwrap_or(DEFAULT_BAUD_RATE),
+            port: Some(port),
+            listening: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+#[cfg(feature = "serial")]
+impl<const T: usize> Default for SerialChannel<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_BAUD_RATE)
+    }
+}
+
+#[cfg(feature = "serial")]
+impl<const T: usize> Channel<T> for SerialChannel<T> {
+    type Error = serialport::Error;
+
+    /// Names of the serial ports on this machine, e.g. `/dev/ttyUSB0` or `COM3`
+    fn list_devices(&self) -> Vec<String> {
+        serialport::available_ports()
+            .map(|ports| ports.into_iter().map(|port| port.port_name).collect())
+            .unwrap_or_default()
+    }
+
+    fn connect(&mut self, device: &String) -> Result<(), Self::Error> {
+        let port = serialport::new(device.as_str(), self.baud_rate)
+            .timeout(Duration::from_millis(10))
+            .open()?;
+        self.port = Some(port);
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> Result<(), Self::Error> {
+        self.unlisten()?;
+        self.port = None;
+        Ok(())
+    }
+
+    /// Starts the receive and transmit threads. `connect` (or `from_port`) must be called first,
+    /// otherwise the returned channels are already closed.
+    fn listen(
+        &mut self,
+        rx_sleep_time_ms: u64,
+        tx_sleep_time_ms: u64,
+    ) -> (Sender<Packet<T>>, Receiver<Packet<T>>) {
+        let (tx_packet_from_program, packet_to_transmit) = mpsc::channel::<Packet<T>>();
+        let (validated_packet, rx_packet_to_program) = mpsc::channel::<Packet<T>>();
+
+        let (rx_port, tx_port) = match self.port.as_mut().map(|port| {
+            port.set_timeout(Duration::from_millis(rx_sleep_time_ms.max(1)))?;
+            Ok::<_, serialport::Error>((port.try_clone()?, port.try_clone()?))
+        }) {
+            Some(Ok(ports)) => ports,
+            _ => return (tx_packet_from_program, rx_packet_to_program),
+        };
+
+        spawn_link(
+            "serial",
+            rx_port,
+            tx_port,
+            self.listening.clone(),
+            validated_packet,
+            packet_to_transmit,
+            tx_sleep_time_ms,
+        );
+
+        (tx_packet_from_program, rx_packet_to_program)
+    }
+
+    fn unlisten(&mut self) -> Result<(), Self::Error> {
+        self.listening.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Starts the receive and transmit threads of a link. Both stop once `listening` is cleared, the
+/// link fails or the program drops its end of the channels.
+#[cfg_attr(
+    not(any(feature = "log", feature = "tracing")),
+    allow(unused_variables)
+)]
+fn spawn_link<const T: usize, R, W>(
+    label: &'static str,
+    mut rx_stream: R,
+    mut tx_stream: W,
+    listening: Arc<AtomicBool>,
+    validated_packet: Sender<Packet<T>>,
+    packet_to_transmit: Receiver<Packet<T>>,
+    tx_sleep_time_ms: u64,
+) where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    listening.store(true, Ordering::Relaxed);
+    let listening_rx = listening.clone();
+    let listening_tx = listening;
+
+    // Rx thread - build packets from the stream and pass validated ones to the program
+    thread::spawn(move || {
+        let mut packet = Packet::<T>::new();
+        let mut buffer = [0u8; 256];
+
+        while listening_rx.load(Ordering::Relaxed) {
+            let count = match rx_stream.read(&mut buffer) {
+                Ok(0) => break, // Peer closed the connection
+                Ok(count) => count,
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    continue
+                }
+                Err(_) => break,
+            };
+
+            for byte in buffer[..count].iter() {
+                match packet.construct(*byte) {
+                    Ok(_) => {
+                        flem_debug!("FLEM {} received: {:?}", label, packet);
+                        if validated_packet.send(packet).is_err() {
+                            return;
+                        }
+                        packet.reset_lazy();
+                    }
+                    Err(Status::PacketBuilding) => {}
+                    Err(_) => packet.reset_lazy(),
+                }
+            }
+        }
+    });
+
+    // Tx thread - transmit packets from the program
+    thread::spawn(move || {
+        while listening_tx.load(Ordering::Relaxed) {
+            match packet_to_transmit.recv_timeout(Duration::from_millis(tx_sleep_time_ms)) {
+                Ok(packet) => {
+                    if tx_stream.write_all(packet.bytes()).is_err() {
+                        break;
+                    }
+                    flem_debug!("FLEM {} sent: {:?}", label, packet);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+}
@@ -0,0 +1,114 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::address::{Address, BROADCAST};
+    use flem::statistics::LinkStatistics;
+    use flem::{request, response, Packet, Status};
+
+    const FLEM_PACKET_SIZE: usize = 32;
+    const HOST: u8 = 0;
+    const DEVICE: u8 = 7;
+
+    /// Feeds a stream of packets, returning the payload of each one received
+    fn receive(
+        rx: &mut Packet<FLEM_PACKET_SIZE>,
+        bytes: &[u8],
+        stats: &mut LinkStatistics,
+    ) -> Vec<Vec<u8>> {
+        let mut received = Vec::new();
+        for byte in bytes {
+            match rx.construct_counted(*byte, stats) {
+                Ok(_) => {
+                    received.push(rx.payload().to_vec());
+                    rx.reset_lazy();
+                }
+                Err(Status::PacketBuilding) | Err(Status::HeaderBytesNotFound) => {}
+                Err(_) => rx.reset_lazy(),
+            }
+        }
+        received
+    }
+
+    fn bus() -> Vec<u8> {
+        let mut tx = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut bytes = Vec::new();
+        for destination in [DEVICE, 3, BROADCAST, 12] {
+            tx.pack_addressed(0x10, Address::new(destination, HOST), &[destination])
+                .unwrap();
+            bytes.extend_from_slice(tx.bytes());
+        }
+        // A corrupted packet for someone else isn't even checked
+        tx.pack_addressed(0x10, Address::new(3, HOST), &[0x55; 20])
+            .unwrap();
+        let mut corrupted = tx.bytes().to_vec();
+        corrupted[2] ^= 0xFF;
+        bytes.extend_from_slice(&corrupted);
+        // Too short to be addressed
+        tx.pack_data(0x10, &[]).unwrap();
+        bytes.extend_from_slice(tx.bytes());
+        bytes
+    }
+
+    #[test]
+    fn packets_are_filtered() {
+        let mut stats = LinkStatistics::new();
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+        rx.set_local_address(Some(DEVICE));
+
+        let received = receive(&mut rx, &bus(), &mut stats);
+        assert_eq!(
+            received,
+            vec![vec![DEVICE, HOST, DEVICE], vec![BROADCAST, HOST, BROADCAST]]
+        );
+        assert_eq!(stats.packets_received, 2);
+        assert_eq!(stats.not_addressed, 4);
+        assert_eq!(stats.checksum_errors, 0);
+        assert_eq!(stats.bytes_discarded, 0);
+
+        // The address survives resets
+        rx.reset();
+        assert_eq!(rx.local_address(), Some(DEVICE));
+    }
+
+    #[test]
+    fn point_to_point_by_default() {
+        let mut stats = LinkStatistics::new();
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+        assert_eq!(rx.local_address(), None);
+
+        let received = receive(&mut rx, &bus(), &mut stats);
+        assert_eq!(received.len(), 5);
+        assert_eq!(stats.checksum_errors, 1);
+        assert_eq!(stats.not_addressed, 0);
+    }
+
+    #[test]
+    fn replies_keep_the_address() {
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut tx = Packet::<FLEM_PACKET_SIZE>::new();
+        rx.pack_addressed(request::ID, Address::new(DEVICE, HOST), &[9, 8])
+            .unwrap();
+
+        let address = rx.strip_address().unwrap();
+        assert!(rx.validate());
+        assert_eq!(address, Address::new(DEVICE, HOST));
+        assert!(!address.is_broadcast());
+        assert_eq!(rx.payload(), &[9, 8]);
+        assert_eq!(rx.address(), Some(Address::new(9, 8)));
+
+        tx.pack_error(request::ID, response::INVALID_ARGUMENT, &[1])
+            .unwrap();
+        tx.add_address(address.reply()).unwrap();
+        assert!(tx.validate());
+        assert_eq!(tx.get_response(), response::INVALID_ARGUMENT);
+        assert_eq!(tx.payload(), &[HOST, DEVICE, 1]);
+
+        tx.pack_data(request::ID, &[0; FLEM_PACKET_SIZE - 1])
+            .unwrap();
+        assert_eq!(tx.add_address(address.reply()), Err(Status::PacketOverflow));
+
+        tx.pack_data(request::ID, &[1]).unwrap();
+        assert_eq!(tx.strip_address(), None);
+        assert_eq!(tx.payload(), &[1]);
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "auth")]
+
+#[cfg(test)]
+mod tests {
+
+    use flem::auth::{self, AuthRouter, Privilege, Route, MAC_SIZE, NONCE_SIZE};
+    use flem::{request, response, Packet, Status};
+    use std::sync::atomic::{AtomicU8, Ordering};
+
+    const FLEM_PACKET_SIZE: usize = 64;
+    const WRITE_CALIBRATION: u16 = 0x30;
+    const ERASE: u16 = 0x31;
+    const READ: u16 = 0x32;
+    const PRIVILEGED: [u16; 2] = [WRITE_CALIBRATION, ERASE];
+    const SECRET: &[u8] = b"shared secret";
+
+    static NONCE_SEED: AtomicU8 = AtomicU8::new(0);
+
+    fn fill_nonce(nonce: &mut [u8; NONCE_SIZE]) {
+        nonce.fill(NONCE_SEED.fetch_add(1, Ordering::Relaxed));
+    }
+
+    fn send(
+        router: &mut AuthRouter,
+        request: u16,
+        data: &[u8],
+    ) -> (Route, Packet<FLEM_PACKET_SIZE>) {
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut tx = Packet::<FLEM_PACKET_SIZE>::new();
+        rx.pack_data(request, data).unwrap();
+        let route = router.route(&rx, &mut tx);
+        (route, tx)
+    }
+
+    #[test]
+    fn privileged_requests_need_authentication() {
+        let mut router = AuthRouter::new(SECRET, &PRIVILEGED, fill_nonce);
+
+        assert_eq!(send(&mut router, READ, &[]).0, Route::Allowed);
+        let (route, reply) = send(&mut router, ERASE, &[]);
+        assert_eq!(route, Route::Rejected);
+        assert_eq!(reply.get_request(), ERASE);
+        assert_eq!(reply.get_response(), response::UNAUTHORIZED);
+
+        let (route, challenge) = send(&mut router, request::AUTH_CHALLENGE, &[]);
+        assert_eq!(route, Route::Handled);
+        assert_eq!(challenge.payload().len(), NONCE_SIZE);
+
+        let mut answer = Packet::<FLEM_PACKET_SIZE>::new();
+        auth::respond(SECRET, &challenge, &mut answer).unwrap();
+        assert_eq!(answer.payload().len(), MAC_SIZE);
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        assert_eq!(router.route(&answer, &mut reply), Route::Handled);
+        assert_eq!(reply.get_response(), response::SUCCESS);
+        assert_eq!(router.privilege(), Privilege::Authenticated);
+
+        assert_eq!(send(&mut router, WRITE_CALIBRATION, &[1]).0, Route::Allowed);
+        assert_eq!(send(&mut router, ERASE, &[]).0, Route::Allowed);
+
+        router.revoke();
+        assert_eq!(send(&mut router, ERASE, &[]).0, Route::Rejected);
+    }
+
+    #[test]
+    fn wrong_and_replayed_responses_are_refused() {
+        let mut router = AuthRouter::new(SECRET, &PRIVILEGED, fill_nonce);
+
+        let (_, challenge) = send(&mut router, request::AUTH_CHALLENGE, &[]);
+        let mut answer = Packet::<FLEM_PACKET_SIZE>::new();
+        auth::respond(b"wrong secret", &challenge, &mut answer).unwrap();
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        router.route(&answer, &mut reply);
+        assert_eq!(reply.get_response(), response::UNAUTHORIZED);
+        assert_eq!(router.privilege(), Privilege::Unauthenticated);
+
+        // The nonce is spent after one attempt, even a correct answer to it is refused now
+        auth::respond(SECRET, &challenge, &mut answer).unwrap();
+        router.route(&answer, &mut reply);
+        assert_eq!(reply.get_response(), response::UNAUTHORIZED);
+
+        // A response captured from an earlier session doesn't answer a new challenge
+        let (_, _) = send(&mut router, request::AUTH_CHALLENGE, &[]);
+        router.route(&answer, &mut reply);
+        assert_eq!(reply.get_response(), response::UNAUTHORIZED);
+        assert_eq!(send(&mut router, ERASE, &[]).0, Route::Rejected);
+
+        // Only successful challenge replies can be answered
+        assert_eq!(
+            auth::respond(SECRET, &reply, &mut answer),
+            Err(Status::UnrecognizedRequest)
+        );
+    }
+}
@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::address::{Address, BROADCAST};
+    use flem::bridge::{convert, find_route, Route, RouteStatistics};
+    use flem::{response, Packet, Status};
+
+    const HOST_PACKET_SIZE: usize = 512;
+    const DEVICE_PACKET_SIZE: usize = 128;
+
+    #[test]
+    fn routes_match_in_order() {
+        const ROUTES: [Route; 4] = [
+            Route::address(3, 0),
+            Route::address(4, 1),
+            Route::requests(0x20, 0x2F, 1),
+            Route::any(2),
+        ];
+
+        let mut packet = Packet::<HOST_PACKET_SIZE>::new();
+        packet
+            .pack_addressed(0x20, Address::new(3, 0), &[])
+            .unwrap();
+        assert_eq!(find_route(&ROUTES, &packet), Some(0));
+        packet
+            .pack_addressed(0x10, Address::new(4, 0), &[])
+            .unwrap();
+        assert_eq!(find_route(&ROUTES, &packet), Some(1));
+        packet
+            .pack_addressed(0x10, Address::new(BROADCAST, 0), &[])
+            .unwrap();
+        assert!(ROUTES[..2].iter().all(|route| route.matches(&packet)));
+
+        packet.pack_data(0x2F, &[]).unwrap();
+        assert_eq!(find_route(&ROUTES, &packet), Some(2));
+        packet.pack_data(0x30, &[]).unwrap();
+        assert_eq!(find_route(&ROUTES, &packet), Some(3));
+        assert_eq!(find_route(&ROUTES[..3], &packet), None);
+    }
+
+    #[test]
+    fn packets_are_resized() {
+        let mut host = Packet::<HOST_PACKET_SIZE>::new();
+        let mut device = Packet::<DEVICE_PACKET_SIZE>::new();
+
+        host.pack_error(0x10, response::INVALID_ARGUMENT, &[7; DEVICE_PACKET_SIZE])
+            .unwrap();
+        convert(&host, &mut device).unwrap();
+        assert!(device.validate());
+        assert_eq!(device.get_request(), 0x10);
+        assert_eq!(device.get_response(), response::INVALID_ARGUMENT);
+        assert_eq!(device.payload(), host.payload());
+        assert_eq!(device.bytes(), host.bytes());
+
+        let mut statistics = RouteStatistics::new();
+        statistics.record(device.length());
+
+        host.pack_data(0x10, &[7; DEVICE_PACKET_SIZE + 1]).unwrap();
+        assert_eq!(convert(&host, &mut device), Err(Status::PacketOverflow));
+        statistics.record_drop();
+        assert_eq!(statistics.packets, 1);
+        assert_eq!(statistics.bytes, 10 + 128);
+        assert_eq!(statistics.dropped, 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn bridge_between_channels() {
+        use flem::bridge::Bridge;
+        use flem::client::Client;
+        use flem::emulator::Emulator;
+        use flem::traits::Channel;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{mpsc, Arc};
+        use std::thread;
+        use std::time::Duration;
+
+        // Two modules, each answering its own range of requests with its number
+        let mut modules: Vec<_> = [1u8, 2]
+            .into_iter()
+            .map(|number| {
+                let mut emulator = Emulator::<_, DEVICE_PACKET_SIZE>::new(number);
+                for code in 0x10..=0x2F {
+                    emulator.handle(code, |number, packet, reply| {
+                        let mut data = vec![*number];
+                        data.extend_from_slice(packet.payload());
+                        reply.pack_data(packet.get_request(), &data).unwrap();
+                    });
+                }
+                emulator
+            })
+            .collect();
+
+        // The host side link, as a host application would see it
+        let (host_tx, bridge_rx) = mpsc::channel::<Packet<HOST_PACKET_SIZE>>();
+        let (bridge_tx, host_rx) = mpsc::channel::<Packet<HOST_PACKET_SIZE>>();
+
+        let mut bridge =
+            Bridge::<HOST_PACKET_SIZE, DEVICE_PACKET_SIZE>::new((bridge_tx, bridge_rx));
+        let first = bridge.add_port(Channel::listen(&mut modules[0], 1, 1));
+        let second = bridge.add_port(Channel::listen(&mut modules[1], 1, 1));
+        let to_first = bridge.add_route(Route::requests(0x10, 0x1F, first));
+        let to_second = bridge.add_route(Route::requests(0x20, 0x2F, second));
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let runner = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                bridge.run(&stop, Duration::from_millis(1));
+                bridge
+            })
+        };
+
+        let client = Client::new(&host_tx, &host_rx);
+        assert_eq!(client.call(0x12, &[9]).unwrap().payload(), &[1, 9]);
+        assert_eq!(client.call(0x21, &[8]).unwrap().payload(), &[2, 8]);
+        assert_eq!(client.call(0x22, &[]).unwrap().payload(), &[2]);
+
+        // Too big for the modules, and unrouted
+        let big = vec![0; DEVICE_PACKET_SIZE + 1];
+        let quick = Client::new(&host_tx, &host_rx)
+            .with_timeout(Duration::from_millis(50))
+            .with_retries(0);
+        assert!(quick.call(0x13, &big).is_err());
+        assert!(quick.call(0x40, &[]).is_err());
+
+        stop.store(true, Ordering::Relaxed);
+        let bridge = runner.join().unwrap();
+        for module in modules.iter_mut() {
+            Channel::<DEVICE_PACKET_SIZE>::unlisten(module).unwrap();
+        }
+
+        let statistics = bridge.route_statistics(to_first);
+        assert_eq!(statistics.packets, 1);
+        assert_eq!(statistics.bytes, 11);
+        assert_eq!(statistics.dropped, 1);
+        assert_eq!(bridge.route_statistics(to_second).packets, 2);
+        assert_eq!(bridge.upstream_statistics().packets, 3);
+        assert_eq!(bridge.unrouted(), 1);
+    }
+}
@@ -0,0 +1,120 @@
+#![cfg(feature = "std")]
+
+#[cfg(test)]
+mod tests {
+
+    use flem::capture::{replay, CaptureReader, CaptureWriter, Direction, Recorder};
+    use flem::{Packet, Status};
+    use std::io::Cursor;
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const FLEM_PACKET_SIZE: usize = 64;
+
+    #[test]
+    fn write_and_read_back() {
+        let mut tx = Packet::<FLEM_PACKET_SIZE>::new();
+        tx.pack_data(0xF, &[1, 2, 3, 4, 5]).unwrap();
+
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut corrupted = tx.bytes().to_vec();
+        corrupted[10] ^= 0xFF;
+        for byte in corrupted.iter() {
+            let _ = rx.construct(*byte);
+        }
+
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        writer.record_packet(Direction::Outbound, &tx).unwrap();
+        writer.record_packet(Direction::Inbound, &rx).unwrap();
+        let capture = writer.into_inner();
+
+        let records: Vec<_> = CaptureReader::new(Cursor::new(capture))
+            .unwrap()
+            .map(|record| record.unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+
+        assert_eq!(records[0].direction, Direction::Outbound);
+        assert_eq!(records[0].bytes, tx.bytes());
+        let replayed = records[0].to_packet::<FLEM_PACKET_SIZE>().unwrap();
+        assert_eq!(replayed.payload(), &[1, 2, 3, 4, 5]);
+
+        assert_eq!(records[1].direction, Direction::Inbound);
+        assert_eq!(records[1].status, Status::ChecksumError);
+        assert_eq!(
+            records[1].to_packet::<FLEM_PACKET_SIZE>().unwrap_err(),
+            Status::ChecksumError
+        );
+    }
+
+    #[test]
+    fn oversized_lengths_are_capped() {
+        // A header claiming 0xFFFF bytes of data
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut result = Ok(());
+        for byte in [0x55, 0x55, 0x00, 0x00, 0x10, 0x00, 0x01, 0x00, 0xFF, 0xFF] {
+            result = rx.construct(byte);
+        }
+        assert_eq!(result, Err(Status::InvalidDataLengthDetected));
+
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        writer.record_packet(Direction::Inbound, &rx).unwrap();
+        let records: Vec<_> = CaptureReader::new(Cursor::new(writer.into_inner()))
+            .unwrap()
+            .map(|record| record.unwrap())
+            .collect();
+        assert_eq!(
+            records[0].bytes.len(),
+            flem::FLEM_HEADER_SIZE + FLEM_PACKET_SIZE
+        );
+        assert_eq!(records[0].status, Status::InvalidDataLengthDetected);
+    }
+
+    #[derive(Clone)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tap_and_replay() {
+        let buffer = SharedBuffer(Arc::new(Mutex::new(Vec::new())));
+        let recorder = Recorder::new(CaptureWriter::new(buffer.clone()).unwrap());
+
+        // Loop-back "transport"
+        let (transport_tx, transport_rx) = mpsc::channel::<Packet<FLEM_PACKET_SIZE>>();
+        let (tx, rx) = recorder.tap(transport_tx.clone(), transport_rx);
+
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(0x10, &[0xAA; 8]).unwrap();
+        tx.send(packet).unwrap();
+        let looped = rx.recv_timeout(Duration::from_millis(500)).unwrap();
+        assert_eq!(looped.bytes(), packet.bytes());
+        drop(tx);
+        drop(transport_tx);
+
+        let capture = buffer.0.lock().unwrap().clone();
+        let reader = CaptureReader::new(Cursor::new(capture.clone())).unwrap();
+        assert_eq!(
+            reader.count(),
+            2,
+            "Outbound and inbound copies are recorded"
+        );
+
+        let (replay_tx, replay_rx) = mpsc::channel::<Packet<FLEM_PACKET_SIZE>>();
+        let reader = CaptureReader::new(Cursor::new(capture)).unwrap();
+        assert_eq!(
+            replay(reader, Direction::Outbound, &replay_tx, false).unwrap(),
+            1
+        );
+        assert_eq!(replay_rx.recv().unwrap().bytes(), packet.bytes());
+    }
+}
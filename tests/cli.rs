@@ -0,0 +1,285 @@
+#![cfg(feature = "cli")]
+
+#[cfg(test)]
+mod tests {
+
+    use flem::introspect::{Field, FieldType, Introspection, RequestDescription};
+    use flem::{DataId, Packet, Status};
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::process::Command;
+    use std::thread;
+
+    const DEVICE_PACKET_SIZE: usize = 128;
+    const ECHO: u16 = 0x10;
+
+    static REQUESTS: [RequestDescription; 1] = [RequestDescription::new(
+        ECHO,
+        "ECHO",
+        &[Field::new("message", FieldType::Text)],
+        &[Field::new("message", FieldType::Text)],
+    )];
+
+    /// Answers requests until the host disconnects
+    fn serve(stream: &mut (impl Read + Write)) {
+        let mut rx = Packet::<DEVICE_PACKET_SIZE>::new();
+        let mut tx = Packet::<DEVICE_PACKET_SIZE>::new();
+        let mut byte = [0u8; 1];
+        let introspection = Introspection::new(&REQUESTS, &[]);
+
+        while let Ok(1) = stream.read(&mut byte) {
+            match rx.construct(byte[0]) {
+                Ok(_) => {
+                    match rx.get_request() {
+                        flem::request::ID => {
+                            let id = DataId::new("CLI Target", 1, 2, 3, DEVICE_PACKET_SIZE);
+                            tx.pack_id(&id, true).unwrap();
+                        }
+                        ECHO => tx.pack_data(ECHO, rx.payload()).unwrap(),
+                        flem::request::DESCRIBE => {
+                            introspection.handle(&rx, &mut tx);
+                        }
+                        request => tx
+                            .pack_error(request, flem::response::UNKNOWN_REQUEST, &[])
+                            .unwrap(),
+                    }
+                    stream.write_all(tx.bytes()).unwrap();
+                    rx.reset_lazy();
+                }
+                Err(Status::PacketBuilding) => {}
+                Err(_) => rx.reset_lazy(),
+            }
+        }
+    }
+
+    /// Accepts one connection and answers requests until the host disconnects
+    fn spawn_device() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            serve(&mut stream);
+        });
+
+        address
+    }
+
+    fn flem(args: &[&str]) -> (bool, String) {
+        let output = Command::new(env!("CARGO_BIN_EXE_flem"))
+            .args(args)
+            .output()
+            .unwrap();
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stdout).to_string(),
+        )
+    }
+
+    #[test]
+    fn send_and_decode_replies() {
+        let address = spawn_device();
+        let (ok, stdout) = flem(&["id", &address]);
+        assert!(ok);
+        assert!(stdout.contains("request: 0x0001 (ID) response: 0x0001 (SUCCESS)"));
+        assert!(stdout.contains("id: name \"CLI Target\" version 1.2.3 max packet size 128"));
+
+        let address = spawn_device();
+        let (ok, stdout) = flem(&["send", &address, "0x10", "01 02 ff"]);
+        assert!(ok);
+        assert!(stdout.contains("payload: 01 02 ff"), "{}", stdout);
+
+        let (ok, _) = flem(&["send", &address, "not-a-request"]);
+        assert!(!ok, "Invalid arguments should fail");
+    }
+
+    #[test]
+    fn send_skips_other_traffic() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut rx = Packet::<DEVICE_PACKET_SIZE>::new();
+            let mut byte = [0u8; 1];
+            loop {
+                stream.read_exact(&mut byte).unwrap();
+                if rx.construct(byte[0]).is_ok() {
+                    break;
+                }
+            }
+
+            // An ASYNC packet and a stale reply to another request go out before the reply
+            let mut packet = Packet::<DEVICE_PACKET_SIZE>::new();
+            packet.reset_lazy();
+            packet.set_request(0x20);
+            packet.set_response(flem::response::ASYNC);
+            packet.add_data(&[0x01]).unwrap();
+            packet.pack();
+            stream.write_all(packet.bytes()).unwrap();
+            packet.pack_data(0x21, &[0x02]).unwrap();
+            stream.write_all(packet.bytes()).unwrap();
+            packet.pack_data(ECHO, rx.payload()).unwrap();
+            stream.write_all(packet.bytes()).unwrap();
+            let _ = stream.read(&mut byte);
+        });
+
+        let (ok, stdout) = flem(&["send", &address, "0x10", "0a 0b"]);
+        assert!(ok);
+        assert!(stdout.contains("request: 0x0010"), "{}", stdout);
+        assert!(stdout.contains("payload: 0a 0b"), "{}", stdout);
+        assert!(
+            !stdout.contains("0x0020") && !stdout.contains("0x0021"),
+            "{}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn describe_and_call_by_name() {
+        let address = spawn_device();
+        let (ok, stdout) = flem(&["describe", &address]);
+        assert!(ok);
+        assert!(
+            stdout.contains("request 0x0010 ECHO(message: text) -> (message: text)"),
+            "{}",
+            stdout
+        );
+
+        let address = spawn_device();
+        let (ok, stdout) = flem(&["call", &address, "ECHO", "hello"]);
+        assert!(ok);
+        assert!(
+            stdout.contains("ECHO (0x0010) message=\"hello\""),
+            "{}",
+            stdout
+        );
+
+        let address = spawn_device();
+        let (ok, _) = flem(&["call", &address, "ECHO"]);
+        assert!(!ok, "Missing arguments should fail");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serial_link() {
+        use serialport::{SerialPort, TTYPort};
+        use std::time::Duration;
+
+        let (mut device, host) = TTYPort::pair().unwrap();
+        device.set_timeout(Duration::from_secs(5)).unwrap();
+        let port = host.name().unwrap();
+
+        thread::spawn(move || {
+            // Keep the host side open until the test is done so the pty stays up
+            let _host = host;
+            serve(&mut device);
+        });
+
+        let (ok, stdout) = flem(&["id", &port, "--baud", "9600"]);
+        assert!(ok, "{}", stdout);
+        assert!(stdout.contains("id: name \"CLI Target\" version 1.2.3 max packet size 128"));
+    }
+
+    #[test]
+    fn decode_hex_dump() {
+        let path = std::env::temp_dir().join("flem_cli_decode_test.txt");
+        std::fs::write(
+            &path,
+            "00000000: 0055 5500 2d01 0001 0000 00  .UU.-......\n",
+        )
+        .unwrap();
+
+        let (ok, stdout) = flem(&["decode", path.to_str().unwrap()]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(ok);
+        assert!(stdout.contains("@000000 framing error: HeaderBytesNotFound, 1 byte(s) discarded"));
+        assert!(stdout.contains("@000001 packet request: 0x0001 (ID) response: 0x0001 (SUCCESS)"));
+    }
+
+    #[test]
+    fn monitor_reports_packets_and_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            let (mut stream, _): (TcpStream, _) = listener.accept().unwrap();
+            let mut packet = Packet::<DEVICE_PACKET_SIZE>::new();
+            packet.pack_data(ECHO, &[0xAA, 0xBB]).unwrap();
+
+            let mut corrupted = packet.bytes().to_vec();
+            corrupted[11] ^= 0xFF;
+
+            stream.write_all(&[0x00, 0x01, 0x02]).unwrap();
+            stream.write_all(packet.bytes()).unwrap();
+            stream.write_all(&corrupted).unwrap();
+        });
+
+        let (ok, stdout) = flem(&["monitor", &address]);
+        assert!(ok);
+        assert!(
+            stdout.contains("error: HeaderBytesNotFound at byte 0, 3 byte(s) discarded"),
+            "{}",
+            stdout
+        );
+        assert!(stdout.contains("request: 0x0010 response: 0x0001 (SUCCESS) length: 2"));
+        assert!(stdout.contains("payload: aa bb"));
+        assert!(
+            stdout.contains("error: ChecksumError at byte 26"),
+            "{}",
+            stdout
+        );
+    }
+
+    #[test]
+    fn sign_and_verify_images() {
+        let directory = std::env::temp_dir();
+        let path = |name: &str| {
+            directory
+                .join(format!("flem_cli_sign_test_{}", name))
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+        std::fs::write(path("key"), [3u8; 32]).unwrap();
+        std::fs::write(path("image"), [0x5Au8; 2000]).unwrap();
+
+        let (ok, public_key) = flem(&["pubkey", &path("key")]);
+        assert!(ok);
+        std::fs::write(path("key.pub"), public_key).unwrap();
+
+        let (ok, stdout) = flem(&[
+            "sign",
+            &path("key"),
+            &path("image"),
+            "1.2.3",
+            &path("manifest"),
+        ]);
+        assert!(ok, "{}", stdout);
+        let (ok, stdout) = flem(&[
+            "verify",
+            &path("key.pub"),
+            &path("image"),
+            &path("manifest"),
+        ]);
+        assert!(ok);
+        assert!(
+            stdout.contains("valid: version 1.2.3 size 2000"),
+            "{}",
+            stdout
+        );
+
+        std::fs::write(path("image"), [0x5Bu8; 2000]).unwrap();
+        let (ok, _) = flem(&[
+            "verify",
+            &path("key.pub"),
+            &path("image"),
+            &path("manifest"),
+        ]);
+        assert!(!ok, "A modified image should fail verification");
+
+        for name in ["key", "key.pub", "image", "manifest"] {
+            std::fs::remove_file(path(name)).unwrap();
+        }
+    }
+}
@@ -0,0 +1,154 @@
+#![cfg(feature = "codegen")]
+
+#[cfg(test)]
+mod tests {
+
+    use flem::codegen::{CodegenError, Schema};
+
+    mod thermostat {
+        include!("schema/thermostat.rs");
+    }
+
+    const FLEM_PACKET_SIZE: usize = 64;
+
+    #[test]
+    fn generated_files_are_current() {
+        let toml = Schema::from_file("tests/schema/thermostat.toml").unwrap();
+        let json = Schema::from_file("tests/schema/thermostat.json").unwrap();
+        assert_eq!(toml, json);
+
+        // Regenerate with `Schema::rust` / `Schema::c_header` when the generator changes
+        assert_eq!(
+            toml.rust(),
+            include_str!("schema/thermostat.rs"),
+            "tests/schema/thermostat.rs is stale"
+        );
+        assert_eq!(
+            toml.c_header(),
+            include_str!("schema/thermostat.h"),
+            "tests/schema/thermostat.h is stale"
+        );
+
+        // Check the header with a C compiler when there is one
+        let output = std::process::Command::new("cc")
+            .args(["-std=c11", "-fsyntax-only", "tests/schema/thermostat.h"])
+            .output();
+        if let Ok(output) = output {
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+    }
+
+    #[test]
+    fn payload_round_trip() {
+        use flem::traits::{DataInterface, DataInterfaceErrors};
+        use flem::Packet;
+
+        assert_eq!(thermostat::request::GET_TEMPERATURE, 0x0010);
+        assert_eq!(thermostat::response::SENSOR_FAULT, 0x0100);
+
+        let target = thermostat::Target {
+            celsius: 21.5,
+            fan: 2,
+            hysteresis: -3,
+            schedule: [0x0600_0000, 0x1600_0000],
+            on: [true, false, true],
+        };
+
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.set_request(thermostat::request::SET_TARGET);
+        target.encode(&mut packet).unwrap();
+        packet.pack();
+        assert_eq!(packet.payload().len(), thermostat::Target::SIZE);
+        assert_eq!(&packet.payload()[..4], &21.5f32.to_le_bytes());
+        assert_eq!(packet.payload()[4], 2);
+        assert_eq!(&packet.payload()[5..7], &(-3i16).to_le_bytes());
+        assert_eq!(&packet.payload()[15..], &[1, 0, 1]);
+
+        let mut decoded = thermostat::Target::default();
+        decoded.decode(&packet).unwrap();
+        assert_eq!(decoded, target);
+
+        // A payload of the wrong size is refused
+        let mut temperature = thermostat::Temperature::default();
+        assert!(matches!(
+            temperature.decode(&packet),
+            Err(DataInterfaceErrors::IncorrectDataLength)
+        ));
+
+        // As is one that doesn't fit the packet
+        let mut small = Packet::<8>::new();
+        assert!(matches!(
+            target.encode(&mut small),
+            Err(DataInterfaceErrors::IncorrectBufferLength)
+        ));
+    }
+
+    #[test]
+    fn docs_cant_end_c_comments() {
+        let schema = Schema::from_toml(
+            "name = \"a\"\n[[requests]]\nname = \"A\"\ncode = 16\ndoc = \"x */ y\"\n\
+             [[payloads]]\nname = \"P\"\ndoc = \"*/\"\n\
+             fields = [{ name = \"x\", type = \"u8\", doc = \"*/ z\" }]",
+        )
+        .unwrap();
+        let header = schema.c_header();
+        assert!(header.contains("/* x * / y */"));
+        assert!(header.contains("/* * / */"));
+        assert!(header.contains("    /* * / z */"));
+        assert_eq!(header.matches("*/").count(), header.matches("/*").count());
+    }
+
+    #[test]
+    fn invalid_schemas() {
+        let invalid = |text: &str| matches!(Schema::from_toml(text), Err(CodegenError::Invalid(_)));
+
+        // Reserved by FLEM
+        assert!(invalid(
+            "name = \"a\"\n[[requests]]\nname = \"PING\"\ncode = 0x0001"
+        ));
+        assert!(invalid(
+            "name = \"a\"\n[[responses]]\nname = \"FAIL\"\ncode = 0xFFFF"
+        ));
+
+        // Duplicate codes
+        assert!(invalid(
+            "name = \"a\"\n[[requests]]\nname = \"A\"\ncode = 16\n[[requests]]\nname = \"B\"\ncode = 16"
+        ));
+
+        // Unknown payload
+        assert!(invalid(
+            "name = \"a\"\n[[requests]]\nname = \"A\"\ncode = 16\nreply = \"Missing\""
+        ));
+
+        // Names that wouldn't compile
+        assert!(invalid(
+            "name = \"a\"\n[[requests]]\nname = \"get\"\ncode = 16"
+        ));
+        assert!(invalid(
+            "name = \"a\"\n[[payloads]]\nname = \"P\"\nfields = [{ name = \"type\", type = \"u8\" }]"
+        ));
+        assert!(invalid(
+            "name = \"a\"\n[[payloads]]\nname = \"P\"\nfields = [{ name = \"_\", type = \"u8\" }]"
+        ));
+        assert!(invalid(
+            "name = \"a\"\n[[payloads]]\nname = \"P\"\nfields = [{ name = \"int\", type = \"u8\" }]"
+        ));
+        assert!(invalid(
+            "name = \"a\"\n[[payloads]]\nname = \"P\"\nfields = [{ name = \"try\", type = \"u8\" }]"
+        ));
+
+        // Not a schema at all
+        assert!(matches!(
+            Schema::from_toml("name = \"a\"\n[[payloads]]\nname = \"P\"\nfields = [{ name = \"x\", type = \"u128\" }]"),
+            Err(CodegenError::Parse(_))
+        ));
+        assert!(matches!(
+            Schema::from_json("{"),
+            Err(CodegenError::Parse(_))
+        ));
+    }
+}
@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::compress::{compress, decompress};
+    use flem::{response, Packet, Status};
+
+    const FLEM_PACKET_SIZE: usize = 128;
+
+    /// Telemetry-like data: slowly changing 16 bit samples
+    fn telemetry() -> [u8; 512] {
+        let mut data = [0u8; 512];
+        for (index, sample) in data.chunks_mut(2).enumerate() {
+            sample.copy_from_slice(&((index / 16) as u16).to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn round_trip() {
+        let inputs: [&[u8]; 4] = [&[], &[7], &telemetry(), b"abcabcabcabcabcabc the end"];
+
+        for input in inputs.iter() {
+            let mut compressed = [0u8; 1024];
+            let length = compress(input, &mut compressed).unwrap();
+            let mut output = [0u8; 1024];
+            let decompressed = decompress(&compressed[..length], &mut output).unwrap();
+            assert_eq!(&output[..decompressed], *input);
+        }
+
+        // Pseudo random bytes don't compress, but still round trip
+        let mut noise = [0u8; 300];
+        let mut state: u32 = 1;
+        for byte in noise.iter_mut() {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            *byte = (state >> 16) as u8;
+        }
+        let mut compressed = [0u8; 310];
+        let length = compress(&noise, &mut compressed).unwrap();
+        assert!(length > noise.len());
+        let mut output = [0u8; 300];
+        assert_eq!(decompress(&compressed[..length], &mut output), Ok(300));
+        assert_eq!(output, noise);
+
+        // Output too small
+        assert_eq!(compress(&noise, &mut [0u8; 100]), None);
+        assert_eq!(
+            decompress(&compressed[..length], &mut [0u8; 100]),
+            Err(Status::PacketOverflow)
+        );
+        // Offset pointing before the start of the output
+        assert_eq!(
+            decompress(&[0x00, 0x41, 0x80, 0x05, 0x00], &mut output),
+            Err(Status::DecompressionFailed)
+        );
+    }
+
+    #[test]
+    fn compressed_packets() {
+        let data = telemetry();
+        let mut tx = Packet::<FLEM_PACKET_SIZE>::new();
+        tx.pack_data_compressed(0x10, &data).unwrap();
+        assert!(tx.is_compressed());
+        assert_eq!(tx.get_response(), response::SUCCESS | response::COMPRESSED);
+        assert!(tx.payload().len() < FLEM_PACKET_SIZE);
+
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut result = Err(Status::PacketBuilding);
+        for byte in tx.bytes() {
+            result = rx.construct(*byte);
+        }
+        assert_eq!(result, Ok(()));
+
+        let mut buffer = [0u8; 512];
+        assert_eq!(rx.decompress_data(&mut buffer), Ok(512));
+        assert_eq!(buffer, data);
+
+        // Data that doesn't shrink is sent as is, and read back the same way
+        tx.pack_data_compressed(0x10, &[1, 2, 3]).unwrap();
+        assert!(!tx.is_compressed());
+        assert_eq!(tx.get_response(), response::SUCCESS);
+        assert_eq!(tx.decompress_data(&mut buffer), Ok(3));
+        assert_eq!(&buffer[..3], &[1, 2, 3]);
+
+        // Error responses never carry the flag
+        assert!(!response::is_compressed(response::UNKNOWN_REQUEST));
+        assert_eq!(
+            response::name(response::SUCCESS | response::COMPRESSED),
+            Some("SUCCESS | COMPRESSED")
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn client_decompresses_replies() {
+        use flem::client::{Client, ClientError};
+        use flem::emulator::Emulator;
+        use flem::traits::Channel;
+
+        let mut emulator = Emulator::<_, FLEM_PACKET_SIZE>::new(());
+        emulator.handle(0x10, |_, _, reply| {
+            reply
+                .pack_data_compressed(0x10, &telemetry()[..FLEM_PACKET_SIZE])
+                .unwrap();
+        });
+        emulator.handle(0x11, |_, _, reply| {
+            reply.pack_data_compressed(0x11, &telemetry()).unwrap();
+        });
+        let (tx, rx) = Channel::<FLEM_PACKET_SIZE>::listen(&mut emulator, 1, 1);
+        let client = Client::new(&tx, &rx);
+
+        let reply = client.request(0x10, &[]).unwrap();
+        assert!(reply.is_compressed());
+        let reply = client.call(0x10, &[]).unwrap();
+        assert!(!reply.is_compressed());
+        assert_eq!(reply.get_response(), response::SUCCESS);
+        assert_eq!(reply.payload(), &telemetry()[..FLEM_PACKET_SIZE]);
+
+        // Larger than a packet once decompressed, left to `request` and `decompress_data`
+        assert_eq!(
+            client.call(0x11, &[]).unwrap_err(),
+            ClientError::Packet(Status::PacketOverflow)
+        );
+        let reply = client.request(0x11, &[]).unwrap();
+        let mut buffer = [0u8; 512];
+        assert_eq!(reply.decompress_data(&mut buffer), Ok(512));
+        assert_eq!(buffer, telemetry());
+        Channel::<FLEM_PACKET_SIZE>::unlisten(&mut emulator).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "std")]
+
+#[cfg(test)]
+mod tests {
+
+    use flem::decode::{parse_hex, DecodeEvent, Decoder};
+    use flem::{Packet, Status};
+
+    const FLEM_PACKET_SIZE: usize = 32;
+
+    #[test]
+    fn hex_formats() {
+        let expected = vec![0x55, 0x55, 0x0A, 0x1B];
+        assert_eq!(parse_hex("55 55 0a 1b").unwrap(), expected);
+        assert_eq!(parse_hex("0x55, 0x55, 0x0A, 0x1B").unwrap(), expected);
+        assert_eq!(parse_hex("55550a1b").unwrap(), expected);
+        assert_eq!(parse_hex("00000000: 5555 0a1b  UU..").unwrap(), expected);
+        assert_eq!(
+            parse_hex("00000000  55 55 0a 1b                                       |UU..|")
+                .unwrap(),
+            expected
+        );
+
+        let error = parse_hex("55 55\n0a 1g").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(error.token, "1g");
+    }
+
+    #[test]
+    fn decode_reports_packets_and_framing_errors() {
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(0x20, &[1, 2, 3]).unwrap();
+
+        let mut corrupted = packet.bytes().to_vec();
+        corrupted[11] ^= 0xFF;
+
+        let mut stream = vec![0x00, 0x11];
+        stream.extend_from_slice(packet.bytes());
+        stream.extend_from_slice(&corrupted);
+        stream.extend_from_slice(&packet.bytes()[..4]);
+
+        let decoder = Decoder::new().with_request_name(0x20, "MOTOR_START");
+        let events = decoder.decode::<FLEM_PACKET_SIZE>(&stream);
+        assert_eq!(events.len(), 4);
+
+        assert_eq!(
+            events[0],
+            DecodeEvent::FramingError {
+                offset: 0,
+                length: 2,
+                status: Status::HeaderBytesNotFound
+            }
+        );
+        match (&events[1], &events[2]) {
+            (DecodeEvent::Packet(good), DecodeEvent::Packet(bad)) => {
+                assert_eq!(good.offset, 2);
+                assert!(good.checksum_valid());
+                assert_eq!(good.payload, vec![1, 2, 3]);
+                assert_eq!(bad.offset, 15);
+                assert!(!bad.checksum_valid());
+            }
+            _ => panic!("Expected two packets, got {:?}", events),
+        }
+        assert_eq!(
+            events[3],
+            DecodeEvent::Truncated {
+                offset: 28,
+                length: 4
+            }
+        );
+
+        let report = decoder.report::<FLEM_PACKET_SIZE>(&stream);
+        assert!(report.contains("request: 0x0020 (MOTOR_START) response: 0x0001 (SUCCESS)"));
+        assert!(report.contains("payload: 01 02 03"));
+        assert!(report.contains("INVALID"));
+    }
+
+    #[test]
+    fn decode_invalid_length() {
+        let mut packet = Packet::<64>::new();
+        packet.pack_data(0x20, &[0u8; 40]).unwrap();
+
+        let events = Decoder::new().decode::<FLEM_PACKET_SIZE>(packet.bytes());
+        assert_eq!(
+            events[0],
+            DecodeEvent::FramingError {
+                offset: 0,
+                length: 10,
+                status: Status::InvalidDataLengthDetected
+            }
+        );
+    }
+}
@@ -0,0 +1,202 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::dfu::{crc32, DfuDevice, DfuError, DfuState, DfuStatus, FlashWriter, ImageInfo};
+    use flem::{request, response, Packet};
+
+    const FLEM_PACKET_SIZE: usize = 64;
+
+    /// RAM backed stand-in for a flash bank
+    struct RamFlash {
+        memory: Vec<u8>,
+        committed: Option<ImageInfo>,
+    }
+
+    impl RamFlash {
+        fn new(capacity: usize) -> Self {
+            RamFlash {
+                memory: vec![0xFF; capacity],
+                committed: None,
+            }
+        }
+    }
+
+    impl FlashWriter for RamFlash {
+        type Error = ();
+
+        fn capacity(&self) -> u32 {
+            self.memory.len() as u32
+        }
+
+        fn erase(&mut self, size: u32) -> Result<(), ()> {
+            self.memory[..size as usize].fill(0xFF);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            self.memory[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn read(&mut self, offset: u32, buffer: &mut [u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            buffer.copy_from_slice(&self.memory[offset..offset + buffer.len()]);
+            Ok(())
+        }
+
+        fn commit(&mut self, image: &ImageInfo) -> Result<(), ()> {
+            self.committed = Some(*image);
+            Ok(())
+        }
+    }
+
+    fn image(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i * 7 + i / 256) as u8).collect()
+    }
+
+    fn send(
+        device: &mut DfuDevice<RamFlash>,
+        code: u16,
+        data: &[u8],
+    ) -> (DfuStatus, Option<DfuError>) {
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(code, data).unwrap();
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        assert!(device.handle(&packet, &mut reply));
+        DfuStatus::from_reply(&reply).unwrap()
+    }
+
+    fn write(
+        device: &mut DfuDevice<RamFlash>,
+        offset: u32,
+        chunk: &[u8],
+    ) -> (DfuStatus, Option<DfuError>) {
+        let mut data = offset.to_le_bytes().to_vec();
+        data.extend_from_slice(chunk);
+        send(device, request::DFU_WRITE, &data)
+    }
+
+    #[test]
+    fn crc32_matches_reference() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn device_state_machine() {
+        let firmware = image(100);
+        let info = ImageInfo::new(&firmware, 1, 2, 3);
+        let mut device = DfuDevice::new(RamFlash::new(128));
+
+        // Not a DFU request
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(0x10, &[]).unwrap();
+        assert!(!device.handle(&packet, &mut Packet::new()));
+
+        assert_eq!(
+            write(&mut device, 0, &firmware[..10]).1,
+            Some(DfuError::NotStarted)
+        );
+        let too_large = ImageInfo::new(&image(200), 1, 2, 3);
+        assert_eq!(
+            send(&mut device, request::DFU_BEGIN, &too_large.to_bytes()).1,
+            Some(DfuError::ImageTooLarge)
+        );
+
+        let (status, error) = send(&mut device, request::DFU_BEGIN, &info.to_bytes());
+        assert_eq!(error, None);
+        assert_eq!(status.state, DfuState::Receiving);
+        assert_eq!(status.size, 100);
+
+        assert_eq!(write(&mut device, 0, &firmware[..50]).0.offset, 50);
+        let (status, error) = write(&mut device, 10, &firmware[10..20]);
+        assert_eq!(error, Some(DfuError::WrongOffset));
+        assert_eq!(status.offset, 50);
+
+        // Link dropped, BEGIN with the same image resumes
+        let (status, _) = send(&mut device, request::DFU_BEGIN, &info.to_bytes());
+        assert_eq!(status.offset, 50);
+        assert_eq!(
+            send(&mut device, request::DFU_COMMIT, &[]).1,
+            Some(DfuError::NotVerified)
+        );
+        write(&mut device, 50, &firmware[50..]);
+
+        assert_eq!(
+            send(&mut device, request::DFU_VERIFY, &[]).0.state,
+            DfuState::Verified
+        );
+        assert_eq!(
+            send(&mut device, request::DFU_COMMIT, &[]).0.state,
+            DfuState::Committed
+        );
+        assert_eq!(device.flash().committed, Some(info));
+        assert_eq!(&device.flash().memory[..100], &firmware[..]);
+
+        // A corrupted image fails verification and has to start over
+        send(&mut device, request::DFU_BEGIN, &info.to_bytes());
+        write(&mut device, 0, &firmware[..50]);
+        write(&mut device, 50, &image(50));
+        let (status, error) = send(&mut device, request::DFU_VERIFY, &[]);
+        assert_eq!(error, Some(DfuError::CrcMismatch));
+        assert_eq!(status.state, DfuState::Idle);
+
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(request::DFU_STATUS, &[]).unwrap();
+        device.handle(&packet, &mut reply);
+        assert_eq!(reply.get_response(), response::SUCCESS);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn upload_survives_dropped_packets() {
+        use flem::dfu::{DfuUploader, UploadError};
+        use flem::emulator::Emulator;
+        use flem::traits::Channel;
+        use std::time::Duration;
+
+        let mut emulator =
+            Emulator::<_, FLEM_PACKET_SIZE>::new(DfuDevice::new(RamFlash::new(4096)));
+        for code in request::DFU_BEGIN..=request::DFU_ABORT {
+            emulator.handle(code, |device, packet, reply| {
+                device.handle(packet, reply);
+            });
+        }
+        let (tx, rx) = Channel::<FLEM_PACKET_SIZE>::listen(&mut emulator, 1, 1);
+
+        let firmware = image(3000);
+        let info = ImageInfo::new(&firmware, 0, 7, 0);
+        let uploader = DfuUploader {
+            timeout: Duration::from_millis(50),
+            retries: 3,
+        };
+
+        assert_eq!(
+            uploader.upload(&tx, &rx, &firmware[..2999], &info, |_, _| {}),
+            Err(UploadError::ImageSize)
+        );
+
+        // Lose a couple of replies part way through
+        let mut dropped = false;
+        let mut last = 0;
+        uploader
+            .upload(&tx, &rx, &firmware, &info, |offset, size| {
+                assert!(offset <= size);
+                if offset > 1000 && !dropped {
+                    emulator.drop_next(2);
+                    dropped = true;
+                }
+                last = offset;
+            })
+            .unwrap();
+        assert_eq!(last, 3000);
+
+        emulator.with_state(|device| {
+            assert_eq!(device.state(), DfuState::Committed);
+            assert_eq!(device.flash().committed, Some(info));
+            assert_eq!(&device.flash().memory[..3000], &firmware[..]);
+        });
+        Channel::<FLEM_PACKET_SIZE>::unlisten(&mut emulator).unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+#![cfg(feature = "log")]
+
+#[cfg(test)]
+mod tests {
+
+    use flem::Packet;
+    use log::{Level, Log, Metadata, Record};
+    use std::sync::Mutex;
+
+    const FLEM_PACKET_SIZE: usize = 16;
+
+    struct CaptureLogger {
+        messages: Mutex<Vec<(Level, String)>>,
+    }
+
+    impl Log for CaptureLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.messages
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CaptureLogger = CaptureLogger {
+        messages: Mutex::new(Vec::new()),
+    };
+
+    #[test]
+    fn parse_errors_are_logged() {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut tx = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+        tx.pack_data(0xF, &[1, 2, 3]).unwrap();
+
+        let mut corrupted = tx.bytes().to_vec();
+        corrupted[11] ^= 0xFF;
+        for byte in [0x00].iter().chain(corrupted.iter()) {
+            let _ = rx.construct(*byte);
+        }
+        rx.reset_lazy();
+        for byte in tx.bytes() {
+            let _ = rx.construct(*byte);
+        }
+
+        let messages = LOGGER.messages.lock().unwrap();
+        assert!(messages
+            .iter()
+            .any(|(level, msg)| *level == Level::Debug && msg.contains("lost sync")));
+        assert!(messages
+            .iter()
+            .any(|(level, msg)| *level == Level::Warn && msg.contains("ChecksumError")));
+        assert!(messages
+            .iter()
+            .any(|(level, msg)| *level == Level::Debug && msg.contains("packet received")));
+        assert!(messages
+            .iter()
+            .any(|(level, msg)| *level == Level::Trace && msg.contains("packed")));
+    }
+}
@@ -0,0 +1,107 @@
+#![cfg(feature = "std")]
+
+#[cfg(test)]
+mod tests {
+
+    use flem::emulator::{Emulator, Faults};
+    use flem::traits::Channel;
+    use flem::transport::TcpChannel;
+    use flem::{response, Packet};
+    use std::time::Duration;
+
+    const FLEM_PACKET_SIZE: usize = 64;
+    const INCREMENT: u16 = 0x10;
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn counter_emulator() -> Emulator<u32, FLEM_PACKET_SIZE> {
+        let mut emulator = Emulator::<u32, FLEM_PACKET_SIZE>::new(0);
+        emulator.handle(INCREMENT, |counter, request, response| {
+            *counter += request.payload().first().copied().unwrap_or(1) as u32;
+            response
+                .pack_data(INCREMENT, &counter.to_le_bytes())
+                .unwrap();
+        });
+        emulator
+    }
+
+    fn request(code: u16, data: &[u8]) -> Packet<FLEM_PACKET_SIZE> {
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(code, data).unwrap();
+        packet
+    }
+
+    #[test]
+    fn handlers_keep_state_across_requests() {
+        let mut emulator = counter_emulator();
+        let (tx, rx) = Channel::<FLEM_PACKET_SIZE>::listen(&mut emulator, 1, 1);
+
+        tx.send(request(INCREMENT, &[2])).unwrap();
+        let reply = rx.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(reply.payload(), &2u32.to_le_bytes());
+
+        tx.send(request(INCREMENT, &[3])).unwrap();
+        let reply = rx.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(reply.payload(), &5u32.to_le_bytes());
+        assert_eq!(emulator.with_state(|counter| *counter), 5);
+
+        // No handler registered
+        tx.send(request(0x20, &[])).unwrap();
+        let reply = rx.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(reply.get_request(), 0x20);
+        assert_eq!(reply.get_response(), response::UNKNOWN_REQUEST);
+
+        Channel::<FLEM_PACKET_SIZE>::unlisten(&mut emulator).unwrap();
+    }
+
+    #[test]
+    fn scheduled_async_packets_and_faults() {
+        let mut emulator = counter_emulator();
+        emulator.every(Duration::from_millis(5), |counter, packet| {
+            packet.set_request(INCREMENT);
+            packet.add_data(&counter.to_le_bytes()).unwrap();
+            true
+        });
+        let (tx, rx) = Channel::<FLEM_PACKET_SIZE>::listen(&mut emulator, 1, 1);
+
+        let reply = rx.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(reply.get_response(), response::ASYNC);
+        assert_eq!(reply.payload(), &0u32.to_le_bytes());
+
+        // Dropped and corrupted replies never reach the program, only their side effects remain
+        emulator.set_faults(Faults {
+            latency: Duration::from_millis(1),
+            drop_every: Some(1),
+            corrupt_every: None,
+        });
+        tx.send(request(INCREMENT, &[1])).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        while rx.try_recv().is_ok() {}
+        assert_eq!(emulator.with_state(|counter| *counter), 1);
+
+        emulator.set_faults(Faults::default());
+        emulator.corrupt_next(1);
+        emulator.send_raw(&[0x00, 0x55, 0x13]);
+        let reply = rx.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(reply.get_response(), response::ASYNC);
+        assert_eq!(reply.payload(), &1u32.to_le_bytes());
+
+        Channel::<FLEM_PACKET_SIZE>::unlisten(&mut emulator).unwrap();
+    }
+
+    #[test]
+    fn serves_over_tcp() {
+        let mut emulator = counter_emulator();
+        let address = emulator.bind_tcp("127.0.0.1:0").unwrap();
+
+        let mut channel = TcpChannel::<FLEM_PACKET_SIZE>::new();
+        channel.connect(&address.to_string()).unwrap();
+        let (tx, rx) = channel.listen(1, 1);
+
+        tx.send(request(INCREMENT, &[7])).unwrap();
+        let reply = rx.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(reply.payload(), &7u32.to_le_bytes());
+
+        channel.disconnect().unwrap();
+        Channel::<FLEM_PACKET_SIZE>::unlisten(&mut emulator).unwrap();
+    }
+}
@@ -0,0 +1,232 @@
+#![cfg(feature = "ffi")]
+
+#[cfg(test)]
+mod tests {
+
+    use flem::ffi::*;
+    use flem::request;
+    use std::path::Path;
+    use std::process::Command;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn pack_and_construct() {
+        use flem::{response, Packet};
+        use std::slice;
+
+        unsafe {
+            let tx = flem_packet_new();
+            let rx = flem_packet_new();
+
+            let data = [0xAA, 0xBB, 0xCC];
+            assert_eq!(
+                flem_packet_pack_data(tx, request::ID, data.as_ptr(), data.len()),
+                FlemStatus::Ok
+            );
+
+            let mut byte = 0u8;
+            let mut status = FlemStatus::PacketBuilding;
+            while flem_packet_get_byte(tx, &mut byte) == FlemStatus::Ok {
+                status = flem_packet_construct(rx, byte);
+            }
+            assert_eq!(status, FlemStatus::Ok);
+            assert_eq!(
+                flem_packet_get_byte(tx, &mut byte),
+                FlemStatus::GetByteFinished
+            );
+
+            assert_eq!(flem_packet_get_request(rx), request::ID);
+            assert_eq!(flem_packet_get_response(rx), response::SUCCESS);
+            assert_eq!(flem_packet_get_checksum(rx), flem_packet_get_checksum(tx));
+            assert_eq!(
+                slice::from_raw_parts(flem_packet_data(rx), flem_packet_data_length(rx)),
+                &data
+            );
+
+            // The wire bytes match a Rust packet
+            let mut packet = Packet::<64>::new();
+            packet.pack_data(request::ID, &data).unwrap();
+            assert_eq!(
+                slice::from_raw_parts(flem_packet_bytes(tx), flem_packet_length(tx)),
+                packet.bytes()
+            );
+
+            assert_eq!(
+                flem_packet_pack_error(
+                    rx,
+                    request::ID,
+                    response::UNKNOWN_REQUEST,
+                    data.as_ptr(),
+                    0
+                ),
+                FlemStatus::Ok
+            );
+            assert_eq!(flem_packet_get_response(rx), response::UNKNOWN_REQUEST);
+            assert_eq!(flem_packet_data_length(rx), 0);
+
+            flem_packet_free(tx);
+            flem_packet_free(rx);
+        }
+    }
+
+    #[test]
+    fn caller_storage() {
+        unsafe {
+            // Any alignment will do
+            let mut storage = vec![0xFFu8; flem_packet_size() + 1];
+            let buffer = storage[1..].as_mut_ptr().cast();
+            assert!(flem_packet_init(std::ptr::null_mut(), flem_packet_size()).is_null());
+            assert!(flem_packet_init(buffer, flem_packet_size() - 1).is_null());
+
+            let packet = flem_packet_init(buffer, flem_packet_size());
+            assert_eq!(packet.cast(), buffer);
+            assert_eq!(flem_packet_data_length(packet), 0);
+            assert_eq!(flem_packet_get_request(packet), 0);
+            assert_eq!(
+                flem_packet_pack_data(packet, request::ID, [0xAA].as_ptr(), 1),
+                FlemStatus::Ok
+            );
+            assert!(flem_packet_validate(packet));
+            assert_eq!(flem_packet_data_length(packet), 1);
+        }
+    }
+
+    #[test]
+    fn oversized_lengths_are_capped() {
+        unsafe {
+            let mut storage = vec![0u8; flem_packet_size()];
+            let packet = flem_packet_init(storage.as_mut_ptr().cast(), flem_packet_size());
+            let header = [0x55, 0x55, 0x00, 0x00, 0x10, 0x00, 0x01, 0x00, 0xFF, 0xFF];
+            let mut status = FlemStatus::PacketBuilding;
+            for byte in header {
+                status = flem_packet_construct(packet, byte);
+            }
+            assert_eq!(status, FlemStatus::InvalidDataLengthDetected);
+            assert_eq!(flem_packet_data_length(packet), flem_max_data_size());
+            assert_eq!(flem_packet_length(packet), 10 + flem_max_data_size());
+            assert!(!flem_packet_data(packet).is_null());
+            assert!(!flem_packet_bytes(packet).is_null());
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn errors_and_null_pointers() {
+        use flem::Status;
+
+        unsafe {
+            let packet = flem_packet_new();
+            let large = vec![0u8; flem_max_data_size() + 1];
+            assert_eq!(
+                flem_packet_add_data(packet, large.as_ptr(), large.len()),
+                FlemStatus::PacketOverflow
+            );
+            assert_eq!(
+                flem_packet_add_data(packet, std::ptr::null(), 0),
+                FlemStatus::Ok
+            );
+            assert_eq!(
+                flem_packet_add_data(packet, std::ptr::null(), 1),
+                FlemStatus::NullPointer
+            );
+            assert_eq!(
+                flem_packet_construct(packet, 0x00),
+                FlemStatus::HeaderBytesNotFound
+            );
+            flem_packet_free(packet);
+
+            let null = std::ptr::null_mut();
+            assert_eq!(flem_packet_reset(null), FlemStatus::NullPointer);
+            assert_eq!(flem_packet_pack(null), FlemStatus::NullPointer);
+            assert_eq!(flem_packet_construct(null, 0x55), FlemStatus::NullPointer);
+            assert_eq!(flem_packet_get_request(null), 0);
+            assert!(flem_packet_data(null).is_null());
+            assert!(!flem_packet_validate(null));
+            flem_packet_free(null);
+        }
+
+        assert_eq!(
+            FlemStatus::from(Status::NotAddressed),
+            FlemStatus::NotAddressed
+        );
+        assert_eq!(
+            FlemStatus::from(Err(Status::ChecksumError)),
+            FlemStatus::ChecksumError
+        );
+    }
+
+    #[test]
+    fn header_matches_exports() {
+        let source = include_str!("../src/ffi.rs");
+        let header = include_str!("../include/flem.h");
+
+        let mut exported = 0;
+        for line in source.lines() {
+            if let Some(rest) = line.split("extern \"C\" fn ").nth(1) {
+                let name = rest.split('(').next().unwrap();
+                assert!(
+                    header.contains(&format!("{}(", name)),
+                    "include/flem.h is missing {}, regenerate it with cbindgen",
+                    name
+                );
+                exported += 1;
+            }
+        }
+        assert!(exported > 0);
+        assert!(header.contains("FLEM_STATUS_NOT_ADDRESSED = 15"));
+        assert!(header.contains("FLEM_STATUS_NULL_POINTER = 255"));
+    }
+
+    /// Builds the crate as a static library and runs tests/ffi/test.c against it, if there is a C
+    /// compiler
+    #[test]
+    fn c_test() {
+        if Command::new("cc").arg("--version").output().is_err() {
+            return;
+        }
+
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let target = root.join("target").join("ffi");
+        let status = Command::new(env!("CARGO"))
+            .current_dir(root)
+            .args([
+                "rustc",
+                "--lib",
+                "--features",
+                "ffi,std",
+                "--crate-type",
+                "staticlib",
+            ])
+            .arg("--target-dir")
+            .arg(&target)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let executable = target.join("ffi_test");
+        let status = Command::new("cc")
+            .current_dir(root)
+            .args([
+                "-std=c99",
+                "-Wall",
+                "-Wextra",
+                "-Werror",
+                "-Iinclude",
+                "tests/ffi/test.c",
+            ])
+            .arg(target.join("debug").join("libflem.a"))
+            .args(["-lpthread", "-ldl", "-lm", "-o"])
+            .arg(&executable)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let output = Command::new(&executable).output().unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+    }
+}
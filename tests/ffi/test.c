@@ -0,0 +1,96 @@
+/* Exercises the C API of the `ffi` feature. Run by tests/ffi.rs, or by hand:
+ *
+ *   cargo rustc --lib --features ffi,std --crate-type staticlib
+ *   cc -std=c99 -Iinclude tests/ffi/test.c target/debug/libflem.a -lpthread -ldl -lm -o test
+ *   ./test
+ */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "flem.h"
+
+#define CHECK(condition)                                                      \
+    do {                                                                      \
+        if (!(condition)) {                                                   \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__,  \
+                    #condition);                                              \
+            return 1;                                                         \
+        }                                                                     \
+    } while (0)
+
+int main(void) {
+    const uint8_t payload[] = {0x01, 0x02, 0x03, 0x04};
+    FlemPacket *tx = flem_packet_new();
+    /* The receiver lives in static storage, as it would in firmware without a heap */
+    static uint8_t storage[2048];
+    CHECK(sizeof(storage) >= flem_packet_size());
+    CHECK(flem_packet_init(storage, flem_packet_size() - 1) == NULL);
+    FlemPacket *rx = flem_packet_init(storage, sizeof(storage));
+    CHECK(tx != NULL && rx != NULL);
+
+    /* Pack and send through a byte loop */
+    CHECK(flem_packet_pack_data(tx, 0x0010, payload, sizeof(payload)) == FLEM_STATUS_OK);
+    CHECK(flem_packet_length(tx) == 10 + sizeof(payload));
+
+    FlemStatus status = FLEM_STATUS_PACKET_BUILDING;
+    uint8_t byte;
+    while (flem_packet_get_byte(tx, &byte) == FLEM_STATUS_OK) {
+        status = flem_packet_construct(rx, byte);
+    }
+    CHECK(status == FLEM_STATUS_OK);
+    CHECK(flem_packet_get_request(rx) == 0x0010);
+    CHECK(flem_packet_get_response(rx) == 0x0001);
+    CHECK(flem_packet_data_length(rx) == sizeof(payload));
+    CHECK(memcmp(flem_packet_data(rx), payload, sizeof(payload)) == 0);
+    CHECK(flem_packet_get_checksum(rx) == flem_packet_get_checksum(tx));
+    CHECK(flem_packet_validate(rx));
+
+    /* A corrupted byte is reported as a checksum error */
+    uint8_t wire[64];
+    size_t length = flem_packet_length(tx);
+    memcpy(wire, flem_packet_bytes(tx), length);
+    wire[length - 1] ^= 0xFF;
+    CHECK(flem_packet_reset_lazy(rx) == FLEM_STATUS_OK);
+    for (size_t i = 0; i < length; i++) {
+        status = flem_packet_construct(rx, wire[i]);
+    }
+    CHECK(status == FLEM_STATUS_CHECKSUM_ERROR);
+
+    /* A header claiming more data than fits is refused, and the accessors stay in bounds */
+    const uint8_t oversized[] = {0x55, 0x55, 0x00, 0x00, 0x10, 0x00, 0x01, 0x00, 0xFF, 0xFF};
+    CHECK(flem_packet_reset_lazy(rx) == FLEM_STATUS_OK);
+    for (size_t i = 0; i < sizeof(oversized); i++) {
+        status = flem_packet_construct(rx, oversized[i]);
+    }
+    CHECK(status == FLEM_STATUS_INVALID_DATA_LENGTH_DETECTED);
+    CHECK(flem_packet_data_length(rx) == flem_max_data_size());
+    CHECK(flem_packet_length(rx) == 10 + flem_max_data_size());
+    CHECK(flem_packet_reset(rx) == FLEM_STATUS_OK);
+    CHECK(flem_packet_data_length(rx) == 0);
+
+    /* Errors, building by hand and overflow */
+    CHECK(flem_packet_pack_error(tx, 0x0010, 0xFFFE, NULL, 0) == FLEM_STATUS_OK);
+    CHECK(flem_packet_get_response(tx) == 0xFFFE);
+
+    CHECK(flem_packet_reset(tx) == FLEM_STATUS_OK);
+    flem_packet_set_request(tx, 0x0011);
+    flem_packet_set_response(tx, 0x0001);
+    CHECK(flem_packet_add_data(tx, payload, 2) == FLEM_STATUS_OK);
+    CHECK(flem_packet_pack(tx) == FLEM_STATUS_OK);
+    CHECK(flem_packet_validate(tx));
+    CHECK(flem_packet_data_length(tx) == 2);
+
+    static uint8_t large[4096];
+    CHECK(sizeof(large) > flem_max_data_size());
+    CHECK(flem_packet_add_data(tx, large, sizeof(large)) == FLEM_STATUS_PACKET_OVERFLOW);
+
+    CHECK(flem_packet_add_data(NULL, payload, 1) == FLEM_STATUS_NULL_POINTER);
+    CHECK(flem_packet_add_data(tx, NULL, 1) == FLEM_STATUS_NULL_POINTER);
+
+    flem_packet_free(tx);
+    flem_packet_free(NULL);
+
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::heartbeat::{Heartbeat, HeartbeatConfig, LinkState};
+    use flem::Packet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const FLEM_PACKET_SIZE: usize = 32;
+
+    static STATE_CHANGES: AtomicUsize = AtomicUsize::new(0);
+
+    #[test]
+    fn link_state_machine() {
+        let config = HeartbeatConfig {
+            interval: 100,
+            degraded_timeout: 300,
+            down_timeout: 1000,
+        };
+        let mut heartbeat = Heartbeat::new(config);
+        heartbeat.set_callback(|_| {
+            STATE_CHANGES.fetch_add(1, Ordering::Relaxed);
+        });
+        let mut tx = Packet::<FLEM_PACKET_SIZE>::new();
+
+        assert_eq!(heartbeat.state(), LinkState::Down);
+        assert!(
+            heartbeat.poll(0, &mut tx),
+            "First heartbeat is sent immediately"
+        );
+        assert_eq!(tx.get_request(), flem::request::HEARTBEAT);
+        assert!(!heartbeat.poll(50, &mut tx), "Heartbeat not due yet");
+        assert!(heartbeat.poll(100, &mut tx));
+
+        // Partner's heartbeat arrives
+        assert!(heartbeat.received(&tx, 100));
+        assert_eq!(heartbeat.state(), LinkState::Up);
+
+        heartbeat.poll(399, &mut tx);
+        assert_eq!(heartbeat.state(), LinkState::Up);
+        heartbeat.poll(400, &mut tx);
+        assert_eq!(heartbeat.state(), LinkState::Degraded);
+        heartbeat.poll(1100, &mut tx);
+        assert_eq!(heartbeat.state(), LinkState::Down);
+
+        assert_eq!(STATE_CHANGES.load(Ordering::Relaxed), 3);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn host_monitor() {
+        use flem::heartbeat::HeartbeatMonitor;
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        let config = HeartbeatConfig {
+            interval: 10,
+            degraded_timeout: 30,
+            down_timeout: 60,
+        };
+        let (tx, rx) = mpsc::channel::<Packet<FLEM_PACKET_SIZE>>();
+        let (state_tx, state_rx) = mpsc::channel();
+        let mut monitor = HeartbeatMonitor::start(config, tx, move |state| {
+            state_tx.send(state).unwrap();
+        });
+
+        // Loop the host's heartbeat back as if the device answered
+        let heartbeat = rx.recv_timeout(Duration::from_millis(500)).unwrap();
+        assert!(monitor.received(&heartbeat));
+        assert_eq!(
+            state_rx.recv_timeout(Duration::from_millis(500)).unwrap(),
+            LinkState::Up
+        );
+
+        // Device goes quiet
+        assert_eq!(
+            state_rx.recv_timeout(Duration::from_millis(500)).unwrap(),
+            LinkState::Degraded
+        );
+        assert_eq!(
+            state_rx.recv_timeout(Duration::from_millis(500)).unwrap(),
+            LinkState::Down
+        );
+        monitor.stop();
+    }
+}
@@ -0,0 +1,211 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::introspect::{
+        Field, FieldType, Introspection, RequestDescription, ResponseDescription, KIND_REQUEST,
+        KIND_RESPONSE,
+    };
+    use flem::{request, response, Packet};
+
+    const FLEM_PACKET_SIZE: usize = 64;
+
+    const GET_TEMPERATURE: u16 = 0x0010;
+    const SET_TARGET: u16 = 0x0011;
+    const SET_LABEL: u16 = 0x0012;
+    const SENSOR_FAULT: u16 = 0x0100;
+
+    static REQUESTS: [RequestDescription; 3] = [
+        RequestDescription::new(
+            GET_TEMPERATURE,
+            "GET_TEMPERATURE",
+            &[],
+            &[
+                Field::new("celsius", FieldType::F32),
+                Field::array("raw", FieldType::U16, 2),
+            ],
+        ),
+        RequestDescription::new(
+            SET_TARGET,
+            "SET_TARGET",
+            &[
+                Field::new("celsius", FieldType::F32),
+                Field::new("offset", FieldType::I8),
+            ],
+            &[],
+        ),
+        RequestDescription::new(
+            SET_LABEL,
+            "SET_LABEL",
+            &[Field::new("label", FieldType::Text)],
+            &[],
+        ),
+    ];
+
+    static RESPONSES: [ResponseDescription; 1] =
+        [ResponseDescription::new(SENSOR_FAULT, "SENSOR_FAULT")];
+
+    fn describe(introspection: &Introspection, data: &[u8]) -> Packet<FLEM_PACKET_SIZE> {
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(request::DESCRIBE, data).unwrap();
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        assert!(introspection.handle(&packet, &mut reply));
+        reply
+    }
+
+    #[test]
+    fn entries_are_encoded() {
+        let introspection = Introspection::new(&REQUESTS, &RESPONSES);
+        assert_eq!(introspection.count(), 4);
+
+        let reply = describe(&introspection, &[0x00, 0x00]);
+        assert_eq!(reply.get_response(), response::SUCCESS);
+        let mut expected = vec![0x04, 0x00, KIND_REQUEST, 0x10, 0x00, 15];
+        expected.extend_from_slice(b"GET_TEMPERATURE");
+        // No data fields, two reply fields
+        expected.extend_from_slice(&[0, 2, FieldType::F32 as u8, 1, 7]);
+        expected.extend_from_slice(b"celsius");
+        expected.extend_from_slice(&[FieldType::U16 as u8, 2, 3]);
+        expected.extend_from_slice(b"raw");
+        assert_eq!(reply.payload(), expected.as_slice());
+
+        let reply = describe(&introspection, &[0x03, 0x00]);
+        let mut expected = vec![0x04, 0x00, KIND_RESPONSE, 0x00, 0x01, 12];
+        expected.extend_from_slice(b"SENSOR_FAULT");
+        assert_eq!(reply.payload(), expected.as_slice());
+
+        let reply = describe(&introspection, &[0x04, 0x00]);
+        assert_eq!(reply.get_response(), response::OUT_OF_RANGE);
+        let reply = describe(&introspection, &[0x04]);
+        assert_eq!(reply.get_response(), response::INVALID_ARGUMENT);
+
+        // Other requests are left alone
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(GET_TEMPERATURE, &[]).unwrap();
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        assert!(!introspection.handle(&packet, &mut reply));
+    }
+
+    #[test]
+    fn entries_must_fit_the_packet() {
+        let introspection = Introspection::new(&REQUESTS, &RESPONSES);
+        let mut packet = Packet::<16>::new();
+        packet.pack_data(request::DESCRIBE, &[0x00, 0x00]).unwrap();
+        let mut reply = Packet::<16>::new();
+        assert!(introspection.handle(&packet, &mut reply));
+        assert_eq!(reply.get_response(), response::INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn long_names_are_cut_on_a_character_boundary() {
+        // 256 bytes of two byte characters
+        let name: &'static str = Box::leak("é".repeat(128).into_boxed_str());
+        let responses = [ResponseDescription::new(SENSOR_FAULT, name)];
+        let introspection = Introspection::new(&[], &responses);
+
+        let mut packet = Packet::<300>::new();
+        packet.pack_data(request::DESCRIBE, &[0x00, 0x00]).unwrap();
+        let mut reply = Packet::<300>::new();
+        assert!(introspection.handle(&packet, &mut reply));
+        let payload = reply.payload();
+        assert_eq!(payload[5], 254);
+        assert_eq!(&payload[6..], &name.as_bytes()[..254]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn host_decodes_and_builds_packets() {
+        use flem::client::Client;
+        use flem::emulator::Emulator;
+        use flem::introspect::{self, DeviceDescription, IntrospectError, Value};
+        use flem::traits::Channel;
+
+        let mut emulator =
+            Emulator::<_, FLEM_PACKET_SIZE>::new(Introspection::new(&REQUESTS, &RESPONSES));
+        emulator.handle(request::DESCRIBE, |introspection, packet, reply| {
+            introspection.handle(packet, reply);
+        });
+        let (tx, rx) = Channel::<FLEM_PACKET_SIZE>::listen(&mut emulator, 1, 1);
+        let client = Client::new(&tx, &rx);
+
+        let description = introspect::describe(&client).unwrap();
+        Channel::<FLEM_PACKET_SIZE>::unlisten(&mut emulator).unwrap();
+
+        assert_eq!(description.requests.len(), 3);
+        assert_eq!(description.responses.len(), 1);
+        let request = description.request(GET_TEMPERATURE).unwrap();
+        assert_eq!(request.name, "GET_TEMPERATURE");
+        assert!(request.data.is_empty());
+        assert_eq!(request.reply[1].to_string(), "raw: [u16; 2]");
+        assert_eq!(
+            description.response_name(SENSOR_FAULT),
+            Some("SENSOR_FAULT")
+        );
+        assert_eq!(
+            description.decoder().request_name(SET_TARGET),
+            Some("SET_TARGET")
+        );
+
+        // Building requests from text
+        let packet = description
+            .build::<FLEM_PACKET_SIZE>("SET_TARGET", &["21.5", "-3"])
+            .unwrap();
+        assert_eq!(packet.get_request(), SET_TARGET);
+        assert_eq!(&packet.payload()[..4], &21.5f32.to_le_bytes());
+        assert_eq!(packet.payload()[4], 0xFD);
+        assert_eq!(
+            description.format_request(&packet),
+            "SET_TARGET (0x0011) celsius=21.5 offset=-3"
+        );
+        let packet = description
+            .build::<FLEM_PACKET_SIZE>("SET_LABEL", &["hall way"])
+            .unwrap();
+        assert_eq!(packet.payload(), b"hall way");
+
+        assert_eq!(
+            description
+                .build::<FLEM_PACKET_SIZE>("SET_TARGET", &["21.5", "-200"])
+                .err(),
+            Some(IntrospectError::InvalidArgument("offset".to_string()))
+        );
+        assert_eq!(
+            description
+                .build::<FLEM_PACKET_SIZE>("SET_TARGET", &["21.5"])
+                .err(),
+            Some(IntrospectError::ArgumentCount {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(
+            description.build::<FLEM_PACKET_SIZE>("REBOOT", &[]).err(),
+            Some(IntrospectError::UnknownRequest("REBOOT".to_string()))
+        );
+
+        // Decoding replies
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut data = 19.25f32.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0x01, 0x02, 0x03, 0x04]);
+        reply.pack_data(GET_TEMPERATURE, &data).unwrap();
+        assert_eq!(
+            description.format_reply(&reply),
+            "GET_TEMPERATURE (0x0010) celsius=19.25 raw=[513, 1027]"
+        );
+        let request = description.request(GET_TEMPERATURE).unwrap();
+        assert_eq!(
+            DeviceDescription::decode_fields(&request.reply, &data).unwrap()[1].1,
+            Value::Array(vec![Value::Unsigned(513), Value::Unsigned(1027)])
+        );
+        assert_eq!(
+            DeviceDescription::decode_fields(&request.reply, &data[..7]),
+            Err(IntrospectError::InvalidPayload)
+        );
+
+        reply
+            .pack_error(GET_TEMPERATURE, SENSOR_FAULT, &[])
+            .unwrap();
+        assert_eq!(
+            description.format_reply(&reply),
+            "GET_TEMPERATURE (0x0010) error: 0x0100 (SENSOR_FAULT)"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::memory::{MemoryAccess, MemoryRegion, MemoryService, RawMemory};
+    use flem::{request, response, Packet};
+
+    const FLEM_PACKET_SIZE: usize = 64;
+    const RAM_START: u32 = 0x2000_0000;
+
+    /// 4 KiB of RAM at `RAM_START`
+    struct Ram {
+        memory: Vec<u8>,
+    }
+
+    impl MemoryAccess for Ram {
+        fn read(&mut self, address: u32, buffer: &mut [u8]) {
+            let offset = (address - RAM_START) as usize;
+            buffer.copy_from_slice(&self.memory[offset..offset + buffer.len()]);
+        }
+
+        fn write(&mut self, address: u32, data: &[u8]) {
+            let offset = (address - RAM_START) as usize;
+            self.memory[offset..offset + data.len()].copy_from_slice(data);
+        }
+    }
+
+    const REGIONS: [MemoryRegion; 2] = [
+        MemoryRegion::read_only(RAM_START, 0x800),
+        MemoryRegion::read_write(RAM_START + 0x800, 0x800),
+    ];
+
+    fn service() -> MemoryService<'static, Ram> {
+        MemoryService::new(
+            &REGIONS,
+            Ram {
+                memory: (0..4096).map(|i| i as u8).collect(),
+            },
+        )
+    }
+
+    fn send(
+        service: &mut MemoryService<Ram>,
+        code: u16,
+        address: u32,
+        data: &[u8],
+    ) -> Packet<FLEM_PACKET_SIZE> {
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.set_request(code);
+        packet.add_data(&address.to_le_bytes()).unwrap();
+        packet.add_data(data).unwrap();
+        packet.pack();
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        assert!(service.handle(&packet, &mut reply));
+        reply
+    }
+
+    #[test]
+    fn allow_list_is_enforced() {
+        let mut service = service();
+
+        let reply = send(
+            &mut service,
+            request::MEMORY_READ,
+            RAM_START + 4,
+            &8u16.to_le_bytes(),
+        );
+        assert_eq!(reply.get_response(), response::SUCCESS);
+        assert_eq!(reply.payload(), &[4, 5, 6, 7, 8, 9, 10, 11]);
+
+        // Reads are capped to the packet size
+        let reply = send(
+            &mut service,
+            request::MEMORY_READ,
+            RAM_START,
+            &1000u16.to_le_bytes(),
+        );
+        assert_eq!(reply.payload().len(), FLEM_PACKET_SIZE);
+
+        // Outside every region, or running off the end of one
+        for address in [0, RAM_START - 1, RAM_START + 0xFFC, u32::MAX - 1] {
+            let reply = send(
+                &mut service,
+                request::MEMORY_READ,
+                address,
+                &8u16.to_le_bytes(),
+            );
+            assert_eq!(
+                reply.get_response(),
+                response::ACCESS_DENIED,
+                "{:#X}",
+                address
+            );
+        }
+
+        // Writes only go to writable regions
+        let reply = send(&mut service, request::MEMORY_WRITE, RAM_START, &[0xAA]);
+        assert_eq!(reply.get_response(), response::ACCESS_DENIED);
+        let reply = send(
+            &mut service,
+            request::MEMORY_WRITE,
+            RAM_START + 0x800,
+            &[0xAA, 0xBB],
+        );
+        assert_eq!(reply.get_response(), response::SUCCESS);
+        assert_eq!(&service.memory().memory[0x800..0x802], &[0xAA, 0xBB]);
+
+        let reply = send(&mut service, request::MEMORY_READ, RAM_START, &[1]);
+        assert_eq!(reply.get_response(), response::INVALID_ARGUMENT);
+    }
+
+    #[test]
+    fn raw_memory() {
+        let mut buffer = [1u8, 2, 3, 4];
+        let address = buffer.as_mut_ptr() as usize as u32;
+        if address as usize != buffer.as_ptr() as usize {
+            // The buffer isn't addressable with 32 bits on this host
+            return;
+        }
+
+        let mut memory = unsafe { RawMemory::new() };
+        let mut read = [0u8; 4];
+        memory.read(address, &mut read);
+        assert_eq!(read, [1, 2, 3, 4]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn host_api() {
+        use flem::client::{Client, ClientError};
+        use flem::emulator::Emulator;
+        use flem::memory;
+        use flem::traits::Channel;
+
+        let mut emulator = Emulator::<_, FLEM_PACKET_SIZE>::new(service());
+        for code in request::MEMORY_READ..=request::MEMORY_REGIONS {
+            emulator.handle(code, |service, packet, reply| {
+                service.handle(packet, reply);
+            });
+        }
+        let (tx, rx) = Channel::<FLEM_PACKET_SIZE>::listen(&mut emulator, 1, 1);
+        let client = Client::new(&tx, &rx);
+
+        assert_eq!(memory::regions(&client).unwrap(), REGIONS.to_vec());
+
+        let data = memory::read(&client, RAM_START + 10, 1000).unwrap();
+        assert_eq!(data.len(), 1000);
+        assert!(data
+            .iter()
+            .enumerate()
+            .all(|(i, byte)| *byte == (i + 10) as u8));
+
+        let pattern: Vec<u8> = (0..300).map(|i| (i * 3) as u8).collect();
+        memory::write(&client, RAM_START + 0x900, &pattern).unwrap();
+        assert_eq!(
+            memory::read(&client, RAM_START + 0x900, 300).unwrap(),
+            pattern
+        );
+
+        assert_eq!(
+            memory::read(&client, 0x1000, 4),
+            Err(ClientError::Response(response::ACCESS_DENIED))
+        );
+        assert_eq!(
+            memory::read(&client, u32::MAX - 1, 4),
+            Err(ClientError::AddressOverflow)
+        );
+        assert_eq!(
+            memory::write(&client, u32::MAX - 1, &pattern),
+            Err(ClientError::AddressOverflow)
+        );
+        Channel::<FLEM_PACKET_SIZE>::unlisten(&mut emulator).unwrap();
+    }
+}
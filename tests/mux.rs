@@ -0,0 +1,155 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::mux::{
+        pack_credit, pack_fragment, Frame, Scheduler, StreamConfig, StreamState, STARVATION_LIMIT,
+    };
+    use flem::{response, Packet};
+
+    const FLEM_PACKET_SIZE: usize = 32;
+
+    const COMMAND: u8 = 0;
+    const LOG: u8 = 1;
+    const BULK: u8 = 2;
+    const STREAMS: [StreamConfig; 3] = [
+        StreamConfig::new(COMMAND, 2, 4),
+        StreamConfig::new(LOG, 1, 4),
+        StreamConfig::new(BULK, 1, 4),
+    ];
+
+    #[test]
+    fn framing() {
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+
+        pack_fragment(&mut packet, BULK, true, &[1, 2, 3]).unwrap();
+        assert_eq!(packet.get_response(), response::ASYNC);
+        assert_eq!(
+            Frame::parse(&packet),
+            Some(Frame::Data {
+                stream: BULK,
+                last: true,
+                data: &[1, 2, 3]
+            })
+        );
+
+        pack_credit(&mut packet, LOG, 300).unwrap();
+        assert_eq!(
+            Frame::parse(&packet),
+            Some(Frame::Credit {
+                stream: LOG,
+                credits: 300
+            })
+        );
+
+        assert!(pack_fragment(&mut packet, BULK, false, &[0; FLEM_PACKET_SIZE - 1]).is_err());
+        packet.pack_data(0x10, &[BULK, 0]).unwrap();
+        assert_eq!(Frame::parse(&packet), None);
+    }
+
+    #[test]
+    fn scheduling_and_credits() {
+        let mut states = STREAMS.map(StreamState::new);
+        let mut scheduler = Scheduler::new(&mut states);
+
+        // Each stream grants its window once
+        let grants: Vec<_> = core::iter::from_fn(|| scheduler.grant()).collect();
+        assert_eq!(grants, vec![(COMMAND, 4), (LOG, 4), (BULK, 4)]);
+
+        // Nothing is sent without credits from the peer
+        assert_eq!(scheduler.next(|_| true), None);
+        for stream in STREAMS.iter() {
+            scheduler.add_credits(stream.id, 2);
+        }
+
+        // Commands first, then log and bulk take turns
+        let order: Vec<_> = core::iter::from_fn(|| scheduler.next(|_| true)).collect();
+        assert_eq!(order, vec![COMMAND, COMMAND, LOG, BULK, LOG, BULK]);
+        assert_eq!(scheduler.credits(BULK), Some(0));
+
+        // A stream without credits doesn't hold up the others
+        scheduler.add_credits(LOG, 1);
+        assert_eq!(scheduler.next(|id| id != COMMAND), Some(LOG));
+
+        // Consumed fragments are granted back in batches of half the window
+        scheduler.consumed(BULK, 1);
+        assert_eq!(scheduler.grant(), None);
+        scheduler.consumed(BULK, 2);
+        assert_eq!(scheduler.grant(), Some((BULK, 3)));
+        assert_eq!(scheduler.grant(), None);
+    }
+
+    #[test]
+    fn busy_commands_dont_starve_bulk() {
+        let mut states = STREAMS.map(StreamState::new);
+        let mut scheduler = Scheduler::new(&mut states);
+        scheduler.add_credits(COMMAND, 1000);
+        scheduler.add_credits(BULK, 1000);
+
+        let order: Vec<_> = (0..100).map(|_| scheduler.next(|_| true)).collect();
+        let bulk = order.iter().filter(|id| **id == Some(BULK)).count();
+        // One bulk fragment after every STARVATION_LIMIT command fragments
+        assert_eq!(bulk, 100 / (STARVATION_LIMIT as usize + 1));
+        assert_eq!(order[STARVATION_LIMIT as usize], Some(BULK));
+        assert!(order[..STARVATION_LIMIT as usize]
+            .iter()
+            .all(|id| *id == Some(COMMAND)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn streams_over_one_link() {
+        use flem::mux::Multiplexer;
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        let (a_tx, b_rx) = mpsc::channel::<Packet<FLEM_PACKET_SIZE>>();
+        let (b_tx, a_rx) = mpsc::channel::<Packet<FLEM_PACKET_SIZE>>();
+
+        let mut host = Multiplexer::new((a_tx, a_rx));
+        let host_streams: Vec<_> = STREAMS.iter().map(|config| host.open(*config)).collect();
+        let mut device = Multiplexer::new((b_tx, b_rx));
+        let device_streams: Vec<_> = STREAMS.iter().map(|config| device.open(*config)).collect();
+        let host = host.start();
+        let device = device.start();
+
+        let timeout = Duration::from_secs(2);
+        let [command, log, bulk] = &host_streams[..] else {
+            unreachable!()
+        };
+        let [device_command, device_log, device_bulk] = &device_streams[..] else {
+            unreachable!()
+        };
+
+        // A message much longer than the window and a packet
+        let image: Vec<u8> = (0..2000).map(|i| i as u8).collect();
+        bulk.send(&image).unwrap();
+        command.send(b"reset").unwrap();
+        device_log.send(b"booting").unwrap();
+        device_log.send(&[]).unwrap();
+
+        assert_eq!(device_command.recv_timeout(timeout).unwrap(), b"reset");
+        assert_eq!(device_bulk.recv_timeout(timeout).unwrap(), image);
+        assert_eq!(log.recv_timeout(timeout).unwrap(), b"booting");
+        assert_eq!(log.recv_timeout(timeout).unwrap(), b"");
+
+        // The device stops reading bulk data: the window fills, commands keep flowing
+        for i in 0..6u8 {
+            bulk.send(&[i]).unwrap();
+        }
+        command.send(b"status").unwrap();
+        assert_eq!(device_command.recv_timeout(timeout).unwrap(), b"status");
+        std::thread::sleep(Duration::from_millis(50));
+        let mut received: Vec<_> = core::iter::from_fn(|| device_bulk.try_recv()).collect();
+        // Credits come back in batches, so the window may not quite be full
+        assert!((3..=4).contains(&received.len()), "{:?}", received);
+
+        // Reading released credits for the rest
+        while received.len() < 6 {
+            received.push(device_bulk.recv_timeout(timeout).unwrap());
+        }
+        assert_eq!(received, (0..6u8).map(|i| vec![i]).collect::<Vec<_>>());
+
+        host.stop();
+        device.stop();
+    }
+}
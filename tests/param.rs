@@ -0,0 +1,175 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::param::{ParamService, ParamType, ParamValue, Parameter};
+    use flem::{request, response, Packet};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const FLEM_PACKET_SIZE: usize = 64;
+
+    const GAIN: u16 = 0x01;
+    const MODE: u16 = 0x02;
+    const SERIAL: u16 = 0x10;
+
+    static TABLE: [Parameter; 3] = [
+        Parameter::new(
+            GAIN,
+            "gain",
+            "dB",
+            ParamValue::F32(0.0),
+            ParamValue::F32(-6.0),
+            ParamValue::F32(24.0),
+        ),
+        Parameter::new(
+            MODE,
+            "mode",
+            "",
+            ParamValue::U8(1),
+            ParamValue::U8(0),
+            ParamValue::U8(3),
+        ),
+        Parameter::read_only(SERIAL, "serial", "", ParamValue::U32(0x1234_5678)),
+    ];
+
+    static CHANGES: AtomicU32 = AtomicU32::new(0);
+
+    fn send(service: &mut ParamService, code: u16, data: &[u8]) -> Packet<FLEM_PACKET_SIZE> {
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(code, data).unwrap();
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        assert!(service.handle(&packet, &mut reply));
+        reply
+    }
+
+    #[test]
+    fn get_and_set_are_checked() {
+        let mut values = [ParamValue::Bool(false); 3];
+        let mut service = ParamService::new(&TABLE, &mut values);
+        service.set_callback(|parameter, _| {
+            assert_eq!(parameter.id, MODE);
+            CHANGES.fetch_add(1, Ordering::Relaxed);
+        });
+
+        // Defaults are loaded
+        let reply = send(&mut service, request::PARAM_GET, &SERIAL.to_le_bytes());
+        assert_eq!(reply.get_response(), response::SUCCESS);
+        assert_eq!(reply.payload(), &[0x10, 0x00, 4, 0x78, 0x56, 0x34, 0x12]);
+
+        let reply = send(&mut service, request::PARAM_SET, &[0x02, 0x00, 1, 3]);
+        assert_eq!(reply.get_response(), response::SUCCESS);
+        assert_eq!(reply.payload(), &[0x02, 0x00, 1, 3]);
+        assert_eq!(service.get(MODE), Some(ParamValue::U8(3)));
+        assert_eq!(CHANGES.load(Ordering::Relaxed), 1);
+
+        for (data, error) in [
+            (&[0x02, 0x00, 1, 4][..], response::OUT_OF_RANGE),
+            (&[0x02, 0x00, 2, 3, 0], response::INVALID_ARGUMENT),
+            (&[0x02, 0x00, 1], response::INVALID_ARGUMENT),
+            (&[0x02, 0x00, 1, 3, 0], response::INVALID_ARGUMENT),
+            (&[0x10, 0x00, 4, 0, 0, 0, 0], response::ACCESS_DENIED),
+            (&[0x99, 0x00, 1, 0], response::UNKNOWN_PARAMETER),
+        ] {
+            let reply = send(&mut service, request::PARAM_SET, data);
+            assert_eq!(reply.get_response(), error, "{:?}", data);
+        }
+        assert_eq!(service.get(MODE), Some(ParamValue::U8(3)));
+        assert_eq!(CHANGES.load(Ordering::Relaxed), 1);
+
+        // Device code may update read-only values, but not change their type
+        assert_eq!(service.set(SERIAL, ParamValue::U32(42)), Ok(()));
+        assert_eq!(
+            service.set(SERIAL, ParamValue::U16(42)),
+            Err(response::INVALID_ARGUMENT)
+        );
+        assert_eq!(
+            service.set(MODE, ParamValue::U8(4)),
+            Err(response::OUT_OF_RANGE)
+        );
+
+        let reply = send(&mut service, request::PARAM_INFO, &3u16.to_le_bytes());
+        assert_eq!(reply.get_response(), response::UNKNOWN_PARAMETER);
+
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(request::ID, &[]).unwrap();
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        assert!(!service.handle(&packet, &mut reply));
+    }
+
+    #[test]
+    fn long_names_are_cut_on_a_character_boundary() {
+        // 256 bytes of two byte characters
+        let name: &'static str = Box::leak("é".repeat(128).into_boxed_str());
+        let table = [Parameter::read_only(0x20, name, "", ParamValue::U8(0))];
+        let mut values = [ParamValue::U8(0)];
+        let mut service = ParamService::new(&table, &mut values);
+
+        let mut packet = Packet::<300>::new();
+        packet
+            .pack_data(request::PARAM_INFO, &[0x00, 0x00])
+            .unwrap();
+        let mut reply = Packet::<300>::new();
+        assert!(service.handle(&packet, &mut reply));
+        let payload = reply.payload();
+        // Name length, name, then the empty units
+        let name_at = payload.len() - 1 - 254;
+        assert_eq!(payload[name_at - 1], 254);
+        assert_eq!(
+            &payload[name_at..payload.len() - 1],
+            &name.as_bytes()[..254]
+        );
+        assert_eq!(payload[payload.len() - 1], 0);
+    }
+
+    #[test]
+    fn ranges() {
+        assert!(ParamValue::F32(24.0).within(&ParamValue::F32(-6.0), &ParamValue::F32(24.0)));
+        assert!(!ParamValue::F32(f32::NAN).within(&ParamValue::F32(-6.0), &ParamValue::F32(24.0)));
+        assert!(!ParamValue::I16(-7).within(&ParamValue::I16(-6), &ParamValue::I16(6)));
+        assert!(!ParamValue::U8(1).within(&ParamValue::U16(0), &ParamValue::U16(6)));
+        assert_eq!(ParamType::from_u8(6), Some(ParamType::F32));
+        assert_eq!(ParamType::from_u8(7), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn host_api() {
+        use flem::client::{Client, ClientError};
+        use flem::emulator::Emulator;
+        use flem::param;
+        use flem::traits::Channel;
+
+        let values = Box::leak(Box::new([ParamValue::Bool(false); 3]));
+        let mut emulator = Emulator::<_, FLEM_PACKET_SIZE>::new(ParamService::new(&TABLE, values));
+        for code in request::PARAM_INFO..=request::PARAM_SET {
+            emulator.handle(code, |service, packet, reply| {
+                service.handle(packet, reply);
+            });
+        }
+        let (tx, rx) = Channel::<FLEM_PACKET_SIZE>::listen(&mut emulator, 1, 1);
+        let client = Client::new(&tx, &rx);
+
+        let parameters = param::list(&client).unwrap();
+        assert_eq!(parameters.len(), 3);
+        assert_eq!(parameters[0].name, "gain");
+        assert_eq!(parameters[0].units, "dB");
+        assert_eq!(parameters[0].param_type, ParamType::F32);
+        assert_eq!(parameters[0].max, ParamValue::F32(24.0));
+        assert!(!parameters[0].read_only);
+        assert!(parameters[2].read_only);
+
+        assert_eq!(
+            param::set(&client, GAIN, ParamValue::F32(-3.5)),
+            Ok(ParamValue::F32(-3.5))
+        );
+        assert_eq!(param::get(&client, GAIN), Ok(ParamValue::F32(-3.5)));
+        assert_eq!(
+            param::set(&client, GAIN, ParamValue::F32(30.0)),
+            Err(ClientError::Response(response::OUT_OF_RANGE))
+        );
+        assert_eq!(
+            param::get(&client, 0x99),
+            Err(ClientError::Response(response::UNKNOWN_PARAMETER))
+        );
+        Channel::<FLEM_PACKET_SIZE>::unlisten(&mut emulator).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::pool::PacketPool;
+    use flem::Packet;
+
+    const FLEM_PACKET_SIZE: usize = 1024;
+
+    #[test]
+    fn pool_exhaustion_and_release() {
+        let pool = PacketPool::<FLEM_PACKET_SIZE, 3>::new();
+
+        let a = pool.alloc().unwrap();
+        let b = pool.alloc().unwrap();
+        let c = pool.alloc().unwrap();
+        assert!(pool.alloc().is_none(), "Pool should be exhausted");
+        assert_eq!(pool.available(), 0);
+
+        drop(b);
+        assert_eq!(pool.available(), 1);
+        let b = pool.alloc();
+        assert!(b.is_some(), "Released packet should be reusable");
+
+        drop(a);
+        drop(b);
+        drop(c);
+        assert_eq!(pool.available(), pool.capacity());
+    }
+
+    #[test]
+    fn pool_fill_in_place_and_queue() {
+        static POOL: PacketPool<FLEM_PACKET_SIZE, 3> = PacketPool::new();
+        let mut queue = heapless::spsc::Queue::<_, 4>::new();
+
+        let mut tx = Packet::<FLEM_PACKET_SIZE>::new();
+        tx.pack_data(0xF, &[1, 2, 3, 4]).unwrap();
+
+        for _ in 0..3 {
+            let mut rx = POOL.alloc().unwrap();
+            for byte in tx.bytes() {
+                let _ = rx.construct(*byte);
+            }
+            assert_eq!(rx.get_status(), flem::Status::PacketReceived);
+            queue.enqueue(rx).unwrap();
+        }
+        assert!(POOL.alloc().is_none(), "All packets are in flight");
+
+        while let Some(rx) = queue.dequeue() {
+            assert_eq!(rx.bytes(), tx.bytes(), "Rx and Tx packets don't match");
+        }
+        assert_eq!(POOL.available(), 3, "Dropped handles should return to the pool");
+    }
+}
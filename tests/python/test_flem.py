@@ -0,0 +1,199 @@
+"""Tests of the Python bindings. Build them with `maturin develop` and run `pytest`."""
+
+import os
+import threading
+import tty
+
+import pytest
+
+import flem
+
+ECHO = 0x0010
+FAIL = 0x0011
+SILENT = 0x0012
+
+
+@pytest.fixture
+def emulator():
+    emulator = flem.Emulator()
+    emulator.handle(ECHO, lambda packet: packet.payload)
+
+    def fail(packet):
+        reply = flem.Packet()
+        reply.pack_error(packet.request, flem.response.INVALID_ARGUMENT)
+        return reply
+
+    emulator.handle(FAIL, fail)
+    emulator.handle(SILENT, lambda packet: None)
+    yield emulator
+    emulator.close()
+
+
+def test_packet_round_trip():
+    packet = flem.Packet()
+    packet.pack_data(flem.request.ID, b"\xaa\xbb\xcc")
+    assert len(packet) == 10 + 3
+    assert packet.validate()
+
+    received = flem.Packet()
+    states = [received.construct(byte) for byte in bytes(packet)]
+    assert states[-1] and not any(states[:-1])
+    assert received.request == flem.request.ID
+    assert received.response == flem.response.SUCCESS
+    assert received.payload == b"\xaa\xbb\xcc"
+    assert received.checksum == packet.checksum
+    assert flem.Packet.from_bytes(bytes(packet)).payload == packet.payload
+    assert "ID" in repr(received)
+
+    packet.reset()
+    packet.request = 0x1234
+    packet.response = flem.response.SUCCESS
+    packet.add_data(b"\x01")
+    packet.pack()
+    assert flem.Packet.from_bytes(bytes(packet)).request == 0x1234
+
+
+def test_packet_errors():
+    with pytest.raises(flem.StatusError) as error:
+        flem.Packet().construct(0x00)
+    assert error.value.status == "HeaderBytesNotFound"
+
+    packet = flem.Packet()
+    packet.pack_data(ECHO, b"\x01\x02")
+    wire = bytearray(bytes(packet))
+    wire[-1] ^= 0xFF
+    with pytest.raises(flem.StatusError) as error:
+        flem.Packet.from_bytes(bytes(wire))
+    assert error.value.status == "ChecksumError"
+    with pytest.raises(flem.StatusError):
+        flem.Packet.from_bytes(bytes(packet)[:-1])
+
+    # A header announcing more data than fits
+    received = flem.Packet()
+    with pytest.raises(flem.StatusError) as error:
+        for byte in b"\x55\x55\x00\x00\x10\x00\x01\x00\xff\xff":
+            received.construct(byte)
+    assert error.value.status == "InvalidDataLengthDetected"
+    assert len(received) == 10
+    assert len(bytes(received)) == 10
+    assert received.payload == b""
+    assert "0 bytes" in repr(received)
+    assert [received.construct(byte) for byte in bytes(packet)][-1]
+
+    with pytest.raises(flem.StatusError) as error:
+        flem.Packet().add_data(bytes(flem.DATA_SIZE + 1))
+    assert error.value.status == "PacketOverflow"
+    assert issubclass(flem.StatusError, flem.FlemError)
+
+
+def test_data_id():
+    id = flem.DataId("bench", 1, 2, 3, 512)
+    packet = flem.Packet()
+    packet.pack_id(id)
+    decoded = flem.DataId.from_bytes(packet.payload)
+    assert (decoded.name, decoded.major, decoded.minor, decoded.patch) == ("bench", 1, 2, 3)
+    assert decoded.max_packet_size == 512
+
+    with pytest.raises(ValueError):
+        flem.DataId("x" * 26, 0, 0, 0, 0)
+    with pytest.raises(ValueError):
+        flem.DataId.from_bytes(b"\x01")
+
+
+def test_buffer():
+    data = bytearray(10)
+    offset = flem.buffer.u16_to_le_buffer(0xBEEF, data)
+    offset = flem.buffer.i32_to_le_buffer(-2, data, offset)
+    offset = flem.buffer.f32_to_le_buffer(1.5, data, offset)
+    assert offset == 10
+    assert data[:2] == b"\xef\xbe"
+
+    value, offset = flem.buffer.le_buffer_to_u16(bytes(data))
+    assert value == 0xBEEF
+    value, offset = flem.buffer.le_buffer_to_i32(bytes(data), offset)
+    assert value == -2
+    value, offset = flem.buffer.le_buffer_to_f32(bytes(data), offset)
+    assert value == 1.5
+
+    with pytest.raises(flem.BufferError):
+        flem.buffer.u32_to_le_buffer(1, data, 8)
+    with pytest.raises(flem.BufferError):
+        flem.buffer.le_buffer_to_u16(b"\x01")
+
+
+def test_codes():
+    assert flem.request.ID == 0x0001
+    assert flem.request.name(flem.request.ID) == "ID"
+    assert flem.response.name(flem.response.UNKNOWN_REQUEST) == "UNKNOWN_REQUEST"
+    assert flem.response.name(0x1234) is None
+
+
+def test_client_with_emulator(emulator):
+    emulator.set_id(flem.DataId("emulator", 0, 7, 0, flem.DATA_SIZE))
+    with flem.Client.emulator(emulator, timeout_ms=100, retries=1) as client:
+        assert client.call(ECHO, b"\x01\x02").payload == b"\x01\x02"
+        assert client.id().name == "emulator"
+
+        reply = client.request(FAIL)
+        assert reply.response == flem.response.INVALID_ARGUMENT
+        with pytest.raises(flem.ResponseError) as error:
+            client.call(FAIL)
+        assert error.value.response == flem.response.INVALID_ARGUMENT
+
+        with pytest.raises(flem.ClientTimeoutError):
+            client.call(SILENT)
+
+        # A dropped reply is retried
+        emulator.drop_next(1)
+        assert client.call(ECHO, b"\x03").payload == b"\x03"
+
+        request = flem.Packet()
+        request.pack_data(ECHO, b"\x04")
+        client.send(request)
+        assert client.receive().payload == b"\x04"
+        assert client.receive(timeout_ms=10) is None
+
+    with pytest.raises(flem.DisconnectedError):
+        client.call(ECHO)
+    assert issubclass(flem.DisconnectedError, flem.ClientError)
+
+
+def test_client_over_tcp(emulator):
+    address = emulator.bind_tcp("127.0.0.1:0")
+    with flem.Client.tcp(address, timeout_ms=200) as client:
+        assert client.call(ECHO, b"\x05").payload == b"\x05"
+
+
+@pytest.mark.skipif(not hasattr(os, "openpty"), reason="needs a pseudo terminal")
+def test_client_over_serial():
+    device, host = os.openpty()
+    tty.setraw(host)
+
+    def serve():
+        packet = flem.Packet()
+        while True:
+            try:
+                byte = os.read(device, 1)
+            except OSError:
+                return
+            if not byte:
+                return
+            try:
+                if packet.construct(byte[0]):
+                    reply = flem.Packet()
+                    reply.pack_data(packet.request, packet.payload)
+                    os.write(device, bytes(reply))
+                    packet.reset()
+            except flem.StatusError:
+                packet.reset()
+
+    threading.Thread(target=serve, daemon=True).start()
+    try:
+        with flem.Client.serial(os.ttyname(host), baud_rate=9600, timeout_ms=200) as client:
+            assert client.call(ECHO, b"\x06").payload == b"\x06"
+    finally:
+        os.close(host)
+        os.close(device)
+
+    with pytest.raises(flem.DisconnectedError):
+        flem.Client.serial("/dev/flem-does-not-exist")
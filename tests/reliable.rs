@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::reliable::{
+        Acknowledgement, Delivery, ReliableError, ReliableReceiver, ReliableSender, RetryConfig,
+    };
+    use flem::Packet;
+
+    const FLEM_PACKET_SIZE: usize = 64;
+    const MOTOR_START: u16 = 0x20;
+
+    fn transfer(tx: &Packet<FLEM_PACKET_SIZE>) -> Packet<FLEM_PACKET_SIZE> {
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+        for byte in tx.bytes() {
+            let _ = rx.construct(*byte);
+        }
+        assert_eq!(rx.get_status(), flem::Status::PacketReceived);
+        rx
+    }
+
+    #[test]
+    fn retransmit_and_deliver_exactly_once() {
+        let config = RetryConfig {
+            max_retries: 3,
+            timeout: 10,
+            backoff: 2,
+            max_timeout: 100,
+        };
+        let mut sender = ReliableSender::<FLEM_PACKET_SIZE, 4>::new(config);
+        let mut receiver = ReliableReceiver::new();
+        let mut ack = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut delivered = 0;
+
+        // First transmission is lost on the wire
+        let _lost = *sender.send(MOTOR_START, &[1, 2, 3], 0).unwrap();
+        assert_eq!(sender.in_flight(), 1);
+        assert_eq!(
+            sender.poll(5, |_| {}).unwrap(),
+            0,
+            "ACK timeout not reached"
+        );
+
+        let mut retransmissions = Vec::new();
+        assert_eq!(sender.poll(10, |p| retransmissions.push(*p)).unwrap(), 1);
+
+        // Retransmission arrives, but the ACK is lost, so the packet arrives a second time
+        for _ in 0..2 {
+            let rx = transfer(&retransmissions[0]);
+            match receiver.receive(&rx, &mut ack).unwrap() {
+                Delivery::Deliver { sequence, data } => {
+                    assert_eq!(sequence, 0);
+                    assert_eq!(data, &[1, 2, 3]);
+                    delivered += 1;
+                }
+                Delivery::Duplicate(sequence) => assert_eq!(sequence, 0),
+            }
+        }
+        assert_eq!(delivered, 1, "Command should be executed exactly once");
+
+        let ack = transfer(&ack);
+        assert_eq!(sender.receive(&ack, 11).unwrap(), Acknowledgement::Acked(0));
+        assert_eq!(sender.in_flight(), 0);
+    }
+
+    #[test]
+    fn window_and_retries() {
+        let config = RetryConfig {
+            max_retries: 1,
+            timeout: 10,
+            backoff: 1,
+            max_timeout: 10,
+        };
+        let mut sender = ReliableSender::<FLEM_PACKET_SIZE, 2>::new(config);
+
+        sender.send(MOTOR_START, &[], 0).unwrap();
+        sender.send(MOTOR_START, &[], 0).unwrap();
+        assert_eq!(
+            sender.send(MOTOR_START, &[], 0).unwrap_err(),
+            ReliableError::WindowFull
+        );
+
+        assert_eq!(sender.poll(10, |_| {}).unwrap(), 2);
+        assert_eq!(
+            sender.poll(20, |_| {}).unwrap_err(),
+            ReliableError::RetriesExhausted(0)
+        );
+        assert_eq!(
+            sender.poll(20, |_| {}).unwrap_err(),
+            ReliableError::RetriesExhausted(1)
+        );
+        assert_eq!(sender.in_flight(), 0);
+    }
+
+    #[test]
+    fn nack_schedules_retransmission() {
+        let mut sender = ReliableSender::<FLEM_PACKET_SIZE, 4>::new(RetryConfig::default());
+        let receiver = ReliableReceiver::new();
+
+        sender.send(MOTOR_START, &[0xAA], 0).unwrap();
+
+        let mut nack = Packet::<FLEM_PACKET_SIZE>::new();
+        receiver.pack_nack(&mut nack).unwrap();
+        assert_eq!(
+            sender.receive(&transfer(&nack), 1).unwrap(),
+            Acknowledgement::Nacked(0)
+        );
+        assert_eq!(
+            sender.poll(1, |_| {}).unwrap(),
+            1,
+            "NACK should retransmit immediately"
+        );
+    }
+}
@@ -0,0 +1,43 @@
+/* Generated by flem::codegen from the "thermostat" schema. Do not edit. */
+/* Payload structs are packed and little endian, like the wire format. */
+
+#ifndef THERMOSTAT_FLEM_H
+#define THERMOSTAT_FLEM_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/* Reads the current temperature */
+/* Reply: Temperature */
+#define THERMOSTAT_REQUEST_GET_TEMPERATURE 0x0010u
+/* Sets the target temperature and the fan mode */
+/* Data: Target */
+#define THERMOSTAT_REQUEST_SET_TARGET 0x0011u
+
+/* The temperature sensor didn't answer */
+#define THERMOSTAT_RESPONSE_SENSOR_FAULT 0x0100u
+
+#pragma pack(push, 1)
+
+/* A temperature reading */
+typedef struct {
+    float celsius;
+    /* Raw ADC samples */
+    uint16_t raw[4];
+    bool valid;
+} thermostat_temperature_t;
+_Static_assert(sizeof(thermostat_temperature_t) == 13, "thermostat_temperature_t should be 13 bytes");
+
+typedef struct {
+    float celsius;
+    uint8_t fan;
+    int16_t hysteresis;
+    uint32_t schedule[2];
+    /* Heating zones switched on */
+    bool on[3];
+} thermostat_target_t;
+_Static_assert(sizeof(thermostat_target_t) == 18, "thermostat_target_t should be 18 bytes");
+
+#pragma pack(pop)
+
+#endif /* THERMOSTAT_FLEM_H */
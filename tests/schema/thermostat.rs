@@ -0,0 +1,155 @@
+// Generated by flem::codegen from the "thermostat" schema. Do not edit.
+
+#[allow(dead_code)]
+pub mod request {
+    /// Reads the current temperature
+    /// Reply: `Temperature`
+    pub const GET_TEMPERATURE: u16 = 0x0010;
+    /// Sets the target temperature and the fan mode
+    /// Data: `Target`
+    pub const SET_TARGET: u16 = 0x0011;
+}
+
+#[allow(dead_code)]
+pub mod response {
+    /// The temperature sensor didn't answer
+    pub const SENSOR_FAULT: u16 = 0x0100;
+}
+
+/// A temperature reading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Temperature {
+    pub celsius: f32,
+    /// Raw ADC samples
+    pub raw: [u16; 4],
+    pub valid: bool,
+}
+
+impl Temperature {
+    /// Encoded bytes
+    pub const SIZE: usize = 13;
+}
+
+impl Default for Temperature {
+    fn default() -> Self {
+        Temperature {
+            celsius: 0.0,
+            raw: [0; 4],
+            valid: false,
+        }
+    }
+}
+
+impl<const T: usize> ::flem::traits::DataInterface<T> for Temperature {
+    fn encode(&self, packet: &mut ::flem::Packet<T>) -> Result<(), ::flem::traits::DataInterfaceErrors> {
+        let mut buffer = [0u8; Temperature::SIZE];
+        let mut offset = 0;
+        buffer[offset..offset + 4].copy_from_slice(&self.celsius.to_le_bytes());
+        offset += 4;
+        for value in self.raw.iter() {
+            buffer[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+            offset += 2;
+        }
+        buffer[offset..offset + 1].copy_from_slice(&[self.valid as u8]);
+        offset += 1;
+        let _ = offset;
+        packet
+            .add_data(&buffer)
+            .map_err(|_| ::flem::traits::DataInterfaceErrors::IncorrectBufferLength)
+    }
+
+    fn decode(&mut self, packet: &::flem::Packet<T>) -> Result<&Self, ::flem::traits::DataInterfaceErrors> {
+        let data = packet.payload();
+        if data.len() != Temperature::SIZE {
+            return Err(::flem::traits::DataInterfaceErrors::IncorrectDataLength);
+        }
+        let mut offset = 0;
+        self.celsius = f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        offset += 4;
+        for value in self.raw.iter_mut() {
+            *value = u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap());
+            offset += 2;
+        }
+        self.valid = data[offset] != 0;
+        offset += 1;
+        let _ = offset;
+        Ok(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Target {
+    pub celsius: f32,
+    pub fan: u8,
+    pub hysteresis: i16,
+    pub schedule: [u32; 2],
+    /// Heating zones switched on
+    pub on: [bool; 3],
+}
+
+impl Target {
+    /// Encoded bytes
+    pub const SIZE: usize = 18;
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target {
+            celsius: 0.0,
+            fan: 0,
+            hysteresis: 0,
+            schedule: [0; 2],
+            on: [false; 3],
+        }
+    }
+}
+
+impl<const T: usize> ::flem::traits::DataInterface<T> for Target {
+    fn encode(&self, packet: &mut ::flem::Packet<T>) -> Result<(), ::flem::traits::DataInterfaceErrors> {
+        let mut buffer = [0u8; Target::SIZE];
+        let mut offset = 0;
+        buffer[offset..offset + 4].copy_from_slice(&self.celsius.to_le_bytes());
+        offset += 4;
+        buffer[offset..offset + 1].copy_from_slice(&self.fan.to_le_bytes());
+        offset += 1;
+        buffer[offset..offset + 2].copy_from_slice(&self.hysteresis.to_le_bytes());
+        offset += 2;
+        for value in self.schedule.iter() {
+            buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            offset += 4;
+        }
+        for value in self.on.iter() {
+            buffer[offset..offset + 1].copy_from_slice(&[*value as u8]);
+            offset += 1;
+        }
+        let _ = offset;
+        packet
+            .add_data(&buffer)
+            .map_err(|_| ::flem::traits::DataInterfaceErrors::IncorrectBufferLength)
+    }
+
+    fn decode(&mut self, packet: &::flem::Packet<T>) -> Result<&Self, ::flem::traits::DataInterfaceErrors> {
+        let data = packet.payload();
+        if data.len() != Target::SIZE {
+            return Err(::flem::traits::DataInterfaceErrors::IncorrectDataLength);
+        }
+        let mut offset = 0;
+        self.celsius = f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        offset += 4;
+        self.fan = data[offset];
+        offset += 1;
+        self.hysteresis = i16::from_le_bytes(data[offset..offset + 2].try_into().unwrap());
+        offset += 2;
+        for value in self.schedule.iter_mut() {
+            *value = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+            offset += 4;
+        }
+        for value in self.on.iter_mut() {
+            *value = data[offset] != 0;
+            offset += 1;
+        }
+        let _ = offset;
+        Ok(self)
+    }
+}
+
@@ -0,0 +1,172 @@
+#![cfg(feature = "secure")]
+
+#[cfg(test)]
+mod tests {
+
+    use flem::secure::{Role, SecureSession, SECURE_OVERHEAD};
+    use flem::{response, Packet, Status};
+
+    const FLEM_PACKET_SIZE: usize = 64;
+    const KEY: [u8; 32] = [0x5A; 32];
+
+    #[test]
+    fn sealed_packets_cross_the_link() {
+        let mut host = SecureSession::new(&KEY, Role::Host);
+        let mut device = SecureSession::new(&KEY, Role::Device);
+        let data = [0xAB; FLEM_PACKET_SIZE - SECURE_OVERHEAD];
+
+        let mut tx = Packet::<FLEM_PACKET_SIZE>::new();
+        host.seal(&mut tx, 0x10, response::SUCCESS, &data).unwrap();
+
+        // The header is in the clear, so the packet frames like any other
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut result = Err(Status::PacketBuilding);
+        for byte in tx.bytes() {
+            result = rx.construct(*byte);
+        }
+        assert_eq!(result, Ok(()));
+        assert_eq!(rx.get_request(), 0x10);
+
+        let mut buffer = [0u8; FLEM_PACKET_SIZE];
+        assert_eq!(device.open(&rx, &mut buffer), Ok(&data[..]));
+
+        // A host can't open its own packets, the nonce is tied to the sender
+        assert_eq!(host.open(&tx, &mut buffer), Err(Status::DecryptionFailed));
+
+        // No room for the counter and tag
+        assert_eq!(
+            host.seal(&mut tx, 0x10, response::SUCCESS, &[0; FLEM_PACKET_SIZE]),
+            Err(Status::PacketOverflow)
+        );
+    }
+
+    #[test]
+    fn tampered_packets_are_rejected() {
+        let mut host = SecureSession::new(&KEY, Role::Host);
+        let mut device = SecureSession::new(&KEY, Role::Device);
+        let mut buffer = [0u8; FLEM_PACKET_SIZE];
+
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        host.seal(&mut packet, 0x10, response::SUCCESS, &[1, 2, 3, 4])
+            .unwrap();
+
+        // Changing the request code and fixing the checksum still fails authentication
+        let mut retargeted = packet;
+        retargeted.set_request(0x11);
+        retargeted.pack();
+        assert_eq!(
+            device.open(&retargeted, &mut buffer),
+            Err(Status::DecryptionFailed)
+        );
+
+        let mut bytes = packet.bytes().to_vec();
+        bytes[12] ^= 0x01;
+        let mut flipped = Packet::<FLEM_PACKET_SIZE>::new();
+        for byte in bytes.iter() {
+            let _ = flipped.construct(*byte);
+        }
+        flipped.pack();
+        assert_eq!(
+            device.open(&flipped, &mut buffer),
+            Err(Status::DecryptionFailed)
+        );
+
+        // Forgeries don't burn the counter of the genuine packet
+        assert_eq!(device.open(&packet, &mut buffer), Ok(&[1u8, 2, 3, 4][..]));
+    }
+
+    #[test]
+    fn replays_are_rejected() {
+        let mut host = SecureSession::new(&KEY, Role::Host);
+        let mut device = SecureSession::new(&KEY, Role::Device);
+        let mut buffer = [0u8; FLEM_PACKET_SIZE];
+
+        let mut packets = [Packet::<FLEM_PACKET_SIZE>::new(); 70];
+        for (index, packet) in packets.iter_mut().enumerate() {
+            host.seal(packet, 0x10, response::SUCCESS, &[index as u8])
+                .unwrap();
+        }
+
+        // Out of order delivery inside the window is fine, repeats are not
+        assert!(device.open(&packets[1], &mut buffer).is_ok());
+        assert!(device.open(&packets[0], &mut buffer).is_ok());
+        assert_eq!(
+            device.open(&packets[0], &mut buffer),
+            Err(Status::ReplayDetected)
+        );
+
+        // Once the window has moved on, old packets are refused
+        assert!(device.open(&packets[69], &mut buffer).is_ok());
+        assert_eq!(
+            device.open(&packets[2], &mut buffer),
+            Err(Status::ReplayDetected)
+        );
+        assert_eq!(device.open(&packets[10], &mut buffer), Ok(&[10u8][..]));
+    }
+
+    #[test]
+    fn addressed_packets_are_routed() {
+        use flem::address::{Address, ADDRESS_PREFIX_SIZE};
+        use flem::bridge::{find_route, Route};
+
+        const ROUTES: [Route; 2] = [Route::address(3, 0), Route::any(1)];
+
+        let mut host = SecureSession::new(&KEY, Role::Host);
+        let mut device = SecureSession::new(&KEY, Role::Device);
+        let mut buffer = [0u8; FLEM_PACKET_SIZE];
+        let data = [0xCD; FLEM_PACKET_SIZE - ADDRESS_PREFIX_SIZE - SECURE_OVERHEAD];
+
+        let mut tx = Packet::<FLEM_PACKET_SIZE>::new();
+        host.seal_addressed(&mut tx, 0x10, response::SUCCESS, Address::new(3, 0), &data)
+            .unwrap();
+        assert_eq!(tx.address(), Some(Address::new(3, 0)));
+        assert_eq!(find_route(&ROUTES, &tx), Some(0));
+
+        // Addressed receivers see the prefix too
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+        rx.set_local_address(Some(3));
+        let mut result = Err(Status::PacketBuilding);
+        for byte in tx.bytes() {
+            result = rx.construct(*byte);
+        }
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            device.open_addressed(&rx, &mut buffer),
+            Ok((Address::new(3, 0), &data[..]))
+        );
+
+        // Redirecting the packet to another device fails authentication
+        host.seal_addressed(
+            &mut tx,
+            0x10,
+            response::SUCCESS,
+            Address::new(3, 0),
+            &[1, 2],
+        )
+        .unwrap();
+        let mut redirected = tx;
+        redirected.strip_address();
+        redirected.add_address(Address::new(4, 0)).unwrap();
+        assert_eq!(find_route(&ROUTES, &redirected), Some(1));
+        assert_eq!(
+            device.open_addressed(&redirected, &mut buffer),
+            Err(Status::DecryptionFailed)
+        );
+        assert_eq!(
+            device.open_addressed(&tx, &mut buffer),
+            Ok((Address::new(3, 0), &[1u8, 2][..]))
+        );
+
+        // No room for the prefix, counter and tag
+        assert_eq!(
+            host.seal_addressed(
+                &mut tx,
+                0x10,
+                response::SUCCESS,
+                Address::new(3, 0),
+                &[0; FLEM_PACKET_SIZE - SECURE_OVERHEAD]
+            ),
+            Err(Status::PacketOverflow)
+        );
+    }
+}
@@ -0,0 +1,48 @@
+#![cfg(all(feature = "serial", unix))]
+
+#[cfg(test)]
+mod tests {
+
+    use flem::traits::Channel;
+    use flem::transport::SerialChannel;
+    use flem::{request, Packet};
+    use serialport::{SerialPort, TTYPort};
+    use std::io::{Read, Write};
+    use std::time::Duration;
+
+    const FLEM_PACKET_SIZE: usize = 64;
+
+    #[test]
+    fn packets_over_a_pty() {
+        let (host, mut device) = TTYPort::pair().unwrap();
+        device.set_timeout(Duration::from_millis(500)).unwrap();
+
+        let mut channel = SerialChannel::<FLEM_PACKET_SIZE>::from_port(Box::new(host));
+        let (tx, rx) = channel.listen(1, 1);
+
+        // Device to host, with line noise in front
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(request::ID, &[0x01, 0x02]).unwrap();
+        device.write_all(&[0x00, 0xFF]).unwrap();
+        device.write_all(packet.bytes()).unwrap();
+        let received = rx.recv_timeout(Duration::from_millis(500)).unwrap();
+        assert_eq!(received.bytes(), packet.bytes());
+
+        // Host to device
+        packet.pack_data(request::HEARTBEAT, &[]).unwrap();
+        tx.send(packet).unwrap();
+        let mut bytes = vec![0u8; packet.length()];
+        device.read_exact(&mut bytes).unwrap();
+        assert_eq!(bytes, packet.bytes());
+
+        channel.disconnect().unwrap();
+    }
+
+    #[test]
+    fn connect_reports_missing_ports() {
+        let mut channel = SerialChannel::<FLEM_PACKET_SIZE>::default();
+        assert!(channel
+            .connect(&"/dev/flem-does-not-exist".to_string())
+            .is_err());
+    }
+}
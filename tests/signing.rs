@@ -0,0 +1,183 @@
+#![cfg(feature = "signing")]
+
+#[cfg(test)]
+mod tests {
+
+    use flem::dfu::{DfuDevice, DfuError, DfuState, DfuStatus, FlashWriter, ImageInfo};
+    use flem::signing::{Manifest, SigningKey, MANIFEST_SIZE};
+    use flem::{request, Packet};
+
+    const FLEM_PACKET_SIZE: usize = 64;
+
+    struct RamFlash {
+        memory: Vec<u8>,
+        committed: Option<ImageInfo>,
+    }
+
+    impl FlashWriter for RamFlash {
+        type Error = ();
+
+        fn capacity(&self) -> u32 {
+            self.memory.len() as u32
+        }
+
+        fn erase(&mut self, size: u32) -> Result<(), ()> {
+            self.memory[..size as usize].fill(0xFF);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            self.memory[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn read(&mut self, offset: u32, buffer: &mut [u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            buffer.copy_from_slice(&self.memory[offset..offset + buffer.len()]);
+            Ok(())
+        }
+
+        fn commit(&mut self, image: &ImageInfo) -> Result<(), ()> {
+            self.committed = Some(*image);
+            Ok(())
+        }
+    }
+
+    fn send(
+        device: &mut DfuDevice<RamFlash>,
+        code: u16,
+        offset: Option<u32>,
+        data: &[u8],
+    ) -> (DfuStatus, Option<DfuError>) {
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.set_request(code);
+        if let Some(offset) = offset {
+            packet.add_data(&offset.to_le_bytes()).unwrap();
+        }
+        packet.add_data(data).unwrap();
+        packet.pack();
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        assert!(device.handle(&packet, &mut reply));
+        DfuStatus::from_reply(&reply).unwrap()
+    }
+
+    /// Transfers `firmware` and the optional manifest, then asks the device to verify it
+    fn transfer(
+        device: &mut DfuDevice<RamFlash>,
+        firmware: &[u8],
+        info: &ImageInfo,
+        manifest: Option<&Manifest>,
+    ) -> (DfuStatus, Option<DfuError>) {
+        send(device, request::DFU_BEGIN, None, &info.to_bytes());
+        for (index, chunk) in firmware.chunks(FLEM_PACKET_SIZE - 4).enumerate() {
+            let offset = (index * (FLEM_PACKET_SIZE - 4)) as u32;
+            send(device, request::DFU_WRITE, Some(offset), chunk);
+        }
+        if let Some(manifest) = manifest {
+            for (index, part) in manifest.to_bytes().chunks(50).enumerate() {
+                let (_, error) = send(device, request::DFU_MANIFEST, Some(index as u32 * 50), part);
+                assert_eq!(error, None);
+            }
+        }
+        send(device, request::DFU_VERIFY, None, &[])
+    }
+
+    #[test]
+    fn manifests() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let firmware = [0x3Cu8; 500];
+        let manifest = Manifest::sign(&key, &firmware, 2, 0, 1);
+
+        let bytes = manifest.to_bytes();
+        assert_eq!(bytes.len(), MANIFEST_SIZE);
+        assert_eq!(Manifest::from_bytes(&bytes), Some(manifest));
+        assert_eq!(Manifest::from_bytes(&bytes[1..]), None);
+        assert!(manifest.verify_image(&key.verifying_key(), &firmware));
+
+        // Wrong key, a different image, or an edited version all fail
+        let other = SigningKey::from_bytes(&[2; 32]);
+        assert!(!manifest.verify_image(&other.verifying_key(), &firmware));
+        assert!(!manifest.verify_image(&key.verifying_key(), &firmware[1..]));
+        let mut edited = manifest;
+        edited.major = 3;
+        assert!(!edited.verify_image(&key.verifying_key(), &firmware));
+    }
+
+    #[test]
+    fn device_refuses_unsigned_firmware() {
+        let key = SigningKey::from_bytes(&[9; 32]);
+        let firmware: Vec<u8> = (0..700).map(|i| i as u8).collect();
+        let info = ImageInfo::new(&firmware, 1, 0, 0);
+
+        let mut device = DfuDevice::new(RamFlash {
+            memory: vec![0xFF; 1024],
+            committed: None,
+        });
+        device.set_verifying_key(key.verifying_key());
+
+        // No manifest
+        let (status, error) = transfer(&mut device, &firmware, &info, None);
+        assert_eq!(error, Some(DfuError::SignatureInvalid));
+        assert_eq!(status.state, DfuState::Idle);
+
+        // Signed by someone else
+        let forged = Manifest::sign(&SigningKey::from_bytes(&[8; 32]), &firmware, 1, 0, 0);
+        let (_, error) = transfer(&mut device, &firmware, &info, Some(&forged));
+        assert_eq!(error, Some(DfuError::SignatureInvalid));
+
+        // Signed for another version
+        let old = Manifest::sign(&key, &firmware, 0, 9, 0);
+        let (_, error) = transfer(&mut device, &firmware, &info, Some(&old));
+        assert_eq!(error, Some(DfuError::SignatureInvalid));
+        assert_eq!(
+            send(&mut device, request::DFU_COMMIT, None, &[]).1,
+            Some(DfuError::NotVerified)
+        );
+
+        let manifest = Manifest::sign(&key, &firmware, 1, 0, 0);
+        let (status, error) = transfer(&mut device, &firmware, &info, Some(&manifest));
+        assert_eq!(error, None);
+        assert_eq!(status.state, DfuState::Verified);
+        send(&mut device, request::DFU_COMMIT, None, &[]);
+        assert_eq!(device.flash().committed, Some(info));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn upload_signed_image() {
+        use flem::dfu::DfuUploader;
+        use flem::emulator::Emulator;
+        use flem::traits::Channel;
+
+        let key = SigningKey::from_bytes(&[4; 32]);
+        let firmware: Vec<u8> = (0..1500).map(|i| (i / 3) as u8).collect();
+        let info = ImageInfo::new(&firmware, 1, 1, 0);
+        let manifest = Manifest::sign(&key, &firmware, 1, 1, 0);
+
+        let mut device = DfuDevice::new(RamFlash {
+            memory: vec![0xFF; 2048],
+            committed: None,
+        });
+        device.set_verifying_key(key.verifying_key());
+        let mut emulator = Emulator::<_, FLEM_PACKET_SIZE>::new(device);
+        for code in request::DFU_BEGIN..=request::DFU_MANIFEST {
+            emulator.handle(code, |device, packet, reply| {
+                device.handle(packet, reply);
+            });
+        }
+        let (tx, rx) = Channel::<FLEM_PACKET_SIZE>::listen(&mut emulator, 1, 1);
+
+        let uploader = DfuUploader::default();
+        assert_eq!(
+            uploader.upload(&tx, &rx, &firmware, &info, |_, _| {}),
+            Err(flem::dfu::UploadError::Device(DfuError::SignatureInvalid))
+        );
+        uploader
+            .upload_signed(&tx, &rx, &firmware, &info, &manifest, |_, _| {})
+            .unwrap();
+        emulator.with_state(|device| assert_eq!(device.flash().committed, Some(info)));
+
+        Channel::<FLEM_PACKET_SIZE>::unlisten(&mut emulator).unwrap();
+    }
+}
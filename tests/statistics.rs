@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::statistics::LinkStatistics;
+    use flem::{Packet, Status};
+
+    const FLEM_PACKET_SIZE: usize = 16;
+
+    fn feed(
+        rx: &mut Packet<FLEM_PACKET_SIZE>,
+        bytes: &[u8],
+        stats: &mut LinkStatistics,
+    ) -> Result<(), Status> {
+        let mut result = Err(Status::PacketBuilding);
+        for byte in bytes {
+            result = rx.construct_counted(*byte, stats);
+            match result {
+                Ok(_) | Err(Status::PacketBuilding) | Err(Status::HeaderBytesNotFound) => {}
+                Err(_) => rx.reset_lazy(),
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn receive_errors_are_counted() {
+        let mut stats = LinkStatistics::new();
+        let mut tx = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut rx = Packet::<FLEM_PACKET_SIZE>::new();
+
+        tx.pack_data(0xF, &[1, 2, 3, 4]).unwrap();
+        let good = tx.bytes().to_vec();
+
+        // Corrupted payload byte
+        let mut corrupted = good.clone();
+        corrupted[12] ^= 0xFF;
+        assert_eq!(
+            feed(&mut rx, &corrupted, &mut stats),
+            Err(Status::ChecksumError)
+        );
+
+        // Length field larger than the receive buffer
+        let mut too_long = good.clone();
+        too_long[8] = 0xFF;
+        assert_eq!(
+            feed(&mut rx, &too_long[..10], &mut stats),
+            Err(Status::InvalidDataLengthDetected)
+        );
+
+        // Half a packet, then the line goes quiet
+        feed(&mut rx, &good[..6], &mut stats).unwrap_err();
+        rx.timeout_counted(&mut stats);
+
+        assert!(feed(&mut rx, &good, &mut stats).is_ok());
+
+        let snapshot = stats.take();
+        assert_eq!(snapshot.packets_received, 1);
+        assert_eq!(snapshot.checksum_errors, 1);
+        assert_eq!(snapshot.invalid_lengths, 1);
+        assert_eq!(snapshot.timeouts, 1);
+        assert_eq!(snapshot.bytes_discarded, 14 + 10 + 6);
+        assert_eq!(
+            stats,
+            LinkStatistics::new(),
+            "take() should reset the counters"
+        );
+    }
+}
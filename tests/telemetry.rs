@@ -0,0 +1,235 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::telemetry::{Publisher, Subscription, TelemetryChannel};
+    use flem::{request, response, Packet};
+
+    const FLEM_PACKET_SIZE: usize = 64;
+
+    const TEMPERATURE: u16 = 0x01;
+    const CURRENT: u16 = 0x02;
+
+    static CHANNELS: [TelemetryChannel; 2] = [
+        TelemetryChannel::new(TEMPERATURE, 100),
+        TelemetryChannel::new(CURRENT, 1),
+    ];
+
+    fn send(publisher: &mut Publisher, code: u16, data: &[u8]) -> Packet<FLEM_PACKET_SIZE> {
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(code, data).unwrap();
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        assert!(publisher.handle(&packet, &mut reply));
+        reply
+    }
+
+    fn subscribe(publisher: &mut Publisher, channel: u16, period: u32) -> Packet<FLEM_PACKET_SIZE> {
+        let mut data = channel.to_le_bytes().to_vec();
+        data.extend_from_slice(&period.to_le_bytes());
+        send(publisher, request::TELEMETRY_SUBSCRIBE, &data)
+    }
+
+    /// Polls until nothing is due, returning the channel of each packet
+    fn drain(publisher: &mut Publisher, now: u32) -> Vec<u16> {
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut channels = Vec::new();
+        while publisher.poll(now, &mut packet, |channel, buffer| {
+            buffer[..2].copy_from_slice(&channel.to_le_bytes());
+            2
+        }) {
+            assert_eq!(packet.get_request(), request::TELEMETRY_DATA);
+            assert_eq!(packet.get_response(), response::ASYNC);
+            channels.push(u16::from_le_bytes([
+                packet.payload()[0],
+                packet.payload()[1],
+            ]));
+        }
+        channels
+    }
+
+    #[test]
+    fn subscriptions_are_scheduled() {
+        let mut subscriptions = [Subscription::new(); 2];
+        let mut publisher = Publisher::new(&CHANNELS, &mut subscriptions);
+
+        // Raised to the channel's minimum period
+        let reply = subscribe(&mut publisher, TEMPERATURE, 10);
+        assert_eq!(reply.get_response(), response::SUCCESS);
+        assert_eq!(reply.payload(), &[0x01, 0x00, 100, 0, 0, 0]);
+        let reply = subscribe(&mut publisher, CURRENT, 20);
+        assert_eq!(reply.payload(), &[0x02, 0x00, 20, 0, 0, 0]);
+
+        assert_eq!(drain(&mut publisher, 1000), vec![TEMPERATURE, CURRENT]);
+        assert_eq!(drain(&mut publisher, 1019), Vec::<u16>::new());
+        assert_eq!(drain(&mut publisher, 1020), vec![CURRENT]);
+        // Missed samples are skipped, not sent in a burst
+        assert_eq!(drain(&mut publisher, 1100), vec![TEMPERATURE, CURRENT]);
+        assert_eq!(drain(&mut publisher, 1119), Vec::<u16>::new());
+        assert_eq!(drain(&mut publisher, 1120), vec![CURRENT]);
+
+        // Changing the rate
+        subscribe(&mut publisher, CURRENT, 50);
+        // The sample already scheduled goes out, then the new period applies
+        assert_eq!(drain(&mut publisher, 1140), vec![CURRENT]);
+        assert_eq!(drain(&mut publisher, 1189), Vec::<u16>::new());
+        assert_eq!(drain(&mut publisher, 1190), vec![CURRENT]);
+        assert_eq!(
+            publisher.subscription(CURRENT).unwrap().period_ms(),
+            Some(50)
+        );
+
+        let reply = send(
+            &mut publisher,
+            request::TELEMETRY_UNSUBSCRIBE,
+            &CURRENT.to_le_bytes(),
+        );
+        assert_eq!(reply.get_response(), response::SUCCESS);
+        assert_eq!(drain(&mut publisher, 1300), vec![TEMPERATURE]);
+        assert_eq!(publisher.subscription(CURRENT).unwrap().period_ms(), None);
+
+        // The clock may wrap
+        publisher.clear();
+        subscribe(&mut publisher, CURRENT, 20);
+        assert_eq!(drain(&mut publisher, u32::MAX - 9), vec![CURRENT]);
+        assert_eq!(drain(&mut publisher, 5), Vec::<u16>::new());
+        assert_eq!(drain(&mut publisher, 10), vec![CURRENT]);
+
+        for (code, data) in [
+            (request::TELEMETRY_SUBSCRIBE, &[0x01, 0x00, 0, 0, 0, 0][..]),
+            (request::TELEMETRY_SUBSCRIBE, &[0x09, 0x00, 10, 0, 0, 0]),
+            (request::TELEMETRY_SUBSCRIBE, &[0x01, 0x00, 10]),
+            (request::TELEMETRY_UNSUBSCRIBE, &[0x09, 0x00]),
+        ] {
+            let reply = send(&mut publisher, code, data);
+            assert_eq!(
+                reply.get_response(),
+                response::INVALID_ARGUMENT,
+                "{:?}",
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn bandwidth_is_limited() {
+        let mut subscriptions = [Subscription::new(); 2];
+        let mut publisher = Publisher::new(&CHANNELS, &mut subscriptions);
+        // Each sample is 16 bytes on the wire: 10 header, 4 channel and sequence, 2 sample
+        publisher.set_bandwidth(1600);
+        subscribe(&mut publisher, CURRENT, 1);
+
+        let mut sent = 0;
+        for now in 0..=1000 {
+            sent += drain(&mut publisher, now).len();
+        }
+        // 100 per second, plus whatever fit in the burst allowance
+        assert!((95..=110).contains(&sent), "{}", sent);
+
+        // Out of budget: neither the sample nor the caller's packet are touched
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        packet.pack_data(0x1234, &[1, 2, 3]).unwrap();
+        let before = packet.bytes().to_vec();
+        assert!(!publisher.poll(1001, &mut packet, |_, _| panic!("sampled without budget")));
+        assert_eq!(packet.bytes(), &before[..]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn host_api() {
+        use flem::client::Client;
+        use flem::emulator::Emulator;
+        use flem::telemetry::{self, Subscriber};
+        use flem::traits::Channel;
+        use std::time::{Duration, Instant};
+
+        let subscriptions = Box::leak(Box::new([Subscription::new(); 2]));
+        let mut emulator =
+            Emulator::<_, FLEM_PACKET_SIZE>::new(Publisher::new(&CHANNELS, subscriptions));
+        for code in request::TELEMETRY_SUBSCRIBE..=request::TELEMETRY_UNSUBSCRIBE {
+            emulator.handle(code, |publisher, packet, reply| {
+                publisher.handle(packet, reply);
+            });
+        }
+        let start = Instant::now();
+        emulator.every(Duration::from_millis(1), move |publisher, packet| {
+            publisher.poll(
+                start.elapsed().as_millis() as u32,
+                packet,
+                |channel, buffer| {
+                    buffer[0] = channel as u8 * 10;
+                    1
+                },
+            )
+        });
+        let (tx, rx) = Channel::<FLEM_PACKET_SIZE>::listen(&mut emulator, 1, 1);
+        let client = Client::new(&tx, &rx);
+
+        assert_eq!(telemetry::subscribe(&client, CURRENT, 5), Ok(5));
+
+        let mut subscriber = Subscriber::from_client(&client);
+        let samples: Vec<_> = subscriber
+            .iter(Duration::from_millis(500))
+            .take(10)
+            .collect();
+        assert_eq!(samples.len(), 10);
+        for pair in samples.windows(2) {
+            assert_eq!(pair[1].sequence, pair[0].sequence.wrapping_add(1));
+            assert_eq!(pair[1].missed, 0);
+        }
+        assert!(samples
+            .iter()
+            .all(|sample| sample.channel == CURRENT && sample.data == vec![20]));
+
+        telemetry::unsubscribe(&client, CURRENT).unwrap();
+        // Samples already in flight may still arrive, then nothing
+        std::thread::sleep(Duration::from_millis(50));
+        while rx.try_recv().is_ok() {}
+        while client.take_unmatched().is_some() {}
+        let mut count = 0;
+        subscriber.run_for(Duration::from_millis(100), |_| count += 1);
+        assert_eq!(count, 0);
+        Channel::<FLEM_PACKET_SIZE>::unlisten(&mut emulator).unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn samples_during_a_request_are_kept() {
+        use flem::client::Client;
+        use flem::telemetry::Subscriber;
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        const ECHO: u16 = 0x30;
+
+        let packet = |request: u16, code: u16, data: &[u8]| {
+            let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+            packet.pack_data(request, data).unwrap();
+            packet.set_response(code);
+            packet.pack();
+            packet
+        };
+        let (tx, _device_rx) = mpsc::channel();
+        let (device_tx, rx) = mpsc::channel();
+        device_tx
+            .send(packet(
+                request::TELEMETRY_DATA,
+                response::ASYNC,
+                &[0x01, 0x00, 0x07, 0x00, 42],
+            ))
+            .unwrap();
+        // An ASYNC packet with the request's code isn't the reply
+        device_tx.send(packet(ECHO, response::ASYNC, &[1])).unwrap();
+        device_tx
+            .send(packet(ECHO, response::SUCCESS, &[2]))
+            .unwrap();
+
+        let client = Client::new(&tx, &rx).with_timeout(Duration::from_millis(100));
+        assert_eq!(client.call(ECHO, &[]).unwrap().payload(), &[2]);
+
+        let mut subscriber = Subscriber::from_client(&client);
+        let sample = subscriber.recv_timeout(Duration::from_millis(10)).unwrap();
+        assert_eq!((sample.channel, sample.sequence), (TEMPERATURE, 7));
+        assert_eq!(sample.data, vec![42]);
+        assert_eq!(subscriber.recv_timeout(Duration::from_millis(10)), None);
+        assert!(client.take_unmatched().is_none());
+    }
+}
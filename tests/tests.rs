@@ -85,6 +85,19 @@ mod tests {
         assert_eq!(checksum, rx.get_checksum(), "Checksum mismatch");
     }
 
+    #[test]
+    fn payload_is_clamped_to_the_buffer() {
+        let mut rx = flem::Packet::<FLEM_PACKET_SIZE>::new();
+        // A header claiming 0xFFFF bytes of data
+        let header = [0x55, 0x55, 0x00, 0x00, 0x10, 0x00, 0x01, 0x00, 0xFF, 0xFF];
+        let mut result = Ok(());
+        for byte in header {
+            result = rx.construct(byte);
+        }
+        assert_eq!(result, Err(flem::Status::InvalidDataLengthDetected));
+        assert_eq!(rx.payload().len(), FLEM_PACKET_SIZE);
+    }
+
     #[test]
     fn size_check() {
         let mut rx = flem::Packet::<FLEM_PACKET_SIZE>::new();
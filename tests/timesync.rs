@@ -0,0 +1,149 @@
+#[cfg(test)]
+mod tests {
+
+    use flem::timesync::{Exchange, TimeSource, TimeSync};
+    use flem::{request, response, Packet};
+
+    const FLEM_PACKET_SIZE: usize = 64;
+
+    /// A 32 kHz tick counter set by the test
+    struct ManualClock {
+        ticks: u64,
+    }
+
+    impl TimeSource for ManualClock {
+        fn ticks(&mut self) -> u64 {
+            self.ticks
+        }
+
+        fn frequency(&self) -> u64 {
+            32_768
+        }
+    }
+
+    #[test]
+    fn exchange() {
+        // Device 1 s ahead, 2 ms each way, 1 ms to reply
+        let exchange = Exchange {
+            t1: 10_000_000,
+            t2: 11_002_000,
+            t3: 11_003_000,
+            t4: 10_005_000,
+        };
+        assert_eq!(exchange.offset(), 1_000_000);
+        assert_eq!(exchange.delay(), 4_000);
+
+        let behind = Exchange {
+            t1: 10_000_000,
+            t2: 2_000,
+            t3: 3_000,
+            t4: 10_005_000,
+        };
+        assert_eq!(behind.offset(), -10_000_000);
+    }
+
+    #[test]
+    fn drift_is_corrected() {
+        let mut sync = TimeSync::new(ManualClock { ticks: 32_768 });
+        assert_eq!(sync.device_us(), 1_000_000);
+        assert_eq!(sync.host_us(), None);
+
+        // The device clock runs 100 ppm fast
+        sync.set(1_000_000, 5_000_000);
+        assert!(sync.is_synchronized());
+        assert_eq!(sync.to_host_us(2_000_000), Some(6_000_000));
+
+        // Too close to measure drift, only the offset moves
+        sync.set(1_500_000, 5_499_950);
+        assert_eq!(sync.drift_ppb(), 0);
+
+        sync.set(11_001_000, 15_000_000);
+        assert_eq!(sync.drift_ppb(), -99_990);
+        let host = sync.to_host_us(21_002_000).unwrap();
+        assert!(host.abs_diff(25_000_000) <= 1, "{}", host);
+
+        // A device restart starts over
+        sync.set(500, 30_000_000);
+        assert_eq!(sync.drift_ppb(), 0);
+    }
+
+    #[test]
+    fn requests() {
+        let mut sync = TimeSync::new(ManualClock { ticks: 65_536 });
+
+        let mut packet = Packet::<FLEM_PACKET_SIZE>::new();
+        let mut reply = Packet::<FLEM_PACKET_SIZE>::new();
+        packet
+            .pack_data(request::TIME_SYNC, &1234u64.to_le_bytes())
+            .unwrap();
+        assert!(sync.handle(&packet, &mut reply));
+        assert_eq!(reply.get_response(), response::SUCCESS);
+        let mut expected = 1234u64.to_le_bytes().to_vec();
+        expected.extend_from_slice(&2_000_000u64.to_le_bytes());
+        expected.extend_from_slice(&2_000_000u64.to_le_bytes());
+        assert_eq!(reply.payload(), expected.as_slice());
+
+        let mut data = 2_000_000u64.to_le_bytes().to_vec();
+        data.extend_from_slice(&7_000_000u64.to_le_bytes());
+        packet.pack_data(request::TIME_SET, &data).unwrap();
+        assert!(sync.handle(&packet, &mut reply));
+        assert_eq!(reply.get_response(), response::SUCCESS);
+        assert_eq!(sync.host_us(), Some(7_000_000));
+
+        packet.pack_data(request::TIME_SET, &data[..8]).unwrap();
+        assert!(sync.handle(&packet, &mut reply));
+        assert_eq!(reply.get_response(), response::INVALID_ARGUMENT);
+
+        packet.pack_data(request::ID, &[]).unwrap();
+        assert!(!sync.handle(&packet, &mut reply));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn host_api() {
+        use flem::client::Client;
+        use flem::emulator::Emulator;
+        use flem::timesync::{self, host_now_us};
+        use flem::traits::Channel;
+        use std::time::Instant;
+
+        /// A 1 MHz counter started an hour ago
+        struct Uptime {
+            start: Instant,
+        }
+
+        impl TimeSource for Uptime {
+            fn ticks(&mut self) -> u64 {
+                3_600_000_000 + self.start.elapsed().as_micros() as u64
+            }
+
+            fn frequency(&self) -> u64 {
+                1_000_000
+            }
+        }
+
+        let mut emulator = Emulator::<_, FLEM_PACKET_SIZE>::new(TimeSync::new(Uptime {
+            start: Instant::now(),
+        }));
+        for code in request::TIME_SYNC..=request::TIME_SET {
+            emulator.handle(code, |sync, packet, reply| {
+                sync.handle(packet, reply);
+            });
+        }
+        let (tx, rx) = Channel::<FLEM_PACKET_SIZE>::listen(&mut emulator, 1, 1);
+        let client = Client::new(&tx, &rx);
+
+        // A late reply left over from an earlier sync is skipped
+        let mut stale = Packet::<FLEM_PACKET_SIZE>::new();
+        stale.pack_data(request::TIME_SYNC, &[0u8; 24]).unwrap();
+        emulator.send_raw(stale.bytes());
+
+        let exchange = timesync::sync(&client, 4).unwrap();
+        assert!(exchange.delay() < 100_000, "{:?}", exchange);
+
+        let device_host_us = emulator.with_state(|sync| sync.host_us()).unwrap();
+        let error = device_host_us.abs_diff(host_now_us());
+        assert!(error < 20_000, "{} us off", error);
+        Channel::<FLEM_PACKET_SIZE>::unlisten(&mut emulator).unwrap();
+    }
+}